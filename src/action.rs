@@ -0,0 +1,376 @@
+use serde::{Deserialize, Serialize};
+
+use crate::app::App;
+use crate::checksum::Algorithm;
+
+/// Something the user asked for in the tree, independent of the key or mouse
+/// gesture that triggered it. `input.rs` translates events into actions and
+/// `App::dispatch` carries them out, so they can also be replayed or sent
+/// from tests without a terminal.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "action", content = "arg", rename_all = "snake_case")]
+pub enum Action {
+    Quit,
+
+    // Navigation
+    MoveUp(usize),
+    MoveDown(usize),
+    GoToTop,
+    GoToBottom,
+    /// Select the nth visible entry (1-based)
+    GoToLine(usize),
+    Expand,
+    Collapse,
+    ToggleExpand,
+    CollapseAll,
+    ExpandAll,
+    CollapseOthers,
+    ExpandRecursive,
+    ScrollLeft(usize),
+    ScrollRight(usize),
+    /// Scroll the tree by this many rows, keeping the selection on screen
+    ScrollUp(usize),
+    ScrollDown(usize),
+    /// Re-root at the nth ancestor shown in the breadcrumb
+    BreadcrumbJump(usize),
+    SearchNext,
+    NextChange,
+    GoToGitRoot,
+    JumpLabels,
+
+    // Mouse, with rows relative to the first tree row
+    Click(u16),
+    Drag(u16),
+    EndDrag,
+    ScrollbarJump(u16),
+    BreadcrumbClick(u16),
+
+    // Tabs
+    OpenTab,
+    CloseTab,
+    NextTab,
+    PrevTab,
+
+    // Marking
+    ToggleMark(usize),
+    ClearMarks,
+    ClearAllMarks,
+    MarkAllInDir,
+    InvertMarks,
+    MarkGlob,
+    /// Mark every file with git changes below the root
+    MarkChanged,
+    MarkedList,
+
+    // File operations
+    Yank,
+    Cut,
+    Paste,
+    /// Prompt for a directory to move the selection into
+    MoveTo,
+    /// Prompt for a directory to copy the selection into
+    CopyTo,
+    PasteSystemClipboard,
+    Delete,
+    Rename,
+    NewFile,
+    NewDir,
+    DragOut,
+    /// Show the selection in the desktop's file manager
+    RevealInFileManager,
+    CopyPath,
+    CopyFilename,
+    /// Hash the selected file and copy the digest
+    Checksum(Algorithm),
+    CopyRelativePath,
+    CopyGitRelativePath,
+
+    // Git
+    Stage,
+    Unstage,
+    Discard,
+    Commit,
+
+    // View
+    Refresh,
+    ToggleHidden,
+    ToggleCleanView,
+    ToggleDirsOnly,
+    ExtensionFilter,
+    ToggleHideEmptyDirs,
+    ToggleFlatView,
+    /// Directories first, last, or mixed with files
+    CycleDirectoryOrder,
+    ToggleSortCase,
+    ToggleHiddenFirst,
+    ToggleNaturalSort,
+    ToggleSizeColumn,
+    ToggleMtimeColumn,
+    TogglePermissionsColumn,
+    ToggleDetailView,
+    CalculateDirSizes,
+    DiskUsage,
+    ToggleCompact,
+    /// Parent / current / preview columns instead of the tree
+    ToggleMillerColumns,
+    TogglePerfOverlay,
+
+    // Preview and commands
+    Preview,
+    ToggleQuickPreview,
+    TogglePreviewWrap,
+    TogglePreviewLineNumbers,
+    GitLog,
+    OpenMarked,
+    OpenInNvim,
+    /// Run the matching `open_with`, last or default command, or ask for one
+    RunCommand,
+    PromptCommand,
+    /// Choose the selection in picker mode
+    Choose,
+    TodoScan,
+    /// Show the metadata of the selected entry
+    FileInfo,
+    /// Compare the two marked directories
+    CompareDirs,
+    Help,
+    /// A character typed while a dropped path may be arriving
+    BufferChar(char),
+}
+
+impl App {
+    pub fn dispatch(&mut self, action: Action) {
+        match action {
+            Action::Quit => self.quit(),
+
+            Action::MoveUp(count) => {
+                for _ in 0..count {
+                    self.move_up();
+                }
+                self.update_quick_preview();
+            }
+            Action::MoveDown(count) => {
+                for _ in 0..count {
+                    self.move_down();
+                }
+                self.update_quick_preview();
+            }
+            Action::GoToTop => {
+                self.move_to_top();
+                self.update_quick_preview();
+            }
+            Action::GoToBottom => {
+                self.move_to_bottom();
+                self.update_quick_preview();
+            }
+            Action::GoToLine(line) => {
+                self.move_to_line(line);
+                self.update_quick_preview();
+            }
+            Action::Expand => {
+                self.expand_current();
+                self.update_quick_preview();
+            }
+            Action::Collapse => {
+                self.collapse_current();
+                self.update_quick_preview();
+            }
+            Action::ToggleExpand => self.toggle_expand(),
+            Action::CollapseAll => self.collapse_all(),
+            Action::ExpandAll => self.expand_all(),
+            Action::CollapseOthers => self.collapse_others(),
+            Action::ExpandRecursive => self.expand_selected_recursive(),
+            Action::ScrollLeft(columns) => self.scroll_left(columns),
+            Action::ScrollRight(columns) => self.scroll_right(columns),
+            Action::ScrollUp(rows) => {
+                self.scroll_up(rows);
+                self.update_quick_preview();
+            }
+            Action::ScrollDown(rows) => {
+                self.scroll_down(rows);
+                self.update_quick_preview();
+            }
+            Action::BreadcrumbJump(n) => self.breadcrumb_jump(n),
+            Action::SearchNext => self.search_next(),
+            Action::NextChange => self.jump_to_next_change(),
+            Action::GoToGitRoot => match self.git_repo.root.clone() {
+                Some(root) => self.change_root(root),
+                None => self.message = Some("Not a git repository".to_string()),
+            },
+            Action::JumpLabels => self.start_jump_labels(),
+
+            Action::Click(row) => {
+                self.handle_click(row);
+                self.update_quick_preview();
+            }
+            Action::Drag(row) => {
+                self.handle_drag(row);
+                self.update_quick_preview();
+            }
+            Action::EndDrag => self.end_drag(),
+            Action::ScrollbarJump(row) => {
+                self.scrollbar_jump(row);
+                self.update_quick_preview();
+            }
+            Action::BreadcrumbClick(column) => self.breadcrumb_click(column),
+
+            Action::OpenTab => self.open_tab(),
+            Action::CloseTab => self.close_tab(),
+            Action::NextTab => self.next_tab(),
+            Action::PrevTab => self.prev_tab(),
+
+            Action::ToggleMark(count) => {
+                // Without auto-advance a count would toggle the same entry
+                let count = if self.config.mark_advance { count } else { 1 };
+                for _ in 0..count {
+                    self.toggle_mark();
+                }
+            }
+            Action::ClearMarks => self.clear_marks(),
+            Action::ClearAllMarks => self.clear_all_marks(),
+            Action::MarkAllInDir => self.mark_all_in_dir(),
+            Action::InvertMarks => self.invert_marks_in_dir(),
+            Action::MarkGlob => self.start_mark_glob(),
+            Action::MarkChanged => self.mark_changed(),
+            Action::MarkedList => self.open_marked_list(),
+
+            Action::Yank => self.yank(),
+            Action::Cut => self.cut(),
+            Action::Paste => self.paste(),
+            Action::MoveTo => self.start_relocate(false),
+            Action::CopyTo => self.start_relocate(true),
+            Action::PasteSystemClipboard => self.paste_from_system_clipboard(),
+            Action::Delete => self.confirm_delete(),
+            Action::Rename => self.start_rename(),
+            Action::NewFile => self.start_new_file(),
+            Action::NewDir => self.start_new_dir(),
+            Action::DragOut => self.drag_out(),
+            Action::RevealInFileManager => self.reveal_in_file_manager(),
+            Action::CopyPath => self.copy_path(),
+            Action::CopyFilename => self.copy_filename(),
+            Action::Checksum(algorithm) => self.start_checksum(algorithm),
+            Action::CopyRelativePath => self.copy_path_relative(self.tree.root.path.clone()),
+            Action::CopyGitRelativePath => match self.git_repo.root.clone() {
+                Some(root) => self.copy_path_relative(root),
+                None => self.message = Some("Not a git repository".to_string()),
+            },
+
+            Action::Stage => self.stage_selected(),
+            Action::Unstage => self.unstage_selected(),
+            Action::Discard => self.confirm_discard(),
+            Action::Commit => self.start_commit(),
+
+            Action::Refresh => self.refresh(),
+            Action::ToggleHidden => self.toggle_hidden(),
+            Action::ToggleCleanView => self.toggle_clean_view(),
+            Action::ToggleDirsOnly => self.toggle_dirs_only(),
+            Action::ExtensionFilter => self.start_extension_filter(),
+            Action::ToggleHideEmptyDirs => self.toggle_hide_empty_dirs(),
+            Action::ToggleFlatView => self.toggle_flat_view(),
+            Action::CycleDirectoryOrder => self.cycle_directory_order(),
+            Action::ToggleSortCase => self.toggle_sort_case(),
+            Action::ToggleHiddenFirst => self.toggle_hidden_first(),
+            Action::ToggleNaturalSort => self.toggle_natural_sort(),
+            Action::ToggleSizeColumn => self.toggle_size_column(),
+            Action::ToggleMtimeColumn => self.toggle_mtime_column(),
+            Action::TogglePermissionsColumn => self.toggle_permissions_column(),
+            Action::ToggleDetailView => self.toggle_detail_view(),
+            Action::CalculateDirSizes => self.calculate_dir_sizes(),
+            Action::DiskUsage => self.open_disk_usage(),
+            Action::ToggleCompact => self.toggle_compact(),
+            Action::ToggleMillerColumns => self.toggle_miller(),
+            Action::TogglePerfOverlay => self.toggle_perf_overlay(),
+
+            Action::Preview => self.preview_file(),
+            Action::ToggleQuickPreview => self.toggle_quick_preview(),
+            Action::TogglePreviewWrap => self.toggle_preview_wrap(),
+            Action::TogglePreviewLineNumbers => self.toggle_preview_line_numbers(),
+            Action::GitLog => self.preview_git_log(),
+            Action::OpenMarked => self.open_marked(),
+            Action::OpenInNvim => self.open_in_nvim(),
+            Action::RunCommand => {
+                if self.has_open_rule()
+                    || self.last_command.is_some()
+                    || self.default_command.is_some()
+                {
+                    self.execute_external_command(None);
+                } else {
+                    // No command yet: ask for one
+                    self.start_external_command();
+                }
+            }
+            Action::PromptCommand => self.start_external_command(),
+            Action::Choose => self.choose_selection(),
+            Action::TodoScan => self.start_todo_scan(),
+            Action::FileInfo => self.open_file_info(),
+            Action::CompareDirs => self.start_compare(),
+            Action::Help => self.open_help(),
+            Action::BufferChar(c) => self.buffer_char(c),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use ratatui::{backend::TestBackend, Terminal};
+    use std::fs;
+
+    #[test]
+    fn test_actions_deserialize() {
+        let actions: Vec<Action> =
+            serde_json::from_str(r#"[{"action":"move_down","arg":3},{"action":"toggle_hidden"}]"#)
+                .unwrap();
+        assert_eq!(actions, vec![Action::MoveDown(3), Action::ToggleHidden]);
+        assert_eq!(
+            serde_json::to_string(&Action::BufferChar('/')).unwrap(),
+            r#"{"action":"buffer_char","arg":"/"}"#
+        );
+    }
+
+    #[test]
+    fn test_dispatch_headless() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        fs::create_dir(root.join("src")).unwrap();
+        fs::write(root.join("src/main.rs"), "").unwrap();
+        fs::write(root.join(".env"), "").unwrap();
+        let config = Config {
+            git: false,
+            ..Config::default()
+        };
+        let mut app = App::new(&root, config).unwrap();
+
+        for action in [
+            Action::MoveDown(1),
+            Action::Expand,
+            Action::MoveDown(1),
+            Action::ToggleMark(1),
+            Action::ToggleHidden,
+        ] {
+            app.dispatch(action);
+        }
+        assert!(app.show_hidden);
+        assert!(app.marked.contains(&root.join("src/main.rs")));
+
+        let mut terminal = Terminal::new(TestBackend::new(60, 12)).unwrap();
+        terminal
+            .draw(|frame| {
+                crate::ui::draw(frame, &mut app);
+            })
+            .unwrap();
+        let screen: String = terminal
+            .backend()
+            .buffer()
+            .content()
+            .iter()
+            .map(|cell| cell.symbol())
+            .collect();
+        assert!(screen.contains("main.rs"));
+        assert!(screen.contains(".env"));
+
+        app.dispatch(Action::Quit);
+        assert!(app.should_quit);
+    }
+}
@@ -1,13 +1,63 @@
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
+use std::ffi::{OsStr, OsString};
 use std::fs;
 use std::io::{BufRead, BufReader, Write};
+#[cfg(unix)]
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
 use std::path::{Path, PathBuf};
-
-use crate::file_ops::{self, Clipboard, ClipboardContent};
-use crate::file_tree::FileTree;
-use crate::git_status::GitRepo;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use crate::action::Action;
+use crate::capture::{self, CommandOutput};
+use crate::checksum::{self, Algorithm};
+use crate::config::{self, Config};
+use crate::dir_compare::{self, CompareEntry, Difference};
+use crate::disk_usage::{self, DuEntry};
+#[cfg(unix)]
+use crate::ipc;
+use crate::job::Job;
+use crate::preview::{self, PreviewCache};
+use crate::session::{self, Session};
+use crate::todo_scan::{self, TodoItem};
+use filetree::file_ops::{self, Clipboard, ClipboardContent, CopyProgress, DirStats};
+use filetree::file_tree::{
+    self, glob_match, ExpandLimits, ExpandOutcome, FileNode, FileTree, SortOrder, ViewFilter,
+};
+use filetree::git_status::{self, GitRepo, GitStatus, LastCommit, LineChange};
+use unicode_width::UnicodeWidthStr;
 
 const HISTORY_LIMIT: usize = 100;
+/// Number of commits shown by the per-file git log preview
+const GIT_LOG_LIMIT: usize = 50;
+/// How long a sequence prefix waits for its continuation
+const SEQUENCE_TIMEOUT: std::time::Duration = std::time::Duration::from_millis(2000);
+/// Upper bound for count prefixes, so a held digit key can't stall the UI
+const MAX_COUNT: usize = 99_999;
+/// How long directories touched by file operations wait before being re-read,
+/// so a burst of operations triggers a single refresh
+const REFRESH_DEBOUNCE: std::time::Duration = std::time::Duration::from_millis(150);
+/// Number of recent quick previews kept in memory
+const PREVIEW_CACHE_SIZE: usize = 32;
+/// Quick preview images are downscaled to at most this many pixels per side,
+/// more than any terminal pane shows with half blocks
+const QUICK_PREVIEW_IMAGE_SIZE: u32 = 512;
+/// How often the previewed files are checked for changes on disk
+const PREVIEW_RELOAD_INTERVAL: std::time::Duration = std::time::Duration::from_millis(500);
+/// The quick preview's previewer starts once the cursor has rested this long,
+/// so scrolling through files does not start one per row
+const PREVIEWER_DELAY: std::time::Duration = std::time::Duration::from_millis(150);
+/// A shell command running in the background with its output captured
+type CommandJob = Job<std::io::Result<CommandOutput>>;
+
+/// Drag-and-drop helpers tried in order when `drag_command` isn't set
+const DRAG_PROGRAMS: &[&str] = &["ripdrag", "dragon-drop", "dragon"];
+/// Characters used for jump labels, number row first
+const JUMP_LABEL_CHARS: &[char] = &[
+    '1', '2', '3', '4', '5', '6', '7', '8', '9', '0', 'q', 'w', 'e', 'r', 't', 'y', 'u', 'i', 'o',
+    'p', 'a', 's', 'd', 'f', 'g', 'h', 'j', 'k', 'l', 'z', 'x', 'c', 'v', 'b', 'n', 'm',
+];
 
 /// Image pixel data for terminal preview (RGB values)
 #[derive(Clone)]
@@ -15,6 +65,44 @@ pub struct ImagePreview {
     pub width: u32,
     pub height: u32,
     pub pixels: Vec<(u8, u8, u8)>, // RGB values
+    /// Size of the image file, larger than `width` x `height` if it was downscaled
+    pub dimensions: (u32, u32),
+}
+
+impl ImagePreview {
+    /// Decode `img`, downscaling it to fit `max_size` pixels on each side if given
+    fn new(img: image::DynamicImage, max_size: Option<u32>) -> Self {
+        let dimensions = (img.width(), img.height());
+        let img = match max_size {
+            Some(max) if dimensions.0 > max || dimensions.1 > max => img.thumbnail(max, max),
+            _ => img,
+        };
+        let img = img.to_rgb8();
+        let (width, height) = img.dimensions();
+        let pixels = img.pixels().map(|p| (p[0], p[1], p[2])).collect();
+        Self {
+            width,
+            height,
+            pixels,
+            dimensions,
+        }
+    }
+}
+
+/// A quick preview kept in `App::preview_cache`
+#[derive(Clone)]
+pub enum CachedPreview {
+    Lines(Vec<String>),
+    Image(ImagePreview),
+}
+
+/// File picker mode: Enter on a file exits and prints the selection to stdout
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PickerMode {
+    /// `--choose-file`: print the selected file
+    Single,
+    /// `--choose-files`: print all marked files (or the selected file)
+    Multiple,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -24,20 +112,201 @@ pub enum InputMode {
     Rename,
     NewFile,
     NewDir,
+    /// Typing the directory to move the selection into
+    MoveTo,
+    /// Typing the directory to copy the selection into
+    CopyTo,
     Confirm(ConfirmAction),
     Preview,
+    /// Typing a search within the full-screen preview
+    PreviewSearch,
     ExternalCommand,
+    Commit,
+    MarkGlob,
+    ExtensionFilter,
+    MarkedList,
+    /// Reviewing every item of a large batch before running it
+    Plan,
+    JumpLabel,
+    TodoList,
+    /// Metadata of the selected entry
+    FileInfo,
+    /// Report of the differences between two marked directories
+    Compare,
+    DiskUsage,
+    Help,
+}
+
+/// A checksum being computed in the background
+pub struct ChecksumJob {
+    pub path: PathBuf,
+    pub algorithm: Algorithm,
+    pub size: u64,
+    /// Bytes hashed so far
+    pub progress: Arc<AtomicU64>,
+    pub job: Job<std::io::Result<Option<String>>>,
+}
+
+/// A match of the preview search: line index and byte range within that line
+pub type PreviewMatch = (usize, std::ops::Range<usize>);
+
+/// Per-tab state of a tab that is not currently active.
+/// The active tab lives directly in `App`'s `tree`, `git_repo`, `selected` and `scroll_offset`.
+pub struct Tab {
+    pub tree: FileTree,
+    pub git_repo: GitRepo,
+    pub selected: usize,
+    pub scroll_offset: usize,
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct DeleteInfo {
     pub paths: Vec<PathBuf>,
     pub has_directories: bool,
+    /// Requested from the disk usage view, which is reopened afterwards
+    pub from_disk_usage: bool,
+}
+
+/// Changed files whose changes will be thrown away
+#[derive(Debug, Clone, PartialEq)]
+pub struct DiscardInfo {
+    pub files: Vec<(PathBuf, GitStatus)>,
+}
+
+/// An operation that failed with permission denied, to be retried with privilege escalation
+#[derive(Debug, Clone, PartialEq)]
+pub struct ElevatedOp {
+    pub description: String,
+    /// Shell command, kept as raw bytes so non-UTF-8 paths survive
+    pub command: OsString,
+}
+
+/// A file operation applied to several paths at once
+#[derive(Debug, Clone, PartialEq)]
+pub enum BatchOp {
+    Delete,
+    /// Copy into this directory
+    Copy(PathBuf),
+    /// Move into this directory
+    Move(PathBuf),
+}
+
+/// A batch delete or move listed item by item for review
+#[derive(Debug, Clone, PartialEq)]
+pub struct BatchPlan {
+    pub op: BatchOp,
+    pub items: Vec<PlanItem>,
+    /// Requested from the disk usage view, which is reopened afterwards
+    pub from_disk_usage: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlanItem {
+    pub path: PathBuf,
+    /// Where a move would put it
+    pub target: Option<PathBuf>,
+    /// Cleared when the item is left out of the batch
+    pub included: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BatchFailure {
+    pub path: PathBuf,
+    pub error: String,
+    pub permission_denied: bool,
+}
+
+/// The paths a batch operation failed on, offered for a retry
+#[derive(Debug, Clone, PartialEq)]
+pub struct BatchReport {
+    pub op: BatchOp,
+    pub failures: Vec<BatchFailure>,
+}
+
+/// Render timings for the performance overlay (F12)
+#[derive(Debug, Clone, Default)]
+pub struct PerfStats {
+    pub frames: u64,
+    pub last_frame: std::time::Duration,
+    pub slowest_frame: std::time::Duration,
+}
+
+/// Incremental reverse search (Ctrl-R) through the command history
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct HistorySearch {
+    pub query: String,
+    /// Index into `command_history` of the current match
+    pub index: Option<usize>,
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum ConfirmAction {
     Delete(DeleteInfo),
+    Discard(DiscardInfo),
+    Elevate(ElevatedOp),
+    Retry(BatchReport),
+    /// A rename that would replace an existing entry
+    Overwrite(RenameInfo),
+    /// More files than `confirm.open_files` to open at once
+    Open(OpenInfo),
+    /// A command to run on more than `confirm.command_files` files
+    Command(CommandRun),
+    /// A copy or delete bigger than `confirm.large_mb` / `confirm.large_files`
+    Large(LargeBatch),
+}
+
+/// An external command and the files it runs on, once for each
+#[derive(Debug, Clone, PartialEq)]
+pub struct CommandRun {
+    /// Command typed at the prompt; `None` uses the `open_with`, last or default command
+    pub command: Option<String>,
+    pub paths: Vec<PathBuf>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RenameInfo {
+    pub path: PathBuf,
+    pub new_name: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LargeBatch {
+    pub op: BatchOp,
+    pub paths: Vec<PathBuf>,
+    pub stats: DirStats,
+    /// Requested from the disk usage view, which is reopened afterwards
+    pub from_disk_usage: bool,
+}
+
+/// Paths being moved to another filesystem in the background
+pub struct MoveJob {
+    pub dest_dir: PathBuf,
+    pub paths: Vec<PathBuf>,
+    pub progress: Arc<CopyProgress>,
+    pub job: Job<Vec<BatchFailure>>,
+}
+
+/// Paths being copied in the background
+pub struct CopyJob {
+    pub dest_dir: PathBuf,
+    pub paths: Vec<PathBuf>,
+    pub progress: Arc<CopyProgress>,
+    pub job: Job<Vec<BatchFailure>>,
+}
+
+/// A copy or delete waiting for the size of its directories
+pub struct SizeCheck {
+    pub op: BatchOp,
+    pub paths: Vec<PathBuf>,
+    pub from_disk_usage: bool,
+    pub job: Job<Option<DirStats>>,
+}
+
+/// Files to open in the editor, or in the Neovim server if `nvim` is set
+#[derive(Debug, Clone, PartialEq)]
+pub struct OpenInfo {
+    pub paths: Vec<PathBuf>,
+    pub nvim: bool,
 }
 
 pub struct App {
@@ -52,20 +321,67 @@ pub struct App {
     pub should_quit: bool,
     pub scroll_offset: usize,
     pub tree_area_height: usize,
+    pub tree_visible_rows: usize,
+    pub tree_hscroll: usize,
+    pub clean_view: bool,
+    /// Show only directories
+    pub dirs_only: bool,
+    /// Prune directories with no visible entries
+    pub hide_empty_dirs: bool,
+    /// Show only files with these extensions (lowercase, without the dot)
+    pub extension_filter: Vec<String>,
+    pub show_size: bool,
+    pub show_mtime: bool,
+    pub show_permissions: bool,
+    /// `ls -l`-style view showing all metadata columns that fit
+    pub detail_view: bool,
+    /// Borderless layout with a one-line status bar
+    pub compact: bool,
+    /// Parent / current / preview columns instead of the tree
+    pub miller: bool,
+    /// Cached uid/gid to name lookups for the permissions column
+    #[cfg(unix)]
+    pub users: uzers::UsersCache,
     pub last_click_time: std::time::Instant,
     pub last_click_index: Option<usize>,
+    /// Entry where the current left-button drag started
+    drag_anchor: Option<usize>,
+    /// Paths the current drag marked, so shrinking the range can unmark them
+    drag_marked: HashSet<PathBuf>,
     pub show_hidden: bool,
     // Preview mode state (full screen)
     pub preview_content: Vec<String>,
     pub preview_scroll: usize,
+    /// Columns scrolled off the left edge of the preview
+    pub preview_hscroll: usize,
+    /// Text searched for in the preview (`/`), its matches in order and the current one
+    pub preview_query: String,
+    pub preview_matches: Vec<PreviewMatch>,
+    pub preview_match: usize,
     pub preview_path: Option<PathBuf>,
+    /// Modification times of the previewed files when they were loaded, so
+    /// changes on disk can be picked up
+    pub preview_mtime: Option<SystemTime>,
+    pub quick_preview_mtime: Option<SystemTime>,
+    pub last_reload_check: std::time::Instant,
+    /// Title override for previews that aren't file content (e.g. git log)
+    pub preview_title: Option<String>,
     pub image_preview: Option<ImagePreview>,
     // Quick preview panel (bottom panel, Quick Look style)
     pub quick_preview_enabled: bool,
+    /// Both previews wrap long lines instead of cutting them off
+    pub preview_wrap: bool,
+    pub preview_line_numbers: bool,
+    /// Lines of the previewed files that differ from `HEAD`, for the gutter
+    pub preview_changes: HashMap<usize, LineChange>,
+    pub quick_preview_changes: HashMap<usize, LineChange>,
     pub quick_preview_content: Vec<String>,
     pub quick_preview_scroll: usize,
     pub quick_preview_path: Option<PathBuf>,
     pub quick_preview_image: Option<ImagePreview>,
+    /// Recently shown quick previews, so moving back over a file doesn't read
+    /// and decode it again
+    pub preview_cache: PreviewCache<CachedPreview>,
     // Drop detection
     pub drop_buffer: String,
     pub last_char_time: std::time::Instant,
@@ -73,12 +389,134 @@ pub struct App {
     pub last_command: Option<String>,
     pub default_command: Option<String>,
     pub command_history: Vec<String>,
+    /// Recent directories typed in the move to / copy to prompts, oldest first
+    pub destination_history: Vec<String>,
+    /// Position in the history of the current prompt while browsing it with Up / Down
     pub history_index: Option<usize>,
+    pub history_search: Option<HistorySearch>,
+    // File picker mode
+    pub picker: Option<PickerMode>,
+    pub chosen: Vec<PathBuf>,
+    pub config: Config,
+    // Tabs (the slot of the active tab is None)
+    pub tabs: Vec<Option<Tab>>,
+    pub active_tab: usize,
+    /// Screen row of the first tree entry
+    pub tree_area_y: u16,
+    /// Row of the breadcrumb bar and the column range of each clickable ancestor
+    pub breadcrumb_y: u16,
+    pub breadcrumb_hits: Vec<(u16, u16, PathBuf)>,
+    /// Column of the tree scrollbar, if one is shown
+    pub tree_scrollbar_x: Option<u16>,
+    /// Top row and height of the quick preview panel, if shown
+    pub quick_preview_rows: Option<(u16, u16)>,
+    /// Control socket from `--listen`
+    #[cfg(unix)]
+    pub ipc: Option<ipc::Server>,
+    // Command to run with the TUI suspended, picked up by the main loop
+    pub pending_foreground: Option<OsString>,
+    // TODO scanner
+    pub todo_job: Option<Job<Vec<TodoItem>>>,
+    pub todo_items: Vec<TodoItem>,
+    pub todo_selected: usize,
+    // Directory comparison: the two directories (A, B) and their differences
+    pub compare_job: Option<Job<Vec<CompareEntry>>>,
+    pub compare_dirs: (PathBuf, PathBuf),
+    pub compare_entries: Vec<CompareEntry>,
+    pub compare_selected: usize,
+    /// Selected row in the marked-items review panel
+    pub marked_selected: usize,
+    /// Batch under review and the selected row of its list
+    pub plan: Option<BatchPlan>,
+    pub plan_selected: usize,
+    /// First key of a multi-key sequence awaiting its continuation, and when it was pressed
+    pub pending_prefix: Option<char>,
+    /// Vim-style count typed before a normal mode key (e.g. the `10` in `10j`)
+    pub pending_count: Option<usize>,
+    pub pending_since: std::time::Instant,
+    /// Help overlay: scroll position, filter text and whether the filter is being typed
+    pub help_scroll: usize,
+    pub help_query: String,
+    pub help_searching: bool,
+    /// Directory shown in the disk usage view and its children by size
+    pub du_dir: PathBuf,
+    pub du_entries: Vec<DuEntry>,
+    pub du_selected: usize,
+    pub du_job: Option<Job<std::io::Result<Option<Vec<DuEntry>>>>>,
+    /// Recursive directory sizes computed on demand, cleared on refresh
+    pub dir_sizes: HashMap<PathBuf, u64>,
+    /// Directories changed by file operations, re-read by `poll_jobs` once
+    /// `refresh_due` has passed
+    pub dirty_dirs: HashSet<PathBuf>,
+    pub refresh_due: Option<std::time::Instant>,
+    pub size_job: Option<Job<Vec<(PathBuf, u64)>>>,
+    pub size_check: Option<SizeCheck>,
+    pub move_job: Option<MoveJob>,
+    pub copy_job: Option<CopyJob>,
+    pub checksum_job: Option<ChecksumJob>,
+    /// Rows of the file info popup: label and value
+    pub file_info: Vec<(&'static str, String)>,
+    /// Expand all (`L`) running in the background on a copy of the tree
+    pub expand_job: Option<Job<anyhow::Result<ExpandOutcome>>>,
+    /// External command whose output is being captured (`!` prefix), with its command line
+    pub command_job: Option<(OsString, CommandJob)>,
+    /// External previewers running for the quick and the full-screen preview,
+    /// with the file each one is previewing
+    pub quick_previewer_job: Option<(PathBuf, CommandJob)>,
+    pub previewer_job: Option<(PathBuf, CommandJob)>,
+    /// Git status being loaded in the background for the active tab
+    pub git_job: Option<Job<GitRepo>>,
+    /// Last commit of previewed directories, cleared when git status reloads
+    pub last_commits: HashMap<PathBuf, Option<LastCommit>>,
+    /// Last commit being looked up for the directory preview, with its directory
+    pub last_commit_job: Option<(PathBuf, Job<Option<LastCommit>>)>,
+    /// Reveal in the file manager over D-Bus, with `xdg-open` as fallback;
+    /// yields the status message
+    pub reveal_job: Option<Job<String>>,
+    /// Files being sent to the Neovim server; yields the status message, `Ok`
+    /// if Neovim took them
+    pub nvim_job: Option<Job<Result<String, String>>>,
+    /// Advances while background work is running, to animate the status bar spinner
+    pub spinner_tick: usize,
+    /// Set while the performance overlay is shown
+    pub perf: Option<PerfStats>,
+    // Mode to return to when the full-screen preview is closed
+    pub preview_return: Option<InputMode>,
 }
 
 impl App {
-    fn shell_quote(filepath: &str) -> String {
-        format!("'{}'", filepath.replace('\'', "'\"'\"'"))
+    /// Single-quote `arg` for `sh`. Works on raw bytes, so paths that are not valid
+    /// UTF-8 are passed through unchanged.
+    #[cfg(unix)]
+    fn shell_quote(arg: impl AsRef<OsStr>) -> OsString {
+        let mut quoted = vec![b'\''];
+        for &byte in arg.as_ref().as_bytes() {
+            if byte == b'\'' {
+                quoted.extend_from_slice(b"'\"'\"'");
+            } else {
+                quoted.push(byte);
+            }
+        }
+        quoted.push(b'\'');
+        OsString::from_vec(quoted)
+    }
+
+    #[cfg(not(unix))]
+    fn shell_quote(arg: impl AsRef<OsStr>) -> OsString {
+        let arg = arg.as_ref().to_string_lossy();
+        OsString::from(format!("'{}'", arg.replace('\'', "'\"'\"'")))
+    }
+
+    /// Join already-quoted shell words with spaces
+    fn shell_join<I: IntoIterator<Item = OsString>>(words: I) -> OsString {
+        let mut command = OsString::new();
+        for (i, word) in words.into_iter().enumerate() {
+            if i > 0 {
+                command.push(" ");
+            }
+            command.push(word);
+        }
+        command
     }
 
     fn trim_history(history: &mut Vec<String>) {
@@ -89,18 +527,15 @@ impl App {
     }
 
     fn get_history_file_path() -> Option<PathBuf> {
-        let config_dir = if let Ok(xdg_config) = std::env::var("XDG_CONFIG_HOME") {
-            PathBuf::from(xdg_config).join("filetree")
-        } else if let Ok(home) = std::env::var("HOME") {
-            PathBuf::from(home).join(".config").join("filetree")
-        } else {
-            return None;
-        };
-        Some(config_dir.join("history.txt"))
+        config::config_dir().map(|dir| dir.join("history.txt"))
+    }
+
+    fn get_destinations_file_path() -> Option<PathBuf> {
+        session::state_dir().map(|dir| dir.join("destinations.txt"))
     }
 
-    fn load_history() -> Vec<String> {
-        let history_path = match Self::get_history_file_path() {
+    fn load_history(history_path: Option<PathBuf>) -> Vec<String> {
+        let history_path = match history_path {
             Some(path) => path,
             None => return Vec::new(),
         };
@@ -124,8 +559,8 @@ impl App {
         }
     }
 
-    fn save_history(&self) {
-        let history_path = match Self::get_history_file_path() {
+    fn save_history(history_path: Option<PathBuf>, history: &[String]) {
+        let history_path = match history_path {
             Some(path) => path,
             None => return,
         };
@@ -137,20 +572,25 @@ impl App {
 
         // Write history to file
         if let Ok(mut file) = fs::File::create(&history_path) {
-            for cmd in &self.command_history {
-                let _ = writeln!(file, "{}", cmd);
+            for entry in history {
+                let _ = writeln!(file, "{}", entry);
             }
         }
     }
 
-    pub fn new(path: &Path, default_command: Option<String>) -> anyhow::Result<Self> {
-        let show_hidden = false;
-        let tree = FileTree::new(path, show_hidden)?;
-        let git_repo = GitRepo::new(path);
-        let command_history = Self::load_history();
-        Ok(Self {
+    pub fn new(path: &Path, config: Config) -> anyhow::Result<Self> {
+        let show_hidden = config.show_hidden;
+        let mut tree = FileTree::with_sort(path, show_hidden, config.sort_order())?;
+        tree.follow_symlinks = config.follow_symlinks;
+        if let Some(depth) = config.depth {
+            tree.expand_to_depth(depth)?;
+        }
+        let default_command = config.default_command.clone();
+        let command_history = Self::load_history(Self::get_history_file_path());
+        let destination_history = Self::load_history(Self::get_destinations_file_path());
+        let mut app = Self {
             tree,
-            git_repo,
+            git_repo: GitRepo::default(),
             selected: 0,
             marked: HashSet::new(),
             clipboard: Clipboard::default(),
@@ -160,39 +600,356 @@ impl App {
             should_quit: false,
             scroll_offset: 0,
             tree_area_height: 20,
+            tree_visible_rows: 20,
+            tree_hscroll: 0,
+            clean_view: false,
+            dirs_only: false,
+            hide_empty_dirs: config.hide_empty_dirs,
+            extension_filter: Vec::new(),
+            show_size: config.show_size,
+            show_mtime: config.show_mtime,
+            show_permissions: config.show_permissions,
+            detail_view: config.detail_view,
+            compact: config.compact,
+            miller: config.miller_columns,
+            #[cfg(unix)]
+            users: uzers::UsersCache::new(),
             last_click_time: std::time::Instant::now(),
             last_click_index: None,
+            drag_anchor: None,
+            drag_marked: HashSet::new(),
             show_hidden,
             preview_content: Vec::new(),
             preview_scroll: 0,
+            preview_hscroll: 0,
+            preview_query: String::new(),
+            preview_matches: Vec::new(),
+            preview_match: 0,
+            preview_mtime: None,
+            quick_preview_mtime: None,
+            last_reload_check: std::time::Instant::now(),
             preview_path: None,
+            preview_title: None,
             image_preview: None,
             quick_preview_enabled: false,
+            preview_wrap: false,
+            preview_line_numbers: config.preview_line_numbers,
+            preview_changes: HashMap::new(),
+            quick_preview_changes: HashMap::new(),
             quick_preview_content: Vec::new(),
             quick_preview_scroll: 0,
             quick_preview_path: None,
             quick_preview_image: None,
+            preview_cache: PreviewCache::new(PREVIEW_CACHE_SIZE),
             drop_buffer: String::new(),
             last_char_time: std::time::Instant::now(),
             last_command: None,
             default_command,
             command_history,
+            destination_history,
             history_index: None,
+            history_search: None,
+            picker: None,
+            chosen: Vec::new(),
+            config,
+            tabs: vec![None],
+            active_tab: 0,
+            tree_area_y: 0,
+            tree_scrollbar_x: None,
+            quick_preview_rows: None,
+            breadcrumb_y: 0,
+            breadcrumb_hits: Vec::new(),
+            pending_foreground: None,
+            #[cfg(unix)]
+            ipc: None,
+            todo_job: None,
+            todo_items: Vec::new(),
+            todo_selected: 0,
+            compare_job: None,
+            compare_dirs: (PathBuf::new(), PathBuf::new()),
+            compare_entries: Vec::new(),
+            compare_selected: 0,
+            marked_selected: 0,
+            plan: None,
+            plan_selected: 0,
+            preview_return: None,
+            pending_prefix: None,
+            pending_count: None,
+            pending_since: std::time::Instant::now(),
+            help_scroll: 0,
+            help_query: String::new(),
+            help_searching: false,
+            du_dir: PathBuf::new(),
+            du_entries: Vec::new(),
+            du_selected: 0,
+            du_job: None,
+            dir_sizes: HashMap::new(),
+            dirty_dirs: HashSet::new(),
+            refresh_due: None,
+            size_job: None,
+            size_check: None,
+            move_job: None,
+            copy_job: None,
+            checksum_job: None,
+            file_info: Vec::new(),
+            expand_job: None,
+            command_job: None,
+            quick_previewer_job: None,
+            previewer_job: None,
+            git_job: None,
+            last_commits: HashMap::new(),
+            last_commit_job: None,
+            reveal_job: None,
+            nvim_job: None,
+            spinner_tick: 0,
+            perf: None,
+        };
+        app.apply_view_filter();
+        app.start_git_refresh();
+        if app.miller {
+            app.miller = false;
+            app.toggle_miller();
+        }
+        Ok(app)
+    }
+
+    fn load_tab(&self, path: &Path) -> anyhow::Result<Tab> {
+        let mut tree = FileTree::with_sort(path, self.show_hidden, self.tree.sort)?;
+        tree.follow_symlinks = self.config.follow_symlinks;
+        if let Some(depth) = self.config.depth {
+            tree.expand_to_depth(depth)?;
+        }
+        // Git status is loaded when the tab becomes active
+        Ok(Tab {
+            tree,
+            git_repo: GitRepo::default(),
+            selected: 0,
+            scroll_offset: 0,
         })
     }
 
+    /// Add a tab rooted at `path` without switching to it
+    pub fn add_tab(&mut self, path: &Path) -> anyhow::Result<()> {
+        let tab = self.load_tab(path)?;
+        self.tabs.push(Some(tab));
+        Ok(())
+    }
+
+    /// Open the selected directory in a new tab and switch to it
+    pub fn open_tab(&mut self) {
+        let path = match self.tree.get_node(self.selected) {
+            Some(node) if node.is_dir => node.path.clone(),
+            Some(node) => match node.path.parent() {
+                Some(parent) => parent.to_path_buf(),
+                None => return,
+            },
+            None => return,
+        };
+        match self.add_tab(&path) {
+            Ok(()) => self.switch_tab(self.tabs.len() - 1),
+            Err(e) => self.message = Some(format!("Error: {}", e)),
+        }
+    }
+
+    pub fn switch_tab(&mut self, index: usize) {
+        if index == self.active_tab {
+            return;
+        }
+        let incoming = match self.tabs.get_mut(index).and_then(|t| t.take()) {
+            Some(tab) => tab,
+            None => return,
+        };
+
+        // Pending refreshes and an expand-all result belong to the outgoing tab's tree
+        self.flush_refresh();
+        self.expand_job = None;
+        let outgoing = Tab {
+            tree: std::mem::replace(&mut self.tree, incoming.tree),
+            git_repo: std::mem::replace(&mut self.git_repo, incoming.git_repo),
+            selected: std::mem::replace(&mut self.selected, incoming.selected),
+            scroll_offset: std::mem::replace(&mut self.scroll_offset, incoming.scroll_offset),
+        };
+        self.tabs[self.active_tab] = Some(outgoing);
+        self.active_tab = index;
+
+        // Files (or hidden-file visibility) may have changed while the tab was inactive
+        let _ = self.tree.set_show_hidden(self.show_hidden);
+        self.apply_view_filter();
+        self.start_git_refresh();
+        if self.selected >= self.tree.len() {
+            self.selected = self.tree.len().saturating_sub(1);
+        }
+        self.update_quick_preview();
+    }
+
+    pub fn next_tab(&mut self) {
+        if self.tabs.len() > 1 {
+            self.switch_tab((self.active_tab + 1) % self.tabs.len());
+        }
+    }
+
+    pub fn prev_tab(&mut self) {
+        if self.tabs.len() > 1 {
+            self.switch_tab((self.active_tab + self.tabs.len() - 1) % self.tabs.len());
+        }
+    }
+
+    pub fn close_tab(&mut self) {
+        if self.tabs.len() <= 1 {
+            self.message = Some("Cannot close the last tab".to_string());
+            return;
+        }
+        let closing = self.active_tab;
+        if closing + 1 < self.tabs.len() {
+            self.switch_tab(closing + 1);
+        } else {
+            self.switch_tab(closing - 1);
+        }
+        self.tabs.remove(closing);
+        if self.active_tab > closing {
+            self.active_tab -= 1;
+        }
+    }
+
+    /// The view state of every tab, for saving on exit
+    pub fn sessions(&self) -> Vec<Session> {
+        self.tabs
+            .iter()
+            .map(|tab| {
+                let (tree, selected) = match tab {
+                    Some(tab) => (&tab.tree, tab.selected),
+                    None => (&self.tree, self.selected),
+                };
+                Session {
+                    root: tree.root.path.clone(),
+                    expanded: tree.collect_expanded_paths(),
+                    selected: tree.get_node(selected).map(|n| n.path.clone()),
+                    show_hidden: self.show_hidden,
+                    quick_preview: self.quick_preview_enabled,
+                    preview_wrap: self.preview_wrap,
+                }
+            })
+            .collect()
+    }
+
+    /// Restore the saved view state of each tab's root. Hidden files and the
+    /// quick preview follow the session of the active tab.
+    pub fn restore_sessions(&mut self, load: impl Fn(&Path) -> Option<Session>) {
+        if let Some(session) = load(&self.tree.root.path) {
+            if session.show_hidden != self.show_hidden {
+                self.toggle_hidden();
+                self.message = None;
+            }
+            self.tree.restore_expanded(&session.expanded);
+            self.apply_view_filter();
+            if let Some(path) = &session.selected {
+                self.select_path(path);
+            }
+            if session.quick_preview != self.quick_preview_enabled {
+                self.toggle_quick_preview();
+            }
+            self.preview_wrap = session.preview_wrap;
+        }
+        let show_hidden = self.show_hidden;
+        for tab in self.tabs.iter_mut().flatten() {
+            let Some(session) = load(&tab.tree.root.path) else {
+                continue;
+            };
+            let _ = tab.tree.set_show_hidden(show_hidden);
+            tab.tree.restore_expanded(&session.expanded);
+            if let Some(index) = session.selected.and_then(|path| {
+                (0..tab.tree.len()).find(|&i| tab.tree.get_node(i).map(|n| &n.path) == Some(&path))
+            }) {
+                tab.selected = index;
+            }
+        }
+    }
+
+    /// Root directory names of all tabs, in order
+    pub fn tab_titles(&self) -> Vec<String> {
+        self.tabs
+            .iter()
+            .map(|tab| {
+                let root = match tab {
+                    Some(tab) => &tab.tree.root,
+                    None => &self.tree.root,
+                };
+                root.name.clone()
+            })
+            .collect()
+    }
+
     pub fn move_up(&mut self) {
-        if self.selected > 0 {
+        if self.miller {
+            self.move_sibling(-1);
+        } else if self.selected > 0 {
             self.selected -= 1;
         }
     }
 
     pub fn move_down(&mut self) {
-        if self.selected < self.tree.len().saturating_sub(1) {
+        if self.miller {
+            self.move_sibling(1);
+        } else if self.selected < self.tree.len().saturating_sub(1) {
             self.selected += 1;
         }
     }
 
+    /// Row of the parent of the entry at `index`, and the rows of the entry and
+    /// its visible siblings in order
+    pub fn sibling_rows(&self, index: usize) -> (Option<usize>, Vec<usize>) {
+        let Some(depth) = self.tree.get_node(index).map(|n| n.depth) else {
+            return (None, Vec::new());
+        };
+        let parent = (0..index)
+            .rev()
+            .find(|&i| self.tree.get_node(i).is_some_and(|n| n.depth < depth));
+        let start = parent.map_or(0, |p| p + 1);
+        let siblings = (start..self.tree.len())
+            .map_while(|i| {
+                self.tree
+                    .get_node(i)
+                    .filter(|n| n.depth >= depth)
+                    .map(|n| (i, n.depth))
+            })
+            .filter(|&(_, d)| d == depth)
+            .map(|(i, _)| i)
+            .collect();
+        (parent, siblings)
+    }
+
+    /// Select the next (`delta` > 0) or previous sibling of the selection
+    fn move_sibling(&mut self, delta: isize) {
+        let (_, siblings) = self.sibling_rows(self.selected);
+        if let Some(pos) = siblings.iter().position(|&i| i == self.selected) {
+            let pos = (pos as isize + delta).clamp(0, siblings.len() as isize - 1);
+            self.selected = siblings[pos as usize];
+        }
+    }
+
+    pub fn toggle_miller(&mut self) {
+        self.miller = !self.miller;
+        if self.miller {
+            // The root has no siblings to move between, so start in its column
+            if self.selected == 0 && self.tree.len() > 1 {
+                self.selected = 1;
+            }
+            // The preview column shows the selection even without the quick preview
+            self.quick_preview_path = None;
+            self.update_quick_preview();
+        } else if !self.quick_preview_enabled {
+            self.quick_preview_content.clear();
+            self.quick_preview_path = None;
+            self.quick_preview_image = None;
+        }
+    }
+
+    /// Whether the quick preview content is kept up to date: for its panel,
+    /// or for the preview column of the Miller layout
+    fn shows_quick_preview(&self) -> bool {
+        self.quick_preview_enabled || self.miller
+    }
+
     pub fn move_to_top(&mut self) {
         self.selected = 0;
     }
@@ -204,11 +961,12 @@ impl App {
     pub fn toggle_expand(&mut self) {
         if let Some(node) = self.tree.get_node(self.selected) {
             if node.is_dir {
-                let path = node.path.clone();
-                if node.expanded {
+                let (path, expanded) = (node.path.clone(), node.expanded);
+                self.cancel_expand();
+                if expanded {
                     let _ = self.tree.collapse_node(self.selected);
-                } else {
-                    let _ = self.tree.expand_node(self.selected);
+                } else if let Err(e) = self.tree.expand_node(self.selected) {
+                    self.message = Some(e.to_string());
                 }
                 // Restore selection to the same path
                 self.select_path(&path);
@@ -217,19 +975,61 @@ impl App {
     }
 
     pub fn expand_current(&mut self) {
+        if self.miller {
+            self.enter_column();
+            return;
+        }
         if let Some(node) = self.tree.get_node(self.selected) {
             if node.is_dir && !node.expanded {
                 let path = node.path.clone();
-                let _ = self.tree.expand_node(self.selected);
+                self.cancel_expand();
+                if let Err(e) = self.tree.expand_node(self.selected) {
+                    self.message = Some(e.to_string());
+                }
                 self.select_path(&path);
             }
         }
     }
 
+    /// Miller layout: open the selected directory in the current column
+    fn enter_column(&mut self) {
+        let Some(node) = self.tree.get_node(self.selected) else {
+            return;
+        };
+        if !node.is_dir {
+            return;
+        }
+        let (path, depth) = (node.path.clone(), node.depth);
+        if !node.expanded {
+            self.cancel_expand();
+            if let Err(e) = self.tree.expand_node(self.selected) {
+                self.message = Some(e.to_string());
+            }
+            self.select_path(&path);
+        }
+        match self.tree.get_node(self.selected + 1) {
+            Some(child) if child.depth > depth => self.selected += 1,
+            _ => self.message = Some(format!("{} is empty", path.display())),
+        }
+    }
+
     pub fn collapse_current(&mut self) {
+        if self.miller {
+            // Go up a column; above the root, re-root at its parent like ranger
+            match self.sibling_rows(self.selected).0 {
+                Some(parent) if parent > 0 => self.selected = parent,
+                _ => {
+                    if let Some(parent) = self.tree.root.path.parent() {
+                        self.change_root(parent.to_path_buf());
+                    }
+                }
+            }
+            return;
+        }
         if let Some(node) = self.tree.get_node(self.selected) {
             if node.is_dir && node.expanded {
                 let path = node.path.clone();
+                self.cancel_expand();
                 let _ = self.tree.collapse_node(self.selected);
                 self.select_path(&path);
             } else if let Some(parent) = node.path.parent() {
@@ -240,6 +1040,107 @@ impl App {
         }
     }
 
+    /// Re-root the active tab at `path`, keeping the previous root's branch selected
+    pub fn change_root(&mut self, path: PathBuf) {
+        match FileTree::with_sort(&path, self.show_hidden, self.tree.sort) {
+            Ok(mut tree) => {
+                tree.follow_symlinks = self.config.follow_symlinks;
+                let previous = std::mem::replace(&mut self.tree, tree);
+                self.selected = 0;
+                self.scroll_offset = 0;
+                self.tree_hscroll = 0;
+                self.apply_view_filter();
+                // Select the child of the new root that leads back to the previous root
+                if let Some(child) = previous
+                    .root
+                    .path
+                    .ancestors()
+                    .find(|p| p.parent() == Some(path.as_path()))
+                {
+                    self.select_path(child);
+                }
+                self.start_git_refresh();
+                self.message = Some(format!("Root: {}", path.display()));
+                self.update_quick_preview();
+            }
+            Err(e) => self.message = Some(format!("Cannot open {}: {}", path.display(), e)),
+        }
+    }
+
+    /// Re-root at the `n`th ancestor of the current root (1 = parent)
+    pub fn breadcrumb_jump(&mut self, n: usize) {
+        if let Some(ancestor) = self.tree.root.path.ancestors().nth(n) {
+            self.change_root(ancestor.to_path_buf());
+        }
+    }
+
+    /// Re-root at the breadcrumb component under the mouse, if any
+    pub fn breadcrumb_click(&mut self, column: u16) {
+        let target = self
+            .breadcrumb_hits
+            .iter()
+            .find(|(start, end, _)| (*start..*end).contains(&column))
+            .map(|(_, _, path)| path.clone());
+        if let Some(path) = target {
+            self.change_root(path);
+        }
+    }
+
+    /// Expand the tree down to `path` and select it
+    pub fn reveal_path(&mut self, path: &Path) -> anyhow::Result<()> {
+        let path = if path.starts_with(&self.tree.root.path) {
+            path.to_path_buf()
+        } else {
+            path.canonicalize()?
+        };
+        self.tree.reveal(&path)?;
+        self.apply_view_filter();
+        self.select_path(&path);
+        if self.tree.get_node(self.selected).map(|n| &n.path) != Some(&path) {
+            anyhow::bail!("{} is hidden or filtered out", path.display());
+        }
+        self.update_quick_preview();
+        Ok(())
+    }
+
+    /// Answer requests from the `--listen` socket
+    #[cfg(unix)]
+    fn handle_ipc(&mut self) {
+        while let Some((request, reply)) = self.ipc.as_ref().and_then(|s| s.try_recv()) {
+            let response = match request {
+                ipc::Request::Reveal { path } => match self.reveal_path(&path) {
+                    Ok(()) => ipc::ok(serde_json::json!({})),
+                    Err(e) => ipc::error(&e.to_string()),
+                },
+                ipc::Request::Refresh => {
+                    self.refresh();
+                    ipc::ok(serde_json::json!({}))
+                }
+                ipc::Request::GetSelection => {
+                    let selected = self
+                        .tree
+                        .get_node(self.selected)
+                        .map(|n| n.path.to_string_lossy().to_string());
+                    let marked: Vec<String> = self
+                        .marked_list()
+                        .iter()
+                        .map(|p| p.to_string_lossy().to_string())
+                        .collect();
+                    ipc::ok(serde_json::json!({ "selected": selected, "marked": marked }))
+                }
+                ipc::Request::Quit => {
+                    self.quit();
+                    if self.should_quit {
+                        ipc::ok(serde_json::json!({}))
+                    } else {
+                        ipc::error(self.message.as_deref().unwrap_or("Cannot quit now"))
+                    }
+                }
+            };
+            let _ = reply.send(response);
+        }
+    }
+
     fn select_path(&mut self, path: &Path) {
         if let Some(idx) = (0..self.tree.len()).find(|&i| {
             self.tree
@@ -258,13 +1159,76 @@ impl App {
                 self.marked.insert(path);
             }
         }
-        self.move_down();
+        if self.config.mark_advance {
+            self.move_down();
+        }
     }
 
     pub fn clear_marks(&mut self) {
         self.marked.clear();
     }
 
+    /// Carry the marks on `from` and everything below it over to `to`, after a rename
+    fn remap_marks(&mut self, from: &Path, to: &Path) {
+        let moved: Vec<PathBuf> = self
+            .marked
+            .iter()
+            .filter(|p| p.starts_with(from))
+            .cloned()
+            .collect();
+        for path in moved {
+            self.marked.remove(&path);
+            if let Ok(rest) = path.strip_prefix(from) {
+                self.marked.insert(to.join(rest));
+            }
+        }
+    }
+
+    /// Drop marks on paths that no longer exist
+    fn prune_marks(&mut self) {
+        self.marked.retain(|p| p.symlink_metadata().is_ok());
+    }
+
+    /// Clear every mark, reporting how many were dropped
+    pub fn clear_all_marks(&mut self) {
+        let count = self.marked.len();
+        self.marked.clear();
+        self.message = Some(format!("Cleared {} mark(s)", count));
+    }
+
+    /// Visible entries that share the selected entry's parent directory
+    fn current_dir_entries(&self) -> Vec<PathBuf> {
+        let Some(parent) = self
+            .tree
+            .get_node(self.selected)
+            .and_then(|n| n.path.parent())
+        else {
+            return Vec::new();
+        };
+        (0..self.tree.len())
+            .filter_map(|i| self.tree.get_node(i))
+            .filter(|n| n.path.parent() == Some(parent))
+            .map(|n| n.path.clone())
+            .collect()
+    }
+
+    /// Mark all visible entries in the current directory
+    pub fn mark_all_in_dir(&mut self) {
+        let entries = self.current_dir_entries();
+        let count = entries.len();
+        self.marked.extend(entries);
+        self.message = Some(format!("Marked {} entries", count));
+    }
+
+    /// Invert the marks of the visible entries in the current directory
+    pub fn invert_marks_in_dir(&mut self) {
+        for path in self.current_dir_entries() {
+            if !self.marked.remove(&path) {
+                self.marked.insert(path);
+            }
+        }
+    }
+
     pub fn yank(&mut self) {
         let paths = self.get_selected_paths();
         if !paths.is_empty() {
@@ -283,49 +1247,339 @@ impl App {
     }
 
     pub fn paste(&mut self) {
+        let cut = matches!(self.clipboard.content, Some(ClipboardContent::Cut(_)));
+        if cut && self.move_job.is_some() {
+            // Checked before taking the clipboard, so the cut is kept for later
+            self.message = Some("Wait for the move to another device to finish".to_string());
+            return;
+        }
         let dest_dir = self.get_paste_destination();
         if let Some(dest_dir) = dest_dir {
             if let Some(content) = self.clipboard.content.take() {
-                let count = match content {
+                let (op, paths) = match content {
                     ClipboardContent::Copy(paths) => {
-                        let mut success = 0;
-                        for path in &paths {
-                            if file_ops::copy_file(path, &dest_dir).is_ok() {
-                                success += 1;
-                            }
+                        self.clipboard.copy(paths.clone());
+                        let op = BatchOp::Copy(dest_dir);
+                        if self.check_size(&op, &paths, false) {
+                            return;
                         }
-                        self.clipboard.copy(paths);
-                        success
+                        (op, paths)
                     }
                     ClipboardContent::Cut(paths) => {
-                        let mut success = 0;
-                        for path in &paths {
-                            if file_ops::move_file(path, &dest_dir).is_ok() {
-                                success += 1;
-                            }
+                        if self.needs_plan(&paths) {
+                            // Kept until the plan runs, so cancelling loses nothing
+                            self.clipboard.cut(paths.clone());
+                            self.open_plan(BatchOp::Move(dest_dir), paths, false);
+                            return;
                         }
                         self.clear_marks();
-                        success
+                        (BatchOp::Move(dest_dir), paths)
                     }
                 };
-
-                self.message = Some(format!("Pasted {} item(s)", count));
-                let _ = self.tree.refresh();
+                self.run_batch(op, &paths);
             }
         }
     }
 
-    fn get_paste_destination(&self) -> Option<PathBuf> {
-        self.tree.get_node(self.selected).map(|node| {
-            if node.is_dir {
-                node.path.clone()
-            } else {
-                node.path
-                    .parent()
-                    .map(|p| p.to_path_buf())
-                    .unwrap_or_else(|| node.path.clone())
+    /// Apply `op` to each of `paths`, then report the result. Failures are
+    /// listed with an offer to retry them, or to escalate if they were all
+    /// permission denied. Copies run in the background and report when done.
+    fn run_batch(&mut self, op: BatchOp, paths: &[PathBuf]) {
+        let verb = match op {
+            BatchOp::Delete => "delete",
+            BatchOp::Move(_) => "move",
+            BatchOp::Copy(_) => "",
+        };
+        if !verb.is_empty() && self.refuse_protected(verb, paths) {
+            return;
+        }
+        if let BatchOp::Move(dest_dir) = &op {
+            let (across, here): (Vec<PathBuf>, Vec<PathBuf>) = paths
+                .iter()
+                .cloned()
+                .partition(|p| file_ops::is_cross_device(p, dest_dir));
+            if !across.is_empty() {
+                self.start_move_job(dest_dir.clone(), across);
+                if !here.is_empty() {
+                    self.run_batch(op, &here);
+                }
+                return;
             }
-        })
+        }
+        if let BatchOp::Copy(dest_dir) = &op {
+            self.start_copy_job(dest_dir.clone(), paths.to_vec());
+            return;
+        }
+        let mut success = 0;
+        let mut failures = Vec::new();
+        for path in paths {
+            let result = match &op {
+                BatchOp::Delete => file_ops::delete_file(path),
+                BatchOp::Copy(_) => unreachable!("copies run in a job"),
+                BatchOp::Move(dest_dir) => file_ops::move_file(path, dest_dir).map(|_| ()),
+            };
+            match result {
+                Ok(()) => success += 1,
+                Err(e) => failures.push(BatchFailure {
+                    path: path.clone(),
+                    error: e.to_string(),
+                    permission_denied: file_ops::is_permission_denied(&e),
+                }),
+            }
+        }
+
+        let verb = match op {
+            BatchOp::Delete => "Deleted",
+            BatchOp::Copy(_) | BatchOp::Move(_) => "Pasted",
+        };
+        self.message = Some(if failures.is_empty() {
+            format!("{} {} item(s)", verb, success)
+        } else {
+            format!("{} {} item(s), {} failed", verb, success, failures.len())
+        });
+        let mut dirs: Vec<PathBuf> = paths
+            .iter()
+            .filter_map(|p| p.parent())
+            .map(Path::to_path_buf)
+            .collect();
+        match &op {
+            BatchOp::Delete => self.prune_marks(),
+            BatchOp::Copy(dest_dir) => dirs = vec![dest_dir.clone()],
+            BatchOp::Move(dest_dir) => dirs.push(dest_dir.clone()),
+        }
+        self.schedule_refresh(dirs);
+
+        if failures.iter().all(|f| f.permission_denied) {
+            let paths: Vec<PathBuf> = failures.into_iter().map(|f| f.path).collect();
+            self.offer_batch_elevation(&op, &paths);
+        } else {
+            self.input_mode =
+                InputMode::Confirm(ConfirmAction::Retry(BatchReport { op, failures }));
+        }
+    }
+
+    /// Copy `paths` into `dest_dir` in the background, showing the progress
+    fn start_copy_job(&mut self, dest_dir: PathBuf, paths: Vec<PathBuf>) {
+        if self.copy_job.is_some() {
+            self.message = Some("Another copy is still running".to_string());
+            return;
+        }
+        let progress = Arc::new(CopyProgress::default());
+        let options = file_ops::CopyOptions {
+            xattrs: self.config.preserve_xattrs,
+        };
+        let job = {
+            let (dest_dir, paths, progress) = (dest_dir.clone(), paths.clone(), progress.clone());
+            Job::spawn(move |cancel| {
+                let mut failures = Vec::new();
+                for path in paths {
+                    let result = if cancel.load(Ordering::Relaxed) {
+                        Err(anyhow::anyhow!("Cancelled"))
+                    } else {
+                        file_ops::copy_file_with(&path, &dest_dir, options, &progress)
+                    };
+                    if let Err(e) = result {
+                        failures.push(BatchFailure {
+                            permission_denied: file_ops::is_permission_denied(&e),
+                            error: e.to_string(),
+                            path,
+                        });
+                    }
+                }
+                failures
+            })
+        };
+        self.copy_job = Some(CopyJob {
+            dest_dir,
+            paths,
+            progress,
+            job,
+        });
+        self.show_copy_progress();
+    }
+
+    fn show_copy_progress(&mut self) {
+        let Some(running) = &self.copy_job else {
+            return;
+        };
+        self.message = Some(format!(
+            "Copying {} item(s): {} of {} file(s)",
+            running.paths.len(),
+            running.progress.copied.load(Ordering::Relaxed),
+            running.progress.total.load(Ordering::Relaxed)
+        ));
+    }
+
+    fn finish_copy_job(&mut self, running: CopyJob, failures: Vec<BatchFailure>) {
+        let copied = running.paths.len() - failures.len();
+        self.message = Some(if failures.is_empty() {
+            format!("Pasted {} item(s)", copied)
+        } else {
+            format!("Pasted {} item(s), {} failed", copied, failures.len())
+        });
+        self.schedule_refresh(vec![running.dest_dir.clone()]);
+        if failures.is_empty() || !matches!(self.input_mode, InputMode::Normal | InputMode::Compare)
+        {
+            return;
+        }
+        let op = BatchOp::Copy(running.dest_dir);
+        if failures.iter().all(|f| f.permission_denied) {
+            let paths: Vec<PathBuf> = failures.into_iter().map(|f| f.path).collect();
+            self.offer_batch_elevation(&op, &paths);
+        } else {
+            self.input_mode =
+                InputMode::Confirm(ConfirmAction::Retry(BatchReport { op, failures }));
+        }
+    }
+
+    /// Move `paths` to another filesystem in the background. An item that fails
+    /// stays where it was, without a partial copy left at the destination.
+    fn start_move_job(&mut self, dest_dir: PathBuf, paths: Vec<PathBuf>) {
+        if self.move_job.is_some() {
+            self.message = Some("Another move to a different device is still running".to_string());
+            return;
+        }
+        let progress = Arc::new(CopyProgress::default());
+        let options = file_ops::CopyOptions {
+            xattrs: self.config.preserve_xattrs,
+        };
+        let job = {
+            let (dest_dir, paths, progress) = (dest_dir.clone(), paths.clone(), progress.clone());
+            Job::spawn(move |cancel| {
+                let mut failures = Vec::new();
+                for path in paths {
+                    let result = if cancel.load(Ordering::Relaxed) {
+                        Err(anyhow::anyhow!("Cancelled"))
+                    } else {
+                        file_ops::move_file_across(&path, &dest_dir, options, &progress)
+                    };
+                    if let Err(e) = result {
+                        failures.push(BatchFailure {
+                            permission_denied: file_ops::is_permission_denied(&e),
+                            error: e.to_string(),
+                            path,
+                        });
+                    }
+                }
+                failures
+            })
+        };
+        self.move_job = Some(MoveJob {
+            dest_dir,
+            paths,
+            progress,
+            job,
+        });
+        self.show_move_progress();
+    }
+
+    fn show_move_progress(&mut self) {
+        let Some(running) = &self.move_job else {
+            return;
+        };
+        self.message = Some(format!(
+            "Moving {} item(s) to another device: {} of {} file(s)",
+            running.paths.len(),
+            running.progress.copied.load(Ordering::Relaxed),
+            running.progress.total.load(Ordering::Relaxed)
+        ));
+    }
+
+    fn finish_move_job(&mut self, running: MoveJob, failures: Vec<BatchFailure>) {
+        let moved = running.paths.len() - failures.len();
+        self.message = Some(if failures.is_empty() {
+            format!("Moved {} item(s)", moved)
+        } else {
+            format!(
+                "Moved {} item(s), {} failed and were left in place",
+                moved,
+                failures.len()
+            )
+        });
+        let mut dirs: Vec<PathBuf> = running
+            .paths
+            .iter()
+            .filter_map(|p| p.parent())
+            .map(Path::to_path_buf)
+            .collect();
+        dirs.push(running.dest_dir.clone());
+        self.schedule_refresh(dirs);
+        self.prune_marks();
+        if !failures.is_empty() && self.input_mode == InputMode::Normal {
+            self.input_mode = InputMode::Confirm(ConfirmAction::Retry(BatchReport {
+                op: BatchOp::Move(running.dest_dir),
+                failures,
+            }));
+        }
+    }
+
+    /// Run the operation again on the paths that failed
+    pub fn retry_batch(&mut self, report: BatchReport) {
+        let paths: Vec<PathBuf> = report.failures.into_iter().map(|f| f.path).collect();
+        self.run_batch(report.op, &paths);
+    }
+
+    /// From the error report, retry the failures that were permission denied
+    /// with `elevate_command`
+    pub fn elevate_batch(&mut self) {
+        let InputMode::Confirm(ConfirmAction::Retry(report)) = &self.input_mode else {
+            return;
+        };
+        let paths: Vec<PathBuf> = report
+            .failures
+            .iter()
+            .filter(|f| f.permission_denied)
+            .map(|f| f.path.clone())
+            .collect();
+        if !paths.is_empty() {
+            let op = report.op.clone();
+            self.offer_batch_elevation(&op, &paths);
+        }
+    }
+
+    fn offer_batch_elevation(&mut self, op: &BatchOp, paths: &[PathBuf]) {
+        if paths.is_empty() {
+            return;
+        }
+        let (description, commands) = match op {
+            BatchOp::Delete => {
+                let mut args: Vec<OsString> = vec!["rm".into(), "-rf".into(), "--".into()];
+                args.extend(paths.iter().map(OsString::from));
+                (format!("Delete {} item(s)", paths.len()), vec![args])
+            }
+            BatchOp::Copy(dest_dir) | BatchOp::Move(dest_dir) => {
+                let program = match op {
+                    BatchOp::Copy(_) => ["cp", "-R"].as_slice(),
+                    _ => ["mv"].as_slice(),
+                };
+                let commands = paths
+                    .iter()
+                    .filter_map(|src| {
+                        let dest = file_ops::unique_destination(src, dest_dir).ok()?;
+                        let mut args: Vec<OsString> = program.iter().map(OsString::from).collect();
+                        args.push("--".into());
+                        args.push(src.into());
+                        args.push(dest.into());
+                        Some(args)
+                    })
+                    .collect();
+                (format!("Paste {} item(s)", paths.len()), commands)
+            }
+        };
+        self.offer_elevation(description, commands);
+    }
+
+    fn get_paste_destination(&self) -> Option<PathBuf> {
+        self.tree.get_node(self.selected).map(|node| {
+            if node.is_dir {
+                node.path.clone()
+            } else {
+                node.path
+                    .parent()
+                    .map(|p| p.to_path_buf())
+                    .unwrap_or_else(|| node.path.clone())
+            }
+        })
     }
 
     fn get_selected_paths(&self) -> Vec<PathBuf> {
@@ -340,10 +1594,20 @@ impl App {
     }
 
     pub fn start_rename(&mut self) {
-        if let Some(node) = self.tree.get_node(self.selected) {
-            self.input_buffer = node.name.clone();
-            self.input_mode = InputMode::Rename;
+        let Some(node) = self.tree.get_node(self.selected) else {
+            return;
+        };
+        let name = node
+            .path
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_else(|| node.name.clone());
+        let path = node.path.clone();
+        if self.refuse_protected("rename", &[path]) {
+            return;
         }
+        self.input_buffer = name;
+        self.input_mode = InputMode::Rename;
     }
 
     pub fn start_new_file(&mut self) {
@@ -356,871 +1620,5551 @@ impl App {
         self.input_mode = InputMode::NewDir;
     }
 
+    /// Prompt for a directory to move (or copy) the selection into
+    pub fn start_relocate(&mut self, copy: bool) {
+        if self.get_selected_paths().is_empty() {
+            return;
+        }
+        self.input_buffer.clear();
+        self.history_index = None;
+        self.input_mode = if copy {
+            InputMode::CopyTo
+        } else {
+            InputMode::MoveTo
+        };
+    }
+
+    /// Complete the destination prompt to the directories it could name,
+    /// listing them if there is more than one
+    pub fn complete_destination(&mut self) {
+        let (completed, candidates) = complete_directory(&self.input_buffer, &self.tree.root.path);
+        self.input_buffer = completed;
+        self.message = (candidates.len() > 1).then(|| candidates.join("  "));
+    }
+
+    fn relocate(&mut self, copy: bool) {
+        let input = self.input_buffer.trim().to_string();
+        if input.is_empty() {
+            return;
+        }
+        let dest_dir = resolve_destination(&input, &self.tree.root.path);
+        if !dest_dir.is_dir() {
+            self.message = Some(format!("Not a directory: {}", dest_dir.display()));
+            return;
+        }
+        let paths = self.get_selected_paths();
+        if paths.is_empty() {
+            return;
+        }
+
+        let entry = dest_dir.to_string_lossy().to_string();
+        self.destination_history.retain(|d| *d != entry);
+        self.destination_history.push(entry);
+        Self::trim_history(&mut self.destination_history);
+        Self::save_history(
+            Self::get_destinations_file_path(),
+            &self.destination_history,
+        );
+
+        let op = if copy {
+            let op = BatchOp::Copy(dest_dir);
+            if self.check_size(&op, &paths, false) {
+                return;
+            }
+            op
+        } else if self.needs_plan(&paths) {
+            self.open_plan(BatchOp::Move(dest_dir), paths, false);
+            return;
+        } else {
+            self.clear_marks();
+            BatchOp::Move(dest_dir)
+        };
+        self.run_batch(op, &paths);
+    }
+
     pub fn confirm_delete(&mut self) {
         let paths = self.get_selected_paths();
         if !paths.is_empty() {
             let has_directories = paths.iter().any(|p| p.is_dir());
-            let delete_info = DeleteInfo {
+            self.request_delete(DeleteInfo {
                 paths,
                 has_directories,
-            };
-            self.input_mode = InputMode::Confirm(ConfirmAction::Delete(delete_info));
+                from_disk_usage: false,
+            });
         }
     }
 
-    pub fn execute_delete(&mut self) {
-        let paths = self.get_selected_paths();
-        let mut success = 0;
-        for path in &paths {
-            if file_ops::delete_file(path).is_ok() {
-                success += 1;
-            }
+    /// Delete `info.paths`, asking first unless `confirm.delete` is off
+    fn request_delete(&mut self, info: DeleteInfo) {
+        if self.refuse_protected("delete", &info.paths) {
+            return;
         }
-        self.message = Some(format!("Deleted {} item(s)", success));
-        self.clear_marks();
-        let _ = self.tree.refresh();
-        if self.selected >= self.tree.len() {
-            self.selected = self.tree.len().saturating_sub(1);
+        if self.check_size(&BatchOp::Delete, &info.paths, info.from_disk_usage) {
+            return;
         }
+        self.ask_delete(info);
     }
 
-    pub fn confirm_input(&mut self) {
-        match &self.input_mode {
-            InputMode::Rename => {
-                if let Some(node) = self.tree.get_node(self.selected) {
-                    let path = node.path.clone();
-                    match file_ops::rename_file(&path, &self.input_buffer) {
-                        Ok(new_path) => {
-                            self.message = Some(format!("Renamed to {}", new_path.display()));
-                            let _ = self.tree.refresh();
-                            self.select_path(&new_path);
-                        }
-                        Err(e) => {
-                            self.message = Some(format!("Error: {}", e));
-                        }
-                    }
-                }
-            }
-            InputMode::NewFile => {
-                if let Some(dest_dir) = self.get_paste_destination() {
-                    match file_ops::create_file(&dest_dir, &self.input_buffer) {
-                        Ok(new_path) => {
-                            self.message = Some(format!("Created {}", new_path.display()));
-                            let _ = self.tree.refresh();
-                            self.select_path(&new_path);
-                        }
-                        Err(e) => {
-                            self.message = Some(format!("Error: {}", e));
-                        }
-                    }
-                }
-            }
-            InputMode::NewDir => {
-                if let Some(dest_dir) = self.get_paste_destination() {
-                    match file_ops::create_directory(&dest_dir, &self.input_buffer) {
-                        Ok(new_path) => {
-                            self.message = Some(format!("Created {}", new_path.display()));
-                            let _ = self.tree.refresh();
-                            self.select_path(&new_path);
-                        }
-                        Err(e) => {
-                            self.message = Some(format!("Error: {}", e));
-                        }
-                    }
-                }
-            }
-            InputMode::Search => {
-                // Check if input looks like a dropped file path
-                if self.try_handle_as_drop() {
-                    self.input_mode = InputMode::Normal;
-                    self.input_buffer.clear();
-                    return;
-                }
-                self.search_next();
-            }
-            InputMode::ExternalCommand => {
-                let command = self.input_buffer.clone();
-                if !command.is_empty() {
-                    // Remove duplicate from history if exists
-                    self.command_history.retain(|c| c != &command);
-                    // Add to end of history
-                    self.command_history.push(command.clone());
-                    Self::trim_history(&mut self.command_history);
-                    // Save history to file
-                    self.save_history();
-                }
-                self.execute_external_command(Some(command));
-            }
-            InputMode::Confirm(ConfirmAction::Delete(_)) => {
-                self.execute_delete();
-            }
-            InputMode::Normal | InputMode::Preview => {}
+    /// Delete `info.paths` through the plan, the confirmation popup or right
+    /// away, depending on `confirm`
+    fn ask_delete(&mut self, info: DeleteInfo) {
+        if self.config.confirm.delete && self.needs_plan(&info.paths) {
+            self.open_plan(BatchOp::Delete, info.paths, info.from_disk_usage);
+        } else if self.config.confirm.delete {
+            self.input_mode = InputMode::Confirm(ConfirmAction::Delete(info));
+        } else {
+            self.execute_delete(info);
         }
-        self.input_mode = InputMode::Normal;
-        self.input_buffer.clear();
     }
 
-    pub fn cancel_input(&mut self) {
-        self.input_mode = InputMode::Normal;
-        self.input_buffer.clear();
+    /// Measure the directories among `paths` in the background before `op` runs
+    /// on them. `poll_jobs` then resumes the batch, warning first if it is
+    /// larger than `confirm.large_mb` or `confirm.large_files`.
+    fn check_size(&mut self, op: &BatchOp, paths: &[PathBuf], from_disk_usage: bool) -> bool {
+        let policy = &self.config.confirm;
+        let is_real_dir = |p: &PathBuf| p.is_dir() && !p.is_symlink();
+        if policy.large_mb == 0 && policy.large_files == 0 || !paths.iter().any(is_real_dir) {
+            return false;
+        }
+        let measured = paths.to_vec();
+        let job = Job::spawn(move |cancel| {
+            let mut total = DirStats::default();
+            for path in &measured {
+                if is_real_dir(path) {
+                    let stats = file_ops::dir_stats(path, cancel)?;
+                    total.bytes += stats.bytes;
+                    total.files += stats.files;
+                } else {
+                    total.bytes += fs::symlink_metadata(path).map_or(0, |m| m.len());
+                    total.files += 1;
+                }
+            }
+            Some(total)
+        });
+        self.message = Some(format!("Measuring {} item(s)...", paths.len()));
+        self.size_check = Some(SizeCheck {
+            op: op.clone(),
+            paths: paths.to_vec(),
+            from_disk_usage,
+            job,
+        });
+        true
     }
 
-    pub fn search_next(&mut self) {
-        let query = self.input_buffer.to_lowercase();
-        if query.is_empty() {
+    /// Continue a measured batch, asking first if it turned out large
+    fn finish_size_check(&mut self, check: SizeCheck, stats: DirStats) {
+        if !matches!(self.input_mode, InputMode::Normal | InputMode::DiskUsage) {
+            self.message = Some("Cancelled".to_string());
             return;
         }
+        self.message = None;
+        let policy = &self.config.confirm;
+        let large = (policy.large_mb > 0 && stats.bytes > policy.large_mb * 1024 * 1024)
+            || (policy.large_files > 0 && stats.files > policy.large_files);
+        if large {
+            self.input_mode = InputMode::Confirm(ConfirmAction::Large(LargeBatch {
+                op: check.op,
+                paths: check.paths,
+                stats,
+                from_disk_usage: check.from_disk_usage,
+            }));
+            return;
+        }
+        match check.op {
+            BatchOp::Delete => self.ask_delete(DeleteInfo {
+                has_directories: check.paths.iter().any(|p| p.is_dir()),
+                paths: check.paths,
+                from_disk_usage: check.from_disk_usage,
+            }),
+            op => self.run_batch(op, &check.paths),
+        }
+    }
 
-        let start = self.selected + 1;
-        let len = self.tree.len();
-
-        for i in 0..len {
-            let idx = (start + i) % len;
-            if let Some(node) = self.tree.get_node(idx) {
-                if node.name.to_lowercase().contains(&query) {
-                    self.selected = idx;
-                    return;
-                }
+    /// Run a batch the large-size warning was accepted for. Big deletes are
+    /// still listed for review; the warning stands in for the popup.
+    pub fn execute_large(&mut self, batch: LargeBatch) {
+        self.input_mode = InputMode::Normal;
+        match batch.op {
+            BatchOp::Delete if self.needs_plan(&batch.paths) => {
+                self.open_plan(BatchOp::Delete, batch.paths, batch.from_disk_usage)
             }
+            BatchOp::Delete => self.execute_delete(DeleteInfo {
+                has_directories: batch.paths.iter().any(|p| p.is_dir()),
+                paths: batch.paths,
+                from_disk_usage: batch.from_disk_usage,
+            }),
+            op => self.run_batch(op, &batch.paths),
         }
-        self.message = Some("No match found".to_string());
     }
 
-    pub fn adjust_scroll(&mut self, visible_height: usize) {
-        if self.selected < self.scroll_offset {
-            self.scroll_offset = self.selected;
-        } else if self.selected >= self.scroll_offset + visible_height {
-            self.scroll_offset = self.selected - visible_height + 1;
-        }
+    /// Whether a batch of `paths` is big enough to be reviewed item by item
+    fn needs_plan(&self, paths: &[PathBuf]) -> bool {
+        let limit = self.config.confirm.plan;
+        limit > 0 && paths.len() > limit
     }
 
-    pub fn refresh(&mut self) {
-        if let Err(e) = self.tree.refresh() {
-            self.message = Some(format!("Refresh error: {}", e));
+    /// List every item of `op` for review instead of running it straight away
+    fn open_plan(&mut self, op: BatchOp, paths: Vec<PathBuf>, from_disk_usage: bool) {
+        let verb = if op == BatchOp::Delete {
+            "delete"
         } else {
-            self.message = Some("Refreshed".to_string());
+            "move"
+        };
+        if self.refuse_protected(verb, &paths) {
+            return;
         }
-        self.git_repo.refresh(&self.tree.root.path);
-        if self.selected >= self.tree.len() {
-            self.selected = self.tree.len().saturating_sub(1);
+        let items = paths
+            .into_iter()
+            .map(|path| {
+                let target = match &op {
+                    BatchOp::Delete => None,
+                    BatchOp::Copy(dest_dir) | BatchOp::Move(dest_dir) => {
+                        file_ops::unique_destination(&path, dest_dir).ok()
+                    }
+                };
+                PlanItem {
+                    path,
+                    target,
+                    included: true,
+                }
+            })
+            .collect();
+        self.plan = Some(BatchPlan {
+            op,
+            items,
+            from_disk_usage,
+        });
+        self.plan_selected = 0;
+        self.input_mode = InputMode::Plan;
+    }
+
+    pub fn plan_move(&mut self, delta: isize) {
+        let len = self.plan.as_ref().map_or(0, |p| p.items.len());
+        let max = len.saturating_sub(1) as isize;
+        self.plan_selected = (self.plan_selected as isize + delta).clamp(0, max) as usize;
+    }
+
+    /// Leave the selected item out of the batch, or put it back, and move down
+    pub fn toggle_plan_item(&mut self) {
+        if let Some(item) = self
+            .plan
+            .as_mut()
+            .and_then(|p| p.items.get_mut(self.plan_selected))
+        {
+            item.included = !item.included;
         }
+        self.plan_move(1);
     }
 
-    pub fn toggle_hidden(&mut self) {
-        self.show_hidden = !self.show_hidden;
-        if let Err(e) = self.tree.set_show_hidden(self.show_hidden) {
+    pub fn cancel_plan(&mut self) {
+        let from_disk_usage = self.plan.take().is_some_and(|p| p.from_disk_usage);
+        self.input_mode = if from_disk_usage {
+            InputMode::DiskUsage
+        } else {
+            InputMode::Normal
+        };
+        self.message = Some("Cancelled".to_string());
+    }
+
+    /// Run the reviewed batch on the items still included
+    pub fn execute_plan(&mut self) {
+        let Some(plan) = self.plan.take() else {
+            return;
+        };
+        self.input_mode = InputMode::Normal;
+        let all: Vec<PathBuf> = plan.items.iter().map(|i| i.path.clone()).collect();
+        let paths: Vec<PathBuf> = plan
+            .items
+            .into_iter()
+            .filter(|i| i.included)
+            .map(|i| i.path)
+            .collect();
+        if paths.is_empty() {
+            self.message = Some("Every item was left out".to_string());
+            if plan.from_disk_usage {
+                self.input_mode = InputMode::DiskUsage;
+            }
+            return;
+        }
+        match plan.op {
+            BatchOp::Delete => self.execute_delete(DeleteInfo {
+                has_directories: paths.iter().any(|p| p.is_dir()),
+                paths,
+                from_disk_usage: plan.from_disk_usage,
+            }),
+            op => {
+                if self.clipboard.content == Some(ClipboardContent::Cut(all)) {
+                    self.clipboard.clear();
+                }
+                self.clear_marks();
+                self.run_batch(op, &paths);
+            }
+        }
+    }
+
+    /// Report the first of `paths` that `protected_paths` forbids to `verb`, if any
+    fn refuse_protected(&mut self, verb: &str, paths: &[PathBuf]) -> bool {
+        let root = &self.tree.root.path;
+        let Some(path) = paths.iter().find(|p| self.config.is_protected(p, root)) else {
+            return false;
+        };
+        self.message = Some(format!(
+            "Refusing to {} protected path {}",
+            verb,
+            path.display()
+        ));
+        true
+    }
+
+    pub fn execute_delete(&mut self, info: DeleteInfo) {
+        self.clear_marks();
+        self.run_batch(BatchOp::Delete, &info.paths);
+        if info.from_disk_usage && self.input_mode == InputMode::Normal {
+            self.start_disk_usage(self.du_dir.clone());
+        }
+    }
+
+    /// Leave a confirmation prompt without acting on it
+    pub fn cancel_confirm(&mut self) {
+        let mode = std::mem::replace(&mut self.input_mode, InputMode::Normal);
+        let from_disk_usage = match mode {
+            InputMode::Confirm(ConfirmAction::Delete(info)) => info.from_disk_usage,
+            InputMode::Confirm(ConfirmAction::Large(batch)) => batch.from_disk_usage,
+            _ => false,
+        };
+        if from_disk_usage {
+            self.input_mode = InputMode::DiskUsage;
+        }
+        self.message = Some("Cancelled".to_string());
+    }
+
+    /// Ask whether to retry failed commands with the configured escalation command
+    fn offer_elevation(&mut self, description: String, commands: Vec<Vec<OsString>>) {
+        if commands.is_empty() {
+            return;
+        }
+        let mut script = OsString::new();
+        for (i, args) in commands.iter().enumerate() {
+            if i > 0 {
+                script.push(" && ");
+            }
+            script.push(Self::shell_join(args.iter().map(Self::shell_quote)));
+        }
+        let mut command = OsString::from(format!("{} sh -c ", self.config.elevate_command));
+        command.push(Self::shell_quote(&script));
+        self.input_mode = InputMode::Confirm(ConfirmAction::Elevate(ElevatedOp {
+            description,
+            command,
+        }));
+    }
+
+    /// Open all marked files (or the selected file) at once, see `execute_open`
+    pub fn open_marked(&mut self) {
+        let mut paths: Vec<PathBuf> = self
+            .get_selected_paths()
+            .into_iter()
+            .filter(|p| !p.is_dir())
+            .collect();
+        if paths.is_empty() {
+            self.message = Some("No files to open".to_string());
+            return;
+        }
+        paths.sort();
+        self.request_open(OpenInfo { paths, nvim: false });
+    }
+
+    /// Open `info.paths`, asking first if they are more than `confirm.open_files`
+    fn request_open(&mut self, info: OpenInfo) {
+        let limit = self.config.confirm.open_files;
+        if limit > 0 && info.paths.len() > limit {
+            self.input_mode = InputMode::Confirm(ConfirmAction::Open(info));
+        } else {
+            self.execute_open(info);
+        }
+    }
+
+    /// Open files matching an `open_with` rule with the rule's command and the
+    /// rest together in the editor. Attached (`@`) and capturing (`!`) rules run
+    /// in the terminal one after another, before the editor.
+    pub fn execute_open(&mut self, info: OpenInfo) {
+        let paths = info.paths;
+        if info.nvim {
+            self.send_to_nvim(&paths);
+            return;
+        }
+        let mut groups: Vec<(&config::CommandRule, Vec<&PathBuf>)> = Vec::new();
+        let mut editor_paths = Vec::new();
+        for path in &paths {
+            match matching_rule(&self.config.open_with, path) {
+                Some(rule) => match groups.iter_mut().find(|(r, _)| std::ptr::eq(*r, rule)) {
+                    Some((_, group)) => group.push(path),
+                    None => groups.push((rule, vec![path])),
+                },
+                None => editor_paths.push(path),
+            }
+        }
+
+        let mut foreground = Vec::new();
+        let mut error = None;
+        for (rule, group) in groups {
+            let (attached, template) = match rule.command.strip_prefix(['@', '!']) {
+                Some(rest) => (true, rest.trim_start()),
+                None => (false, rule.command.as_str()),
+            };
+            for path in group {
+                let command = Self::expand_placeholders(template, path, &self.tree.root.path);
+                if attached {
+                    foreground.push(command);
+                    continue;
+                }
+                let spawned = std::process::Command::new("sh")
+                    .arg("-c")
+                    .arg(&command)
+                    .stdin(std::process::Stdio::null())
+                    .stdout(std::process::Stdio::null())
+                    .stderr(std::process::Stdio::null())
+                    .spawn();
+                if let Err(e) = spawned {
+                    error = Some(format!("Command failed: {}", e));
+                }
+            }
+        }
+        if !editor_paths.is_empty() {
+            let editor = std::env::var("VISUAL")
+                .or_else(|_| std::env::var("EDITOR"))
+                .unwrap_or_else(|_| "vi".to_string());
+            let words = std::iter::once(OsString::from(editor))
+                .chain(editor_paths.into_iter().map(Self::shell_quote));
+            foreground.push(Self::shell_join(words));
+        }
+
+        if !foreground.is_empty() {
+            self.pending_foreground = Some(Self::join_commands(foreground));
+        }
+        if error.is_some() {
+            self.message = error;
+        }
+        self.clear_marks();
+    }
+
+    /// Open the marked (or selected) files in the Neovim instance at
+    /// `nvim_server`, e.g. the one hosting ft in a terminal split
+    pub fn open_in_nvim(&mut self) {
+        if self.config.nvim_server.is_none() {
+            self.message =
+                Some("No Neovim server (run ft inside Neovim or pass --nvim-server)".to_string());
+            return;
+        }
+        let paths: Vec<PathBuf> = self
+            .get_selected_paths()
+            .into_iter()
+            .filter(|p| !p.is_dir())
+            .collect();
+        if paths.is_empty() {
+            self.message = Some("No files to open".to_string());
+            return;
+        }
+        self.request_open(OpenInfo { paths, nvim: true });
+    }
+
+    fn send_to_nvim(&mut self, paths: &[PathBuf]) {
+        let Some(server) = self.config.nvim_server.clone() else {
+            return;
+        };
+        // A stale socket or a busy server can take a while to answer
+        let paths = paths.to_vec();
+        self.nvim_job = Some(Job::spawn(move |_| {
+            let status = std::process::Command::new("nvim")
+                .arg("--server")
+                .arg(&server)
+                .arg("--remote")
+                .args(&paths)
+                .stdin(std::process::Stdio::null())
+                .stdout(std::process::Stdio::null())
+                .stderr(std::process::Stdio::null())
+                .status();
+            match status {
+                Ok(status) if status.success() => {
+                    Ok(format!("Opened {} file(s) in Neovim", paths.len()))
+                }
+                Ok(_) => Err(format!("Neovim at {} did not accept the files", server)),
+                Err(e) => Err(format!("Cannot run nvim: {}", e)),
+            }
+        }));
+    }
+
+    /// Hand the marked (or selected) paths to a drag-and-drop helper window
+    /// so they can be dragged out into GUI applications
+    pub fn drag_out(&mut self) {
+        let paths = self.get_selected_paths();
+        if paths.is_empty() {
+            return;
+        }
+        let candidates: Vec<String> = match &self.config.drag_command {
+            Some(command) => vec![command.clone()],
+            None => DRAG_PROGRAMS.iter().map(|p| p.to_string()).collect(),
+        };
+        for candidate in &candidates {
+            let mut words = candidate.split_whitespace();
+            let Some(program) = words.next() else {
+                continue;
+            };
+            let spawned = std::process::Command::new(program)
+                .args(words)
+                .args(&paths)
+                .stdin(std::process::Stdio::null())
+                .stdout(std::process::Stdio::null())
+                .stderr(std::process::Stdio::null())
+                .spawn();
+            match spawned {
+                Ok(_) => {
+                    self.message =
+                        Some(format!("Dragging {} item(s) with {}", paths.len(), program));
+                    return;
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(e) => {
+                    self.message = Some(format!("{} failed: {}", program, e));
+                    return;
+                }
+            }
+        }
+        self.message = Some(format!(
+            "No drag helper found (install {} or set drag_command)",
+            DRAG_PROGRAMS.join(" / ")
+        ));
+    }
+
+    /// Show the selected entry in the desktop's file manager, highlighted in its folder:
+    /// `open -R` on macOS; elsewhere the freedesktop `FileManager1` D-Bus interface,
+    /// falling back to opening the parent folder with `xdg-open`
+    pub fn reveal_in_file_manager(&mut self) {
+        let Some(path) = self.tree.get_node(self.selected).map(|n| n.path.clone()) else {
+            return;
+        };
+        let null = std::process::Stdio::null;
+        if let Some(command) = &self.config.reveal_command {
+            let mut words = command.split_whitespace();
+            let Some(program) = words.next() else {
+                return;
+            };
+            let spawned = std::process::Command::new(program)
+                .args(words)
+                .arg(&path)
+                .stdin(null())
+                .stdout(null())
+                .stderr(null())
+                .spawn();
+            self.message = Some(match spawned {
+                Ok(_) => format!("Revealed {} with {}", path.display(), program),
+                Err(e) => format!("{} failed: {}", program, e),
+            });
+            return;
+        }
+
+        if cfg!(target_os = "macos") {
+            let spawned = std::process::Command::new("open")
+                .arg("-R")
+                .arg(&path)
+                .stdin(null())
+                .stdout(null())
+                .stderr(null())
+                .spawn();
+            self.message = Some(match spawned {
+                Ok(_) => format!("Revealed {} in Finder", path.display()),
+                Err(e) => format!("open failed: {}", e),
+            });
+            return;
+        }
+
+        if cfg!(target_os = "windows") {
+            let mut select = OsString::from("/select,");
+            select.push(&path);
+            let spawned = std::process::Command::new("explorer")
+                .arg(select)
+                .stdin(null())
+                .stdout(null())
+                .stderr(null())
+                .spawn();
+            self.message = Some(match spawned {
+                Ok(_) => format!("Revealed {} in Explorer", path.display()),
+                Err(e) => format!("explorer failed: {}", e),
+            });
+            return;
+        }
+
+        // Nautilus, Dolphin, Nemo and others select the item; waiting for the
+        // reply tells whether any file manager provides the interface, so it
+        // runs in the background
+        self.reveal_job = Some(Job::spawn(move |_| {
+            let shown = std::process::Command::new("dbus-send")
+                .args([
+                    "--session",
+                    "--print-reply",
+                    "--reply-timeout=2000",
+                    "--dest=org.freedesktop.FileManager1",
+                    "/org/freedesktop/FileManager1",
+                    "org.freedesktop.FileManager1.ShowItems",
+                ])
+                .arg(format!("array:string:{}", file_uri(&path)))
+                .arg("string:")
+                .stdin(null())
+                .stdout(null())
+                .stderr(null())
+                .status()
+                .is_ok_and(|status| status.success());
+            if shown {
+                return format!("Revealed {} in the file manager", path.display());
+            }
+            let folder = path.parent().unwrap_or(&path);
+            let spawned = std::process::Command::new("xdg-open")
+                .arg(folder)
+                .stdin(null())
+                .stdout(null())
+                .stderr(null())
+                .spawn();
+            match spawned {
+                Ok(_) => format!("Opened {} in the file manager", folder.display()),
+                Err(e) => format!("xdg-open failed: {} (or set reveal_command)", e),
+            }
+        }));
+    }
+
+    /// Retry an elevated operation with the TUI suspended so the password prompt is usable
+    pub fn execute_elevated(&mut self, op: ElevatedOp) {
+        self.pending_foreground = Some(op.command);
+    }
+
+    /// Called by the main loop after a foreground command has finished
+    pub fn finish_foreground(
+        &mut self,
+        command: &OsStr,
+        result: std::io::Result<std::process::ExitStatus>,
+    ) {
+        let command = command.to_string_lossy();
+        self.message = Some(match result {
+            Ok(status) if status.success() => format!("Done: {}", command),
+            Ok(status) => match status.code() {
+                Some(code) => format!("Exit code {}: {}", code, command),
+                None => format!("Terminated: {}", command),
+            },
+            Err(e) => format!("Command failed: {}", e),
+        });
+        self.cancel_expand();
+        let _ = self.tree.refresh();
+        self.start_git_refresh();
+        self.apply_view_filter();
+        if self.selected >= self.tree.len() {
+            self.selected = self.tree.len().saturating_sub(1);
+        }
+    }
+
+    /// Show the output of a captured command in the preview, with its exit
+    /// status as the message
+    fn finish_captured_command(
+        &mut self,
+        command: OsString,
+        result: std::io::Result<CommandOutput>,
+    ) {
+        let (status, lines) = match result {
+            Ok(output) if output.truncated => {
+                let mut lines = output.lines;
+                lines.push(String::new());
+                lines.push(format!(
+                    "[Output truncated: only the first {} are kept]",
+                    Self::format_size(capture::MAX_OUTPUT_BYTES)
+                ));
+                (Ok(output.status), lines)
+            }
+            Ok(output) => (Ok(output.status), output.lines),
+            Err(e) => (Err(e), Vec::new()),
+        };
+        let failed_to_start = status.is_err();
+        self.finish_foreground(&command, status);
+        if failed_to_start || !matches!(self.input_mode, InputMode::Normal | InputMode::Preview) {
+            return;
+        }
+        self.preview_title = Some(format!(" $ {} ", command.to_string_lossy()));
+        self.preview_changes.clear();
+        self.clear_preview_search();
+        self.preview_content = if lines.is_empty() {
+            vec!["(no output)".to_string()]
+        } else {
+            lines
+        };
+        self.preview_scroll = 0;
+        self.preview_hscroll = 0;
+        self.preview_path = None;
+        self.image_preview = None;
+        self.input_mode = InputMode::Preview;
+    }
+
+    pub fn confirm_input(&mut self) {
+        let mode = std::mem::replace(&mut self.input_mode, InputMode::Normal);
+        match mode {
+            InputMode::Rename => {
+                if let Some(node) = self.tree.get_node(self.selected) {
+                    let path = node.path.clone();
+                    // The prompt was prefilled with the lossy display name; an unchanged
+                    // prompt must not rename a non-UTF-8 file to its display name
+                    if path.file_name().map(|n| n.to_string_lossy()).as_deref()
+                        == Some(self.input_buffer.as_str())
+                    {
+                        self.input_buffer.clear();
+                        return;
+                    }
+                    let info = RenameInfo {
+                        path,
+                        new_name: self.input_buffer.clone(),
+                    };
+                    let target = info.path.with_file_name(&info.new_name);
+                    if self.config.confirm.overwrite && target.symlink_metadata().is_ok() {
+                        self.input_mode = InputMode::Confirm(ConfirmAction::Overwrite(info));
+                    } else {
+                        self.execute_rename(info);
+                    }
+                }
+            }
+            InputMode::NewFile => {
+                if let Some(dest_dir) = self.get_paste_destination() {
+                    match file_ops::create_file(&dest_dir, &self.input_buffer) {
+                        Ok(new_path) => {
+                            self.message = Some(format!("Created {}", new_path.display()));
+                            self.refresh_dirs(std::slice::from_ref(&dest_dir));
+                            self.select_path(&new_path);
+                        }
+                        Err(e) if file_ops::is_permission_denied(&e) => {
+                            let path = dest_dir.join(&self.input_buffer);
+                            let args = vec!["touch".into(), "--".into(), path.into()];
+                            self.offer_elevation(
+                                format!("Create {}", self.input_buffer),
+                                vec![args],
+                            );
+                        }
+                        Err(e) => {
+                            self.message = Some(format!("Error: {}", e));
+                        }
+                    }
+                }
+            }
+            InputMode::NewDir => {
+                if let Some(dest_dir) = self.get_paste_destination() {
+                    match file_ops::create_directory(&dest_dir, &self.input_buffer) {
+                        Ok(new_path) => {
+                            self.message = Some(format!("Created {}", new_path.display()));
+                            self.refresh_dirs(std::slice::from_ref(&dest_dir));
+                            self.select_path(&new_path);
+                        }
+                        Err(e) if file_ops::is_permission_denied(&e) => {
+                            let path = dest_dir.join(&self.input_buffer);
+                            let args = vec!["mkdir".into(), "--".into(), path.into()];
+                            self.offer_elevation(
+                                format!("Create {}", self.input_buffer),
+                                vec![args],
+                            );
+                        }
+                        Err(e) => {
+                            self.message = Some(format!("Error: {}", e));
+                        }
+                    }
+                }
+            }
+            InputMode::Commit => {
+                let message = self.input_buffer.trim();
+                if message.is_empty() {
+                    self.message = Some("Commit aborted: empty message".to_string());
+                } else {
+                    match self.git_repo.commit(message) {
+                        Ok(hash) => {
+                            self.message = Some(format!("Committed {}", hash));
+                            self.start_git_refresh();
+                        }
+                        Err(e) => self.message = Some(format!("Commit failed: {}", e)),
+                    }
+                }
+            }
+            InputMode::ExtensionFilter => {
+                let input = self.input_buffer.clone();
+                self.set_extension_filter(&input);
+            }
+            InputMode::MoveTo => self.relocate(false),
+            InputMode::CopyTo => self.relocate(true),
+            InputMode::MarkGlob => {
+                let pattern = self.input_buffer.trim().to_string();
+                if !pattern.is_empty() {
+                    self.mark_glob(&pattern);
+                }
+            }
+            InputMode::Search => {
+                // Check if input looks like a dropped file path
+                if self.try_handle_as_drop() {
+                    self.input_buffer.clear();
+                    return;
+                }
+                self.search_next();
+            }
+            InputMode::ExternalCommand => {
+                let command = self.input_buffer.clone();
+                if !command.is_empty() {
+                    // Remove duplicate from history if exists
+                    self.command_history.retain(|c| c != &command);
+                    // Add to end of history
+                    self.command_history.push(command.clone());
+                    Self::trim_history(&mut self.command_history);
+                    // Save history to file
+                    Self::save_history(Self::get_history_file_path(), &self.command_history);
+                }
+                self.execute_external_command(Some(command));
+            }
+            InputMode::Confirm(ConfirmAction::Delete(info)) => {
+                self.execute_delete(info);
+            }
+            InputMode::Confirm(ConfirmAction::Discard(info)) => {
+                self.execute_discard(info);
+            }
+            InputMode::Confirm(ConfirmAction::Elevate(op)) => {
+                self.execute_elevated(op);
+            }
+            InputMode::Confirm(ConfirmAction::Retry(report)) => {
+                self.retry_batch(report);
+            }
+            InputMode::Confirm(ConfirmAction::Overwrite(info)) => {
+                self.execute_rename(info);
+            }
+            InputMode::Confirm(ConfirmAction::Open(info)) => {
+                self.execute_open(info);
+            }
+            InputMode::Confirm(ConfirmAction::Command(run)) => {
+                self.run_external_command(run);
+            }
+            InputMode::Confirm(ConfirmAction::Large(batch)) => {
+                self.execute_large(batch);
+            }
+            InputMode::Normal
+            | InputMode::Preview
+            | InputMode::PreviewSearch
+            | InputMode::JumpLabel
+            | InputMode::MarkedList
+            | InputMode::Plan
+            | InputMode::TodoList
+            | InputMode::FileInfo
+            | InputMode::Compare
+            | InputMode::DiskUsage
+            | InputMode::Help => {}
+        }
+        self.input_buffer.clear();
+    }
+
+    pub fn execute_rename(&mut self, info: RenameInfo) {
+        let RenameInfo { path, new_name } = info;
+        match file_ops::rename_file(&path, &new_name) {
+            Ok(new_path) => {
+                self.message = Some(format!("Renamed to {}", new_path.display()));
+                self.remap_marks(&path, &new_path);
+                let dirs: Vec<PathBuf> = [&path, &new_path]
+                    .iter()
+                    .filter_map(|p| p.parent())
+                    .map(Path::to_path_buf)
+                    .collect();
+                self.refresh_dirs(&dirs);
+                self.select_path(&new_path);
+            }
+            Err(e) if file_ops::is_permission_denied(&e) => {
+                let new_path = path.with_file_name(&new_name);
+                let args = vec![
+                    "mv".into(),
+                    "--".into(),
+                    path.clone().into(),
+                    new_path.into(),
+                ];
+                self.offer_elevation(
+                    format!(
+                        "Rename {}",
+                        path.file_name().unwrap_or_default().to_string_lossy()
+                    ),
+                    vec![args],
+                );
+            }
+            Err(e) => {
+                self.message = Some(format!("Error: {}", e));
+            }
+        }
+    }
+
+    pub fn cancel_input(&mut self) {
+        self.input_mode = match self.input_mode {
+            InputMode::PreviewSearch => InputMode::Preview,
+            _ => InputMode::Normal,
+        };
+        self.input_buffer.clear();
+        self.history_search = None;
+    }
+
+    pub fn search_next(&mut self) {
+        let query = self.input_buffer.to_lowercase();
+        if query.is_empty() {
+            return;
+        }
+
+        let start = self.selected + 1;
+        let len = self.tree.len();
+
+        for i in 0..len {
+            let idx = (start + i) % len;
+            if let Some(node) = self.tree.get_node(idx) {
+                if node.name.to_lowercase().contains(&query) {
+                    self.selected = idx;
+                    return;
+                }
+            }
+        }
+        self.message = Some("No match found".to_string());
+    }
+
+    /// Generate `count` jump labels, all of the same length so none is a prefix of another
+    pub fn jump_labels(count: usize) -> Vec<String> {
+        let base = JUMP_LABEL_CHARS.len();
+        let mut len = 1;
+        let mut capacity = base;
+        while capacity < count {
+            len += 1;
+            capacity *= base;
+        }
+
+        (0..count)
+            .map(|mut n| {
+                let mut label = vec![' '; len];
+                for slot in label.iter_mut().rev() {
+                    *slot = JUMP_LABEL_CHARS[n % base];
+                    n /= base;
+                }
+                label.into_iter().collect()
+            })
+            .collect()
+    }
+
+    /// Labels for the rows currently visible in the tree
+    pub fn visible_jump_labels(&self) -> Vec<String> {
+        let count = self
+            .tree
+            .len()
+            .saturating_sub(self.scroll_offset)
+            .min(self.tree_visible_rows);
+        Self::jump_labels(count)
+    }
+
+    pub fn start_jump_labels(&mut self) {
+        if self.tree.is_empty() {
+            return;
+        }
+        self.input_buffer.clear();
+        self.input_mode = InputMode::JumpLabel;
+    }
+
+    pub fn jump_label_input(&mut self, c: char) {
+        self.input_buffer.push(c);
+        let labels = self.visible_jump_labels();
+
+        if let Some(row) = labels.iter().position(|l| *l == self.input_buffer) {
+            self.selected = self.scroll_offset + row;
+            self.cancel_input();
+        } else if !labels.iter().any(|l| l.starts_with(&self.input_buffer)) {
+            self.message = Some(format!("No label: {}", self.input_buffer));
+            self.cancel_input();
+        }
+    }
+
+    pub fn adjust_scroll(&mut self, visible_height: usize) {
+        if self.selected < self.scroll_offset {
+            self.scroll_offset = self.selected;
+        } else if self.selected >= self.scroll_offset + visible_height {
+            self.scroll_offset = self.selected - visible_height + 1;
+        }
+    }
+
+    /// Fit the tree to a resized terminal before the next frame is drawn, so the
+    /// selection stays on screen when the window shrinks
+    pub fn handle_resize(&mut self, height: u16) {
+        self.tree_area_height = self.tree_rows(height);
+        self.adjust_scroll(self.tree_area_height.max(1));
+    }
+
+    pub fn refresh(&mut self) {
+        self.dir_sizes.clear();
+        self.dirty_dirs.clear();
+        self.refresh_due = None;
+        self.prune_marks();
+        self.cancel_expand();
+        if let Err(e) = self.tree.refresh() {
+            self.message = Some(format!("Refresh error: {}", e));
+        } else {
+            self.message = Some("Refreshed".to_string());
+        }
+        self.start_git_refresh();
+        self.apply_view_filter();
+        if self.selected >= self.tree.len() {
+            self.selected = self.tree.len().saturating_sub(1);
+        }
+    }
+
+    /// Stage the selected or marked paths
+    pub fn stage_selected(&mut self) {
+        let paths = self.get_selected_paths();
+        match self.git_repo.stage(&paths) {
+            Ok(()) => {
+                self.message = Some(format!("Staged {} item(s)", paths.len()));
+                self.start_git_refresh();
+            }
+            Err(e) => self.message = Some(format!("Stage failed: {}", e)),
+        }
+    }
+
+    /// Unstage the selected or marked paths
+    pub fn unstage_selected(&mut self) {
+        let paths = self.get_selected_paths();
+        match self.git_repo.unstage(&paths) {
+            Ok(()) => {
+                self.message = Some(format!("Unstaged {} item(s)", paths.len()));
+                self.start_git_refresh();
+            }
+            Err(e) => self.message = Some(format!("Unstage failed: {}", e)),
+        }
+    }
+
+    /// Open the commit message input for the staged changes
+    /// Marked paths in display order
+    pub fn marked_list(&self) -> Vec<PathBuf> {
+        let mut paths: Vec<PathBuf> = self.marked.iter().cloned().collect();
+        paths.sort();
+        paths
+    }
+
+    pub fn open_marked_list(&mut self) {
+        if self.marked.is_empty() {
+            self.message = Some("No marked entries".to_string());
+            return;
+        }
+        self.marked_selected = 0;
+        self.input_mode = InputMode::MarkedList;
+    }
+
+    pub fn close_marked_list(&mut self) {
+        self.input_mode = InputMode::Normal;
+    }
+
+    pub fn marked_move(&mut self, delta: isize) {
+        let max = self.marked.len().saturating_sub(1) as isize;
+        self.marked_selected = (self.marked_selected as isize + delta).clamp(0, max) as usize;
+    }
+
+    /// Unmark the selected entry of the review panel, closing it once nothing is left
+    pub fn unmark_selected(&mut self) {
+        if let Some(path) = self.marked_list().get(self.marked_selected) {
+            self.marked.remove(path);
+        }
+        if self.marked.is_empty() {
+            self.close_marked_list();
+        } else {
+            self.marked_move(0);
+        }
+    }
+
+    pub fn toggle_hide_empty_dirs(&mut self) {
+        let path = self.tree.get_node(self.selected).map(|n| n.path.clone());
+        self.hide_empty_dirs = !self.hide_empty_dirs;
+        self.apply_view_filter();
+        if let Some(path) = path {
+            self.select_path(&path);
+        }
+        self.message = Some(if self.hide_empty_dirs {
+            "Hiding empty directories".to_string()
+        } else {
+            "Showing empty directories".to_string()
+        });
+    }
+
+    /// Prompt for the extensions to show, prefilled with the current filter
+    pub fn start_extension_filter(&mut self) {
+        self.input_buffer = self.extension_filter.join(",");
+        self.input_mode = InputMode::ExtensionFilter;
+    }
+
+    /// Show only files with the given comma- or space-separated extensions
+    /// (e.g. `rs,toml` or `.rs .toml`). An empty list removes the filter.
+    pub fn set_extension_filter(&mut self, input: &str) {
+        let path = self.tree.get_node(self.selected).map(|n| n.path.clone());
+        self.extension_filter = input
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .map(|e| {
+                e.trim_start_matches("*.")
+                    .trim_start_matches('.')
+                    .to_lowercase()
+            })
+            .filter(|e| !e.is_empty())
+            .collect();
+        self.apply_view_filter();
+        if let Some(path) = path {
+            self.select_path(&path);
+        }
+        self.message = Some(if self.extension_filter.is_empty() {
+            "Extension filter off".to_string()
+        } else {
+            format!("Showing only .{} files", self.extension_filter.join(", ."))
+        });
+    }
+
+    pub fn start_mark_glob(&mut self) {
+        self.input_buffer.clear();
+        self.input_mode = InputMode::MarkGlob;
+    }
+
+    /// Mark every visible entry whose name matches `pattern`. Patterns containing
+    /// `/` are matched against the path relative to the root instead.
+    pub fn mark_glob(&mut self, pattern: &str) {
+        let root = self.tree.root.path.clone();
+        let matches: Vec<PathBuf> = (0..self.tree.len())
+            .filter_map(|i| self.tree.get_node(i))
+            .filter(|node| {
+                if pattern.contains('/') {
+                    let relative = node.path.strip_prefix(&root).unwrap_or(&node.path);
+                    glob_match(pattern, &relative.to_string_lossy())
+                } else {
+                    glob_match(pattern, &node.name)
+                }
+            })
+            .map(|node| node.path.clone())
+            .collect();
+        self.message = Some(format!(
+            "Marked {} entries matching {}",
+            matches.len(),
+            pattern
+        ));
+        self.marked.extend(matches);
+    }
+
+    /// Mark every existing file below the root that git reports as changed,
+    /// whether or not its directory is expanded
+    pub fn mark_changed(&mut self) {
+        if !self.git_repo.is_inside_repo() {
+            self.message = Some("Not a git repository".to_string());
+            return;
+        }
+        let root = self.tree.root.path.clone();
+        let changed: Vec<PathBuf> = self
+            .git_repo
+            .changed_files_under(std::slice::from_ref(&root))
+            .into_iter()
+            .filter(|(path, status)| *status != GitStatus::Deleted && path.is_file())
+            .map(|(path, _)| path)
+            .collect();
+        self.message = Some(format!("Marked {} changed file(s)", changed.len()));
+        self.marked.extend(changed);
+    }
+
+    pub fn start_commit(&mut self) {
+        if !self.git_repo.is_inside_repo() {
+            self.message = Some("Not a git repository".to_string());
+            return;
+        }
+        self.input_buffer.clear();
+        self.input_mode = InputMode::Commit;
+    }
+
+    /// Ask to discard all git changes in the selected or marked paths
+    pub fn confirm_discard(&mut self) {
+        let files = self
+            .git_repo
+            .changed_files_under(&self.get_selected_paths());
+        if files.is_empty() {
+            self.message = Some("No changes to discard".to_string());
+        } else {
+            self.input_mode = InputMode::Confirm(ConfirmAction::Discard(DiscardInfo { files }));
+        }
+    }
+
+    /// Restore changed files from HEAD and delete new ones
+    pub fn execute_discard(&mut self, info: DiscardInfo) {
+        let mut success = 0;
+        let mut errors = Vec::new();
+        for (path, status) in &info.files {
+            match self.git_repo.discard(path, *status) {
+                Ok(()) => success += 1,
+                Err(e) => errors.push(e.to_string()),
+            }
+        }
+        self.message = Some(match errors.first() {
+            Some(e) => format!(
+                "Discarded {} file(s), {} failed: {}",
+                success,
+                errors.len(),
+                e
+            ),
+            None => format!("Discarded changes in {} file(s)", success),
+        });
+        self.clear_marks();
+        self.schedule_refresh(
+            info.files
+                .iter()
+                .filter_map(|(path, _)| path.parent())
+                .map(Path::to_path_buf),
+        );
+        self.start_git_refresh();
+    }
+
+    /// Reload git status on a background thread; `poll_jobs` merges the result.
+    /// Replaces any refresh still in flight, whose result would be stale.
+    pub fn start_git_refresh(&mut self) {
+        if !self.config.git {
+            return;
+        }
+        let root = self.tree.root.path.clone();
+        self.git_job = Some(Job::spawn(move |_| GitRepo::new(&root)));
+    }
+
+    pub fn toggle_size_column(&mut self) {
+        self.show_size = !self.show_size;
+    }
+
+    pub fn toggle_mtime_column(&mut self) {
+        self.show_mtime = !self.show_mtime;
+    }
+
+    pub fn toggle_permissions_column(&mut self) {
+        self.show_permissions = !self.show_permissions;
+    }
+
+    /// Reorder every tab with `sort`, keeping the selected entry selected
+    fn set_sort(&mut self, sort: SortOrder, message: String) {
+        let selected = self.tree.get_node(self.selected).map(|n| n.path.clone());
+        self.tree.set_sort(sort);
+        for tab in self.tabs.iter_mut().flatten() {
+            tab.tree.set_sort(sort);
+        }
+        if let Some(path) = selected {
+            self.select_path(&path);
+        }
+        self.message = Some(message);
+    }
+
+    pub fn cycle_directory_order(&mut self) {
+        let directories = self.tree.sort.directories.next();
+        let sort = SortOrder {
+            directories,
+            ..self.tree.sort
+        };
+        self.set_sort(sort, format!("Sorting {}", directories.label()));
+    }
+
+    pub fn toggle_sort_case(&mut self) {
+        let case_insensitive = !self.tree.sort.case_insensitive;
+        let sort = SortOrder {
+            case_insensitive,
+            ..self.tree.sort
+        };
+        let message = if case_insensitive {
+            "Sorting ignores case"
+        } else {
+            "Sorting is case-sensitive"
+        };
+        self.set_sort(sort, message.to_string());
+    }
+
+    pub fn toggle_hidden_first(&mut self) {
+        let hidden_first = !self.tree.sort.hidden_first;
+        let sort = SortOrder {
+            hidden_first,
+            ..self.tree.sort
+        };
+        let message = if hidden_first {
+            "Sorting hidden files first"
+        } else {
+            "Sorting hidden files with the others"
+        };
+        self.set_sort(sort, message.to_string());
+    }
+
+    pub fn toggle_natural_sort(&mut self) {
+        let natural = !self.tree.sort.natural;
+        let sort = SortOrder {
+            natural,
+            ..self.tree.sort
+        };
+        let message = if natural {
+            "Sorting numbers by value"
+        } else {
+            "Sorting numbers digit by digit"
+        };
+        self.set_sort(sort, message.to_string());
+    }
+
+    pub fn toggle_compact(&mut self) {
+        self.compact = !self.compact;
+    }
+
+    /// Rows left for tree entries in a terminal `height` rows tall, before the
+    /// frame is laid out
+    pub fn tree_rows(&self, height: u16) -> usize {
+        let chrome = if self.compact { 2 } else { 5 };
+        height.saturating_sub(chrome) as usize
+    }
+
+    pub fn toggle_perf_overlay(&mut self) {
+        self.perf = match self.perf {
+            Some(_) => None,
+            None => Some(PerfStats::default()),
+        };
+    }
+
+    /// Account a drawn frame for the performance overlay
+    pub fn record_frame(&mut self, duration: std::time::Duration) {
+        if let Some(perf) = &mut self.perf {
+            perf.frames += 1;
+            perf.last_frame = duration;
+            perf.slowest_frame = perf.slowest_frame.max(duration);
+        }
+    }
+
+    /// Resident memory of this process in bytes (Linux only)
+    pub fn resident_memory() -> Option<u64> {
+        let status = fs::read_to_string("/proc/self/status").ok()?;
+        let line = status.lines().find(|l| l.starts_with("VmRSS:"))?;
+        let kib: u64 = line.split_whitespace().nth(1)?.parse().ok()?;
+        Some(kib * 1024)
+    }
+
+    pub fn toggle_detail_view(&mut self) {
+        self.detail_view = !self.detail_view;
+    }
+
+    /// `ls`-style permission string (`rwxr-xr-x`), including setuid/setgid/sticky bits
+    pub fn format_mode(mode: u32) -> String {
+        let bit = |mask: u32, c: char| if mode & mask != 0 { c } else { '-' };
+        let special = |exec: u32, flag: u32, set: char| match (mode & exec != 0, mode & flag != 0) {
+            (true, true) => set,
+            (false, true) => set.to_ascii_uppercase(),
+            (true, false) => 'x',
+            (false, false) => '-',
+        };
+        [
+            bit(0o400, 'r'),
+            bit(0o200, 'w'),
+            special(0o100, 0o4000, 's'),
+            bit(0o040, 'r'),
+            bit(0o020, 'w'),
+            special(0o010, 0o2000, 's'),
+            bit(0o004, 'r'),
+            bit(0o002, 'w'),
+            special(0o001, 0o1000, 't'),
+        ]
+        .iter()
+        .collect()
+    }
+
+    /// Show a popup with the full metadata of the selected entry
+    pub fn open_file_info(&mut self) {
+        let Some(node) = self.tree.get_node(self.selected) else {
+            return;
+        };
+        let path = node.path.clone();
+        let meta = match fs::symlink_metadata(&path) {
+            Ok(meta) => meta,
+            Err(e) => {
+                self.message = Some(format!("Cannot read metadata: {}", e));
+                return;
+            }
+        };
+
+        let mut rows = vec![("Path", path.display().to_string())];
+        let file_type = meta.file_type();
+        if file_type.is_symlink() {
+            rows.push(("Type", "symbolic link".to_string()));
+            let target = fs::read_link(&path)
+                .map(|t| t.display().to_string())
+                .unwrap_or_else(|e| e.to_string());
+            let broken = if path.exists() { "" } else { " (broken)" };
+            rows.push(("Target", format!("{}{}", target, broken)));
+        } else if file_type.is_dir() {
+            rows.push(("Type", "directory".to_string()));
+        } else {
+            rows.push(("Type", "file".to_string()));
+        }
+
+        let size = if meta.is_dir() {
+            match self.dir_sizes.get(&path) {
+                Some(&size) => format!("{} (contents)", Self::format_size(size)),
+                None => "press s to calculate".to_string(),
+            }
+        } else {
+            format!("{} ({} bytes)", Self::format_size(meta.len()), meta.len())
+        };
+        rows.push(("Size", size));
+
+        let now = SystemTime::now();
+        let time = |t: std::io::Result<SystemTime>| match t {
+            Ok(t) => format!(
+                "{} ({})",
+                Self::format_timestamp(t),
+                Self::format_mtime(t, now)
+            ),
+            Err(_) => "unavailable".to_string(),
+        };
+        rows.push(("Modified", time(meta.modified())));
+        rows.push(("Accessed", time(meta.accessed())));
+        rows.push(("Created", time(meta.created())));
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt;
+            rows.push((
+                "Permissions",
+                format!(
+                    "{} ({:04o})",
+                    Self::format_mode(meta.mode()),
+                    meta.mode() & 0o7777
+                ),
+            ));
+            let (user, group) = self.owner_names(meta.uid(), meta.gid());
+            rows.push(("Owner", format!("{}:{}", user, group)));
+            rows.push(("Inode", format!("{} on device {}", meta.ino(), meta.dev())));
+            rows.push(("Links", meta.nlink().to_string()));
+        }
+        #[cfg(not(unix))]
+        {
+            let access = if meta.permissions().readonly() {
+                "read-only"
+            } else {
+                "read-write"
+            };
+            rows.push(("Permissions", access.to_string()));
+        }
+
+        let mime = if file_type.is_dir() {
+            "inode/directory"
+        } else if path.is_file() {
+            preview::mime_type(&path)
+        } else {
+            "-"
+        };
+        rows.push(("MIME type", mime.to_string()));
+        if self.git_repo.root.is_some() {
+            let status = self
+                .git_repo
+                .get_status(&path)
+                .name()
+                .unwrap_or("unchanged");
+            rows.push(("Git", status.to_string()));
+        }
+
+        self.file_info = rows;
+        self.input_mode = InputMode::FileInfo;
+    }
+
+    /// User and group names for the given ids, falling back to the numeric ids
+    #[cfg(unix)]
+    pub fn owner_names(&self, uid: u32, gid: u32) -> (String, String) {
+        use uzers::{Groups, Users};
+        let user = self
+            .users
+            .get_user_by_uid(uid)
+            .map(|u| u.name().to_string_lossy().to_string())
+            .unwrap_or_else(|| uid.to_string());
+        let group = self
+            .users
+            .get_group_by_gid(gid)
+            .map(|g| g.name().to_string_lossy().to_string())
+            .unwrap_or_else(|| gid.to_string());
+        (user, group)
+    }
+
+    #[cfg(not(unix))]
+    pub fn owner_names(&self, uid: u32, gid: u32) -> (String, String) {
+        (uid.to_string(), gid.to_string())
+    }
+
+    /// Rebuild the tree's view filter from the current view toggles
+    pub fn apply_view_filter(&mut self) {
+        let mut filter = ViewFilter::default();
+        if self.clean_view {
+            filter.hide_dotfiles = true;
+            filter.excluded = self.git_repo.ignored_paths().cloned().collect();
+        }
+        filter.dirs_only = self.dirs_only;
+        filter.hide_empty_dirs = self.hide_empty_dirs;
+        filter.ignore = self
+            .config
+            .ignore
+            .iter()
+            .map(|p| p.to_lowercase())
+            .collect();
+        if !self.extension_filter.is_empty() {
+            filter.extensions = Some(self.extension_filter.iter().cloned().collect());
+        }
+        self.tree.set_filter(filter);
+        if self.selected >= self.tree.len() {
+            self.selected = self.tree.len().saturating_sub(1);
+        }
+    }
+
+    /// Labels of active view filters, for the status bar
+    pub fn active_filters(&self) -> Vec<String> {
+        let mut filters = Vec::new();
+        if self.clean_view {
+            filters.push("clean".to_string());
+        }
+        if self.dirs_only {
+            filters.push("dirs".to_string());
+        }
+        if self.hide_empty_dirs {
+            filters.push("no-empty".to_string());
+        }
+        if !self.extension_filter.is_empty() {
+            filters.push(format!("*.{{{}}}", self.extension_filter.join(",")));
+        }
+        if self.tree.flat_view.is_some() {
+            filters.push("flat".to_string());
+        }
+        filters
+    }
+
+    /// Toggle hiding git-ignored paths, `.git` and hidden files all at once
+    pub fn toggle_clean_view(&mut self) {
+        let path = self.tree.get_node(self.selected).map(|n| n.path.clone());
+        self.clean_view = !self.clean_view;
+        self.apply_view_filter();
+        if let Some(path) = path {
+            self.select_path(&path);
+        }
+        self.message = Some(if self.clean_view {
+            "Clean view: hiding ignored and hidden files".to_string()
+        } else {
+            "Clean view off".to_string()
+        });
+    }
+
+    /// Toggle hiding files to show just the directory structure
+    pub fn toggle_dirs_only(&mut self) {
+        let path = self.tree.get_node(self.selected).map(|n| n.path.clone());
+        self.dirs_only = !self.dirs_only;
+        self.apply_view_filter();
+        // Keep the selection on the file's directory when the file disappears
+        if let Some(path) = path {
+            for ancestor in path.ancestors() {
+                if self.tree.find_node(ancestor).is_some_and(|n| n.is_dir) {
+                    self.select_path(ancestor);
+                    break;
+                }
+            }
+        }
+        self.message = Some(if self.dirs_only {
+            "Directories only".to_string()
+        } else {
+            "Showing files".to_string()
+        });
+    }
+
+    pub fn toggle_hidden(&mut self) {
+        self.show_hidden = !self.show_hidden;
+        if let Err(e) = self.tree.set_show_hidden(self.show_hidden) {
             self.message = Some(format!("Error: {}", e));
         } else {
-            self.message = Some(if self.show_hidden {
-                "Showing hidden files".to_string()
+            self.message = Some(if self.show_hidden {
+                "Showing hidden files".to_string()
+            } else {
+                "Hiding hidden files".to_string()
+            });
+        }
+        if self.selected >= self.tree.len() {
+            self.selected = self.tree.len().saturating_sub(1);
+        }
+    }
+
+    pub fn collapse_all(&mut self) {
+        self.expand_job = None;
+        self.tree.collapse_all();
+        self.selected = 0;
+        self.scroll_offset = 0;
+        self.message = Some("Collapsed all".to_string());
+    }
+
+    /// List every file below the selected directory (or the root) as one flat list.
+    /// Leaving the flat view reveals the selected file in the tree.
+    pub fn toggle_flat_view(&mut self) {
+        let selected = self.tree.get_node(self.selected).map(|n| n.path.clone());
+        if self.tree.flat_view.take().is_some() {
+            self.expand_job = None;
+            match selected {
+                Some(path) => {
+                    self.tree.collapse_others(&path);
+                    self.select_path(&path);
+                }
+                None => self.tree.rebuild_flat_list(),
+            }
+            return;
+        }
+
+        let base = self
+            .tree
+            .get_node(self.selected)
+            .filter(|n| n.is_dir && self.selected != 0)
+            .map(|n| n.path.clone())
+            .unwrap_or_else(|| self.tree.root.path.clone());
+        let Some(node) = self.tree.find_node(&base).cloned() else {
+            return;
+        };
+        self.tree.flat_view = Some(base);
+        self.tree.rebuild_flat_list();
+        self.selected = 0;
+        self.scroll_offset = 0;
+        // Load everything below the base; the list fills in when the job finishes
+        self.start_expand(node);
+    }
+
+    /// Collapse everything except the path leading to the selection
+    pub fn collapse_others(&mut self) {
+        let Some(path) = self.tree.get_node(self.selected).map(|n| n.path.clone()) else {
+            return;
+        };
+        self.expand_job = None;
+        self.tree.collapse_others(&path);
+        self.select_path(&path);
+        self.message = Some("Collapsed others".to_string());
+    }
+
+    /// Expand every directory in the background, within the configured depth and entry cap
+    pub fn expand_all(&mut self) {
+        self.start_expand(self.tree.root.clone());
+    }
+
+    /// Expand the selected directory and all of its descendants, with the same limits as `L`
+    pub fn expand_selected_recursive(&mut self) {
+        let Some(path) = self
+            .tree
+            .get_node(self.selected)
+            .filter(|n| n.is_dir)
+            .map(|n| n.path.clone())
+        else {
+            self.message = Some("Not a directory".to_string());
+            return;
+        };
+        if let Some(node) = self.tree.find_node(&path) {
+            self.start_expand(node.clone());
+        }
+    }
+
+    fn start_expand(&mut self, node: FileNode) {
+        let (show_hidden, sort) = (self.tree.show_hidden, self.tree.sort);
+        let limits = ExpandLimits {
+            max_depth: self.config.expand_max_depth,
+            max_entries: self.config.expand_max_entries,
+            follow_symlinks: self.config.follow_symlinks,
+        };
+        self.message = Some("Expanding...".to_string());
+        self.expand_job = Some(Job::spawn(move |cancel| {
+            file_tree::expand_subtree(node, show_hidden, sort, limits, cancel)
+        }));
+    }
+
+    /// Drop a running expand all: it works on a copy of the tree taken before
+    /// the change about to be made, which its result would undo
+    fn cancel_expand(&mut self) {
+        if self.expand_job.take().is_some() {
+            self.message = Some("Expand cancelled: the tree changed".to_string());
+        }
+    }
+
+    fn finish_expand(&mut self, result: anyhow::Result<ExpandOutcome>) {
+        match result {
+            Ok(ExpandOutcome::Done(node, entries)) => {
+                let selected = self.tree.get_node(self.selected).map(|n| n.path.clone());
+                let unreadable = node.unreadable_count();
+                // The node is gone if the tree was re-rooted or refreshed in the meantime
+                if self.tree.replace_node(node) {
+                    if let Some(path) = selected {
+                        self.select_path(&path);
+                    }
+                    self.message = Some(if unreadable > 0 {
+                        format!(
+                            "Expanded {} entries ({} unreadable directories)",
+                            entries, unreadable
+                        )
+                    } else {
+                        format!("Expanded {} entries", entries)
+                    });
+                }
+            }
+            Ok(ExpandOutcome::CapReached) => {
+                self.message = Some(format!(
+                    "Expand aborted: more than {} entries (expand_max_entries)",
+                    self.config.expand_max_entries
+                ));
+            }
+            Ok(ExpandOutcome::Cancelled) => {}
+            Err(e) => self.message = Some(format!("Error: {}", e)),
+        }
+    }
+
+    fn format_dir_preview(path: &Path) -> Vec<String> {
+        let mut lines = vec!["[Directory]".to_string(), String::new()];
+
+        if let Ok(entries) = std::fs::read_dir(path) {
+            let mut files = 0;
+            let mut dirs = 0;
+            let mut hidden = 0;
+            let mut total_size: u64 = 0;
+
+            for entry in entries.filter_map(|e| e.ok()) {
+                let name = entry.file_name();
+                let is_hidden = name.to_str().map(|s| s.starts_with('.')).unwrap_or(false);
+
+                if is_hidden {
+                    hidden += 1;
+                }
+
+                if let Ok(meta) = entry.metadata() {
+                    if meta.is_dir() {
+                        dirs += 1;
+                    } else {
+                        files += 1;
+                        total_size += meta.len();
+                    }
+                }
+            }
+
+            lines.push(format!("  Files: {}", files));
+            lines.push(format!("  Directories: {}", dirs));
+            if hidden > 0 {
+                lines.push(format!("  Hidden: {}", hidden));
+            }
+            lines.push(format!("  Size: {}", Self::format_size(total_size)));
+        }
+
+        lines
+    }
+
+    /// Git summary lines for a directory preview (empty outside a repository)
+    fn format_dir_git_summary(
+        git_repo: &GitRepo,
+        path: &Path,
+        last_commit: Option<&LastCommit>,
+    ) -> Vec<String> {
+        if git_repo.root.is_none() {
+            return Vec::new();
+        }
+
+        let mut lines = vec![String::new(), "[Git]".to_string(), String::new()];
+        let (changed, untracked) = git_repo.count_changes_under(path);
+        if changed == 0 && untracked == 0 {
+            lines.push("  Clean".to_string());
+        } else {
+            lines.push(format!("  Changed: {}", changed));
+            lines.push(format!("  Untracked: {}", untracked));
+        }
+        if let Some(commit) = last_commit {
+            lines.push(format!(
+                "  Last commit: {} {} {}",
+                commit.short_id,
+                Self::format_mtime(commit.time, SystemTime::now()),
+                commit.summary
+            ));
+        }
+        lines
+    }
+
+    pub fn format_size(bytes: u64) -> String {
+        const KB: u64 = 1024;
+        const MB: u64 = KB * 1024;
+        const GB: u64 = MB * 1024;
+
+        if bytes >= GB {
+            format!("{:.1} GB", bytes as f64 / GB as f64)
+        } else if bytes >= MB {
+            format!("{:.1} MB", bytes as f64 / MB as f64)
+        } else if bytes >= KB {
+            format!("{:.1} KB", bytes as f64 / KB as f64)
+        } else {
+            format!("{} B", bytes)
+        }
+    }
+
+    /// Relative time for recent changes ("3h ago"), the date (UTC) for older ones
+    pub fn format_mtime(modified: SystemTime, now: SystemTime) -> String {
+        const MINUTE: u64 = 60;
+        const HOUR: u64 = 60 * MINUTE;
+        const DAY: u64 = 24 * HOUR;
+
+        let age = now.duration_since(modified).unwrap_or_default().as_secs();
+        if age < MINUTE {
+            "just now".to_string()
+        } else if age < HOUR {
+            format!("{}m ago", age / MINUTE)
+        } else if age < DAY {
+            format!("{}h ago", age / HOUR)
+        } else if age < 7 * DAY {
+            format!("{}d ago", age / DAY)
+        } else {
+            let secs = modified
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs();
+            let (year, month, day) = civil_from_days((secs / DAY) as i64);
+            format!("{:04}-{:02}-{:02}", year, month, day)
+        }
+    }
+
+    /// Absolute time in UTC, e.g. `2024-01-03 00:00:00 UTC`
+    pub fn format_timestamp(time: SystemTime) -> String {
+        let secs = time
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let (year, month, day) = civil_from_days((secs / 86400) as i64);
+        let secs_of_day = secs % 86400;
+        format!(
+            "{:04}-{:02}-{:02} {:02}:{:02}:{:02} UTC",
+            year,
+            month,
+            day,
+            secs_of_day / 3600,
+            secs_of_day / 60 % 60,
+            secs_of_day % 60
+        )
+    }
+
+    fn set_system_clipboard(text: &str) -> Result<(), &'static str> {
+        let mut clip = arboard::Clipboard::new().map_err(|_| "Clipboard not available")?;
+        clip.set_text(text)
+            .map_err(|_| "Failed to copy to clipboard")
+    }
+
+    fn copy_to_system_clipboard(&mut self, text: &str) {
+        self.message = Some(match Self::set_system_clipboard(text) {
+            Ok(()) => format!("Copied: {}", text),
+            Err(e) => e.to_string(),
+        });
+    }
+
+    pub fn copy_path(&mut self) {
+        if let Some(node) = self.tree.get_node(self.selected) {
+            let path_str = node.path.to_string_lossy().to_string();
+            self.copy_to_system_clipboard(&path_str);
+        }
+    }
+
+    /// Copy the selected path relative to `base` ("." for `base` itself)
+    pub fn copy_path_relative(&mut self, base: PathBuf) {
+        let Some(node) = self.tree.get_node(self.selected) else {
+            return;
+        };
+        match node.path.strip_prefix(&base) {
+            Ok(relative) if relative.as_os_str().is_empty() => {
+                self.copy_to_system_clipboard(".");
+            }
+            Ok(relative) => {
+                let text = relative.to_string_lossy().to_string();
+                self.copy_to_system_clipboard(&text);
+            }
+            Err(_) => {
+                self.message = Some(format!(
+                    "{} is outside {}",
+                    node.path.display(),
+                    base.display()
+                ));
+            }
+        }
+    }
+
+    pub fn copy_filename(&mut self) {
+        if let Some(node) = self.tree.get_node(self.selected) {
+            // Not `node.name`, which is a relative path in the flat view
+            let name = node
+                .path
+                .file_name()
+                .map(|n| n.to_string_lossy().to_string())
+                .unwrap_or_else(|| node.name.clone());
+            self.copy_to_system_clipboard(&name);
+        }
+    }
+
+    pub fn preview_file(&mut self) {
+        if let Some(node) = self.tree.get_node(self.selected) {
+            if node.is_dir {
+                self.message = Some("Cannot preview directory".to_string());
+                return;
+            }
+            if let (true, Some(target)) = (node.broken_link, &node.symlink_target) {
+                self.message = Some(format!("Broken symlink: {} is missing", target.display()));
+                return;
+            }
+
+            let path = node.path.clone();
+            self.open_preview(path);
+        }
+    }
+
+    /// Open the full-screen preview for a file
+    fn open_preview(&mut self, path: PathBuf) {
+        self.preview_mtime = Self::modified(&path);
+        if let Some(job) = self.start_previewer(&path, std::time::Duration::ZERO) {
+            self.preview_content = vec!["Loading preview…".to_string()];
+            self.preview_scroll = 0;
+            self.preview_hscroll = 0;
+            self.preview_path = Some(path.clone());
+            self.image_preview = None;
+            self.input_mode = InputMode::Preview;
+            self.previewer_job = Some((path, job));
+            return;
+        }
+
+        // Check if it's an image file
+        if Self::is_image_file(&path) {
+            match self.load_image_preview(&path) {
+                Ok(()) => return,
+                Err(e) => {
+                    self.message = Some(format!("Image error: {}", e));
+                    // Fall through to binary preview
+                }
+            }
+        }
+
+        match preview::load_lines(&path, 100) {
+            Ok(lines) => {
+                self.preview_changes = self.line_changes(&path);
+                self.preview_content = lines;
+                self.preview_scroll = 0;
+                self.preview_hscroll = 0;
+                self.preview_path = Some(path);
+                self.image_preview = None;
+                self.input_mode = InputMode::Preview;
+            }
+            Err(e) => {
+                self.message = Some(format!("Cannot read file: {}", e));
+            }
+        }
+    }
+
+    /// Whether the content of `path` is an image format we can decode,
+    /// regardless of its extension
+    fn is_image_file(path: &Path) -> bool {
+        preview::sniff_mime_type(path)
+            .and_then(image::ImageFormat::from_mime_type)
+            .is_some_and(|format| format.reading_enabled())
+    }
+
+    /// Decode an image, detecting the format from its content rather than its extension
+    fn decode_image(path: &Path) -> Result<image::DynamicImage, String> {
+        image::ImageReader::open(path)
+            .and_then(|reader| reader.with_guessed_format())
+            .map_err(|e| e.to_string())?
+            .decode()
+            .map_err(|e| e.to_string())
+    }
+
+    fn load_image_preview(&mut self, path: &Path) -> Result<(), String> {
+        let img = Self::decode_image(path)?;
+        self.image_preview = Some(ImagePreview::new(img, None));
+        self.preview_path = Some(path.to_path_buf());
+        self.preview_content.clear();
+        self.preview_scroll = 0;
+        self.preview_hscroll = 0;
+        self.input_mode = InputMode::Preview;
+        Ok(())
+    }
+
+    pub fn close_preview(&mut self) {
+        self.previewer_job = None;
+        self.preview_changes.clear();
+        self.clear_preview_search();
+        self.input_mode = self.preview_return.take().unwrap_or(InputMode::Normal);
+        self.preview_content.clear();
+        self.preview_path = None;
+        self.preview_title = None;
+        self.preview_scroll = 0;
+        self.preview_hscroll = 0;
+        self.image_preview = None;
+    }
+
+    /// Show the recent commits touching the selected path in the preview
+    pub fn preview_git_log(&mut self) {
+        let Some(path) = self.tree.get_node(self.selected).map(|n| n.path.clone()) else {
+            return;
+        };
+        match self.git_repo.file_log(&path, GIT_LOG_LIMIT) {
+            Ok(lines) if lines.is_empty() => {
+                self.message = Some("No commits touch this path".to_string());
+            }
+            Ok(lines) => {
+                let name = path
+                    .file_name()
+                    .map(|n| n.to_string_lossy().to_string())
+                    .unwrap_or_else(|| path.display().to_string());
+                self.preview_title = Some(format!(" git log: {} ", name));
+                self.preview_changes.clear();
+                self.clear_preview_search();
+                self.preview_content = lines;
+                self.preview_scroll = 0;
+                self.preview_hscroll = 0;
+                self.preview_path = Some(path);
+                self.image_preview = None;
+                self.input_mode = InputMode::Preview;
+            }
+            Err(e) => self.message = Some(format!("git log failed: {}", e)),
+        }
+    }
+
+    /// Start scanning the tree root for TODO/FIXME/HACK markers in the background
+    pub fn start_todo_scan(&mut self) {
+        let root = self.tree.root.path.clone();
+        let show_hidden = self.show_hidden;
+        let excluded: HashSet<PathBuf> = self.git_repo.ignored_paths().cloned().collect();
+        self.todo_job = Some(Job::spawn(move |cancel| {
+            todo_scan::scan(&root, show_hidden, &excluded, cancel)
+        }));
+        self.todo_items.clear();
+        self.todo_selected = 0;
+        self.input_mode = InputMode::TodoList;
+    }
+
+    pub fn close_todo_list(&mut self) {
+        if let Some(job) = self.todo_job.take() {
+            job.cancel();
+        }
+        self.input_mode = InputMode::Normal;
+    }
+
+    pub fn todo_move(&mut self, delta: isize) {
+        let max = self.todo_items.len().saturating_sub(1) as isize;
+        self.todo_selected = (self.todo_selected as isize + delta).clamp(0, max) as usize;
+    }
+
+    /// Open the selected TODO in the full-screen preview, scrolled to its line
+    pub fn open_todo(&mut self) {
+        let item = match self.todo_items.get(self.todo_selected) {
+            Some(item) => item.clone(),
+            None => return,
+        };
+        self.open_preview(item.path);
+        if self.input_mode == InputMode::Preview {
+            self.preview_scroll = item.line.saturating_sub(1);
+            self.preview_hscroll = 0;
+            self.preview_return = Some(InputMode::TodoList);
+        }
+    }
+
+    /// Compare the two marked directories in the background
+    pub fn start_compare(&mut self) {
+        let dirs: Vec<PathBuf> = self
+            .marked_list()
+            .into_iter()
+            .filter(|p| p.is_dir())
+            .collect();
+        let [a, b] = dirs.as_slice() else {
+            self.message = Some("Mark exactly two directories to compare".to_string());
+            return;
+        };
+        self.compare_dirs = (a.clone(), b.clone());
+        self.rescan_compare();
+    }
+
+    pub fn rescan_compare(&mut self) {
+        let (a, b) = self.compare_dirs.clone();
+        let show_hidden = self.show_hidden;
+        self.compare_job = Some(Job::spawn(move |cancel| {
+            dir_compare::compare(&a, &b, show_hidden, cancel)
+        }));
+        self.compare_entries.clear();
+        self.compare_selected = 0;
+        self.input_mode = InputMode::Compare;
+    }
+
+    pub fn close_compare(&mut self) {
+        if let Some(job) = self.compare_job.take() {
+            job.cancel();
+        }
+        self.input_mode = InputMode::Normal;
+    }
+
+    pub fn compare_move(&mut self, delta: isize) {
+        let max = self.compare_entries.len().saturating_sub(1) as isize;
+        self.compare_selected = (self.compare_selected as isize + delta).clamp(0, max) as usize;
+    }
+
+    /// Preview the selected entry (side A's copy if it exists on both sides)
+    pub fn open_compare_entry(&mut self) {
+        let Some(entry) = self.compare_entries.get(self.compare_selected) else {
+            return;
+        };
+        let root = match entry.difference {
+            Difference::OnlyInB => &self.compare_dirs.1,
+            Difference::OnlyInA | Difference::Differs => &self.compare_dirs.0,
+        };
+        if entry.is_dir {
+            self.message = Some(format!("{} is a directory", entry.path.display()));
+            return;
+        }
+        self.open_preview(root.join(&entry.path));
+        if self.input_mode == InputMode::Preview {
+            self.preview_return = Some(InputMode::Compare);
+        }
+    }
+
+    /// Copy the selected entry to the side it is missing from
+    pub fn copy_compare_entry(&mut self) {
+        let Some(entry) = self.compare_entries.get(self.compare_selected).cloned() else {
+            return;
+        };
+        let (a, b) = &self.compare_dirs;
+        let (from, to) = match entry.difference {
+            Difference::OnlyInA => (a, b),
+            Difference::OnlyInB => (b, a),
+            Difference::Differs => {
+                self.message = Some(format!(
+                    "{} exists on both sides; only missing entries are copied",
+                    entry.path.display()
+                ));
+                return;
+            }
+        };
+        // Missing entries are reported at their topmost level, so the parent exists on both sides
+        let dest_dir = match entry.path.parent() {
+            Some(parent) => to.join(parent),
+            None => to.clone(),
+        };
+        if self.copy_job.is_some() {
+            self.message = Some("Another copy is still running".to_string());
+            return;
+        }
+        let src = from.join(&entry.path);
+        self.run_batch(BatchOp::Copy(dest_dir), &[src]);
+        // Resolved once the copy starts; a failure opens the retry prompt when it finishes
+        if self.input_mode == InputMode::Compare {
+            self.compare_entries.remove(self.compare_selected);
+            self.compare_move(0);
+        }
+    }
+
+    /// Append a digit to the pending count
+    pub fn push_count_digit(&mut self, digit: u32) {
+        let count = self.pending_count.unwrap_or(0);
+        self.pending_count = Some(
+            count
+                .saturating_mul(10)
+                .saturating_add(digit as usize)
+                .min(MAX_COUNT),
+        );
+    }
+
+    /// Consume the pending count, defaulting to 1
+    pub fn take_count(&mut self) -> usize {
+        self.pending_count.take().unwrap_or(1).max(1)
+    }
+
+    /// Jump to the nth entry (1-based), clamped to the tree
+    pub fn move_to_line(&mut self, line: usize) {
+        self.selected = line
+            .saturating_sub(1)
+            .min(self.tree.len().saturating_sub(1));
+    }
+
+    /// Wait for the continuation of a multi-key sequence
+    pub fn start_sequence(&mut self, prefix: char) {
+        self.pending_prefix = Some(prefix);
+        self.pending_since = std::time::Instant::now();
+    }
+
+    /// Run the action bound to `prefix` followed by `key`
+    pub fn run_sequence(&mut self, prefix: char, key: char) {
+        let count = self.pending_count.take();
+        let action = match (prefix, key) {
+            ('g', 'g') => match count {
+                Some(line) => Action::GoToLine(line),
+                None => Action::GoToTop,
+            },
+            ('g', 'd') => Action::NextChange,
+            ('g', 'p') => Action::GoToGitRoot,
+            ('g', 'o') => Action::RevealInFileManager,
+            (',', 'd') => Action::CycleDirectoryOrder,
+            (',', 'c') => Action::ToggleSortCase,
+            (',', 'h') => Action::ToggleHiddenFirst,
+            (',', 'n') => Action::ToggleNaturalSort,
+            ('Y', 'p') => Action::CopyPath,
+            ('Y', 'r') => Action::CopyRelativePath,
+            ('Y', 'g') => Action::CopyGitRelativePath,
+            ('Y', 'n') => Action::CopyFilename,
+            ('Y', 's') => Action::Checksum(Algorithm::Sha256),
+            ('Y', 'm') => Action::Checksum(Algorithm::Md5),
+            _ => {
+                self.message = Some(format!("Unknown key sequence: {}{}", prefix, key));
+                return;
+            }
+        };
+        self.dispatch(action);
+    }
+
+    /// Move to the next visible entry with git changes, wrapping around.
+    /// Expanded directories are skipped since their changed children are visible.
+    pub fn jump_to_next_change(&mut self) {
+        let len = self.tree.len();
+        let next = (1..=len)
+            .map(|offset| (self.selected + offset) % len)
+            .find(|&i| {
+                self.tree.get_node(i).is_some_and(|node| {
+                    let expanded_dir = node.is_dir && node.expanded;
+                    !expanded_dir
+                        && !matches!(
+                            self.git_repo.get_status(&node.path),
+                            GitStatus::None | GitStatus::Ignored
+                        )
+                })
+            });
+        match next {
+            Some(index) => {
+                self.selected = index;
+                self.update_quick_preview();
+            }
+            None => self.message = Some("No git changes".to_string()),
+        }
+    }
+
+    pub fn open_help(&mut self) {
+        self.help_scroll = 0;
+        self.help_query.clear();
+        self.help_searching = false;
+        self.input_mode = InputMode::Help;
+    }
+
+    pub fn help_scroll_by(&mut self, delta: isize) {
+        self.help_scroll = (self.help_scroll as isize + delta).max(0) as usize;
+    }
+
+    /// Open the disk usage view for the selected directory (or the parent of a file)
+    pub fn open_disk_usage(&mut self) {
+        let dir = match self.tree.get_node(self.selected) {
+            Some(node) if node.is_dir => node.path.clone(),
+            Some(node) => match node.path.parent() {
+                Some(parent) => parent.to_path_buf(),
+                None => return,
+            },
+            None => return,
+        };
+        self.start_disk_usage(dir);
+    }
+
+    /// Scan `dir` in the background and show its children by size
+    pub fn start_disk_usage(&mut self, dir: PathBuf) {
+        let scan_dir = dir.clone();
+        self.du_job = Some(Job::spawn(move |cancel| {
+            disk_usage::scan(&scan_dir, cancel)
+        }));
+        self.du_dir = dir;
+        self.du_entries.clear();
+        self.du_selected = 0;
+        self.input_mode = InputMode::DiskUsage;
+    }
+
+    pub fn close_disk_usage(&mut self) {
+        if let Some(job) = self.du_job.take() {
+            job.cancel();
+        }
+        self.input_mode = InputMode::Normal;
+    }
+
+    pub fn du_move(&mut self, delta: isize) {
+        let max = self.du_entries.len().saturating_sub(1) as isize;
+        self.du_selected = (self.du_selected as isize + delta).clamp(0, max) as usize;
+    }
+
+    /// Drill into the selected directory
+    pub fn du_enter(&mut self) {
+        if let Some(entry) = self.du_entries.get(self.du_selected) {
+            if entry.is_dir {
+                self.start_disk_usage(entry.path.clone());
+            }
+        }
+    }
+
+    pub fn du_parent(&mut self) {
+        if let Some(parent) = self.du_dir.parent() {
+            self.start_disk_usage(parent.to_path_buf());
+        }
+    }
+
+    /// Ask to delete the selected entry; the view is rescanned afterwards
+    pub fn du_delete(&mut self) {
+        if let Some(entry) = self.du_entries.get(self.du_selected) {
+            let info = DeleteInfo {
+                paths: vec![entry.path.clone()],
+                has_directories: entry.is_dir,
+                from_disk_usage: true,
+            };
+            self.request_delete(info);
+        }
+    }
+
+    /// Compute the recursive size of the selected or marked directories in the background
+    pub fn calculate_dir_sizes(&mut self) {
+        let dirs: Vec<PathBuf> = self
+            .get_selected_paths()
+            .into_iter()
+            .filter(|p| p.is_dir())
+            .collect();
+        if dirs.is_empty() {
+            self.message = Some("No directory selected".to_string());
+            return;
+        }
+        self.message = Some(format!(
+            "Calculating size of {} directory(s)...",
+            dirs.len()
+        ));
+        self.size_job = Some(Job::spawn(move |cancel| {
+            let mut sizes = Vec::new();
+            for dir in dirs {
+                match file_ops::dir_size(&dir, cancel) {
+                    Some(size) => sizes.push((dir, size)),
+                    None => break,
+                }
+            }
+            sizes
+        }));
+    }
+
+    /// Hash the selected file in the background, then copy the digest to the clipboard.
+    /// Running it again while a checksum is in progress cancels that one instead.
+    pub fn start_checksum(&mut self, algorithm: Algorithm) {
+        if let Some(running) = self.checksum_job.take() {
+            running.job.cancel();
+            self.message = Some(format!("{} cancelled", running.algorithm.name()));
+            return;
+        }
+        let Some(node) = self.tree.get_node(self.selected) else {
+            return;
+        };
+        if node.is_dir {
+            self.message = Some("Select a file to checksum".to_string());
+            return;
+        }
+        let path = node.path.clone();
+        let size = fs::metadata(&path).map(|m| m.len()).unwrap_or(0);
+        let progress = Arc::new(AtomicU64::new(0));
+        let job = {
+            let path = path.clone();
+            let progress = Arc::clone(&progress);
+            Job::spawn(move |cancel| checksum::compute(&path, algorithm, &progress, cancel))
+        };
+        self.checksum_job = Some(ChecksumJob {
+            path,
+            algorithm,
+            size,
+            progress,
+            job,
+        });
+        self.show_checksum_progress();
+    }
+
+    fn show_checksum_progress(&mut self) {
+        let Some(running) = &self.checksum_job else {
+            return;
+        };
+        let done = running.progress.load(Ordering::Relaxed);
+        let percent = (done * 100).checked_div(running.size).unwrap_or(0).min(100);
+        self.message = Some(format!(
+            "{} of {}: {}% of {} (repeat to cancel)",
+            running.algorithm.name(),
+            running
+                .path
+                .file_name()
+                .unwrap_or_default()
+                .to_string_lossy(),
+            percent,
+            Self::format_size(running.size)
+        ));
+    }
+
+    /// Reload the tree and git status when the terminal regains focus, e.g. after
+    /// switching back from an editor. The selection stays on the same path.
+    /// Skipped while a prompt or popup is open, since those act on the selection.
+    pub fn focus_gained(&mut self) {
+        if !self.config.refresh_on_focus || !matches!(self.input_mode, InputMode::Normal) {
+            return;
+        }
+        let selected = self.tree.get_node(self.selected).map(|n| n.path.clone());
+        self.dirty_dirs.clear();
+        self.refresh_due = None;
+        self.prune_marks();
+        self.cancel_expand();
+        if let Err(e) = self.tree.refresh() {
+            self.message = Some(format!("Refresh error: {}", e));
+        }
+        self.start_git_refresh();
+        self.apply_view_filter();
+        if let Some(path) = selected {
+            self.select_path(&path);
+        }
+        if self.selected >= self.tree.len() {
+            self.selected = self.tree.len().saturating_sub(1);
+        }
+        self.update_quick_preview();
+    }
+
+    /// Re-read `dirs` right away, keeping expanded subdirectories as they are
+    pub fn refresh_dirs(&mut self, dirs: &[PathBuf]) {
+        for dir in dirs {
+            self.dir_sizes.remove(dir);
+        }
+        self.cancel_expand();
+        self.tree.refresh_dirs(dirs);
+        if self.selected >= self.tree.len() {
+            self.selected = self.tree.len().saturating_sub(1);
+        }
+    }
+
+    /// Quit, unless a copy or a move to another device is still running:
+    /// stopping it part way would leave a partial copy behind
+    pub fn quit(&mut self) {
+        if self.move_job.is_some() {
+            self.message = Some("Wait for the move to another device to finish".to_string());
+        } else if self.copy_job.is_some() {
+            self.message = Some("Wait for the copy to finish".to_string());
+        } else {
+            self.should_quit = true;
+        }
+    }
+
+    /// Re-read `dirs` once no other file operation has touched the tree for
+    /// `REFRESH_DEBOUNCE`
+    pub fn schedule_refresh(&mut self, dirs: impl IntoIterator<Item = PathBuf>) {
+        self.dirty_dirs.extend(dirs);
+        self.refresh_due = Some(std::time::Instant::now() + REFRESH_DEBOUNCE);
+    }
+
+    /// Carry out a scheduled refresh now
+    pub fn flush_refresh(&mut self) {
+        self.refresh_due = None;
+        if self.dirty_dirs.is_empty() {
+            return;
+        }
+        let dirs: Vec<PathBuf> = self.dirty_dirs.drain().collect();
+        self.refresh_dirs(&dirs);
+    }
+
+    fn modified(path: &Path) -> Option<SystemTime> {
+        fs::metadata(path).and_then(|m| m.modified()).ok()
+    }
+
+    /// Reload the previews whose file changed on disk, keeping their scroll
+    /// position (and the search in the full-screen preview)
+    pub fn reload_changed_previews(&mut self) {
+        let changed = |path: &Path, loaded: Option<SystemTime>| {
+            let mtime = Self::modified(path);
+            loaded.is_some() && mtime.is_some() && mtime != loaded
+        };
+
+        // File content only: git log and command output have a title
+        if let (InputMode::Preview, None, Some(path)) = (
+            &self.input_mode,
+            &self.preview_title,
+            self.preview_path.clone(),
+        ) {
+            if changed(&path, self.preview_mtime) {
+                let (scroll, hscroll) = (self.preview_scroll, self.preview_hscroll);
+                let (query, current) =
+                    (std::mem::take(&mut self.preview_query), self.preview_match);
+                self.open_preview(path);
+                self.preview_scroll = if self.previewer_job.is_some() {
+                    scroll
+                } else {
+                    scroll.min(self.preview_content.len().saturating_sub(1))
+                };
+                self.preview_hscroll = hscroll;
+                self.preview_matches = Self::find_preview_matches(&self.preview_content, &query);
+                self.preview_match = current.min(self.preview_matches.len().saturating_sub(1));
+                self.preview_query = query;
+            }
+        }
+
+        if let Some(path) = self.quick_preview_path.clone() {
+            if self.shows_quick_preview() && changed(&path, self.quick_preview_mtime) {
+                let scroll = self.quick_preview_scroll;
+                self.quick_preview_path = None;
+                self.update_quick_preview();
+                self.quick_preview_scroll =
+                    scroll.min(self.quick_preview_content.len().saturating_sub(1));
+            }
+        }
+    }
+
+    /// Collect results of finished background jobs; called by the main loop
+    pub fn poll_jobs(&mut self) {
+        #[cfg(unix)]
+        self.handle_ipc();
+        if self.last_reload_check.elapsed() >= PREVIEW_RELOAD_INTERVAL {
+            self.last_reload_check = std::time::Instant::now();
+            self.reload_changed_previews();
+        }
+        if self
+            .refresh_due
+            .is_some_and(|due| std::time::Instant::now() >= due)
+        {
+            self.flush_refresh();
+        }
+        if let Some(job) = &self.du_job {
+            match job.poll() {
+                Ok(Some(Ok(Some(entries)))) => {
+                    for entry in entries.iter().filter(|e| e.is_dir) {
+                        self.dir_sizes.insert(entry.path.clone(), entry.size);
+                    }
+                    self.du_entries = entries;
+                    self.du_job = None;
+                }
+                Ok(Some(Ok(None))) => self.du_job = None,
+                Ok(Some(Err(e))) => {
+                    self.message = Some(format!("Cannot read {}: {}", self.du_dir.display(), e));
+                    self.du_job = None;
+                    if self.input_mode == InputMode::DiskUsage {
+                        self.input_mode = InputMode::Normal;
+                    }
+                }
+                Ok(None) => {}
+                Err(()) => {
+                    self.message = Some("Disk usage scan failed".to_string());
+                    self.du_job = None;
+                }
+            }
+        }
+        if let Some(job) = &self.size_job {
+            match job.poll() {
+                Ok(Some(sizes)) => {
+                    let total: u64 = sizes.iter().map(|(_, size)| size).sum();
+                    self.message = Some(format!("Total size: {}", Self::format_size(total)));
+                    self.dir_sizes.extend(sizes);
+                    self.size_job = None;
+                    self.update_quick_preview();
+                }
+                Ok(None) => {}
+                Err(()) => {
+                    self.message = Some("Size calculation failed".to_string());
+                    self.size_job = None;
+                }
+            }
+        }
+        if let Some(running) = &self.checksum_job {
+            match running.job.poll() {
+                Ok(Some(result)) => {
+                    let running = self.checksum_job.take().unwrap();
+                    let name = running.algorithm.name();
+                    self.message = Some(match result {
+                        Ok(Some(digest)) => match Self::set_system_clipboard(&digest) {
+                            Ok(()) => format!("{} (copied): {}", name, digest),
+                            Err(e) => format!("{}: {} ({})", name, digest, e),
+                        },
+                        Ok(None) => format!("{} cancelled", name),
+                        Err(e) => format!("{} failed: {}", name, e),
+                    });
+                }
+                Ok(None) => self.show_checksum_progress(),
+                Err(()) => {
+                    self.message = Some("Checksum failed".to_string());
+                    self.checksum_job = None;
+                }
+            }
+        }
+        if let Some(running) = &self.copy_job {
+            match running.job.poll() {
+                Ok(Some(failures)) => {
+                    let running = self.copy_job.take().unwrap();
+                    self.finish_copy_job(running, failures);
+                }
+                Ok(None) => self.show_copy_progress(),
+                Err(()) => {
+                    self.message = Some("Copy failed".to_string());
+                    self.copy_job = None;
+                }
+            }
+        }
+        if let Some(running) = &self.move_job {
+            match running.job.poll() {
+                Ok(Some(failures)) => {
+                    let running = self.move_job.take().unwrap();
+                    self.finish_move_job(running, failures);
+                }
+                Ok(None) => self.show_move_progress(),
+                Err(()) => {
+                    self.message = Some("Move failed".to_string());
+                    self.move_job = None;
+                }
+            }
+        }
+        if let Some(check) = &self.size_check {
+            match check.job.poll() {
+                Ok(Some(stats)) => {
+                    let check = self.size_check.take().unwrap();
+                    if let Some(stats) = stats {
+                        self.finish_size_check(check, stats);
+                    }
+                }
+                Ok(None) => {}
+                Err(()) => {
+                    self.message = Some("Size check failed".to_string());
+                    self.size_check = None;
+                }
+            }
+        }
+        if let Some(job) = &self.expand_job {
+            match job.poll() {
+                Ok(Some(result)) => {
+                    self.expand_job = None;
+                    self.finish_expand(result);
+                }
+                Ok(None) => {}
+                Err(()) => {
+                    self.message = Some("Expand failed".to_string());
+                    self.expand_job = None;
+                }
+            }
+        }
+        if let Some((path, result)) = Self::finished_previewer(&mut self.quick_previewer_job) {
+            if self.quick_preview_path.as_ref() == Some(&path) {
+                self.quick_preview_content = Self::previewer_lines(result);
+            }
+        }
+        if let Some((path, result)) = Self::finished_previewer(&mut self.previewer_job) {
+            let previewing = matches!(
+                self.input_mode,
+                InputMode::Preview | InputMode::PreviewSearch
+            );
+            if previewing && self.preview_path.as_ref() == Some(&path) {
+                self.preview_content = Self::previewer_lines(result);
+                self.clear_preview_search();
+            }
+        }
+        if let Some((_, job)) = &self.command_job {
+            match job.poll() {
+                Ok(Some(result)) => {
+                    if let Some((command, _)) = self.command_job.take() {
+                        self.finish_captured_command(command, result);
+                    }
+                }
+                Ok(None) => {}
+                Err(()) => {
+                    self.message = Some("Command failed".to_string());
+                    self.command_job = None;
+                }
+            }
+        }
+        if let Some(job) = &self.git_job {
+            self.spinner_tick = self.spinner_tick.wrapping_add(1);
+            match job.poll() {
+                Ok(Some(repo)) => {
+                    self.git_repo = repo;
+                    self.git_job = None;
+                    self.last_commits.clear();
+                    self.apply_view_filter();
+                    self.update_quick_preview();
+                }
+                Ok(None) => {}
+                Err(()) => self.git_job = None,
+            }
+        }
+        if let Some((path, job)) = &self.last_commit_job {
+            match job.poll() {
+                Ok(Some(commit)) => {
+                    let path = path.clone();
+                    self.last_commit_job = None;
+                    self.last_commits.insert(path.clone(), commit);
+                    if self.quick_preview_path.as_ref() == Some(&path) {
+                        self.update_quick_preview();
+                    }
+                }
+                Ok(None) => {}
+                Err(()) => self.last_commit_job = None,
+            }
+        }
+        if let Some(job) = &self.nvim_job {
+            match job.poll() {
+                Ok(Some(result)) => {
+                    self.nvim_job = None;
+                    if result.is_ok() {
+                        self.clear_marks();
+                    }
+                    self.message = Some(result.unwrap_or_else(|e| e));
+                }
+                Ok(None) => {}
+                Err(()) => self.nvim_job = None,
+            }
+        }
+        if let Some(job) = &self.reveal_job {
+            match job.poll() {
+                Ok(Some(message)) => {
+                    self.message = Some(message);
+                    self.reveal_job = None;
+                }
+                Ok(None) => {}
+                Err(()) => self.reveal_job = None,
+            }
+        }
+        if self.pending_prefix.is_some() && self.pending_since.elapsed() >= SEQUENCE_TIMEOUT {
+            self.pending_prefix = None;
+            self.pending_count = None;
+        }
+        if let Some(job) = &self.compare_job {
+            match job.poll() {
+                Ok(Some(entries)) => {
+                    self.message = Some(format!("{} difference(s)", entries.len()));
+                    self.compare_entries = entries;
+                    self.compare_job = None;
+                }
+                Ok(None) => {}
+                Err(()) => {
+                    self.message = Some("Directory comparison failed".to_string());
+                    self.compare_job = None;
+                }
+            }
+        }
+        if let Some(job) = &self.todo_job {
+            match job.poll() {
+                Ok(Some(items)) => {
+                    self.message = Some(format!("Found {} TODO(s)", items.len()));
+                    self.todo_items = items;
+                    self.todo_job = None;
+                }
+                Ok(None) => {}
+                Err(()) => {
+                    self.message = Some("TODO scan failed".to_string());
+                    self.todo_job = None;
+                }
+            }
+        }
+    }
+
+    pub fn toggle_quick_preview(&mut self) {
+        self.quick_preview_enabled = !self.quick_preview_enabled;
+        if self.quick_preview_enabled {
+            self.update_quick_preview();
+        } else if !self.miller {
+            self.quick_preview_content.clear();
+            self.quick_preview_changes.clear();
+            self.quick_preview_path = None;
+            self.quick_preview_scroll = 0;
+            self.quick_preview_image = None;
+        }
+    }
+
+    /// Look up the last commit of `dir` in the background for its preview,
+    /// unless it is already being looked up
+    fn start_last_commit(&mut self, dir: &Path) {
+        let Some(root) = self.git_repo.root.clone() else {
+            return;
+        };
+        if self
+            .last_commit_job
+            .as_ref()
+            .is_some_and(|(path, _)| path == dir)
+        {
+            return;
+        }
+        let path = dir.to_path_buf();
+        let job = Job::spawn(move |cancel| git_status::last_commit(&root, &path, cancel));
+        self.last_commit_job = Some((dir.to_path_buf(), job));
+    }
+
+    pub fn update_quick_preview(&mut self) {
+        if !self.shows_quick_preview() {
+            return;
+        }
+
+        let node = match self.tree.get_node(self.selected) {
+            Some(n) => n,
+            None => return,
+        };
+
+        if node.is_dir {
+            let mut content = Self::format_dir_preview(&node.path);
+            if let Some(&size) = self.dir_sizes.get(&node.path) {
+                content.push(format!("  Total size: {}", Self::format_size(size)));
+            }
+            let path = node.path.clone();
+            let last_commit = self.last_commits.get(&path);
+            content.extend(Self::format_dir_git_summary(
+                &self.git_repo,
+                &path,
+                last_commit.and_then(Option::as_ref),
+            ));
+            if last_commit.is_none() {
+                self.start_last_commit(&path);
+            }
+            self.quick_preview_content = content;
+            self.quick_preview_changes.clear();
+            self.quick_preview_mtime = None;
+            self.quick_preview_path = Some(path);
+            self.quick_preview_scroll = 0;
+            self.quick_preview_image = None;
+            return;
+        }
+
+        let path = node.path.clone();
+
+        // Check if it's the same file
+        if self.quick_preview_path.as_ref() == Some(&path) {
+            return;
+        }
+        self.quick_preview_changes.clear();
+        self.quick_preview_mtime = Self::modified(&path);
+
+        if node.broken_link {
+            let target = node.symlink_target.clone().unwrap_or_default();
+            self.quick_preview_content = vec![
+                "[Broken symlink]".to_string(),
+                String::new(),
+                format!("  Target: {} (missing)", target.display()),
+            ];
+            self.quick_preview_path = Some(path);
+            self.quick_preview_scroll = 0;
+            self.quick_preview_image = None;
+            return;
+        }
+
+        self.quick_previewer_job = None;
+        if let Some(job) = self.start_previewer(&path, PREVIEWER_DELAY) {
+            self.quick_preview_content = vec!["Loading preview…".to_string()];
+            self.quick_preview_image = None;
+            self.quick_preview_path = Some(path.clone());
+            self.quick_preview_scroll = 0;
+            self.quick_previewer_job = Some((path, job));
+            return;
+        }
+
+        let mtime = self.quick_preview_mtime;
+        let cached = mtime.and_then(|mtime| self.preview_cache.get(&path, mtime).cloned());
+        let loaded = cached.or_else(|| {
+            let loaded = Self::load_quick_preview(&path)?;
+            if let Some(mtime) = mtime {
+                self.preview_cache
+                    .insert(path.clone(), mtime, loaded.clone());
+            }
+            Some(loaded)
+        });
+        match loaded {
+            Some(CachedPreview::Image(img)) => {
+                self.quick_preview_image = Some(img);
+                self.quick_preview_content.clear();
+            }
+            Some(CachedPreview::Lines(lines)) => {
+                self.quick_preview_image = None;
+                self.quick_preview_content = lines;
+                self.quick_preview_changes = self.line_changes(&path);
+            }
+            None => {
+                self.quick_preview_image = None;
+                self.quick_preview_content = vec!["[Cannot read file]".to_string()];
+            }
+        }
+        self.quick_preview_path = Some(path);
+        self.quick_preview_scroll = 0;
+    }
+
+    /// Decode an image (downscaled), or read text or a hex dump of binary content
+    fn load_quick_preview(path: &Path) -> Option<CachedPreview> {
+        if Self::is_image_file(path) {
+            if let Ok(img) = Self::decode_image(path) {
+                let img = ImagePreview::new(img, Some(QUICK_PREVIEW_IMAGE_SIZE));
+                return Some(CachedPreview::Image(img));
+            }
+        }
+        preview::load_lines(path, 50).ok().map(CachedPreview::Lines)
+    }
+
+    /// Lines of `path` that differ from `HEAD`, if the git gutter is enabled
+    /// and the file has changes
+    fn line_changes(&self, path: &Path) -> HashMap<usize, LineChange> {
+        let changed = matches!(
+            self.git_repo.get_status(path),
+            GitStatus::Modified | GitStatus::Staged | GitStatus::Renamed
+        );
+        if self.config.preview_git_gutter && changed {
+            self.git_repo.line_changes(path)
+        } else {
+            HashMap::new()
+        }
+    }
+
+    pub fn toggle_preview_line_numbers(&mut self) {
+        self.preview_line_numbers = !self.preview_line_numbers;
+    }
+
+    pub fn toggle_preview_wrap(&mut self) {
+        self.preview_wrap = !self.preview_wrap;
+        self.preview_hscroll = 0;
+        self.message = Some(
+            if self.preview_wrap {
+                "Preview: wrap long lines"
+            } else {
+                "Preview: cut long lines"
+            }
+            .to_string(),
+        );
+    }
+
+    pub fn quick_preview_scroll_up(&mut self) {
+        if self.quick_preview_scroll > 0 {
+            self.quick_preview_scroll -= 1;
+        }
+    }
+
+    pub fn quick_preview_scroll_down(&mut self, visible_height: usize) {
+        if self.quick_preview_scroll + visible_height < self.quick_preview_content.len() {
+            self.quick_preview_scroll += 1;
+        }
+    }
+
+    pub fn preview_scroll_up(&mut self) {
+        if self.preview_scroll > 0 {
+            self.preview_scroll -= 1;
+        }
+    }
+
+    pub fn preview_scroll_down(&mut self, visible_height: usize) {
+        if self.preview_scroll + visible_height < self.preview_content.len() {
+            self.preview_scroll += 1;
+        }
+    }
+
+    pub fn preview_page_up(&mut self, visible_height: usize) {
+        self.preview_scroll = self.preview_scroll.saturating_sub(visible_height);
+    }
+
+    pub fn preview_page_down(&mut self, visible_height: usize) {
+        let max_scroll = self.preview_content.len().saturating_sub(visible_height);
+        self.preview_scroll = (self.preview_scroll + visible_height).min(max_scroll);
+    }
+
+    pub fn start_preview_search(&mut self) {
+        self.input_buffer.clear();
+        self.input_mode = InputMode::PreviewSearch;
+    }
+
+    fn clear_preview_search(&mut self) {
+        self.preview_query.clear();
+        self.preview_matches.clear();
+        self.preview_match = 0;
+    }
+
+    /// Search the preview for the typed text and show the first match at or
+    /// below the top of the view
+    pub fn search_preview(&mut self, visible_height: usize) {
+        self.input_mode = InputMode::Preview;
+        self.preview_query = std::mem::take(&mut self.input_buffer);
+        self.preview_matches =
+            Self::find_preview_matches(&self.preview_content, &self.preview_query);
+        if self.preview_query.is_empty() {
+            return;
+        }
+        if self.preview_matches.is_empty() {
+            self.message = Some(format!("Pattern not found: {}", self.preview_query));
+            return;
+        }
+        self.preview_match = self
+            .preview_matches
+            .iter()
+            .position(|(line, _)| *line >= self.preview_scroll)
+            .unwrap_or(0);
+        self.show_preview_match(visible_height);
+    }
+
+    /// Go to the next (or previous) match of the preview search, wrapping around
+    pub fn preview_search_next(&mut self, forward: bool, visible_height: usize) {
+        let count = self.preview_matches.len();
+        if count == 0 {
+            if !self.preview_query.is_empty() {
+                self.message = Some(format!("Pattern not found: {}", self.preview_query));
+            }
+            return;
+        }
+        let wrapped = if forward {
+            self.preview_match = (self.preview_match + 1) % count;
+            self.preview_match == 0
+        } else {
+            self.preview_match = (self.preview_match + count - 1) % count;
+            self.preview_match == count - 1
+        };
+        self.message = wrapped.then(|| {
+            if forward {
+                "Search hit bottom, continuing at top".to_string()
             } else {
-                "Hiding hidden files".to_string()
-            });
+                "Search hit top, continuing at bottom".to_string()
+            }
+        });
+        self.show_preview_match(visible_height);
+    }
+
+    /// Scroll the current match into view, centered if it was off screen
+    fn show_preview_match(&mut self, visible_height: usize) {
+        let Some((line, _)) = self.preview_matches.get(self.preview_match) else {
+            return;
+        };
+        if *line < self.preview_scroll || *line >= self.preview_scroll + visible_height {
+            let max_scroll = self.preview_content.len().saturating_sub(visible_height);
+            self.preview_scroll = line.saturating_sub(visible_height / 2).min(max_scroll);
+        }
+    }
+
+    /// Occurrences of `query` in `lines`, ignoring ASCII case
+    fn find_preview_matches(lines: &[String], query: &str) -> Vec<PreviewMatch> {
+        let mut matches = Vec::new();
+        if query.is_empty() {
+            return matches;
+        }
+        // ASCII lowercasing keeps byte offsets valid for the original line
+        let query = query.to_ascii_lowercase();
+        for (i, line) in lines.iter().enumerate() {
+            let line = line.to_ascii_lowercase();
+            let mut from = 0;
+            while let Some(pos) = line[from..].find(&query) {
+                let start = from + pos;
+                matches.push((i, start..start + query.len()));
+                from = start + query.len();
+            }
+        }
+        matches
+    }
+
+    pub fn preview_scroll_left(&mut self, columns: usize) {
+        self.preview_hscroll = self.preview_hscroll.saturating_sub(columns);
+    }
+
+    /// Scroll the preview right, stopping once the longest line's end is in view
+    pub fn preview_scroll_right(&mut self, columns: usize) {
+        let longest = self
+            .preview_content
+            .iter()
+            .map(|line| line.width())
+            .max()
+            .unwrap_or(0);
+        self.preview_hscroll = (self.preview_hscroll + columns).min(longest.saturating_sub(1));
+    }
+
+    pub fn handle_click(&mut self, row: u16) {
+        let index = self.scroll_offset + row as usize;
+        if index >= self.tree.len() {
+            return;
+        }
+
+        let now = std::time::Instant::now();
+        let is_double_click = self.last_click_index == Some(index)
+            && now.duration_since(self.last_click_time).as_millis() < 400;
+
+        self.selected = index;
+        self.last_click_time = now;
+        self.last_click_index = Some(index);
+        self.drag_anchor = Some(index);
+        self.drag_marked.clear();
+
+        if is_double_click {
+            self.toggle_expand();
+        }
+    }
+
+    /// Extend a drag from the clicked entry to `row`, marking the entries in
+    /// between (the root is never marked). Entries the drag marked earlier
+    /// are unmarked again when the range shrinks.
+    pub fn handle_drag(&mut self, row: u16) {
+        let Some(anchor) = self.drag_anchor else {
+            return;
+        };
+        let last = self.tree.len().saturating_sub(1);
+        let index = (self.scroll_offset + row as usize).min(last);
+        self.selected = index;
+
+        let range: HashSet<PathBuf> = (anchor.min(index).max(1)..=anchor.max(index))
+            .filter_map(|i| self.tree.get_node(i))
+            .map(|node| node.path.clone())
+            .collect();
+        self.drag_marked.retain(|path| {
+            let keep = range.contains(path);
+            if !keep {
+                self.marked.remove(path);
+            }
+            keep
+        });
+        for path in range {
+            if self.marked.insert(path.clone()) {
+                self.drag_marked.insert(path);
+            }
+        }
+    }
+
+    pub fn end_drag(&mut self) {
+        self.drag_anchor = None;
+        self.drag_marked.clear();
+    }
+
+    pub fn scroll_left(&mut self, columns: usize) {
+        self.tree_hscroll = self.tree_hscroll.saturating_sub(columns);
+    }
+
+    /// Scroll the tree right; the UI clamps this to the widest visible row
+    pub fn scroll_right(&mut self, columns: usize) {
+        self.tree_hscroll += columns;
+    }
+
+    /// Select the entry at the position of `row` (0-based, within the tree rows)
+    /// along the scrollbar track
+    pub fn scrollbar_jump(&mut self, row: u16) {
+        let track = self.tree_visible_rows.saturating_sub(1).max(1);
+        let last = self.tree.len().saturating_sub(1);
+        self.selected = (row as usize).min(track) * last / track;
+    }
+
+    pub fn scroll_up(&mut self, lines: usize) {
+        for _ in 0..lines {
+            self.move_up();
+        }
+    }
+
+    pub fn scroll_down(&mut self, lines: usize) {
+        for _ in 0..lines {
+            self.move_down();
+        }
+    }
+
+    pub fn buffer_char(&mut self, c: char) {
+        let now = std::time::Instant::now();
+        let elapsed = now.duration_since(self.last_char_time).as_millis();
+        // If more than 50ms since last char, start new buffer
+        if elapsed > 50 {
+            self.drop_buffer.clear();
+        }
+        self.drop_buffer.push(c);
+        self.last_char_time = now;
+    }
+
+    pub fn check_drop_buffer(&mut self) {
+        if self.drop_buffer.is_empty() {
+            return;
+        }
+
+        let elapsed = std::time::Instant::now()
+            .duration_since(self.last_char_time)
+            .as_millis();
+
+        // Wait for input to stop (100ms)
+        if elapsed < 100 {
+            return;
+        }
+
+        let text = self.drop_buffer.trim().to_string();
+        self.drop_buffer.clear();
+
+        // Normalize the path: remove quotes and unescape backslashes
+        let normalized = Self::normalize_dropped_path(&text);
+
+        // Check if it's an absolute path that exists
+        if normalized.starts_with('/') {
+            let path = PathBuf::from(&normalized);
+            if path.exists() {
+                if let Some(dest_dir) = self.get_paste_destination() {
+                    match file_ops::copy_file(&path, &dest_dir) {
+                        Ok(_) => {
+                            self.message = Some(format!(
+                                "Dropped: {}",
+                                path.file_name().unwrap_or_default().to_string_lossy()
+                            ));
+                            self.schedule_refresh([dest_dir]);
+                        }
+                        Err(e) => {
+                            self.message = Some(format!("Copy error: {}", e));
+                        }
+                    }
+                }
+                return;
+            }
+        }
+
+        // Not a valid path, treat first char as command
+        if let Some(rest) = text.strip_prefix('/') {
+            // Start search with remaining chars
+            self.input_buffer = rest.to_string();
+            self.input_mode = InputMode::Search;
+        }
+    }
+
+    /// Normalize a dropped path by removing quotes and unescaping backslashes
+    fn normalize_dropped_path(text: &str) -> String {
+        let text = text.trim();
+
+        // Remove surrounding quotes if present
+        let text = if (text.starts_with('\'') && text.ends_with('\''))
+            || (text.starts_with('"') && text.ends_with('"'))
+        {
+            &text[1..text.len() - 1]
+        } else {
+            text
+        };
+
+        // Unescape backslash-escaped characters (e.g., "\ " -> " ")
+        let mut result = String::with_capacity(text.len());
+        let mut chars = text.chars().peekable();
+        while let Some(c) = chars.next() {
+            if c == '\\' {
+                if let Some(&next) = chars.peek() {
+                    // Common escaped characters in shell paths
+                    if matches!(
+                        next,
+                        ' ' | '\''
+                            | '"'
+                            | '\\'
+                            | '('
+                            | ')'
+                            | '['
+                            | ']'
+                            | '&'
+                            | ';'
+                            | '!'
+                            | '$'
+                            | '`'
+                    ) {
+                        result.push(chars.next().unwrap());
+                        continue;
+                    }
+                }
+            }
+            result.push(c);
+        }
+        result
+    }
+
+    fn try_handle_as_drop(&mut self) -> bool {
+        let text = self.input_buffer.trim();
+        // Normalize the path (remove quotes, unescape)
+        let normalized = Self::normalize_dropped_path(text);
+
+        // Check if it looks like an absolute path
+        if !normalized.starts_with('/') {
+            return false;
+        }
+
+        // Try as single path first
+        let path = PathBuf::from(&normalized);
+        if path.exists() {
+            let dest_dir = match self.get_paste_destination() {
+                Some(dir) => dir,
+                None => {
+                    self.message = Some("No destination".to_string());
+                    return false;
+                }
+            };
+
+            match file_ops::copy_file(&path, &dest_dir) {
+                Ok(_) => {
+                    self.message = Some(format!(
+                        "Dropped: {}",
+                        path.file_name().unwrap_or_default().to_string_lossy()
+                    ));
+                    self.schedule_refresh([dest_dir]);
+                    return true;
+                }
+                Err(e) => {
+                    self.message = Some(format!("Copy error: {}", e));
+                    return false;
+                }
+            }
+        }
+
+        // Try parsing multiple paths
+        let paths = Self::parse_dropped_paths(text);
+        if paths.is_empty() {
+            return false;
         }
-        if self.selected >= self.tree.len() {
-            self.selected = self.tree.len().saturating_sub(1);
+
+        let dest_dir = match self.get_paste_destination() {
+            Some(dir) => dir,
+            None => return false,
+        };
+
+        let mut success = 0;
+        for path in &paths {
+            if file_ops::copy_file(path, &dest_dir).is_ok() {
+                success += 1;
+            }
+        }
+
+        if success > 0 {
+            self.message = Some(format!("Dropped {} item(s)", success));
+            self.schedule_refresh([dest_dir]);
+            true
+        } else {
+            false
         }
     }
 
-    pub fn collapse_all(&mut self) {
-        self.tree.collapse_all();
-        self.selected = 0;
-        self.scroll_offset = 0;
-        self.message = Some("Collapsed all".to_string());
+    pub fn handle_drop(&mut self, text: &str) {
+        // Parse dropped text as file paths
+        // Paths can be separated by newlines or spaces (with quotes for paths containing spaces)
+        let paths: Vec<PathBuf> = Self::parse_dropped_paths(text);
+
+        if paths.is_empty() {
+            return;
+        }
+
+        // Get destination directory
+        let dest_dir = match self.get_paste_destination() {
+            Some(dir) => dir,
+            None => return,
+        };
+
+        let mut success = 0;
+        for path in &paths {
+            if path.exists() && file_ops::copy_file(path, &dest_dir).is_ok() {
+                success += 1;
+            }
+        }
+
+        if success > 0 {
+            self.message = Some(format!("Dropped {} item(s)", success));
+            self.schedule_refresh([dest_dir]);
+        }
     }
 
-    pub fn expand_all(&mut self) {
-        if let Err(e) = self.tree.expand_all() {
-            self.message = Some(format!("Error: {}", e));
-        } else {
-            self.message = Some("Expanded all".to_string());
+    /// Copy the files listed in the system clipboard into the selected
+    /// directory, or save an image in the clipboard there as a PNG
+    pub fn paste_from_system_clipboard(&mut self) {
+        let Some(dest_dir) = self.get_paste_destination() else {
+            return;
+        };
+        let mut clip = match arboard::Clipboard::new() {
+            Ok(clip) => clip,
+            Err(_) => {
+                self.message = Some("Clipboard not available".to_string());
+                return;
+            }
+        };
+
+        let paths = clip
+            .get_text()
+            .map(|text| Self::parse_clipboard_paths(&text))
+            .unwrap_or_default();
+        if !paths.is_empty() {
+            let mut success = 0;
+            let mut errors = Vec::new();
+            for path in &paths {
+                match file_ops::copy_file(path, &dest_dir) {
+                    Ok(_) => success += 1,
+                    Err(e) => errors.push(e),
+                }
+            }
+            self.message = Some(match errors.first() {
+                None => format!("Pasted {} item(s) from the system clipboard", success),
+                Some(e) => format!("Pasted {} item(s), {} failed: {}", success, errors.len(), e),
+            });
+            self.schedule_refresh([dest_dir]);
+            return;
+        }
+
+        match clip.get_image() {
+            Ok(img) => {
+                let result = file_ops::write_png(
+                    &dest_dir,
+                    "clipboard",
+                    img.width as u32,
+                    img.height as u32,
+                    img.bytes.into_owned(),
+                );
+                match result {
+                    Ok(path) => {
+                        self.message = Some(format!(
+                            "Saved clipboard image as {}",
+                            path.file_name().unwrap_or_default().to_string_lossy()
+                        ));
+                        self.schedule_refresh([dest_dir]);
+                    }
+                    Err(e) => self.message = Some(format!("Failed to save image: {}", e)),
+                }
+            }
+            Err(_) => {
+                self.message = Some("System clipboard has no files or image".to_string());
+            }
         }
     }
 
-    fn format_hex_preview(bytes: &[u8], max_lines: usize) -> Vec<String> {
-        bytes
-            .chunks(16)
-            .take(max_lines)
-            .map(|chunk| {
-                let hex: Vec<String> = chunk.iter().map(|b| format!("{:02x}", b)).collect();
-                let ascii: String = chunk
-                    .iter()
-                    .map(|&b| {
-                        if b.is_ascii_graphic() || b == b' ' {
-                            b as char
-                        } else {
-                            '.'
-                        }
-                    })
-                    .collect();
-                format!("{:<48} {}", hex.join(" "), ascii)
+    /// Paths in clipboard text: `file://` URIs (one per line, as file managers
+    /// copy them) or plain paths like a terminal drop
+    fn parse_clipboard_paths(text: &str) -> Vec<PathBuf> {
+        let text = text.trim();
+        if !text.starts_with("file://") {
+            return Self::parse_dropped_paths(text);
+        }
+        text.lines()
+            .filter_map(|line| {
+                let line = line.trim().strip_prefix("file://")?;
+                // Skip an optional host ("file://localhost/...")
+                let line = &line[line.find('/')?..];
+                let bytes = percent_decode(line);
+                #[cfg(unix)]
+                let path = OsString::from_vec(bytes);
+                #[cfg(not(unix))]
+                let path = String::from_utf8_lossy(&bytes).into_owned();
+                Some(PathBuf::from(path))
             })
+            .filter(|path| path.exists())
             .collect()
     }
 
-    fn format_dir_preview(path: &Path) -> Vec<String> {
-        let mut lines = vec!["[Directory]".to_string(), String::new()];
-
-        if let Ok(entries) = std::fs::read_dir(path) {
-            let mut files = 0;
-            let mut dirs = 0;
-            let mut hidden = 0;
-            let mut total_size: u64 = 0;
-
-            for entry in entries.filter_map(|e| e.ok()) {
-                let name = entry.file_name();
-                let is_hidden = name.to_str().map(|s| s.starts_with('.')).unwrap_or(false);
+    fn parse_dropped_paths(text: &str) -> Vec<PathBuf> {
+        let mut paths = Vec::new();
+        let text = text.trim();
 
-                if is_hidden {
-                    hidden += 1;
+        // Try newline-separated first
+        if text.contains('\n') {
+            for line in text.lines() {
+                let normalized = Self::normalize_dropped_path(line);
+                if !normalized.is_empty() {
+                    let path = PathBuf::from(&normalized);
+                    if path.is_absolute() && path.exists() {
+                        paths.push(path);
+                    }
                 }
+            }
+            return paths;
+        }
 
-                if let Ok(meta) = entry.metadata() {
-                    if meta.is_dir() {
-                        dirs += 1;
+        // Single path or space-separated paths
+        // Handle quoted paths and escaped spaces
+        let mut chars = text.chars().peekable();
+        let mut current = String::new();
+        let mut in_quote = false;
+        let mut quote_char: Option<char> = None;
+
+        while let Some(c) = chars.next() {
+            match c {
+                '"' | '\'' => {
+                    if in_quote && Some(c) == quote_char {
+                        in_quote = false;
+                        quote_char = None;
+                    } else if !in_quote {
+                        in_quote = true;
+                        quote_char = Some(c);
                     } else {
-                        files += 1;
-                        total_size += meta.len();
+                        // Different quote inside quoted string
+                        current.push(c);
+                    }
+                }
+                '\\' if !in_quote => {
+                    // Handle escaped characters outside quotes
+                    if let Some(next) = chars.next() {
+                        current.push(next);
+                    }
+                }
+                ' ' if !in_quote => {
+                    if !current.is_empty() {
+                        let path = PathBuf::from(&current);
+                        if path.is_absolute() && path.exists() {
+                            paths.push(path);
+                        }
+                        current.clear();
                     }
                 }
+                _ => {
+                    current.push(c);
+                }
             }
+        }
 
-            lines.push(format!("  Files: {}", files));
-            lines.push(format!("  Directories: {}", dirs));
-            if hidden > 0 {
-                lines.push(format!("  Hidden: {}", hidden));
+        if !current.is_empty() {
+            let path = PathBuf::from(&current);
+            if path.is_absolute() && path.exists() {
+                paths.push(path);
             }
-            lines.push(format!("  Size: {}", Self::format_size(total_size)));
         }
 
-        lines
+        paths
     }
 
-    fn format_size(bytes: u64) -> String {
-        const KB: u64 = 1024;
-        const MB: u64 = KB * 1024;
-        const GB: u64 = MB * 1024;
+    /// Command of the first `open_with` rule matching the name of the file at `path`
+    pub fn open_rule_command(&self, path: &Path) -> Option<&String> {
+        if path.is_dir() {
+            return None;
+        }
+        matching_rule(&self.config.open_with, path).map(|rule| &rule.command)
+    }
 
-        if bytes >= GB {
-            format!("{:.1} GB", bytes as f64 / GB as f64)
-        } else if bytes >= MB {
-            format!("{:.1} MB", bytes as f64 / MB as f64)
-        } else if bytes >= KB {
-            format!("{:.1} KB", bytes as f64 / KB as f64)
+    /// Whether one of the marked files (or the selected file) has an `open_with` rule
+    pub fn has_open_rule(&self) -> bool {
+        self.get_selected_paths()
+            .iter()
+            .any(|path| self.open_rule_command(path).is_some())
+    }
+
+    /// Start the configured previewer for `path` in the background after
+    /// `delay`, if one matches. Dropping the job before then skips it.
+    fn start_previewer(&self, path: &Path, delay: std::time::Duration) -> Option<CommandJob> {
+        let rule = matching_rule(&self.config.previewers, path)?;
+        let command = Self::expand_placeholders(&rule.command, path, &self.tree.root.path);
+        Some(Job::spawn(move |cancel| {
+            let start = std::time::Instant::now() + delay;
+            while std::time::Instant::now() < start {
+                if cancel.load(Ordering::Relaxed) {
+                    return Err(std::io::ErrorKind::Interrupted.into());
+                }
+                std::thread::sleep(std::time::Duration::from_millis(10));
+            }
+            capture::run(&command, cancel)
+        }))
+    }
+
+    /// Take a finished previewer out of its slot, with the file it previewed
+    fn finished_previewer(
+        slot: &mut Option<(PathBuf, CommandJob)>,
+    ) -> Option<(PathBuf, std::io::Result<CommandOutput>)> {
+        let result = match slot.as_ref()?.1.poll() {
+            Ok(Some(result)) => result,
+            Ok(None) => return None,
+            Err(()) => Err(std::io::Error::other("previewer crashed")),
+        };
+        slot.take().map(|(path, _)| (path, result))
+    }
+
+    /// Preview lines from a previewer's output, with escape sequences removed
+    fn previewer_lines(result: std::io::Result<CommandOutput>) -> Vec<String> {
+        match result {
+            Ok(output) if output.lines.is_empty() && !output.status.success() => {
+                vec![format!("[Previewer failed: {}]", output.status)]
+            }
+            Ok(output) => {
+                let mut lines: Vec<String> = output
+                    .lines
+                    .iter()
+                    .map(|line| preview::strip_ansi(line))
+                    .collect();
+                if output.truncated {
+                    lines.push("[Output truncated]".to_string());
+                }
+                lines
+            }
+            Err(e) => vec![format!("[Previewer failed: {}]", e)],
+        }
+    }
+
+    /// Replace `<filepath>`, `<dir>`, `<name>`, `<stem>`, `<ext>` and `<root>`
+    /// in a command template with shell-quoted parts of `path` (byte for byte).
+    /// Other `<...>` text is left alone.
+    fn expand_placeholders(template: &str, path: &Path, root: &Path) -> OsString {
+        let empty = OsStr::new("");
+        let mut command = OsString::new();
+        let mut rest = template;
+        while let Some(start) = rest.find('<') {
+            command.push(&rest[..start]);
+            rest = &rest[start..];
+            let placeholder = rest.find('>').map(|end| &rest[1..end]);
+            let value = match placeholder {
+                Some("filepath") => Some(path.as_os_str()),
+                Some("dir") => Some(path.parent().unwrap_or(path).as_os_str()),
+                Some("name") => Some(path.file_name().unwrap_or(empty)),
+                Some("stem") => Some(path.file_stem().unwrap_or(empty)),
+                Some("ext") => Some(path.extension().unwrap_or(empty)),
+                Some("root") => Some(root.as_os_str()),
+                _ => None,
+            };
+            match (placeholder, value) {
+                (Some(name), Some(value)) => {
+                    command.push(Self::shell_quote(value));
+                    rest = &rest[name.len() + 2..];
+                }
+                _ => {
+                    command.push("<");
+                    rest = &rest[1..];
+                }
+            }
+        }
+        command.push(rest);
+        command
+    }
+
+    /// Run a command on the marked files, or the selected one, asking first if
+    /// they are more than `confirm.command_files`
+    pub fn execute_external_command(&mut self, command_override: Option<String>) {
+        let mut paths = self.get_selected_paths();
+        if paths.is_empty() {
+            self.message = Some("No file selected".to_string());
+            return;
+        }
+        paths.sort();
+        let run = CommandRun {
+            command: command_override,
+            paths,
+        };
+        let limit = self.config.confirm.command_files;
+        if limit > 0 && run.paths.len() > limit {
+            self.input_mode = InputMode::Confirm(ConfirmAction::Command(run));
         } else {
-            format!("{} B", bytes)
+            self.run_external_command(run);
         }
     }
 
-    fn copy_to_system_clipboard(&mut self, text: &str) {
-        match arboard::Clipboard::new() {
-            Ok(mut clip) => {
-                if clip.set_text(text).is_ok() {
-                    self.message = Some(format!("Copied: {}", text));
-                } else {
-                    self.message = Some("Failed to copy to clipboard".to_string());
+    /// Run `run`'s command once for each of its files, one after another
+    pub fn run_external_command(&mut self, run: CommandRun) {
+        let command_override = run.command;
+        // Each file takes the typed command, else its `open_with` rule, else the
+        // last or default command; files sharing a command run as one group
+        let mut groups: Vec<(&String, Vec<&PathBuf>)> = Vec::new();
+        let mut without_command = 0;
+        for path in &run.paths {
+            let template = command_override
+                .as_ref()
+                .or(self.open_rule_command(path))
+                .or(self.last_command.as_ref())
+                .or(self.default_command.as_ref());
+            match template {
+                Some(template) => match groups.iter_mut().find(|(t, _)| *t == template) {
+                    Some((_, group)) => group.push(path),
+                    None => groups.push((template, vec![path])),
+                },
+                None => without_command += 1,
+            }
+        }
+        if groups.is_empty() {
+            self.message = Some("No command available. Enter a command first.".to_string());
+            return;
+        }
+
+        let mut foreground = Vec::new();
+        let mut captured = Vec::new();
+        let mut detached = Vec::new();
+        for (template, group) in groups {
+            // A leading `!` captures the output and a leading `@` runs the command
+            // attached to the terminal, instead of running it detached
+            let (scripts, template) = match template.chars().next() {
+                Some('!') => (&mut captured, template[1..].trim_start()),
+                Some('@') => (&mut foreground, template[1..].trim_start()),
+                _ => (&mut detached, template.as_str()),
+            };
+            let commands = group
+                .into_iter()
+                .map(|path| Self::expand_placeholders(template, path, &self.tree.root.path));
+            scripts.push(Self::join_commands(commands));
+        }
+
+        let mut message = None;
+        for command in &detached {
+            // Execute the command with stdout/stderr redirected to null to prevent terminal corruption
+            let spawned = std::process::Command::new("sh")
+                .arg("-c")
+                .arg(command)
+                .stdin(std::process::Stdio::null())
+                .stdout(std::process::Stdio::null())
+                .stderr(std::process::Stdio::null())
+                .spawn();
+            message = Some(match spawned {
+                Ok(_) => format!("Executed: {}", command.to_string_lossy()),
+                Err(e) => {
+                    self.message = Some(format!("Command failed: {}", e));
+                    return;
                 }
+            });
+        }
+        if !captured.is_empty() {
+            let command = Self::join_commands(captured);
+            message = Some(format!("Running: {}", command.to_string_lossy()));
+            let script = command.clone();
+            let job = Job::spawn(move |cancel| capture::run(&script, cancel));
+            self.command_job = Some((command, job));
+        }
+        if !foreground.is_empty() {
+            self.pending_foreground = Some(Self::join_commands(foreground));
+        }
+        if without_command > 0 {
+            message = Some(format!("No command for {} file(s)", without_command));
+        }
+        if message.is_some() {
+            self.message = message;
+        }
+        // Save the command for next time
+        if let Some(cmd) = command_override {
+            self.last_command = Some(cmd);
+        }
+    }
+
+    /// Join shell commands to run one after another
+    fn join_commands(commands: impl IntoIterator<Item = OsString>) -> OsString {
+        let mut script = OsString::new();
+        for (i, command) in commands.into_iter().enumerate() {
+            if i > 0 {
+                script.push("; ");
             }
-            Err(_) => {
-                self.message = Some("Clipboard not available".to_string());
+            script.push(command);
+        }
+        script
+    }
+
+    /// Finish picker mode with the current selection.
+    /// A selected directory is expanded/collapsed instead of being chosen.
+    pub fn choose_selection(&mut self) {
+        let mode = match self.picker {
+            Some(mode) => mode,
+            None => return,
+        };
+
+        if mode == PickerMode::Multiple && !self.marked.is_empty() {
+            let mut paths: Vec<PathBuf> = self.marked.iter().cloned().collect();
+            paths.sort();
+            self.chosen = paths;
+        } else {
+            match self.tree.get_node(self.selected) {
+                Some(node) if node.is_dir => {
+                    self.toggle_expand();
+                    return;
+                }
+                Some(node) => self.chosen = vec![node.path.clone()],
+                None => return,
             }
         }
+
+        self.should_quit = true;
     }
 
-    pub fn copy_path(&mut self) {
-        if let Some(node) = self.tree.get_node(self.selected) {
-            let path_str = node.path.to_string_lossy().to_string();
-            self.copy_to_system_clipboard(&path_str);
+    pub fn start_external_command(&mut self) {
+        self.input_buffer.clear();
+        self.history_index = None;
+        self.input_mode = InputMode::ExternalCommand;
+    }
+
+    /// History that Up / Down walk through in the current prompt
+    fn prompt_history(&self) -> &[String] {
+        match self.input_mode {
+            InputMode::MoveTo | InputMode::CopyTo => &self.destination_history,
+            _ => &self.command_history,
         }
     }
 
-    pub fn copy_filename(&mut self) {
-        if let Some(node) = self.tree.get_node(self.selected) {
-            let name = node.name.clone();
-            self.copy_to_system_clipboard(&name);
+    pub fn history_prev(&mut self) {
+        if self.prompt_history().is_empty() {
+            return;
+        }
+
+        let new_index = match self.history_index {
+            None => Some(self.prompt_history().len() - 1),
+            Some(0) => Some(0), // Already at oldest
+            Some(i) => Some(i - 1),
+        };
+
+        if let Some(idx) = new_index {
+            self.input_buffer = self.prompt_history()[idx].clone();
+            self.history_index = new_index;
         }
     }
 
-    pub fn preview_file(&mut self) {
-        if let Some(node) = self.tree.get_node(self.selected) {
-            if node.is_dir {
-                self.message = Some("Cannot preview directory".to_string());
-                return;
-            }
-
-            let path = node.path.clone();
-
-            // Check if it's an image file
-            if Self::is_image_file(&path) {
-                match self.load_image_preview(&path) {
-                    Ok(()) => return,
-                    Err(e) => {
-                        self.message = Some(format!("Image error: {}", e));
-                        // Fall through to binary preview
-                    }
-                }
-            }
-
-            match std::fs::read_to_string(&path) {
-                Ok(content) => {
-                    self.preview_content = content.lines().map(|s| s.to_string()).collect();
-                    self.preview_scroll = 0;
-                    self.preview_path = Some(path);
-                    self.image_preview = None;
-                    self.input_mode = InputMode::Preview;
-                }
-                Err(e) => {
-                    // Try to read as binary and show hex preview
-                    if let Ok(bytes) = std::fs::read(&path) {
-                        self.preview_content = Self::format_hex_preview(&bytes, 100);
-                        self.preview_scroll = 0;
-                        self.preview_path = Some(path);
-                        self.image_preview = None;
-                        self.input_mode = InputMode::Preview;
-                    } else {
-                        self.message = Some(format!("Cannot read file: {}", e));
-                    }
-                }
+    /// Start a reverse search, or jump to the next older match if one is running
+    pub fn history_search_older(&mut self) {
+        match self.history_search.take() {
+            None => self.history_search = Some(HistorySearch::default()),
+            Some(search) => {
+                let before = search.index.unwrap_or(self.command_history.len());
+                let index = self.find_in_history(&search.query, before).or(search.index);
+                self.history_search = Some(HistorySearch { index, ..search });
             }
         }
     }
 
-    fn is_image_file(path: &Path) -> bool {
-        let ext = path
-            .extension()
-            .and_then(|e| e.to_str())
-            .map(|e| e.to_lowercase());
-        matches!(
-            ext.as_deref(),
-            Some("png" | "jpg" | "jpeg" | "gif" | "webp" | "bmp")
-        )
+    pub fn history_search_push(&mut self, c: char) {
+        if let Some(mut search) = self.history_search.take() {
+            search.query.push(c);
+            // Keep the current match while it still matches
+            let before = search.index.map_or(self.command_history.len(), |i| i + 1);
+            search.index = self.find_in_history(&search.query, before);
+            self.history_search = Some(search);
+        }
     }
 
-    fn load_image_preview(&mut self, path: &Path) -> Result<(), String> {
-        let img = image::open(path).map_err(|e| e.to_string())?;
-        let img = img.to_rgb8();
-        let (width, height) = img.dimensions();
-        let pixels: Vec<(u8, u8, u8)> = img.pixels().map(|p| (p[0], p[1], p[2])).collect();
+    pub fn history_search_pop(&mut self) {
+        if let Some(mut search) = self.history_search.take() {
+            search.query.pop();
+            search.index = self.find_in_history(&search.query, self.command_history.len());
+            self.history_search = Some(search);
+        }
+    }
 
-        self.image_preview = Some(ImagePreview {
-            width,
-            height,
-            pixels,
-        });
-        self.preview_path = Some(path.to_path_buf());
-        self.preview_content.clear();
-        self.preview_scroll = 0;
-        self.input_mode = InputMode::Preview;
-        Ok(())
+    /// End the search, putting the match (if any) into the input
+    pub fn accept_history_search(&mut self) {
+        if let Some(index) = self.history_search.take().and_then(|s| s.index) {
+            self.input_buffer = self.command_history[index].clone();
+            self.history_index = Some(index);
+        }
     }
 
-    pub fn close_preview(&mut self) {
-        self.input_mode = InputMode::Normal;
-        self.preview_content.clear();
-        self.preview_path = None;
-        self.preview_scroll = 0;
-        self.image_preview = None;
+    pub fn cancel_history_search(&mut self) {
+        self.history_search = None;
     }
 
-    pub fn toggle_quick_preview(&mut self) {
-        self.quick_preview_enabled = !self.quick_preview_enabled;
-        if self.quick_preview_enabled {
-            self.update_quick_preview();
-        } else {
-            self.quick_preview_content.clear();
-            self.quick_preview_path = None;
-            self.quick_preview_scroll = 0;
-            self.quick_preview_image = None;
+    /// Newest history entry before `before` containing `query`
+    fn find_in_history(&self, query: &str, before: usize) -> Option<usize> {
+        if query.is_empty() {
+            return None;
         }
+        (0..before.min(self.command_history.len()))
+            .rev()
+            .find(|&i| self.command_history[i].contains(query))
     }
 
-    pub fn update_quick_preview(&mut self) {
-        if !self.quick_preview_enabled {
+    pub fn history_next(&mut self) {
+        if self.prompt_history().is_empty() {
             return;
         }
 
-        let node = match self.tree.get_node(self.selected) {
-            Some(n) => n,
-            None => return,
+        let new_index = match self.history_index {
+            None => None,
+            Some(i) if i + 1 >= self.prompt_history().len() => {
+                // Back to empty input
+                self.input_buffer.clear();
+                None
+            }
+            Some(i) => Some(i + 1),
         };
 
-        if node.is_dir {
-            self.quick_preview_content = Self::format_dir_preview(&node.path);
-            self.quick_preview_path = Some(node.path.clone());
-            self.quick_preview_scroll = 0;
-            self.quick_preview_image = None;
-            return;
+        if let Some(idx) = new_index {
+            self.input_buffer = self.prompt_history()[idx].clone();
         }
+        self.history_index = new_index;
+    }
+}
 
-        let path = node.path.clone();
+/// The directory a destination prompt names: `~` is the home directory and
+/// relative paths start at `base`
+fn resolve_destination(input: &str, base: &Path) -> PathBuf {
+    let home = std::env::var_os("HOME").map(PathBuf::from);
+    let path = match (input.strip_prefix('~'), home) {
+        (Some(""), Some(home)) => home,
+        (Some(rest), Some(home)) if rest.starts_with('/') => {
+            home.join(rest.trim_start_matches('/'))
+        }
+        _ => PathBuf::from(input),
+    };
+    base.join(path)
+}
 
-        // Check if it's the same file
-        if self.quick_preview_path.as_ref() == Some(&path) {
-            return;
+/// Complete the last component of `input` to the directories whose name starts
+/// with it. A single match is completed with a trailing `/`; several are
+/// completed to their common prefix. Returns the new input and the matching names.
+fn complete_directory(input: &str, base: &Path) -> (String, Vec<String>) {
+    let (dir, prefix) = match input.rfind('/') {
+        Some(i) => input.split_at(i + 1),
+        None => ("", input),
+    };
+    let Ok(entries) = fs::read_dir(resolve_destination(dir, base)) else {
+        return (input.to_string(), Vec::new());
+    };
+    let mut names: Vec<String> = entries
+        .filter_map(|e| e.ok())
+        .filter(|e| e.path().is_dir())
+        .map(|e| e.file_name().to_string_lossy().to_string())
+        .filter(|name| {
+            name.starts_with(prefix) && (prefix.starts_with('.') || !name.starts_with('.'))
+        })
+        .collect();
+    names.sort();
+
+    let completed = match names.as_slice() {
+        [] => return (input.to_string(), names),
+        [name] => format!("{}{}/", dir, name),
+        [first, rest @ ..] => {
+            let common = rest.iter().fold(first.as_str(), |common, name| {
+                let len = common
+                    .char_indices()
+                    .zip(name.chars())
+                    .find(|((_, a), b)| a != b)
+                    .map_or(common.len().min(name.len()), |((i, _), _)| i);
+                &common[..len]
+            });
+            format!("{}{}", dir, common)
         }
+    };
+    (completed, names)
+}
 
-        // Check if it's an image file
-        if Self::is_image_file(&path) {
-            if let Ok(img) = image::open(&path) {
-                let img = img.to_rgb8();
-                let (width, height) = img.dimensions();
-                let pixels: Vec<(u8, u8, u8)> = img.pixels().map(|p| (p[0], p[1], p[2])).collect();
-
-                self.quick_preview_image = Some(ImagePreview {
-                    width,
-                    height,
-                    pixels,
-                });
-                self.quick_preview_content.clear();
-                self.quick_preview_path = Some(path);
-                self.quick_preview_scroll = 0;
-                return;
-            }
+/// First rule whose pattern matches the file name of `path` (case-insensitive)
+fn matching_rule<'a>(
+    rules: &'a [config::CommandRule],
+    path: &Path,
+) -> Option<&'a config::CommandRule> {
+    let name = path.file_name()?.to_string_lossy().to_lowercase();
+    rules
+        .iter()
+        .find(|rule| glob_match(&rule.pattern.to_lowercase(), &name))
+}
+
+/// `file://` URI of an absolute path, percent-encoding all but unreserved characters and `/`
+fn file_uri(path: &Path) -> String {
+    let mut uri = String::from("file://");
+    for &byte in path.as_os_str().as_encoded_bytes() {
+        if byte.is_ascii_alphanumeric() || b"/-._~".contains(&byte) {
+            uri.push(byte as char);
+        } else {
+            uri.push_str(&format!("%{:02X}", byte));
         }
+    }
+    uri
+}
 
-        // Try to read as text
-        self.quick_preview_image = None;
-        match std::fs::read_to_string(&path) {
-            Ok(content) => {
-                self.quick_preview_content = content.lines().map(|s| s.to_string()).collect();
+/// Decode `%XX` escapes in a URI path into raw bytes
+fn percent_decode(text: &str) -> Vec<u8> {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .and_then(|h| std::str::from_utf8(h).ok())
+            .and_then(|h| u8::from_str_radix(h, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
             }
-            Err(_) => {
-                // Try to read as binary and show hex preview
-                if let Ok(bytes) = std::fs::read(&path) {
-                    self.quick_preview_content = Self::format_hex_preview(&bytes, 50);
-                } else {
-                    self.quick_preview_content = vec!["[Cannot read file]".to_string()];
-                }
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
             }
         }
-        self.quick_preview_path = Some(path);
-        self.quick_preview_scroll = 0;
     }
+    decoded
+}
 
-    #[allow(dead_code)]
-    pub fn quick_preview_scroll_up(&mut self) {
-        if self.quick_preview_scroll > 0 {
-            self.quick_preview_scroll -= 1;
-        }
-    }
+/// Convert days since the Unix epoch to a (year, month, day) date
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    // Howard Hinnant's days_from_civil inverse
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
 
-    #[allow(dead_code)]
-    pub fn quick_preview_scroll_down(&mut self, visible_height: usize) {
-        if self.quick_preview_scroll + visible_height < self.quick_preview_content.len() {
-            self.quick_preview_scroll += 1;
-        }
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_jump_labels_single_char() {
+        let labels = App::jump_labels(12);
+        assert_eq!(labels.len(), 12);
+        assert_eq!(labels[0], "1");
+        assert_eq!(labels[9], "0");
+        assert_eq!(labels[10], "q");
     }
 
-    pub fn preview_scroll_up(&mut self) {
-        if self.preview_scroll > 0 {
-            self.preview_scroll -= 1;
-        }
+    #[test]
+    fn test_jump_labels_two_chars_are_unique() {
+        let count = JUMP_LABEL_CHARS.len() + 5;
+        let labels = App::jump_labels(count);
+        assert!(labels.iter().all(|l| l.chars().count() == 2));
+        let unique: HashSet<&String> = labels.iter().collect();
+        assert_eq!(unique.len(), count);
     }
 
-    pub fn preview_scroll_down(&mut self, visible_height: usize) {
-        if self.preview_scroll + visible_height < self.preview_content.len() {
-            self.preview_scroll += 1;
+    #[test]
+    fn test_git_status_loads_in_background() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        std::process::Command::new("git")
+            .args(["init", "-q"])
+            .current_dir(&root)
+            .output()
+            .unwrap();
+        fs::write(root.join("new.txt"), "").unwrap();
+
+        let mut app = App::new(&root, Config::default()).unwrap();
+        assert!(app.git_job.is_some());
+        for _ in 0..200 {
+            app.poll_jobs();
+            if app.git_job.is_none() {
+                break;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(5));
         }
+        assert!(app.git_job.is_none());
+        assert_eq!(
+            app.git_repo.get_status(&root.join("new.txt")),
+            GitStatus::Untracked
+        );
     }
 
-    pub fn preview_page_up(&mut self, visible_height: usize) {
-        self.preview_scroll = self.preview_scroll.saturating_sub(visible_height);
+    #[test]
+    fn test_scheduled_refresh_is_debounced() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        let config = Config {
+            git: false,
+            ..Config::default()
+        };
+        let mut app = App::new(&root, config).unwrap();
+        assert_eq!(app.tree.len(), 1);
+
+        fs::write(root.join("a.txt"), "").unwrap();
+        app.schedule_refresh([root.clone()]);
+        fs::write(root.join("b.txt"), "").unwrap();
+        app.schedule_refresh([root.clone()]);
+        app.poll_jobs();
+        assert_eq!(app.tree.len(), 1);
+
+        std::thread::sleep(REFRESH_DEBOUNCE);
+        app.poll_jobs();
+        assert_eq!(app.tree.len(), 3);
+        assert!(app.refresh_due.is_none() && app.dirty_dirs.is_empty());
     }
 
-    pub fn preview_page_down(&mut self, visible_height: usize) {
-        let max_scroll = self.preview_content.len().saturating_sub(visible_height);
-        self.preview_scroll = (self.preview_scroll + visible_height).min(max_scroll);
+    #[test]
+    fn test_focus_gained_reloads_and_keeps_selection() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        fs::write(root.join("b.txt"), "").unwrap();
+        let config = Config {
+            git: false,
+            ..Config::default()
+        };
+        let mut app = App::new(&root, config).unwrap();
+        app.select_path(&root.join("b.txt"));
+
+        fs::write(root.join("a.txt"), "").unwrap();
+        app.input_mode = InputMode::Rename;
+        app.focus_gained();
+        assert_eq!(app.tree.len(), 2);
+
+        app.input_mode = InputMode::Normal;
+        app.focus_gained();
+        assert_eq!(app.tree.len(), 3);
+        assert_eq!(app.tree.get_node(app.selected).unwrap().name, "b.txt");
     }
 
-    pub fn handle_click(&mut self, row: u16) {
-        let index = self.scroll_offset + row as usize;
-        if index >= self.tree.len() {
-            return;
-        }
-
-        let now = std::time::Instant::now();
-        let is_double_click = self.last_click_index == Some(index)
-            && now.duration_since(self.last_click_time).as_millis() < 400;
-
-        self.selected = index;
-        self.last_click_time = now;
-        self.last_click_index = Some(index);
-
-        if is_double_click {
-            self.toggle_expand();
+    #[test]
+    fn test_resize_keeps_selection_visible() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        for i in 0..30 {
+            fs::write(root.join(format!("{:02}.txt", i)), "").unwrap();
         }
+        let config = Config {
+            git: false,
+            ..Config::default()
+        };
+        let mut app = App::new(&root, config).unwrap();
+        app.selected = 20;
+        app.adjust_scroll(25);
+        assert_eq!(app.scroll_offset, 0);
+
+        app.handle_resize(15);
+        assert_eq!(app.tree_area_height, 10);
+        assert_eq!(app.scroll_offset, 11);
     }
 
-    pub fn scroll_up(&mut self, lines: usize) {
-        for _ in 0..lines {
-            self.move_up();
-        }
+    #[test]
+    fn test_preview_search() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        let mut content: Vec<String> = (0..40).map(|i| format!("line {}", i)).collect();
+        content[5] = "Needle and NEEDLE".to_string();
+        content[30] = "needle".to_string();
+        fs::write(root.join("a.txt"), content.join("\n")).unwrap();
+        let config = Config {
+            git: false,
+            ..Config::default()
+        };
+        let mut app = App::new(&root, config).unwrap();
+        app.open_preview(root.join("a.txt"));
+        assert_eq!(app.input_mode, InputMode::Preview);
+
+        app.start_preview_search();
+        app.input_buffer = "needle".to_string();
+        app.search_preview(10);
+        assert_eq!(app.input_mode, InputMode::Preview);
+        assert_eq!(
+            app.preview_matches,
+            vec![(5, 0..6), (5, 11..17), (30, 0..6)]
+        );
+        assert_eq!(app.preview_match, 0);
+        assert_eq!(app.preview_scroll, 0);
+
+        app.preview_search_next(true, 10);
+        app.preview_search_next(true, 10);
+        assert_eq!(app.preview_match, 2);
+        assert_eq!(app.preview_scroll, 25);
+        app.preview_search_next(true, 10);
+        assert_eq!(app.preview_match, 0);
+        assert!(app
+            .message
+            .as_deref()
+            .unwrap()
+            .contains("continuing at top"));
+        app.preview_search_next(false, 10);
+        assert_eq!(app.preview_match, 2);
+
+        app.start_preview_search();
+        app.input_buffer = "missing".to_string();
+        app.search_preview(10);
+        assert!(app.preview_matches.is_empty());
+        assert_eq!(app.message.as_deref(), Some("Pattern not found: missing"));
+
+        app.close_preview();
+        assert!(app.preview_query.is_empty());
     }
 
-    pub fn scroll_down(&mut self, lines: usize) {
-        for _ in 0..lines {
-            self.move_down();
-        }
+    #[test]
+    fn test_changed_preview_reloads_in_place() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        let file = root.join("out.log");
+        let lines: Vec<String> = (0..20).map(|i| format!("line {}", i)).collect();
+        fs::write(&file, lines.join("\n")).unwrap();
+        let config = Config {
+            git: false,
+            ..Config::default()
+        };
+        let mut app = App::new(&root, config).unwrap();
+        app.select_path(&file);
+        app.toggle_quick_preview();
+        assert_eq!(app.quick_preview_content.len(), 20);
+        app.open_preview(file.clone());
+        app.preview_scroll = 5;
+        app.reload_changed_previews();
+        assert_eq!(app.preview_content.len(), 20);
+
+        fs::write(&file, format!("{}\nline 20", lines.join("\n"))).unwrap();
+        let later = SystemTime::now() + std::time::Duration::from_secs(5);
+        fs::File::options()
+            .write(true)
+            .open(&file)
+            .unwrap()
+            .set_modified(later)
+            .unwrap();
+        app.reload_changed_previews();
+        assert_eq!(app.preview_content.len(), 21);
+        assert_eq!(app.preview_scroll, 5);
+        assert_eq!(app.quick_preview_content.len(), 21);
+        assert_eq!(app.preview_mtime, Some(later));
     }
 
-    pub fn buffer_char(&mut self, c: char) {
-        let now = std::time::Instant::now();
-        let elapsed = now.duration_since(self.last_char_time).as_millis();
-        // If more than 50ms since last char, start new buffer
-        if elapsed > 50 {
-            self.drop_buffer.clear();
+    #[test]
+    fn test_compare_copies_missing_entry_across() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        fs::create_dir_all(root.join("a/sub")).unwrap();
+        fs::create_dir_all(root.join("b/sub")).unwrap();
+        fs::write(root.join("a/sub/new.txt"), "new").unwrap();
+        let config = Config {
+            git: false,
+            ..Config::default()
+        };
+        let mut app = App::new(&root, config).unwrap();
+
+        app.marked.insert(root.join("a"));
+        app.start_compare();
+        assert_eq!(app.input_mode, InputMode::Normal);
+
+        app.marked.insert(root.join("b"));
+        app.start_compare();
+        assert_eq!(app.input_mode, InputMode::Compare);
+        for _ in 0..200 {
+            app.poll_jobs();
+            if app.compare_job.is_none() {
+                break;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(5));
         }
-        self.drop_buffer.push(c);
-        self.last_char_time = now;
+        assert_eq!(app.compare_entries.len(), 1);
+        assert_eq!(app.compare_entries[0].difference, Difference::OnlyInA);
+
+        app.copy_compare_entry();
+        assert!(app.copy_job.is_some());
+        for _ in 0..200 {
+            app.poll_jobs();
+            if app.copy_job.is_none() {
+                break;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(5));
+        }
+        assert_eq!(
+            fs::read_to_string(root.join("b/sub/new.txt")).unwrap(),
+            "new"
+        );
+        assert!(app.compare_entries.is_empty());
+        assert_eq!(app.input_mode, InputMode::Compare);
     }
 
-    pub fn check_drop_buffer(&mut self) {
-        if self.drop_buffer.is_empty() {
-            return;
-        }
+    #[test]
+    #[cfg(unix)]
+    fn test_file_info_lists_metadata() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        fs::write(root.join("notes.md"), "hello").unwrap();
+        std::os::unix::fs::symlink(root.join("missing"), root.join("dangling")).unwrap();
+        let config = Config {
+            git: false,
+            ..Config::default()
+        };
+        let mut app = App::new(&root, config).unwrap();
+        let value = |app: &App, label: &str| {
+            app.file_info
+                .iter()
+                .find(|(l, _)| *l == label)
+                .map(|(_, v)| v.clone())
+        };
 
-        let elapsed = std::time::Instant::now()
-            .duration_since(self.last_char_time)
-            .as_millis();
+        app.select_path(&root.join("notes.md"));
+        app.open_file_info();
+        assert_eq!(app.input_mode, InputMode::FileInfo);
+        assert_eq!(value(&app, "Size").unwrap(), "5 B (5 bytes)");
+        assert_eq!(value(&app, "MIME type").unwrap(), "text/markdown");
+        assert_eq!(value(&app, "Links").unwrap(), "1");
+        assert_eq!(value(&app, "Target"), None);
+        assert_eq!(value(&app, "Git"), None);
+
+        app.input_mode = InputMode::Normal;
+        app.select_path(&root.join("dangling"));
+        app.open_file_info();
+        assert!(value(&app, "Target").unwrap().ends_with("missing (broken)"));
+    }
 
-        // Wait for input to stop (100ms)
-        if elapsed < 100 {
-            return;
-        }
+    #[test]
+    fn test_image_without_extension_previews_as_image() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        let path = root.join("screenshot");
+        image::RgbImage::new(3, 2)
+            .save_with_format(&path, image::ImageFormat::Png)
+            .unwrap();
+        let config = Config {
+            git: false,
+            ..Config::default()
+        };
+        let mut app = App::new(&root, config).unwrap();
 
-        let text = self.drop_buffer.trim().to_string();
-        self.drop_buffer.clear();
+        app.open_preview(path);
+        assert_eq!(app.input_mode, InputMode::Preview);
+        assert_eq!(app.image_preview.as_ref().unwrap().dimensions, (3, 2));
+    }
 
-        // Normalize the path: remove quotes and unescape backslashes
-        let normalized = Self::normalize_dropped_path(&text);
+    #[test]
+    fn test_complete_directory() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let root = dir.path();
+        fs::create_dir_all(root.join("archive/2023")).unwrap();
+        fs::create_dir(root.join("archive/2024")).unwrap();
+        fs::create_dir(root.join("assets")).unwrap();
+        fs::create_dir(root.join(".cache")).unwrap();
+        fs::write(root.join("archive.txt"), "").unwrap();
+
+        assert_eq!(
+            complete_directory("arc", root),
+            ("archive/".to_string(), vec!["archive".to_string()])
+        );
+        assert_eq!(
+            complete_directory("archive/20", root),
+            (
+                "archive/202".to_string(),
+                vec!["2023".to_string(), "2024".to_string()]
+            )
+        );
+        assert_eq!(complete_directory("a", root).0, "a");
+        assert_eq!(complete_directory("", root).1, ["archive", "assets"]);
+        assert_eq!(complete_directory(".c", root).0, ".cache/");
+        assert_eq!(
+            complete_directory("missing/x", root).1,
+            Vec::<String>::new()
+        );
+
+        let absolute = format!("{}/as", root.display());
+        assert_eq!(
+            complete_directory(&absolute, Path::new("/")).0,
+            format!("{}/assets/", root.display())
+        );
+    }
 
-        // Check if it's an absolute path that exists
-        if normalized.starts_with('/') {
-            let path = PathBuf::from(&normalized);
-            if path.exists() {
-                if let Some(dest_dir) = self.get_paste_destination() {
-                    match file_ops::copy_file(&path, &dest_dir) {
-                        Ok(_) => {
-                            self.message = Some(format!(
-                                "Dropped: {}",
-                                path.file_name().unwrap_or_default().to_string_lossy()
-                            ));
-                            let _ = self.tree.refresh();
-                        }
-                        Err(e) => {
-                            self.message = Some(format!("Copy error: {}", e));
-                        }
-                    }
-                }
-                return;
-            }
+    #[test]
+    fn test_resolve_destination() {
+        let base = Path::new("/project");
+        assert_eq!(resolve_destination("out", base), Path::new("/project/out"));
+        assert_eq!(resolve_destination("/tmp", base), Path::new("/tmp"));
+        if let Some(home) = std::env::var_os("HOME") {
+            let home = PathBuf::from(home);
+            assert_eq!(resolve_destination("~", base), home);
+            assert_eq!(resolve_destination("~/docs", base), home.join("docs"));
         }
+    }
 
-        // Not a valid path, treat first char as command
-        if let Some(rest) = text.strip_prefix('/') {
-            // Start search with remaining chars
-            self.input_buffer = rest.to_string();
-            self.input_mode = InputMode::Search;
-        }
+    #[test]
+    fn test_format_mtime() {
+        use std::time::Duration;
+        let now = SystemTime::UNIX_EPOCH + Duration::from_secs(1_704_240_000); // 2024-01-03
+        let ago = |secs| now - Duration::from_secs(secs);
+        assert_eq!(App::format_mtime(ago(5), now), "just now");
+        assert_eq!(App::format_mtime(ago(120), now), "2m ago");
+        assert_eq!(App::format_mtime(ago(3 * 3600), now), "3h ago");
+        assert_eq!(App::format_mtime(ago(2 * 86400), now), "2d ago");
+        assert_eq!(App::format_mtime(ago(0), ago(0)), "just now");
+        assert_eq!(
+            App::format_mtime(now, now + Duration::from_secs(30 * 86400)),
+            "2024-01-03"
+        );
+        assert_eq!(App::format_timestamp(ago(3723)), "2024-01-02 22:57:57 UTC");
+        assert_eq!(civil_from_days(0), (1970, 1, 1));
+        assert_eq!(civil_from_days(11_016), (2000, 2, 29));
     }
 
-    /// Normalize a dropped path by removing quotes and unescaping backslashes
-    fn normalize_dropped_path(text: &str) -> String {
-        let text = text.trim();
+    #[test]
+    fn test_format_mode() {
+        assert_eq!(App::format_mode(0o755), "rwxr-xr-x");
+        assert_eq!(App::format_mode(0o640), "rw-r-----");
+        assert_eq!(App::format_mode(0o4755), "rwsr-xr-x");
+        assert_eq!(App::format_mode(0o1777), "rwxrwxrwt");
+        assert_eq!(App::format_mode(0o2644), "rw-r-Sr--");
+    }
 
-        // Remove surrounding quotes if present
-        let text = if (text.starts_with('\'') && text.ends_with('\''))
-            || (text.starts_with('"') && text.ends_with('"'))
-        {
-            &text[1..text.len() - 1]
-        } else {
-            text
+    #[test]
+    fn test_breadcrumb_jump_reroots_and_selects_previous_root() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        let nested = root.join("a").join("b");
+        fs::create_dir_all(&nested).unwrap();
+
+        let config = Config {
+            git: false,
+            ..Config::default()
         };
+        let mut app = App::new(&nested, config).unwrap();
+        app.breadcrumb_jump(2);
+        assert_eq!(app.tree.root.path, root);
+        assert_eq!(
+            app.tree.get_node(app.selected).unwrap().path,
+            root.join("a")
+        );
+    }
 
-        // Unescape backslash-escaped characters (e.g., "\ " -> " ")
-        let mut result = String::with_capacity(text.len());
-        let mut chars = text.chars().peekable();
-        while let Some(c) = chars.next() {
-            if c == '\\' {
-                if let Some(&next) = chars.peek() {
-                    // Common escaped characters in shell paths
-                    if matches!(
-                        next,
-                        ' ' | '\''
-                            | '"'
-                            | '\\'
-                            | '('
-                            | ')'
-                            | '['
-                            | ']'
-                            | '&'
-                            | ';'
-                            | '!'
-                            | '$'
-                            | '`'
-                    ) {
-                        result.push(chars.next().unwrap());
-                        continue;
-                    }
-                }
+    #[test]
+    fn test_confirm_policy_and_protected_paths() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        fs::create_dir(root.join(".git")).unwrap();
+        fs::write(root.join(".git/HEAD"), "").unwrap();
+        fs::write(root.join("a.txt"), "a").unwrap();
+        fs::write(root.join("b.txt"), "b").unwrap();
+        fs::write(root.join("c.txt"), "c").unwrap();
+        let config = Config {
+            git: false,
+            show_hidden: true,
+            protected_paths: vec![".git/**".to_string()],
+            confirm: config::ConfirmPolicy {
+                delete: false,
+                overwrite: true,
+                open_files: 1,
+                command_files: 1,
+                ..config::ConfirmPolicy::default()
+            },
+            ..Config::default()
+        };
+        let mut app = App::new(&root, config).unwrap();
+
+        app.select_path(&root.join(".git"));
+        app.confirm_delete();
+        assert_eq!(app.input_mode, InputMode::Normal);
+        assert!(root.join(".git/HEAD").exists());
+        assert!(app
+            .message
+            .as_deref()
+            .unwrap()
+            .starts_with("Refusing to delete"));
+        app.start_rename();
+        assert_eq!(app.input_mode, InputMode::Normal);
+
+        // Deleting needs no confirmation with `delete = false`
+        app.select_path(&root.join("c.txt"));
+        app.confirm_delete();
+        assert_eq!(app.input_mode, InputMode::Normal);
+        assert!(!root.join("c.txt").exists());
+
+        // Renaming onto an existing file asks first
+        app.select_path(&root.join("a.txt"));
+        app.start_rename();
+        app.input_buffer = "b.txt".to_string();
+        app.confirm_input();
+        assert!(matches!(
+            app.input_mode,
+            InputMode::Confirm(ConfirmAction::Overwrite(_))
+        ));
+        app.confirm_input();
+        assert_eq!(fs::read_to_string(root.join("b.txt")).unwrap(), "a");
+        assert!(!root.join("a.txt").exists());
+
+        // Opening more files than `open_files` asks first
+        app.marked.insert(root.join("b.txt"));
+        app.marked.insert(root.join(".git/HEAD"));
+        app.open_marked();
+        assert!(matches!(
+            app.input_mode,
+            InputMode::Confirm(ConfirmAction::Open(OpenInfo { ref paths, nvim: false }))
+                if paths.len() == 2
+        ));
+        app.cancel_confirm();
+        assert!(app.pending_foreground.is_none());
+
+        // So does running a command on more than `command_files` files, once for each
+        app.execute_external_command(Some("@cat <name>".to_string()));
+        assert!(matches!(
+            app.input_mode,
+            InputMode::Confirm(ConfirmAction::Command(CommandRun { ref paths, .. }))
+                if paths.len() == 2
+        ));
+        app.confirm_input();
+        assert_eq!(
+            app.pending_foreground,
+            Some(OsString::from("cat 'HEAD'; cat 'b.txt'"))
+        );
+    }
+
+    #[test]
+    fn test_plan_leaves_out_excluded_items() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        fs::create_dir(root.join("dest")).unwrap();
+        for name in ["a", "b", "c", "d"] {
+            fs::write(root.join(name), "").unwrap();
+        }
+        let config = Config {
+            git: false,
+            confirm: config::ConfirmPolicy {
+                plan: 2,
+                ..config::ConfirmPolicy::default()
+            },
+            ..Config::default()
+        };
+        let mut app = App::new(&root, config).unwrap();
+
+        // A small batch still uses the popup
+        app.marked.insert(root.join("a"));
+        app.marked.insert(root.join("b"));
+        app.confirm_delete();
+        assert!(matches!(
+            app.input_mode,
+            InputMode::Confirm(ConfirmAction::Delete(_))
+        ));
+        app.cancel_confirm();
+
+        // Moving three cut files lists them; leaving one out keeps it in place
+        app.clipboard
+            .cut(vec![root.join("a"), root.join("b"), root.join("c")]);
+        app.select_path(&root.join("dest"));
+        app.paste();
+        assert_eq!(app.input_mode, InputMode::Plan);
+        let plan = app.plan.as_ref().unwrap();
+        assert_eq!(plan.items.len(), 3);
+        assert_eq!(plan.items[0].target, Some(root.join("dest/a")));
+        app.toggle_plan_item();
+        assert_eq!(app.plan_selected, 1);
+
+        // Cancelling keeps the cut clipboard
+        app.cancel_plan();
+        assert_eq!(app.input_mode, InputMode::Normal);
+        assert!(app.clipboard.content.is_some());
+        assert!(root.join("a").exists());
+
+        app.paste();
+        app.toggle_plan_item();
+        app.execute_plan();
+        assert_eq!(app.input_mode, InputMode::Normal);
+        assert!(root.join("a").exists());
+        assert!(root.join("dest/b").exists());
+        assert!(root.join("dest/c").exists());
+        assert!(app.clipboard.content.is_none());
+        assert!(app.marked.is_empty());
+    }
+
+    #[test]
+    fn test_large_directories_warn_first() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        fs::create_dir_all(root.join("big/sub")).unwrap();
+        for name in ["a", "b", "sub/c"] {
+            fs::write(root.join("big").join(name), "").unwrap();
+        }
+        fs::create_dir(root.join("small")).unwrap();
+        fs::write(root.join("small/a"), "").unwrap();
+        let config = Config {
+            git: false,
+            confirm: config::ConfirmPolicy {
+                delete: false,
+                large_mb: 0,
+                large_files: 2,
+                ..config::ConfirmPolicy::default()
+            },
+            ..Config::default()
+        };
+        let mut app = App::new(&root, config).unwrap();
+        let wait = |app: &mut App| {
+            while app.size_check.is_some() {
+                std::thread::sleep(std::time::Duration::from_millis(5));
+                app.poll_jobs();
             }
-            result.push(c);
+        };
+
+        // Small enough: deleted once measured, without asking
+        app.select_path(&root.join("small"));
+        app.confirm_delete();
+        assert!(app.size_check.is_some());
+        wait(&mut app);
+        assert_eq!(app.input_mode, InputMode::Normal);
+        assert!(!root.join("small").exists());
+
+        app.select_path(&root.join("big"));
+        app.confirm_delete();
+        wait(&mut app);
+        let InputMode::Confirm(ConfirmAction::Large(batch)) = &app.input_mode else {
+            panic!("expected a warning, got {:?}", app.input_mode);
+        };
+        assert_eq!(batch.stats.files, 3);
+        app.cancel_confirm();
+        assert!(root.join("big").exists());
+
+        app.confirm_delete();
+        wait(&mut app);
+        app.confirm_input();
+        assert!(!root.join("big").exists());
+    }
+
+    #[test]
+    fn test_marks_follow_renames_and_drop_deleted_paths() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        fs::create_dir(root.join("docs")).unwrap();
+        fs::write(root.join("docs/guide.md"), "").unwrap();
+        fs::write(root.join("notes.txt"), "").unwrap();
+        let config = Config {
+            git: false,
+            mark_advance: false,
+            ..Config::default()
+        };
+        let mut app = App::new(&root, config).unwrap();
+        app.select_path(&root.join("docs"));
+        app.dispatch(Action::ToggleMark(3));
+        assert_eq!(
+            app.tree.get_node(app.selected).unwrap().path,
+            root.join("docs")
+        );
+        app.marked.insert(root.join("docs/guide.md"));
+        app.marked.insert(root.join("notes.txt"));
+
+        app.start_rename();
+        app.input_buffer = "manual".to_string();
+        app.confirm_input();
+        let expected: HashSet<PathBuf> = [
+            root.join("manual"),
+            root.join("manual/guide.md"),
+            root.join("notes.txt"),
+        ]
+        .into_iter()
+        .collect();
+        assert_eq!(app.marked, expected);
+
+        fs::remove_file(root.join("notes.txt")).unwrap();
+        app.refresh();
+        assert!(!app.marked.contains(&root.join("notes.txt")));
+        assert_eq!(app.marked.len(), 2);
+    }
+
+    #[test]
+    fn test_mark_changed_files() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        fs::create_dir(root.join("src")).unwrap();
+        for name in ["src/lib.rs", "src/new.rs", "clean.txt", "build.log"] {
+            fs::write(root.join(name), "").unwrap();
         }
-        result
+        let config = Config {
+            git: false,
+            ..Config::default()
+        };
+        let mut app = App::new(&root, config).unwrap();
+        app.mark_changed();
+        assert_eq!(app.message.as_deref(), Some("Not a git repository"));
+
+        app.git_repo.root = Some(root.clone());
+        app.git_repo.statuses = HashMap::from([
+            (root.join("src/lib.rs"), GitStatus::Modified),
+            (root.join("src/new.rs"), GitStatus::Untracked),
+            (root.join("gone.rs"), GitStatus::Deleted),
+            (root.join("build.log"), GitStatus::Ignored),
+        ]);
+        app.mark_changed();
+        let expected: HashSet<PathBuf> = [root.join("src/lib.rs"), root.join("src/new.rs")]
+            .into_iter()
+            .collect();
+        assert_eq!(app.marked, expected);
+        assert_eq!(app.message.as_deref(), Some("Marked 2 changed file(s)"));
     }
 
-    fn try_handle_as_drop(&mut self) -> bool {
-        let text = self.input_buffer.trim();
-        // Normalize the path (remove quotes, unescape)
-        let normalized = Self::normalize_dropped_path(text);
+    #[test]
+    fn test_mark_all_and_invert_in_dir() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        fs::create_dir(root.join("sub")).unwrap();
+        fs::write(root.join("sub/inner.txt"), "").unwrap();
+        fs::write(root.join("a.txt"), "").unwrap();
+        fs::write(root.join("b.txt"), "").unwrap();
+        let config = Config {
+            git: false,
+            ..Config::default()
+        };
+        let mut app = App::new(&root, config).unwrap();
+        let sub = (0..app.tree.len())
+            .find(|&i| app.tree.get_node(i).unwrap().path == root.join("sub"))
+            .unwrap();
+        app.tree.expand_node(sub).unwrap();
+        let a = (0..app.tree.len())
+            .find(|&i| app.tree.get_node(i).unwrap().path == root.join("a.txt"))
+            .unwrap();
+        app.selected = a;
+
+        app.mark_all_in_dir();
+        assert_eq!(app.marked.len(), 3);
+        assert!(!app.marked.contains(&root.join("sub/inner.txt")));
+
+        app.marked.remove(&root.join("a.txt"));
+        app.marked.insert(root.join("sub/inner.txt"));
+        app.invert_marks_in_dir();
+        assert!(app.marked.contains(&root.join("a.txt")));
+        assert!(!app.marked.contains(&root.join("b.txt")));
+        assert!(app.marked.contains(&root.join("sub/inner.txt")));
+
+        app.clear_all_marks();
+        assert!(app.marked.is_empty());
+    }
 
-        // Check if it looks like an absolute path
-        if !normalized.starts_with('/') {
-            return false;
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("*.log", "server.log"));
+        assert!(!glob_match("*.log", "server.log.gz"));
+        assert!(glob_match("test_*", "test_app.rs"));
+        assert!(glob_match("a?c", "abc"));
+        assert!(!glob_match("a?c", "ac"));
+        assert!(glob_match("*", ""));
+        assert!(glob_match("*a*b*", "xxaxxbxx"));
+        assert!(!glob_match("*a*b", "xxbxxa"));
+        assert!(glob_match("src/*.rs", "src/main.rs"));
+    }
+
+    #[test]
+    fn test_mark_glob() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        fs::create_dir(root.join("logs")).unwrap();
+        fs::write(root.join("logs/a.log"), "").unwrap();
+        fs::write(root.join("b.log"), "").unwrap();
+        fs::write(root.join("c.txt"), "").unwrap();
+        let config = Config {
+            git: false,
+            ..Config::default()
+        };
+        let mut app = App::new(&root, config).unwrap();
+
+        // Collapsed directories are not searched
+        app.mark_glob("*.log");
+        assert_eq!(app.marked.len(), 1);
+        assert!(app.marked.contains(&root.join("b.log")));
+
+        // logs/ sorts first, directly below the root
+        app.tree.expand_node(1).unwrap();
+        app.mark_glob("logs/*");
+        assert!(app.marked.contains(&root.join("logs/a.log")));
+        assert!(!app.marked.contains(&root.join("c.txt")));
+    }
+
+    #[test]
+    fn test_unmark_from_marked_list() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let config = Config {
+            git: false,
+            ..Config::default()
+        };
+        let mut app = App::new(dir.path(), config).unwrap();
+        app.open_marked_list();
+        assert_eq!(app.input_mode, InputMode::Normal);
+
+        app.marked.insert(PathBuf::from("/b"));
+        app.marked.insert(PathBuf::from("/a"));
+        app.marked.insert(PathBuf::from("/c"));
+        app.open_marked_list();
+        assert_eq!(app.input_mode, InputMode::MarkedList);
+
+        app.marked_move(10);
+        app.unmark_selected();
+        assert_eq!(
+            app.marked_list(),
+            vec![PathBuf::from("/a"), PathBuf::from("/b")]
+        );
+        assert_eq!(app.marked_selected, 1);
+
+        app.unmark_selected();
+        app.unmark_selected();
+        assert!(app.marked.is_empty());
+        assert_eq!(app.input_mode, InputMode::Normal);
+    }
+
+    #[test]
+    fn test_g_sequences() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        for name in ["a.txt", "b.txt", "c.txt"] {
+            fs::write(root.join(name), "").unwrap();
         }
+        let config = Config {
+            git: false,
+            ..Config::default()
+        };
+        let mut app = App::new(&root, config).unwrap();
+        app.git_repo
+            .statuses
+            .insert(root.join("b.txt"), GitStatus::Modified);
+
+        app.run_sequence('g', 'd');
+        assert_eq!(
+            app.tree.get_node(app.selected).unwrap().path,
+            root.join("b.txt")
+        );
+        // Wraps around to the only change
+        app.run_sequence('g', 'd');
+        assert_eq!(
+            app.tree.get_node(app.selected).unwrap().path,
+            root.join("b.txt")
+        );
+
+        app.run_sequence('g', 'g');
+        assert_eq!(app.selected, 0);
+        app.pending_count = Some(3);
+        app.run_sequence('g', 'g');
+        assert_eq!(app.selected, 2);
+        assert_eq!(app.pending_count, None);
+
+        app.run_sequence('g', 'p');
+        assert_eq!(app.message.as_deref(), Some("Not a git repository"));
+    }
 
-        // Try as single path first
-        let path = PathBuf::from(&normalized);
-        if path.exists() {
-            let dest_dir = match self.get_paste_destination() {
-                Some(dir) => dir,
-                None => {
-                    self.message = Some("No destination".to_string());
-                    return false;
-                }
-            };
+    #[test]
+    fn test_expand_all_runs_in_background_with_cap() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        fs::create_dir_all(root.join("a/b")).unwrap();
+        fs::write(root.join("a/b/c.txt"), "").unwrap();
+        let config = Config {
+            git: false,
+            ..Config::default()
+        };
+        let mut app = App::new(&root, config).unwrap();
 
-            match file_ops::copy_file(&path, &dest_dir) {
-                Ok(_) => {
-                    self.message = Some(format!(
-                        "Dropped: {}",
-                        path.file_name().unwrap_or_default().to_string_lossy()
-                    ));
-                    let _ = self.tree.refresh();
-                    return true;
-                }
-                Err(e) => {
-                    self.message = Some(format!("Copy error: {}", e));
-                    return false;
+        let wait = |app: &mut App| {
+            for _ in 0..200 {
+                app.poll_jobs();
+                if app.expand_job.is_none() {
+                    break;
                 }
+                std::thread::sleep(std::time::Duration::from_millis(5));
             }
-        }
+        };
 
-        // Try parsing multiple paths
-        let paths = Self::parse_dropped_paths(text);
-        if paths.is_empty() {
-            return false;
+        app.config.expand_max_entries = 1;
+        app.expand_all();
+        wait(&mut app);
+        assert!(app.message.as_deref().unwrap().contains("aborted"));
+        assert_eq!(app.tree.len(), 2);
+
+        app.config.expand_max_entries = 100;
+        app.expand_all();
+        wait(&mut app);
+        assert_eq!(app.tree.len(), 4);
+
+        app.collapse_all();
+        app.selected = 1;
+        app.expand_selected_recursive();
+        wait(&mut app);
+        assert_eq!(app.tree.len(), 4);
+        assert_eq!(
+            app.tree.get_node(app.selected).unwrap().path,
+            root.join("a")
+        );
+    }
+
+    #[test]
+    fn test_paste_copies_in_background() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        fs::create_dir_all(root.join("src/sub")).unwrap();
+        fs::create_dir(root.join("dest")).unwrap();
+        fs::write(root.join("src/a.txt"), "a").unwrap();
+        fs::write(root.join("src/sub/b.txt"), "b").unwrap();
+        let config = Config {
+            git: false,
+            ..Config::default()
+        };
+        let mut app = App::new(&root, config).unwrap();
+
+        app.select_path(&root.join("src"));
+        app.yank();
+        app.select_path(&root.join("dest"));
+        app.paste();
+        // Directories are measured first, then copied
+        while app.size_check.is_some() {
+            std::thread::sleep(std::time::Duration::from_millis(5));
+            app.poll_jobs();
         }
+        assert!(app.copy_job.is_some());
+        assert!(app
+            .message
+            .as_deref()
+            .unwrap()
+            .starts_with("Copying 1 item(s)"));
+        app.quit();
+        assert!(!app.should_quit);
+
+        for _ in 0..200 {
+            app.poll_jobs();
+            if app.copy_job.is_none() {
+                break;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(5));
+        }
+        assert_eq!(app.message.as_deref(), Some("Pasted 1 item(s)"));
+        assert_eq!(
+            fs::read_to_string(root.join("dest/src/sub/b.txt")).unwrap(),
+            "b"
+        );
+        assert_eq!(app.input_mode, InputMode::Normal);
+    }
 
-        let dest_dir = match self.get_paste_destination() {
-            Some(dir) => dir,
-            None => return false,
+    #[test]
+    fn test_paste_keeps_cut_while_move_across_runs() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        fs::create_dir(root.join("dest")).unwrap();
+        fs::write(root.join("a.txt"), "").unwrap();
+        let config = Config {
+            git: false,
+            ..Config::default()
         };
+        let mut app = App::new(&root, config).unwrap();
+        app.move_job = Some(MoveJob {
+            dest_dir: root.join("dest"),
+            paths: Vec::new(),
+            progress: Arc::new(CopyProgress::default()),
+            job: Job::spawn(|_| Vec::new()),
+        });
 
-        let mut success = 0;
-        for path in &paths {
-            if file_ops::copy_file(path, &dest_dir).is_ok() {
-                success += 1;
+        app.select_path(&root.join("a.txt"));
+        app.cut();
+        app.select_path(&root.join("dest"));
+        app.paste();
+        assert!(root.join("a.txt").exists());
+        assert_eq!(
+            app.clipboard.content,
+            Some(ClipboardContent::Cut(vec![root.join("a.txt")]))
+        );
+
+        app.move_job = None;
+        app.paste();
+        assert!(root.join("dest/a.txt").exists());
+        assert!(app.clipboard.content.is_none());
+    }
+
+    #[test]
+    fn test_tree_changes_cancel_expand_all() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        fs::create_dir_all(root.join("a/b")).unwrap();
+        let config = Config {
+            git: false,
+            ..Config::default()
+        };
+        let mut app = App::new(&root, config).unwrap();
+
+        // A result computed before the change would undo it
+        app.expand_all();
+        app.selected = 1;
+        app.toggle_expand();
+        assert!(app.expand_job.is_none());
+        assert!(app.message.as_deref().unwrap().contains("cancelled"));
+
+        app.expand_all();
+        app.refresh_dirs(std::slice::from_ref(&root));
+        assert!(app.expand_job.is_none());
+    }
+
+    #[test]
+    fn test_flat_view_lists_files_recursively() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        fs::create_dir_all(root.join("a/b")).unwrap();
+        fs::write(root.join("a/b/deep.txt"), "").unwrap();
+        fs::write(root.join("top.txt"), "").unwrap();
+        let config = Config {
+            git: false,
+            ..Config::default()
+        };
+        let mut app = App::new(&root, config).unwrap();
+
+        app.toggle_flat_view();
+        for _ in 0..200 {
+            app.poll_jobs();
+            if app.expand_job.is_none() {
+                break;
             }
+            std::thread::sleep(std::time::Duration::from_millis(5));
         }
+        let names: Vec<&str> = (1..app.tree.len())
+            .map(|i| app.tree.get_node(i).unwrap().name.as_str())
+            .collect();
+        assert_eq!(names, vec!["a/b/deep.txt", "top.txt"]);
+        assert_eq!(app.active_filters(), vec!["flat"]);
+
+        // Leaving the flat view reveals the selection in the tree
+        app.selected = 1;
+        app.toggle_flat_view();
+        assert!(app.tree.flat_view.is_none());
+        assert_eq!(
+            app.tree.get_node(app.selected).unwrap().path,
+            root.join("a/b/deep.txt")
+        );
+    }
 
-        if success > 0 {
-            self.message = Some(format!("Dropped {} item(s)", success));
-            let _ = self.tree.refresh();
-            true
-        } else {
-            false
+    #[test]
+    fn test_sort_knobs_reorder_all_views() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        fs::create_dir(root.join("src")).unwrap();
+        fs::write(root.join("src/item10.rs"), "").unwrap();
+        fs::write(root.join("src/item9.rs"), "").unwrap();
+        fs::write(root.join(".env"), "").unwrap();
+        fs::write(root.join("Makefile"), "").unwrap();
+        fs::write(root.join("build.sh"), "").unwrap();
+        let config = Config {
+            git: false,
+            show_hidden: true,
+            ..Config::default()
+        };
+        let mut app = App::new(&root, config).unwrap();
+        let names = |app: &App| -> Vec<String> {
+            (1..app.tree.len())
+                .map(|i| app.tree.get_node(i).unwrap().name.clone())
+                .collect()
+        };
+        assert_eq!(names(&app), ["src", ".env", "Makefile", "build.sh"]);
+
+        app.run_sequence(',', 'd');
+        assert_eq!(names(&app), [".env", "Makefile", "build.sh", "src"]);
+        app.run_sequence(',', 'h');
+        app.run_sequence(',', 'd');
+        app.run_sequence(',', 'c');
+        assert_eq!(app.tree.sort.directories, file_tree::DirectoryOrder::Mixed);
+        assert_eq!(names(&app), [".env", "build.sh", "Makefile", "src"]);
+        app.run_sequence(',', 'd');
+        assert_eq!(names(&app), [".env", "src", "build.sh", "Makefile"]);
+
+        // The flat view and newly loaded directories follow the same order
+        app.select_path(&root.join("src"));
+        app.expand_current();
+        assert_eq!(names(&app)[2..4], ["item9.rs", "item10.rs"]);
+        app.run_sequence(',', 'n');
+        assert_eq!(names(&app)[2..4], ["item10.rs", "item9.rs"]);
+        app.toggle_flat_view();
+        for _ in 0..200 {
+            app.poll_jobs();
+            if app.expand_job.is_none() {
+                break;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(5));
         }
+        assert_eq!(names(&app), ["item10.rs", "item9.rs"]);
     }
 
-    pub fn handle_drop(&mut self, text: &str) {
-        // Parse dropped text as file paths
-        // Paths can be separated by newlines or spaces (with quotes for paths containing spaces)
-        let paths: Vec<PathBuf> = Self::parse_dropped_paths(text);
+    #[test]
+    fn test_extension_filter() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        fs::create_dir(root.join("src")).unwrap();
+        for name in ["main.rs", "Cargo.toml", "README.md"] {
+            fs::write(root.join(name), "").unwrap();
+        }
+        let config = Config {
+            git: false,
+            ..Config::default()
+        };
+        let mut app = App::new(&root, config).unwrap();
+
+        app.set_extension_filter("*.rs, .TOML");
+        assert_eq!(app.extension_filter, vec!["rs", "toml"]);
+        let names: Vec<&str> = (1..app.tree.len())
+            .map(|i| app.tree.get_node(i).unwrap().name.as_str())
+            .collect();
+        assert_eq!(names, vec!["src", "Cargo.toml", "main.rs"]);
+        assert_eq!(app.active_filters(), vec!["*.{rs,toml}"]);
+
+        app.set_extension_filter("");
+        assert_eq!(app.tree.len(), 5);
+        assert!(app.active_filters().is_empty());
+    }
 
-        if paths.is_empty() {
-            return;
+    #[test]
+    fn test_drag_marks_range() {
+        let dir = tempfile::TempDir::new().unwrap();
+        for name in ["a", "b", "c", "d"] {
+            fs::write(dir.path().join(name), "").unwrap();
         }
+        let config = Config {
+            git: false,
+            ..Config::default()
+        };
+        let mut app = App::new(dir.path(), config).unwrap();
+        let path = |app: &App, i: usize| app.tree.get_node(i).unwrap().path.clone();
+        let b = path(&app, 2);
+        app.marked.insert(b.clone());
+
+        app.handle_click(1);
+        app.handle_drag(3);
+        assert_eq!(app.selected, 3);
+        assert_eq!(app.marked.len(), 3);
+
+        // Shrinking the range only unmarks what the drag itself marked
+        app.handle_drag(0);
+        assert_eq!(app.marked, HashSet::from([path(&app, 1), b.clone()]));
+        app.end_drag();
+
+        app.handle_drag(4);
+        assert_eq!(app.marked.len(), 2);
+    }
 
-        // Get destination directory
-        let dest_dir = match self.get_paste_destination() {
-            Some(dir) => dir,
-            None => return,
+    #[cfg(unix)]
+    #[test]
+    fn test_parse_clipboard_file_uris() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let spaced = dir.path().join("my file.txt");
+        let raw = dir.path().join(OsStr::from_bytes(b"f\xff"));
+        fs::write(&spaced, "").unwrap();
+        fs::write(&raw, "").unwrap();
+        let dir_uri = dir.path().to_string_lossy().to_string();
+        let text = format!(
+            "file://{0}/my%20file.txt\r\nfile://localhost{0}/f%FF\nfile://{0}/missing\n",
+            dir_uri
+        );
+        assert_eq!(App::parse_clipboard_paths(&text), vec![spaced.clone(), raw]);
+        // Plain paths go through the drop parser
+        assert_eq!(
+            App::parse_clipboard_paths(&format!("'{}'", spaced.display())),
+            vec![spaced]
+        );
+        assert_eq!(percent_decode("a%2Fb%zz%"), b"a/b%zz%");
+        let path = Path::new("/tmp/my file,ü.txt");
+        assert_eq!(file_uri(path), "file:///tmp/my%20file%2C%C3%BC.txt");
+        assert_eq!(
+            percent_decode(file_uri(path).strip_prefix("file://").unwrap()),
+            path.as_os_str().as_bytes()
+        );
+    }
+
+    #[test]
+    fn test_drag_out_uses_configured_command() {
+        let dir = tempfile::TempDir::new().unwrap();
+        fs::write(dir.path().join("a.txt"), "").unwrap();
+        let config = Config {
+            git: false,
+            drag_command: Some("true --flag".to_string()),
+            ..Config::default()
         };
+        let mut app = App::new(dir.path(), config).unwrap();
+        app.selected = 1;
+        app.drag_out();
+        assert_eq!(app.message.as_deref(), Some("Dragging 1 item(s) with true"));
+
+        app.config.drag_command = Some("ft-no-such-drag-helper".to_string());
+        app.drag_out();
+        assert!(app.message.unwrap().starts_with("No drag helper found"));
+    }
 
-        let mut success = 0;
-        for path in &paths {
-            if path.exists() && file_ops::copy_file(path, &dest_dir).is_ok() {
-                success += 1;
+    #[test]
+    fn test_captured_command_output_opens_preview() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let config = Config {
+            git: false,
+            ..Config::default()
+        };
+        let mut app = App::new(dir.path(), config).unwrap();
+        app.execute_external_command(Some("!echo hello; exit 4".to_string()));
+        assert_eq!(app.last_command.as_deref(), Some("!echo hello; exit 4"));
+        for _ in 0..200 {
+            app.poll_jobs();
+            if app.command_job.is_none() {
+                break;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+        assert_eq!(app.input_mode, InputMode::Preview);
+        assert_eq!(app.preview_content, vec!["hello"]);
+        assert_eq!(
+            app.message.as_deref(),
+            Some("Exit code 4: echo hello; exit 4")
+        );
+
+        // Endless output is cut off, and the preview says so
+        app.input_mode = InputMode::Normal;
+        app.execute_external_command(Some("!yes | head -n 1000000".to_string()));
+        for _ in 0..500 {
+            app.poll_jobs();
+            if app.command_job.is_none() {
+                break;
             }
+            std::thread::sleep(std::time::Duration::from_millis(10));
         }
+        assert_eq!(
+            app.preview_content.last().map(String::as_str),
+            Some("[Output truncated: only the first 1.0 MB are kept]")
+        );
+    }
 
-        if success > 0 {
-            self.message = Some(format!("Dropped {} item(s)", success));
-            let _ = self.tree.refresh();
+    #[test]
+    fn test_preview_horizontal_scroll() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let config = Config {
+            git: false,
+            ..Config::default()
+        };
+        let mut app = App::new(dir.path(), config).unwrap();
+        app.preview_content = vec!["short".to_string(), "日本語のとても長い行".to_string()];
+
+        app.preview_scroll_right(4);
+        assert_eq!(app.preview_hscroll, 4);
+        // Stops with the last column of the widest line (20 columns) in view
+        app.preview_scroll_right(100);
+        assert_eq!(app.preview_hscroll, 19);
+        app.preview_scroll_left(8);
+        assert_eq!(app.preview_hscroll, 11);
+        app.preview_scroll_left(100);
+        assert_eq!(app.preview_hscroll, 0);
+    }
+
+    #[test]
+    fn test_foreground_command_is_handed_to_main_loop() {
+        let dir = tempfile::TempDir::new().unwrap();
+        fs::write(dir.path().join("a.txt"), "").unwrap();
+        let config = Config {
+            git: false,
+            ..Config::default()
+        };
+        let mut app = App::new(dir.path(), config).unwrap();
+        app.selected = 1;
+        app.execute_external_command(Some("@ less <name>".to_string()));
+        assert_eq!(app.pending_foreground, Some(OsString::from("less 'a.txt'")));
+        assert_eq!(app.last_command.as_deref(), Some("@ less <name>"));
+    }
+
+    #[test]
+    fn test_open_marked_runs_open_with_rules_per_file() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        for name in ["a.md", "b.txt", "c.MD", "d.png"] {
+            fs::write(root.join(name), "").unwrap();
         }
+        let config = Config {
+            git: false,
+            open_with: vec![
+                config::CommandRule {
+                    pattern: "*.md".to_string(),
+                    command: "@glow <name>".to_string(),
+                },
+                config::CommandRule {
+                    pattern: "*.png".to_string(),
+                    command: "true <name>".to_string(),
+                },
+            ],
+            ..Config::default()
+        };
+        let mut app = App::new(&root, config).unwrap();
+        let paths = ["a.md", "b.txt", "c.MD", "d.png"].map(|name| root.join(name));
+        app.execute_open(OpenInfo {
+            paths: paths.to_vec(),
+            nvim: false,
+        });
+        let script = app.pending_foreground.take().unwrap();
+        let script = script.to_string_lossy();
+        assert!(script.starts_with("glow 'a.md'; glow 'c.MD'; "));
+        assert!(script.ends_with(&format!(" '{}'", root.join("b.txt").display())));
+        assert!(!script.contains("d.png"));
+
+        // Only detached rules matched: nothing runs in the terminal
+        app.execute_open(OpenInfo {
+            paths: vec![root.join("d.png")],
+            nvim: false,
+        });
+        assert!(app.pending_foreground.is_none());
     }
 
-    fn parse_dropped_paths(text: &str) -> Vec<PathBuf> {
-        let mut paths = Vec::new();
-        let text = text.trim();
+    #[test]
+    fn test_open_with_rules_pick_command_by_name() {
+        let dir = tempfile::TempDir::new().unwrap();
+        for name in ["README.MD", "notes.txt"] {
+            fs::write(dir.path().join(name), "").unwrap();
+        }
+        let config = Config {
+            git: false,
+            open_with: vec![config::CommandRule {
+                pattern: "*.md".to_string(),
+                command: "@glow <filepath>".to_string(),
+            }],
+            ..Config::default()
+        };
+        let mut app = App::new(dir.path(), config).unwrap();
+        app.select_path(&dir.path().join("README.MD"));
+        assert!(app.has_open_rule());
+        assert_eq!(
+            app.open_rule_command(&dir.path().join("README.MD"))
+                .map(String::as_str),
+            Some("@glow <filepath>")
+        );
+        app.execute_external_command(None);
+        assert!(app.pending_foreground.take().is_some());
+        assert!(app.last_command.is_none());
+
+        app.select_path(&dir.path().join("notes.txt"));
+        assert!(!app.has_open_rule());
+        app.selected = 0;
+        assert!(!app.has_open_rule());
+    }
 
-        // Try newline-separated first
-        if text.contains('\n') {
-            for line in text.lines() {
-                let normalized = Self::normalize_dropped_path(line);
-                if !normalized.is_empty() {
-                    let path = PathBuf::from(&normalized);
-                    if path.is_absolute() && path.exists() {
-                        paths.push(path);
-                    }
-                }
-            }
-            return paths;
+    #[test]
+    fn test_run_command_picks_rule_per_marked_file() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        fs::create_dir(root.join("docs")).unwrap();
+        for name in ["a.pdf", "b.png", "c.PDF", "d.txt"] {
+            fs::write(root.join(name), "").unwrap();
+        }
+        let rule = |pattern: &str, command: &str| config::CommandRule {
+            pattern: pattern.to_string(),
+            command: command.to_string(),
+        };
+        let config = Config {
+            git: false,
+            open_with: vec![
+                rule("*.pdf", "@zathura <name>"),
+                rule("*.png", "@imv <name>"),
+            ],
+            ..Config::default()
+        };
+        let mut app = App::new(&root, config).unwrap();
+        for name in ["a.pdf", "b.png", "c.PDF"] {
+            app.marked.insert(root.join(name));
         }
 
-        // Single path or space-separated paths
-        // Handle quoted paths and escaped spaces
-        let mut chars = text.chars().peekable();
-        let mut current = String::new();
-        let mut in_quote = false;
-        let mut quote_char: Option<char> = None;
+        // The cursor on a directory does not turn the rules off
+        app.select_path(&root.join("docs"));
+        assert!(app.has_open_rule());
+        app.execute_external_command(None);
+        assert_eq!(
+            app.pending_foreground.take(),
+            Some(OsString::from(
+                "zathura 'a.pdf'; zathura 'c.PDF'; imv 'b.png'"
+            ))
+        );
+
+        // Files without a rule get no command when there is no last one
+        app.marked.insert(root.join("d.txt"));
+        app.execute_external_command(None);
+        assert!(app.pending_foreground.take().is_some());
+        assert_eq!(app.message.as_deref(), Some("No command for 1 file(s)"));
+    }
 
-        while let Some(c) = chars.next() {
-            match c {
-                '"' | '\'' => {
-                    if in_quote && Some(c) == quote_char {
-                        in_quote = false;
-                        quote_char = None;
-                    } else if !in_quote {
-                        in_quote = true;
-                        quote_char = Some(c);
-                    } else {
-                        // Different quote inside quoted string
-                        current.push(c);
-                    }
-                }
-                '\\' if !in_quote => {
-                    // Handle escaped characters outside quotes
-                    if let Some(next) = chars.next() {
-                        current.push(next);
-                    }
-                }
-                ' ' if !in_quote => {
-                    if !current.is_empty() {
-                        let path = PathBuf::from(&current);
-                        if path.is_absolute() && path.exists() {
-                            paths.push(path);
-                        }
-                        current.clear();
-                    }
-                }
-                _ => {
-                    current.push(c);
-                }
-            }
+    #[test]
+    fn test_history_reverse_search() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let config = Config {
+            git: false,
+            ..Config::default()
+        };
+        let mut app = App::new(dir.path(), config).unwrap();
+        app.command_history = vec![
+            "cargo build".to_string(),
+            "git status".to_string(),
+            "cargo test".to_string(),
+        ];
+        app.start_external_command();
+        app.history_search_older();
+        for c in "car".chars() {
+            app.history_search_push(c);
         }
+        assert_eq!(app.history_search.as_ref().unwrap().index, Some(2));
+        app.history_search_older();
+        assert_eq!(app.history_search.as_ref().unwrap().index, Some(0));
+        // No older match: stay on the current one
+        app.history_search_older();
+        assert_eq!(app.history_search.as_ref().unwrap().index, Some(0));
+        app.history_search_push('x');
+        assert_eq!(app.history_search.as_ref().unwrap().index, None);
+        app.history_search_pop();
+        assert_eq!(app.history_search.as_ref().unwrap().index, Some(2));
+
+        app.accept_history_search();
+        assert!(app.history_search.is_none());
+        assert_eq!(app.input_buffer, "cargo test");
+    }
 
-        if !current.is_empty() {
-            let path = PathBuf::from(&current);
-            if path.is_absolute() && path.exists() {
-                paths.push(path);
+    #[test]
+    fn test_external_previewer_output() {
+        let dir = tempfile::TempDir::new().unwrap();
+        fs::write(dir.path().join("data.csv"), "a,b\n1,2\n").unwrap();
+        let config = Config {
+            git: false,
+            previewers: vec![config::CommandRule {
+                pattern: "*.CSV".to_string(),
+                command: "printf '\\033[1m%s\\033[0m\\n' <name>; tail -n 1 <filepath>".to_string(),
+            }],
+            ..Config::default()
+        };
+        let mut app = App::new(dir.path(), config).unwrap();
+        app.selected = 1;
+        app.preview_file();
+        assert_eq!(app.preview_content, vec!["Loading preview…"]);
+        for _ in 0..200 {
+            app.poll_jobs();
+            if app.previewer_job.is_none() {
+                break;
             }
+            std::thread::sleep(std::time::Duration::from_millis(10));
         }
-
-        paths
+        assert_eq!(app.preview_content, vec!["data.csv", "1,2"]);
     }
 
-    pub fn execute_external_command(&mut self, command_override: Option<String>) {
-        // Determine which command to use
-        let command_template = command_override
-            .as_ref()
-            .or(self.last_command.as_ref())
-            .or(self.default_command.as_ref());
+    #[test]
+    fn test_reveal_path_expands_ancestors() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        fs::create_dir_all(root.join("a/b")).unwrap();
+        fs::write(root.join("a/b/c.txt"), "").unwrap();
+        fs::write(root.join(".hidden"), "").unwrap();
+        let config = Config {
+            git: false,
+            ..Config::default()
+        };
+        let mut app = App::new(&root, config).unwrap();
+
+        app.reveal_path(&root.join("a/b/c.txt")).unwrap();
+        assert_eq!(
+            app.tree.get_node(app.selected).unwrap().path,
+            root.join("a/b/c.txt")
+        );
+        assert_eq!(app.tree.len(), 4);
+        assert!(app.reveal_path(&root.join(".hidden")).is_err());
+        assert!(app.reveal_path(Path::new("/")).is_err());
+    }
 
-        let command_template = match command_template {
-            Some(cmd) => cmd,
-            None => {
-                self.message = Some("No command available. Enter a command first.".to_string());
-                return;
-            }
+    #[test]
+    fn test_batch_failures_can_be_retried() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        fs::write(root.join("a.txt"), "").unwrap();
+        let config = Config {
+            git: false,
+            ..Config::default()
         };
+        let mut app = App::new(&root, config).unwrap();
 
-        // Get the selected file path
-        let filepath = match self.tree.get_node(self.selected) {
-            Some(node) => node.path.to_string_lossy().to_string(),
-            None => {
-                self.message = Some("No file selected".to_string());
-                return;
-            }
+        app.execute_delete(DeleteInfo {
+            paths: vec![root.join("a.txt"), root.join("missing.txt")],
+            has_directories: false,
+            from_disk_usage: false,
+        });
+        assert!(!root.join("a.txt").exists());
+        assert_eq!(app.message.as_deref(), Some("Deleted 1 item(s), 1 failed"));
+        let InputMode::Confirm(ConfirmAction::Retry(report)) = app.input_mode.clone() else {
+            panic!("expected an error report");
         };
+        assert_eq!(report.op, BatchOp::Delete);
+        assert_eq!(report.failures.len(), 1);
+        assert_eq!(report.failures[0].path, root.join("missing.txt"));
+        assert!(!report.failures[0].permission_denied);
+
+        // Nothing was denied, so there is nothing to escalate
+        app.elevate_batch();
+        assert!(matches!(
+            app.input_mode,
+            InputMode::Confirm(ConfirmAction::Retry(_))
+        ));
+
+        fs::write(root.join("missing.txt"), "").unwrap();
+        app.input_mode = InputMode::Normal;
+        app.retry_batch(report);
+        assert!(!root.join("missing.txt").exists());
+        assert_eq!(app.input_mode, InputMode::Normal);
+        assert_eq!(app.message.as_deref(), Some("Deleted 1 item(s)"));
+    }
 
-        // Replace <filepath> placeholder with actual path (quoted)
-        let command = command_template.replace("<filepath>", &Self::shell_quote(&filepath));
+    #[test]
+    fn test_perf_overlay_records_frames() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let config = Config {
+            git: false,
+            ..Config::default()
+        };
+        let mut app = App::new(dir.path(), config).unwrap();
+        app.record_frame(std::time::Duration::from_millis(5));
+        assert!(app.perf.is_none());
+
+        app.toggle_perf_overlay();
+        app.record_frame(std::time::Duration::from_millis(8));
+        app.record_frame(std::time::Duration::from_millis(3));
+        let perf = app.perf.as_ref().unwrap();
+        assert_eq!(perf.frames, 2);
+        assert_eq!(perf.last_frame, std::time::Duration::from_millis(3));
+        assert_eq!(perf.slowest_frame, std::time::Duration::from_millis(8));
+        assert!(App::resident_memory().is_some_and(|bytes| bytes > 0));
+
+        app.toggle_perf_overlay();
+        assert!(app.perf.is_none());
+    }
 
-        // Execute the command with stdout/stderr redirected to null to prevent terminal corruption
-        match std::process::Command::new("sh")
-            .arg("-c")
-            .arg(&command)
-            .stdin(std::process::Stdio::null())
-            .stdout(std::process::Stdio::null())
-            .stderr(std::process::Stdio::null())
-            .spawn()
-        {
-            Ok(_) => {
-                self.message = Some(format!("Executed: {}", command));
-                // Save the command for next time
-                if let Some(cmd) = command_override {
-                    self.last_command = Some(cmd);
-                }
-            }
-            Err(e) => {
-                self.message = Some(format!("Command failed: {}", e));
-            }
-        }
+    #[test]
+    fn test_sessions_round_trip() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        fs::create_dir_all(root.join("a/b")).unwrap();
+        fs::write(root.join("a/b/c.txt"), "").unwrap();
+        fs::write(root.join(".hidden"), "").unwrap();
+        let config = Config {
+            git: false,
+            ..Config::default()
+        };
+        let mut app = App::new(&root, config.clone()).unwrap();
+        app.toggle_hidden();
+        app.reveal_path(&root.join("a/b/c.txt")).unwrap();
+        app.toggle_quick_preview();
+        let saved = app.sessions();
+        assert_eq!(saved.len(), 1);
+
+        let mut app = App::new(&root, config).unwrap();
+        app.restore_sessions(|path| saved.iter().find(|s| s.root == path).cloned());
+        assert!(app.show_hidden);
+        assert!(app.quick_preview_enabled);
+        assert_eq!(
+            app.tree.get_node(app.selected).unwrap().path,
+            root.join("a/b/c.txt")
+        );
+        assert_eq!(app.tree.len(), 5);
+        assert!(app.message.is_none());
     }
 
-    pub fn start_external_command(&mut self) {
-        self.input_buffer.clear();
-        self.history_index = None;
-        self.input_mode = InputMode::ExternalCommand;
+    #[test]
+    fn test_expand_placeholders() {
+        let path = Path::new("/tmp/my docs/report.final.md");
+        let expand = |template: &str| {
+            App::expand_placeholders(template, path, Path::new("/tmp"))
+                .into_string()
+                .unwrap()
+        };
+        assert_eq!(
+            expand("pandoc <filepath> -o <dir>/<stem>.pdf"),
+            "pandoc '/tmp/my docs/report.final.md' -o '/tmp/my docs'/'report.final'.pdf"
+        );
+        assert_eq!(
+            expand("echo <name> <ext> <root>"),
+            "echo 'report.final.md' 'md' '/tmp'"
+        );
+        assert_eq!(expand("sort <in >out <filepath"), "sort <in >out <filepath");
     }
 
-    pub fn history_prev(&mut self) {
-        if self.command_history.is_empty() {
-            return;
-        }
+    #[cfg(unix)]
+    #[test]
+    fn test_shell_quote_keeps_raw_bytes() {
+        assert_eq!(App::shell_quote("a b"), OsString::from("'a b'"));
+        assert_eq!(App::shell_quote("it's"), OsString::from("'it'\"'\"'s'"));
+        let raw = OsStr::from_bytes(b"f\xff.txt");
+        assert_eq!(App::shell_quote(raw).as_bytes(), b"'f\xff.txt'");
+        assert_eq!(
+            App::shell_join([OsString::from("vi"), App::shell_quote(raw)]).as_bytes(),
+            b"vi 'f\xff.txt'"
+        );
+    }
 
-        let new_index = match self.history_index {
-            None => Some(self.command_history.len() - 1),
-            Some(0) => Some(0), // Already at oldest
-            Some(i) => Some(i - 1),
+    #[test]
+    fn test_count_prefix() {
+        let dir = tempfile::TempDir::new().unwrap();
+        fs::write(dir.path().join("a.txt"), "").unwrap();
+        fs::write(dir.path().join("b.txt"), "").unwrap();
+        let config = Config {
+            git: false,
+            ..Config::default()
         };
+        let mut app = App::new(dir.path(), config).unwrap();
+        assert_eq!(app.take_count(), 1);
 
-        if let Some(idx) = new_index {
-            self.input_buffer = self.command_history[idx].clone();
-            self.history_index = new_index;
-        }
-    }
+        app.push_count_digit(1);
+        app.push_count_digit(2);
+        assert_eq!(app.pending_count, Some(12));
+        assert_eq!(app.take_count(), 12);
+        assert_eq!(app.pending_count, None);
 
-    pub fn history_next(&mut self) {
-        if self.command_history.is_empty() {
-            return;
+        for _ in 0..10 {
+            app.push_count_digit(9);
         }
+        assert_eq!(app.take_count(), MAX_COUNT);
 
-        let new_index = match self.history_index {
-            None => None,
-            Some(i) if i + 1 >= self.command_history.len() => {
-                // Back to empty input
-                self.input_buffer.clear();
-                None
-            }
-            Some(i) => Some(i + 1),
-        };
+        app.move_to_line(2);
+        assert_eq!(app.selected, 1);
+        app.move_to_line(usize::MAX);
+        assert_eq!(app.selected, app.tree.len() - 1);
+    }
 
-        if let Some(idx) = new_index {
-            self.input_buffer = self.command_history[idx].clone();
+    #[test]
+    fn test_miller_columns_navigate_siblings() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        fs::create_dir(root.join("a")).unwrap();
+        fs::write(root.join("a/x.txt"), "").unwrap();
+        fs::write(root.join("a/y.txt"), "inside y").unwrap();
+        fs::create_dir(root.join("b")).unwrap();
+        fs::write(root.join("c.txt"), "").unwrap();
+        let config = Config {
+            git: false,
+            ..Config::default()
+        };
+        let mut app = App::new(&root, config).unwrap();
+        let selected = |app: &App| app.tree.get_node(app.selected).unwrap().path.clone();
+
+        app.toggle_miller();
+        assert_eq!(selected(&app), root.join("a"));
+        app.expand_current();
+        assert_eq!(selected(&app), root.join("a/x.txt"));
+        for _ in 0..5 {
+            app.move_down();
         }
-        self.history_index = new_index;
+        app.update_quick_preview();
+        assert_eq!(selected(&app), root.join("a/y.txt"));
+
+        let mut terminal =
+            ratatui::Terminal::new(ratatui::backend::TestBackend::new(80, 12)).unwrap();
+        terminal
+            .draw(|frame| {
+                crate::ui::draw(frame, &mut app);
+            })
+            .unwrap();
+        let screen: String = terminal
+            .backend()
+            .buffer()
+            .content()
+            .iter()
+            .map(|cell| cell.symbol())
+            .collect();
+        for text in ["b", "c.txt", "x.txt", "inside y"] {
+            assert!(screen.contains(text), "{}", text);
+        }
+
+        app.collapse_current();
+        assert_eq!(selected(&app), root.join("a"));
+        // Siblings skip over the expanded directory's children
+        app.move_down();
+        assert_eq!(selected(&app), root.join("b"));
+        app.move_down();
+        assert_eq!(selected(&app), root.join("c.txt"));
     }
 }
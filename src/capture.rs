@@ -0,0 +1,116 @@
+use std::ffi::{OsStr, OsString};
+use std::io::{self, Read};
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::Duration;
+
+/// Output kept from a command; the rest is read and dropped so a chatty
+/// command cannot fill memory
+pub const MAX_OUTPUT_BYTES: u64 = 1024 * 1024;
+
+/// Output of a shell command, stdout and stderr interleaved
+pub struct CommandOutput {
+    pub status: ExitStatus,
+    pub lines: Vec<String>,
+    /// Set if the output went past `MAX_OUTPUT_BYTES` and was cut off
+    pub truncated: bool,
+}
+
+/// Run `command` with `sh -c`, collecting its output. The command and
+/// everything it started are killed if `cancel` is set before it exits.
+pub fn run(command: &OsStr, cancel: &AtomicBool) -> std::io::Result<CommandOutput> {
+    // Group the command so stderr of every part lands in the same pipe
+    let mut script = OsString::from("{ ");
+    script.push(command);
+    script.push("\n} 2>&1");
+
+    let mut sh = Command::new("sh");
+    sh.arg("-c")
+        .arg(&script)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null());
+    // In a process group of its own, so cancelling reaches the programs the
+    // script runs, which would otherwise keep the pipe open
+    #[cfg(unix)]
+    std::os::unix::process::CommandExt::process_group(&mut sh, 0);
+    let mut child = sh.spawn()?;
+
+    // Read on another thread so a full pipe can't block the command
+    let mut stdout = child.stdout.take().expect("stdout is piped");
+    let reader = thread::spawn(move || {
+        let mut bytes = Vec::new();
+        let _ = (&mut stdout).take(MAX_OUTPUT_BYTES).read_to_end(&mut bytes);
+        let dropped = io::copy(&mut stdout, &mut io::sink()).unwrap_or(0);
+        (bytes, dropped > 0)
+    });
+
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        if cancel.load(Ordering::Relaxed) {
+            kill(&mut child);
+            break child.wait()?;
+        }
+        thread::sleep(Duration::from_millis(20));
+    };
+
+    let (bytes, truncated) = reader.join().unwrap_or_default();
+    let lines = String::from_utf8_lossy(&bytes)
+        .lines()
+        .map(str::to_string)
+        .collect();
+    Ok(CommandOutput {
+        status,
+        lines,
+        truncated,
+    })
+}
+
+/// Kill `child` and, on Unix, the rest of its process group
+fn kill(child: &mut Child) {
+    #[cfg(unix)]
+    // SAFETY: `kill` takes no pointers; the negated id names the group `child` leads
+    unsafe {
+        libc::kill(-(child.id() as libc::pid_t), libc::SIGKILL);
+    }
+    #[cfg(not(unix))]
+    let _ = child.kill();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_run_captures_stdout_and_stderr() {
+        let cancel = AtomicBool::new(false);
+        let output = run(OsStr::new("echo out; echo err >&2; exit 3"), &cancel).unwrap();
+        assert_eq!(output.lines, vec!["out", "err"]);
+        assert_eq!(output.status.code(), Some(3));
+    }
+
+    #[test]
+    fn test_run_kills_on_cancel() {
+        let cancel = AtomicBool::new(true);
+        let started = std::time::Instant::now();
+        let output = run(OsStr::new("sleep 5; true"), &cancel).unwrap();
+        assert!(!output.status.success());
+        // `sleep` holds the pipe open unless it is killed along with `sh`
+        assert!(started.elapsed() < Duration::from_secs(4));
+    }
+
+    #[test]
+    fn test_run_caps_output() {
+        let cancel = AtomicBool::new(false);
+        let output = run(OsStr::new("yes | head -n 1000000"), &cancel).unwrap();
+        assert!(output.status.success());
+        assert!(output.truncated);
+        assert_eq!(output.lines.len() as u64, MAX_OUTPUT_BYTES / 2);
+
+        let output = run(OsStr::new("echo short"), &cancel).unwrap();
+        assert!(!output.truncated);
+    }
+}
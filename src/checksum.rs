@@ -0,0 +1,98 @@
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+use md5::Md5;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Algorithm {
+    Sha256,
+    Md5,
+}
+
+impl Algorithm {
+    pub fn name(self) -> &'static str {
+        match self {
+            Algorithm::Sha256 => "SHA-256",
+            Algorithm::Md5 => "MD5",
+        }
+    }
+}
+
+/// Hex digest of the file at `path`. `progress` is advanced by the number of bytes
+/// read so far. Returns `Ok(None)` if cancelled.
+pub fn compute(
+    path: &Path,
+    algorithm: Algorithm,
+    progress: &AtomicU64,
+    cancel: &AtomicBool,
+) -> io::Result<Option<String>> {
+    match algorithm {
+        Algorithm::Sha256 => digest::<Sha256>(path, progress, cancel),
+        Algorithm::Md5 => digest::<Md5>(path, progress, cancel),
+    }
+}
+
+fn digest<D: Digest>(
+    path: &Path,
+    progress: &AtomicU64,
+    cancel: &AtomicBool,
+) -> io::Result<Option<String>> {
+    let mut file = File::open(path)?;
+    let mut hasher = D::new();
+    let mut buf = vec![0u8; 256 * 1024];
+    loop {
+        if cancel.load(Ordering::Relaxed) {
+            return Ok(None);
+        }
+        let n = match file.read(&mut buf) {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        hasher.update(&buf[..n]);
+        progress.fetch_add(n as u64, Ordering::Relaxed);
+    }
+    let hex = hasher
+        .finalize()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect();
+    Ok(Some(hex))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_compute_known_digests() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("abc.txt");
+        std::fs::write(&path, "abc").unwrap();
+
+        let progress = AtomicU64::new(0);
+        let cancel = AtomicBool::new(false);
+        assert_eq!(
+            compute(&path, Algorithm::Sha256, &progress, &cancel).unwrap(),
+            Some("ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad".to_string())
+        );
+        assert_eq!(progress.load(Ordering::Relaxed), 3);
+        assert_eq!(
+            compute(&path, Algorithm::Md5, &progress, &cancel).unwrap(),
+            Some("900150983cd24fb0d6963f7d28e17f72".to_string())
+        );
+
+        cancel.store(true, Ordering::Relaxed);
+        assert_eq!(
+            compute(&path, Algorithm::Md5, &progress, &cancel).unwrap(),
+            None
+        );
+    }
+}
@@ -0,0 +1,239 @@
+use std::path::{Path, PathBuf};
+
+use clap::Parser;
+
+use crate::app::PickerMode;
+use crate::config::{self, Config};
+
+/// A VSCode-like file explorer TUI
+#[derive(Debug, Parser)]
+#[command(name = "ft", version, about)]
+pub struct Cli {
+    /// Directories to browse, each in its own tab (default: current directory)
+    pub paths: Vec<PathBuf>,
+
+    /// Expand the tree down to this file and select it; the tree is rooted at
+    /// the current directory if it contains the file, else at its parent
+    #[arg(long, value_name = "PATH")]
+    pub select: Option<PathBuf>,
+
+    /// Show hidden files
+    #[arg(long)]
+    pub hidden: bool,
+
+    /// Disable git status integration
+    #[arg(long)]
+    pub no_git: bool,
+
+    /// Expand directories up to N levels on startup
+    #[arg(long, value_name = "N")]
+    pub depth: Option<usize>,
+
+    /// Command run by Enter (use <filepath> for the selected file)
+    #[arg(long, value_name = "COMMAND")]
+    pub cmd: Option<String>,
+
+    /// Neovim server to open files in with Ctrl-e (default: $NVIM)
+    #[arg(long, value_name = "ADDRESS")]
+    pub nvim_server: Option<String>,
+
+    /// Accept JSON commands on this Unix socket (reveal, refresh, get_selection, quit)
+    #[cfg(unix)]
+    #[arg(long, value_name = "SOCKET")]
+    pub listen: Option<PathBuf>,
+
+    /// Print the tree to stdout and exit instead of starting the TUI
+    #[arg(long)]
+    pub dump: bool,
+
+    /// With --dump, print JSON instead of indented text
+    #[arg(long, requires = "dump")]
+    pub json: bool,
+
+    /// Config file to use instead of ~/.config/filetree/config.toml
+    #[arg(long, value_name = "PATH")]
+    pub config: Option<PathBuf>,
+
+    /// File picker: Enter on a file prints its path to stdout and exits
+    #[arg(long, conflicts_with = "choose_files")]
+    pub choose_file: bool,
+
+    /// File picker: Enter prints all marked paths to stdout and exits
+    #[arg(long)]
+    pub choose_files: bool,
+}
+
+impl Cli {
+    pub fn picker(&self) -> Option<PickerMode> {
+        if self.choose_file {
+            Some(PickerMode::Single)
+        } else if self.choose_files {
+            Some(PickerMode::Multiple)
+        } else {
+            None
+        }
+    }
+
+    /// Root directories of the tabs and the path to select in the first one.
+    /// A file given as a root opens its parent with the file selected.
+    pub fn roots(&self, cwd: &Path) -> anyhow::Result<(Vec<PathBuf>, Option<PathBuf>)> {
+        let absolute = |p: &Path| {
+            let p = cwd.join(p);
+            p.canonicalize().unwrap_or(p)
+        };
+        let mut select = self
+            .select
+            .as_deref()
+            .map(|p| {
+                let p = cwd.join(p);
+                p.canonicalize()
+                    .map_err(|e| anyhow::anyhow!("Cannot select {}: {}", p.display(), e))
+            })
+            .transpose()?;
+
+        let mut roots = Vec::new();
+        for path in &self.paths {
+            let path = absolute(path);
+            match path.parent() {
+                Some(parent) if !path.is_dir() && path.exists() => {
+                    select.get_or_insert_with(|| path.clone());
+                    roots.push(parent.to_path_buf());
+                }
+                _ => roots.push(path),
+            }
+        }
+        if roots.is_empty() {
+            let cwd = absolute(cwd);
+            roots.push(match &select {
+                Some(path) if !path.starts_with(&cwd) => match path.parent() {
+                    Some(parent) if !path.is_dir() => parent.to_path_buf(),
+                    _ => path.clone(),
+                },
+                _ => cwd,
+            });
+        }
+        if let Some(path) = &select {
+            if !path.starts_with(&roots[0]) {
+                anyhow::bail!("{} is not inside {}", path.display(), roots[0].display());
+            }
+        }
+        Ok((roots, select))
+    }
+
+    /// Load the config file and the `.filetree.toml` of `root`, then apply
+    /// command-line and environment overrides
+    pub fn load_config(&self, root: &Path) -> anyhow::Result<Config> {
+        let global = match &self.config {
+            Some(path) => Some(path.clone()),
+            None => config::config_dir()
+                .map(|dir| dir.join("config.toml"))
+                .filter(|path| path.exists()),
+        };
+        let project = root.join(config::PROJECT_FILE);
+        let project = project.is_file().then_some(project);
+        let mut config = Config::load_layers(global.as_deref(), project.as_deref())?;
+
+        if self.hidden {
+            config.show_hidden = true;
+        }
+        if self.no_git {
+            config.git = false;
+        }
+        if self.depth.is_some() {
+            config.depth = self.depth;
+        }
+        if let Some(cmd) = self
+            .cmd
+            .clone()
+            .or_else(|| std::env::var("FILETREE_DEFAULT_CMD").ok())
+        {
+            config.default_command = Some(cmd);
+        }
+        if let Some(server) = self
+            .nvim_server
+            .clone()
+            .or_else(|| std::env::var("NVIM").ok())
+        {
+            config.nvim_server = Some(server);
+        }
+
+        Ok(config)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cli_parses_flags() {
+        let cli = Cli::try_parse_from([
+            "ft", "--hidden", "--no-git", "--depth", "2", "--cmd", "vim", "/tmp",
+        ])
+        .unwrap();
+        assert!(cli.hidden);
+        assert!(cli.no_git);
+        assert_eq!(cli.depth, Some(2));
+        assert_eq!(cli.cmd.as_deref(), Some("vim"));
+        assert_eq!(cli.paths, vec![PathBuf::from("/tmp")]);
+    }
+
+    #[test]
+    fn test_cli_nvim_server() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let empty = dir.path().join("config.toml");
+        std::fs::write(&empty, "").unwrap();
+        let cli = Cli::try_parse_from(["ft", "--nvim-server", "/tmp/nvim.sock"]).unwrap();
+        let config = Cli {
+            config: Some(empty),
+            ..cli
+        }
+        .load_config(dir.path())
+        .unwrap();
+        assert_eq!(config.nvim_server.as_deref(), Some("/tmp/nvim.sock"));
+    }
+
+    #[test]
+    fn test_cli_multiple_paths() {
+        let cli = Cli::try_parse_from(["ft", "/a", "/b", "--hidden"]).unwrap();
+        assert_eq!(cli.paths, vec![PathBuf::from("/a"), PathBuf::from("/b")]);
+    }
+
+    #[test]
+    fn test_cli_roots_and_select() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        std::fs::create_dir_all(root.join("a/b")).unwrap();
+        std::fs::write(root.join("a/b/c.txt"), "").unwrap();
+        let roots = |args: &[&str], cwd: &Path| {
+            let cli = Cli::try_parse_from(std::iter::once("ft").chain(args.iter().copied()));
+            cli.unwrap().roots(cwd)
+        };
+
+        // Relative to the current directory, which contains the file
+        let (paths, select) = roots(&["--select", "a/b/c.txt"], &root).unwrap();
+        assert_eq!(paths, vec![root.clone()]);
+        assert_eq!(select, Some(root.join("a/b/c.txt")));
+
+        // Outside the current directory: rooted at the parent
+        std::fs::create_dir(root.join("other")).unwrap();
+        let (paths, _) = roots(&["--select", "../a/b/c.txt"], &root.join("other")).unwrap();
+        assert_eq!(paths, vec![root.join("a/b")]);
+
+        // A file argument opens its parent
+        let (paths, select) = roots(&["a/b/c.txt", "a"], &root).unwrap();
+        assert_eq!(paths, vec![root.join("a/b"), root.join("a")]);
+        assert_eq!(select, Some(root.join("a/b/c.txt")));
+
+        assert!(roots(&["a/b", "--select", "a/b/c.txt"], &root).is_ok());
+        assert!(roots(&["a/b", "--select", "a"], &root).is_err());
+        assert!(roots(&["--select", "missing"], &root).is_err());
+    }
+
+    #[test]
+    fn test_cli_choose_flags_conflict() {
+        assert!(Cli::try_parse_from(["ft", "--choose-file", "--choose-files"]).is_err());
+        let cli = Cli::try_parse_from(["ft", "--choose-files"]).unwrap();
+        assert_eq!(cli.picker(), Some(PickerMode::Multiple));
+    }
+}
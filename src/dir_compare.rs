@@ -0,0 +1,177 @@
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Difference {
+    OnlyInA,
+    OnlyInB,
+    /// Present on both sides with different contents (or a file on one side
+    /// and a directory on the other)
+    Differs,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompareEntry {
+    /// Path relative to both compared directories
+    pub path: PathBuf,
+    pub is_dir: bool,
+    pub difference: Difference,
+}
+
+/// Recursively compare directories `a` and `b`, like `diff -rq`.
+/// A directory present on only one side is reported once, not per file inside it.
+/// Skips `.git` and, unless `show_hidden`, dotfiles. Symlinks are compared by target.
+pub fn compare(a: &Path, b: &Path, show_hidden: bool, cancel: &AtomicBool) -> Vec<CompareEntry> {
+    let mut entries = Vec::new();
+    compare_dir(a, b, Path::new(""), show_hidden, cancel, &mut entries);
+    entries.sort_by(|x, y| x.path.cmp(&y.path));
+    entries
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Kind {
+    Dir,
+    File,
+    Symlink,
+}
+
+fn list(dir: &Path, show_hidden: bool) -> BTreeMap<String, Kind> {
+    let mut names = BTreeMap::new();
+    let Ok(entries) = fs::read_dir(dir) else {
+        return names;
+    };
+    for entry in entries.filter_map(|e| e.ok()) {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if name == ".git" || (!show_hidden && name.starts_with('.')) {
+            continue;
+        }
+        // file_type() does not follow symlinks, which avoids cycles
+        let kind = match entry.file_type() {
+            Ok(t) if t.is_dir() => Kind::Dir,
+            Ok(t) if t.is_symlink() => Kind::Symlink,
+            Ok(_) => Kind::File,
+            Err(_) => continue,
+        };
+        names.insert(name, kind);
+    }
+    names
+}
+
+fn compare_dir(
+    a: &Path,
+    b: &Path,
+    relative: &Path,
+    show_hidden: bool,
+    cancel: &AtomicBool,
+    entries: &mut Vec<CompareEntry>,
+) {
+    let in_a = list(&a.join(relative), show_hidden);
+    let mut in_b = list(&b.join(relative), show_hidden);
+
+    for (name, kind_a) in in_a {
+        if cancel.load(Ordering::Relaxed) {
+            return;
+        }
+        let path = relative.join(&name);
+        let entry = |difference| CompareEntry {
+            path: path.clone(),
+            is_dir: kind_a == Kind::Dir,
+            difference,
+        };
+        match in_b.remove(&name) {
+            None => entries.push(entry(Difference::OnlyInA)),
+            Some(Kind::Dir) if kind_a == Kind::Dir => {
+                compare_dir(a, b, &path, show_hidden, cancel, entries)
+            }
+            Some(kind_b) if kind_b != kind_a => entries.push(entry(Difference::Differs)),
+            Some(_) => {
+                if !same_contents(&a.join(&path), &b.join(&path), kind_a) {
+                    entries.push(entry(Difference::Differs));
+                }
+            }
+        }
+    }
+
+    for (name, kind_b) in in_b {
+        entries.push(CompareEntry {
+            path: relative.join(name),
+            is_dir: kind_b == Kind::Dir,
+            difference: Difference::OnlyInB,
+        });
+    }
+}
+
+fn same_contents(a: &Path, b: &Path, kind: Kind) -> bool {
+    if kind == Kind::Symlink {
+        return fs::read_link(a).ok() == fs::read_link(b).ok();
+    }
+    match (fs::metadata(a), fs::metadata(b)) {
+        (Ok(ma), Ok(mb)) if ma.len() == mb.len() => same_bytes(a, b).unwrap_or(false),
+        _ => false,
+    }
+}
+
+fn same_bytes(a: &Path, b: &Path) -> io::Result<bool> {
+    let mut fa = File::open(a)?;
+    let mut fb = File::open(b)?;
+    let mut buf_a = [0u8; 64 * 1024];
+    let mut buf_b = [0u8; 64 * 1024];
+    loop {
+        let n = fa.read(&mut buf_a)?;
+        if n == 0 {
+            // Sizes matched up front, but the file may have grown since
+            return Ok(fb.read(&mut buf_b)? == 0);
+        }
+        fb.read_exact(&mut buf_b[..n])?;
+        if buf_a[..n] != buf_b[..n] {
+            return Ok(false);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_compare_reports_differences() {
+        let dir = TempDir::new().unwrap();
+        let a = dir.path().join("a");
+        let b = dir.path().join("b");
+        for root in [&a, &b] {
+            fs::create_dir_all(root.join("sub")).unwrap();
+            fs::write(root.join("same.txt"), "same").unwrap();
+            fs::write(root.join(".hidden"), root.to_string_lossy().as_bytes()).unwrap();
+        }
+        fs::write(a.join("sub/changed.txt"), "one").unwrap();
+        fs::write(b.join("sub/changed.txt"), "two").unwrap();
+        fs::write(a.join("only_a.txt"), "a").unwrap();
+        fs::create_dir_all(b.join("only_b/deep")).unwrap();
+        fs::write(b.join("only_b/deep/file"), "b").unwrap();
+        fs::write(a.join("kind"), "file").unwrap();
+        fs::create_dir(b.join("kind")).unwrap();
+
+        let entries = compare(&a, &b, false, &AtomicBool::new(false));
+        let summary: Vec<(&Path, Difference)> = entries
+            .iter()
+            .map(|e| (e.path.as_path(), e.difference))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (Path::new("kind"), Difference::Differs),
+                (Path::new("only_a.txt"), Difference::OnlyInA),
+                (Path::new("only_b"), Difference::OnlyInB),
+                (Path::new("sub/changed.txt"), Difference::Differs),
+            ]
+        );
+        assert!(entries[2].is_dir);
+
+        let entries = compare(&a, &b, true, &AtomicBool::new(false));
+        assert!(entries.iter().any(|e| e.path == Path::new(".hidden")));
+    }
+}
@@ -0,0 +1,69 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::AtomicBool;
+
+use filetree::file_ops;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DuEntry {
+    pub path: PathBuf,
+    pub name: String,
+    pub is_dir: bool,
+    /// Recursive size in bytes
+    pub size: u64,
+}
+
+/// Sizes of the direct children of `dir`, largest first. Symlinks are counted by their
+/// own size and not followed. Returns `None` if cancelled, and an error if `dir`
+/// cannot be read.
+pub fn scan(dir: &Path, cancel: &AtomicBool) -> io::Result<Option<Vec<DuEntry>>> {
+    let mut entries = Vec::new();
+    for entry in fs::read_dir(dir)?.filter_map(|e| e.ok()) {
+        let Ok(metadata) = entry.metadata() else {
+            continue;
+        };
+        let path = entry.path();
+        let size = if metadata.is_dir() {
+            match file_ops::dir_size(&path, cancel) {
+                Some(size) => size,
+                None => return Ok(None),
+            }
+        } else {
+            metadata.len()
+        };
+        entries.push(DuEntry {
+            name: entry.file_name().to_string_lossy().to_string(),
+            path,
+            is_dir: metadata.is_dir(),
+            size,
+        });
+    }
+    entries.sort_by(|a, b| b.size.cmp(&a.size).then_with(|| a.name.cmp(&b.name)));
+    Ok(Some(entries))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_scan_sorts_by_recursive_size() {
+        let dir = TempDir::new().unwrap();
+        let root = dir.path();
+        fs::create_dir_all(root.join("big/nested")).unwrap();
+        fs::write(root.join("big/nested/a.bin"), [0u8; 300]).unwrap();
+        fs::write(root.join("medium.bin"), [0u8; 200]).unwrap();
+        fs::write(root.join("small.bin"), [0u8; 10]).unwrap();
+
+        let entries = scan(root, &AtomicBool::new(false)).unwrap().unwrap();
+        let names: Vec<&str> = entries.iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(names, vec!["big", "medium.bin", "small.bin"]);
+        assert_eq!(entries[0].size, 300);
+        assert!(entries[0].is_dir);
+
+        assert_eq!(scan(root, &AtomicBool::new(true)).unwrap(), None);
+        assert!(scan(&root.join("missing"), &AtomicBool::new(false)).is_err());
+    }
+}
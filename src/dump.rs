@@ -0,0 +1,185 @@
+use std::path::Path;
+use std::sync::atomic::AtomicBool;
+
+use serde_json::{json, Value};
+
+use crate::app::App;
+use crate::config::Config;
+use filetree::file_ops;
+use filetree::file_tree::{self, ExpandLimits, ExpandOutcome, FileNode};
+use filetree::git_status::GitRepo;
+
+/// Render the tree below `path` for `--dump`: `config.depth` levels (or up
+/// to `expand_max_depth`), as JSON or indented text
+pub fn dump(path: &Path, config: &Config, as_json: bool) -> anyhow::Result<String> {
+    let mut root = FileNode::new(path.to_path_buf(), 0);
+    if !root.is_dir {
+        anyhow::bail!("{} is not a directory", path.display());
+    }
+    root.expanded = true;
+    root.load_children(config.show_hidden, config.sort_order())?;
+
+    let limits = ExpandLimits {
+        max_depth: config.depth.unwrap_or(config.expand_max_depth).max(1),
+        max_entries: config.expand_max_entries,
+        follow_symlinks: config.follow_symlinks,
+    };
+    let cancel = AtomicBool::new(false);
+    let root = match file_tree::expand_subtree(
+        root,
+        config.show_hidden,
+        config.sort_order(),
+        limits,
+        &cancel,
+    )? {
+        ExpandOutcome::Done(root, _) => root,
+        ExpandOutcome::CapReached | ExpandOutcome::Cancelled => anyhow::bail!(
+            "More than {} entries (expand_max_entries); limit the output with --depth",
+            config.expand_max_entries
+        ),
+    };
+    let git = if config.git {
+        GitRepo::new(path)
+    } else {
+        GitRepo::default()
+    };
+
+    Ok(if as_json {
+        format!("{:#}\n", to_json(&root, &git))
+    } else {
+        let mut text = String::new();
+        write_text(&root, &git, &mut text);
+        text
+    })
+}
+
+/// Recursive size of `node`; symlinks count as their own size
+fn size(node: &FileNode) -> Option<u64> {
+    if node.is_dir && !node.is_symlink() {
+        file_ops::dir_size(&node.path, &AtomicBool::new(false))
+    } else {
+        node.size
+    }
+}
+
+fn to_json(node: &FileNode, git: &GitRepo) -> Value {
+    let kind = if node.is_symlink() {
+        "symlink"
+    } else if node.is_dir {
+        "dir"
+    } else {
+        "file"
+    };
+    let mut value = json!({
+        "name": node.name,
+        "path": node.path.to_string_lossy(),
+        "type": kind,
+        "size": size(node),
+        "git": git.get_status(&node.path).name(),
+    });
+    if let Some(target) = &node.symlink_target {
+        value["target"] = json!(target.to_string_lossy());
+    }
+    if let Some(error) = &node.read_error {
+        value["error"] = json!(error);
+    }
+    if node.expanded {
+        value["children"] = node.children.iter().map(|c| to_json(c, git)).collect();
+    }
+    value
+}
+
+fn write_text(node: &FileNode, git: &GitRepo, out: &mut String) {
+    out.push_str(&"  ".repeat(node.depth));
+    out.push_str(&node.name);
+    if node.is_dir {
+        out.push('/');
+    }
+    if let Some(target) = &node.symlink_target {
+        out.push_str(&format!(" -> {}", target.display()));
+    }
+    if let Some(size) = size(node) {
+        out.push_str(&format!("  {}", App::format_size(size)));
+    }
+    if let Some(status) = git.get_status(&node.path).name() {
+        out.push_str(&format!("  [{}]", status));
+    }
+    if let Some(error) = &node.read_error {
+        out.push_str(&format!("  [{}]", error));
+    }
+    out.push('\n');
+    if node.expanded {
+        for child in &node.children {
+            write_text(child, git, out);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn config() -> Config {
+        Config {
+            git: false,
+            ..Config::default()
+        }
+    }
+
+    #[test]
+    fn test_dump_text_respects_depth() {
+        let dir = tempfile::TempDir::new().unwrap();
+        fs::create_dir_all(dir.path().join("src/deep")).unwrap();
+        fs::write(dir.path().join("src/main.rs"), "fn main() {}").unwrap();
+        fs::write(dir.path().join("src/deep/x.rs"), "").unwrap();
+        let name = dir.path().file_name().unwrap().to_string_lossy();
+
+        let text = dump(dir.path(), &config(), false).unwrap();
+        assert_eq!(
+            text,
+            format!(
+                "{}/  12 B\n  src/  12 B\n    deep/  0 B\n      x.rs  0 B\n    main.rs  12 B\n",
+                name
+            )
+        );
+
+        let shallow = Config {
+            depth: Some(1),
+            ..config()
+        };
+        let text = dump(dir.path(), &shallow, false).unwrap();
+        assert_eq!(text.lines().count(), 2);
+    }
+
+    #[test]
+    fn test_dump_json() {
+        let dir = tempfile::TempDir::new().unwrap();
+        fs::create_dir(dir.path().join("sub")).unwrap();
+        fs::write(dir.path().join("sub/a.txt"), "abc").unwrap();
+
+        let value: Value =
+            serde_json::from_str(&dump(dir.path(), &config(), true).unwrap()).unwrap();
+        assert_eq!(value["type"], "dir");
+        assert_eq!(value["size"], 3);
+        let file = &value["children"][0]["children"][0];
+        assert_eq!(file["name"], "a.txt");
+        assert_eq!(file["type"], "file");
+        assert_eq!(file["git"], Value::Null);
+        assert!(file.get("children").is_none());
+    }
+
+    #[test]
+    fn test_dump_entry_cap() {
+        let dir = tempfile::TempDir::new().unwrap();
+        for i in 0..5 {
+            fs::create_dir(dir.path().join(format!("d{}", i))).unwrap();
+            fs::write(dir.path().join(format!("d{}/f", i)), "").unwrap();
+        }
+        let capped = Config {
+            expand_max_entries: 3,
+            ..config()
+        };
+        assert!(dump(dir.path(), &capped, false).is_err());
+    }
+}
@@ -1,8 +1,16 @@
+//! File operations used by the explorer; destinations never overwrite existing entries
+
 use std::fs::{self, OpenOptions};
 use std::io::ErrorKind;
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+/// Upper bound on the threads copying the files of a directory
+const MAX_COPY_THREADS: usize = 8;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum ClipboardContent {
     Copy(Vec<PathBuf>),
     Cut(Vec<PathBuf>),
@@ -22,7 +30,6 @@ impl Clipboard {
         self.content = Some(ClipboardContent::Cut(paths));
     }
 
-    #[allow(dead_code)]
     pub fn clear(&mut self) {
         self.content = None;
     }
@@ -32,40 +39,163 @@ impl Clipboard {
     }
 }
 
-pub fn copy_file(src: &Path, dest_dir: &Path) -> anyhow::Result<PathBuf> {
+/// Whether an operation failed because of missing permissions
+pub fn is_permission_denied(err: &anyhow::Error) -> bool {
+    err.downcast_ref::<std::io::Error>()
+        .is_some_and(|e| e.kind() == ErrorKind::PermissionDenied)
+}
+
+/// Path `src` would be copied or moved to inside `dest_dir`, avoiding existing names
+pub fn unique_destination(src: &Path, dest_dir: &Path) -> anyhow::Result<PathBuf> {
     let file_name = src
         .file_name()
         .ok_or_else(|| anyhow::anyhow!("Invalid file name"))?;
-    let dest = dest_dir.join(file_name);
-    let dest = get_unique_path(&dest);
+    Ok(get_unique_path(&dest_dir.join(file_name)))
+}
+
+pub fn copy_file(src: &Path, dest_dir: &Path) -> anyhow::Result<PathBuf> {
+    copy_file_with(
+        src,
+        dest_dir,
+        CopyOptions::default(),
+        &CopyProgress::default(),
+    )
+}
+
+/// What a copy keeps besides the contents. Mode bits and modification times
+/// are always kept, and symlinks are recreated rather than followed.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct CopyOptions {
+    /// Also copy extended attributes
+    pub xattrs: bool,
+}
+
+/// Files of a copy: how many there are and how many are done
+#[derive(Debug, Default)]
+pub struct CopyProgress {
+    pub total: AtomicU64,
+    /// Counts only the files copied without a gap before them, so it advances
+    /// through the tree in order even though the files are copied in parallel
+    pub copied: AtomicU64,
+}
 
-    if src.is_dir() {
-        copy_dir_recursive(src, &dest)?;
+/// `copy_file` with `options`, reporting the files of a directory copy to `progress`
+pub fn copy_file_with(
+    src: &Path,
+    dest_dir: &Path,
+    options: CopyOptions,
+    progress: &CopyProgress,
+) -> anyhow::Result<PathBuf> {
+    let dest = unique_destination(src, dest_dir)?;
+    copy_tree(src, &dest, options, progress)?;
+    Ok(dest)
+}
+
+/// Copy the file, symlink or directory `src` to `dest`
+fn copy_tree(
+    src: &Path,
+    dest: &Path,
+    options: CopyOptions,
+    progress: &CopyProgress,
+) -> anyhow::Result<()> {
+    if fs::symlink_metadata(src)?.is_dir() {
+        copy_dir_recursive(src, dest, options, progress)
     } else {
-        fs::copy(src, &dest)?;
+        progress.total.fetch_add(1, Ordering::Relaxed);
+        copy_entry(src, dest, options)?;
+        progress.copied.fetch_add(1, Ordering::Relaxed);
+        Ok(())
     }
-    Ok(dest)
 }
 
 pub fn move_file(src: &Path, dest_dir: &Path) -> anyhow::Result<PathBuf> {
-    let file_name = src
-        .file_name()
-        .ok_or_else(|| anyhow::anyhow!("Invalid file name"))?;
-    let dest = dest_dir.join(file_name);
-    let dest = get_unique_path(&dest);
+    let dest = unique_destination(src, dest_dir)?;
 
     if fs::rename(src, &dest).is_err() {
-        if src.is_dir() {
-            copy_dir_recursive(src, &dest)?;
-            fs::remove_dir_all(src)?;
-        } else {
-            fs::copy(src, &dest)?;
-            fs::remove_file(src)?;
-        }
+        move_by_copy(src, &dest, CopyOptions::default(), &CopyProgress::default())?;
     }
     Ok(dest)
 }
 
+/// Whether moving `src` into `dest_dir` crosses filesystems, so it has to copy
+#[cfg(unix)]
+pub fn is_cross_device(src: &Path, dest_dir: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+
+    match (fs::symlink_metadata(src), fs::metadata(dest_dir)) {
+        (Ok(src), Ok(dest)) => src.dev() != dest.dev(),
+        _ => false,
+    }
+}
+
+/// `is_cross_device` by drive: paths on different volumes cannot be renamed
+#[cfg(not(unix))]
+pub fn is_cross_device(src: &Path, dest_dir: &Path) -> bool {
+    let volume = |path: &Path| {
+        let path = path.canonicalize().ok()?;
+        path.components().next().map(|c| c.as_os_str().to_owned())
+    };
+    match (volume(src), volume(dest_dir)) {
+        (Some(src), Some(dest)) => src != dest,
+        _ => false,
+    }
+}
+
+/// `move_file` for a move across filesystems, reporting progress
+pub fn move_file_across(
+    src: &Path,
+    dest_dir: &Path,
+    options: CopyOptions,
+    progress: &CopyProgress,
+) -> anyhow::Result<PathBuf> {
+    let dest = unique_destination(src, dest_dir)?;
+    move_by_copy(src, &dest, options, progress)?;
+    Ok(dest)
+}
+
+/// Copy `src` to `dest`, then delete `src`. If the copy fails, the partial copy
+/// is removed and `src` is left as it was.
+fn move_by_copy(
+    src: &Path,
+    dest: &Path,
+    options: CopyOptions,
+    progress: &CopyProgress,
+) -> anyhow::Result<()> {
+    if let Err(e) = copy_tree(src, dest, options, progress) {
+        if fs::symlink_metadata(dest).is_ok() {
+            let _ = make_writable(dest);
+            let _ = delete_file(dest);
+        }
+        return Err(e);
+    }
+    delete_file(src)
+        .map_err(|e| anyhow::anyhow!("Copied, but could not remove the original: {}", e))
+}
+
+/// Give the owner write access to the directories below `path` so a partial
+/// copy of read-only directories can be removed
+fn make_writable(path: &Path) -> std::io::Result<()> {
+    let metadata = fs::symlink_metadata(path)?;
+    if metadata.is_dir() {
+        #[cfg(unix)]
+        let permissions = {
+            use std::os::unix::fs::PermissionsExt;
+            fs::Permissions::from_mode(metadata.permissions().mode() | 0o700)
+        };
+        #[cfg(not(unix))]
+        let permissions = {
+            let mut permissions = metadata.permissions();
+            permissions.set_readonly(false);
+            permissions
+        };
+        fs::set_permissions(path, permissions)?;
+        for entry in fs::read_dir(path)?.flatten() {
+            make_writable(&entry.path())?;
+        }
+    }
+    Ok(())
+}
+
 pub fn delete_file(path: &Path) -> anyhow::Result<()> {
     if path.is_dir() {
         fs::remove_dir_all(path)?;
@@ -97,13 +227,12 @@ pub fn rename_file(path: &Path, new_name: &str) -> anyhow::Result<PathBuf> {
             if new_path.exists() {
                 anyhow::bail!("File already exists: {}", new_path.display());
             }
-            if path.is_dir() {
-                copy_dir_recursive(path, &new_path)?;
-                fs::remove_dir_all(path)?;
-            } else {
-                fs::copy(path, &new_path)?;
-                fs::remove_file(path)?;
-            }
+            move_by_copy(
+                path,
+                &new_path,
+                CopyOptions::default(),
+                &CopyProgress::default(),
+            )?;
             Ok(new_path)
         }
         Err(e) => Err(e.into()),
@@ -123,6 +252,21 @@ pub fn create_file(parent_dir: &Path, name: &str) -> anyhow::Result<PathBuf> {
     }
 }
 
+/// Save raw RGBA pixels as `<name>.png` in `dest_dir`, avoiding existing names
+pub fn write_png(
+    dest_dir: &Path,
+    name: &str,
+    width: u32,
+    height: u32,
+    rgba: Vec<u8>,
+) -> anyhow::Result<PathBuf> {
+    let image = image::RgbaImage::from_raw(width, height, rgba)
+        .ok_or_else(|| anyhow::anyhow!("Image data does not match its size"))?;
+    let path = get_unique_path(&dest_dir.join(format!("{}.png", name)));
+    image.save_with_format(&path, image::ImageFormat::Png)?;
+    Ok(path)
+}
+
 pub fn create_directory(parent_dir: &Path, name: &str) -> anyhow::Result<PathBuf> {
     let path = parent_dir.join(name);
 
@@ -136,23 +280,339 @@ pub fn create_directory(parent_dir: &Path, name: &str) -> anyhow::Result<PathBuf
     }
 }
 
-fn copy_dir_recursive(src: &Path, dest: &Path) -> anyhow::Result<()> {
-    fs::create_dir_all(dest)?;
+/// Create the directories of `src` below `dest` in order, then copy the files
+/// on several threads, which is much faster for many small files. Directory
+/// metadata is applied last, as adding files changes it.
+fn copy_dir_recursive(
+    src: &Path,
+    dest: &Path,
+    options: CopyOptions,
+    progress: &CopyProgress,
+) -> anyhow::Result<()> {
+    let mut dirs = Vec::new();
+    let mut files = Vec::new();
+    let mut pending = vec![(src.to_path_buf(), dest.to_path_buf())];
+    while let Some((src_dir, dest_dir)) = pending.pop() {
+        fs::create_dir_all(&dest_dir)?;
+        let mut entries = fs::read_dir(&src_dir)?.collect::<Result<Vec<_>, _>>()?;
+        entries.sort_by_key(|e| e.file_name());
+        // Subdirectories are pushed last first so they are walked in name order
+        for entry in entries.into_iter().rev() {
+            let src_path = entry.path();
+            let dest_path = dest_dir.join(entry.file_name());
+            if entry.file_type()?.is_dir() {
+                pending.push((src_path, dest_path));
+            } else {
+                files.push((src_path, dest_path));
+            }
+        }
+        dirs.push((src_dir, dest_dir));
+    }
+    progress
+        .total
+        .fetch_add(files.len() as u64, Ordering::Relaxed);
+    copy_files_parallel(&files, options, progress)?;
 
-    for entry in fs::read_dir(src)? {
-        let entry = entry?;
-        let src_path = entry.path();
-        let dest_path = dest.join(entry.file_name());
+    for (src_dir, dest_dir) in dirs.iter().rev() {
+        let metadata = fs::metadata(src_dir)?;
+        fs::set_permissions(dest_dir, metadata.permissions())?;
+        copy_times(&metadata, dest_dir)?;
+        if options.xattrs {
+            copy_xattrs(src_dir, dest_dir)?;
+        }
+    }
+    Ok(())
+}
 
-        if src_path.is_dir() {
-            copy_dir_recursive(&src_path, &dest_path)?;
+/// Copy a file or recreate a symlink, keeping the modification time
+fn copy_entry(src: &Path, dest: &Path, options: CopyOptions) -> std::io::Result<()> {
+    let metadata = fs::symlink_metadata(src)?;
+    if metadata.is_symlink() {
+        let target = fs::read_link(src)?;
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(target, dest)?;
+        #[cfg(windows)]
+        if src.is_dir() {
+            std::os::windows::fs::symlink_dir(target, dest)?;
         } else {
-            fs::copy(&src_path, &dest_path)?;
+            std::os::windows::fs::symlink_file(target, dest)?;
         }
+        return copy_times(&metadata, dest);
+    }
+    clone_or_copy(src, dest)?;
+    copy_times(&metadata, dest)?;
+    if options.xattrs {
+        copy_xattrs(src, dest)?;
+    }
+    Ok(())
+}
+
+/// Set the access and modification times of `dest`, or of the link itself for
+/// a symlink, to those in `metadata`. Works on files that cannot be opened.
+#[cfg(unix)]
+fn copy_times(metadata: &fs::Metadata, dest: &Path) -> std::io::Result<()> {
+    use std::os::unix::fs::MetadataExt;
+
+    let timespec = |secs: i64, nanos: i64| {
+        // SAFETY: `timespec` is plain data; some targets add private padding
+        let mut time: libc::timespec = unsafe { std::mem::zeroed() };
+        time.tv_sec = secs as libc::time_t;
+        time.tv_nsec = nanos as _;
+        time
+    };
+    let times = [
+        timespec(metadata.atime(), metadata.atime_nsec()),
+        timespec(metadata.mtime(), metadata.mtime_nsec()),
+    ];
+    let path = c_path(dest)?;
+    // SAFETY: `path` is NUL-terminated and `times` holds two entries
+    let result = unsafe {
+        libc::utimensat(
+            libc::AT_FDCWD,
+            path.as_ptr(),
+            times.as_ptr(),
+            libc::AT_SYMLINK_NOFOLLOW,
+        )
+    };
+    if result == -1 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// `copy_times` for files only: directories and links cannot be opened for
+/// writing here, so they keep the time they were created with
+#[cfg(not(unix))]
+fn copy_times(metadata: &fs::Metadata, dest: &Path) -> std::io::Result<()> {
+    if !metadata.is_file() {
+        return Ok(());
+    }
+    let times = fs::FileTimes::new()
+        .set_accessed(metadata.accessed()?)
+        .set_modified(metadata.modified()?);
+    fs::File::options().write(true).open(dest)?.set_times(times)
+}
+
+#[cfg(unix)]
+fn c_path(path: &Path) -> std::io::Result<std::ffi::CString> {
+    use std::os::unix::ffi::OsStrExt;
+
+    std::ffi::CString::new(path.as_os_str().as_bytes())
+        .map_err(|e| std::io::Error::new(ErrorKind::InvalidInput, e))
+}
+
+/// Copy each `(src, dest)` pair, stopping at the first failure
+fn copy_files_parallel(
+    files: &[(PathBuf, PathBuf)],
+    options: CopyOptions,
+    progress: &CopyProgress,
+) -> anyhow::Result<()> {
+    let workers = thread::available_parallelism()
+        .map_or(1, |n| n.get())
+        .min(MAX_COPY_THREADS)
+        .min(files.len());
+    let base = progress.copied.load(Ordering::Relaxed);
+    let next = AtomicUsize::new(0);
+    let failed = AtomicBool::new(false);
+    let error = Mutex::new(None);
+    // Which files are done, and how many of them form an unbroken prefix
+    let done = Mutex::new((vec![false; files.len()], 0));
+
+    thread::scope(|scope| {
+        for _ in 0..workers {
+            scope.spawn(|| {
+                while !failed.load(Ordering::Relaxed) {
+                    let i = next.fetch_add(1, Ordering::Relaxed);
+                    let Some((src, dest)) = files.get(i) else {
+                        break;
+                    };
+                    if let Err(e) = copy_entry(src, dest, options) {
+                        failed.store(true, Ordering::Relaxed);
+                        error.lock().unwrap().get_or_insert(e);
+                        break;
+                    }
+                    let mut done = done.lock().unwrap();
+                    let (finished, prefix) = &mut *done;
+                    finished[i] = true;
+                    while finished.get(*prefix) == Some(&true) {
+                        *prefix += 1;
+                    }
+                    progress
+                        .copied
+                        .store(base + *prefix as u64, Ordering::Relaxed);
+                }
+            });
+        }
+    });
+
+    match error.into_inner().unwrap() {
+        Some(e) => Err(e.into()),
+        None => Ok(()),
+    }
+}
+
+/// Copy the file `src` to `dest`, sharing its blocks copy-on-write (a reflink)
+/// where the filesystem supports it, so large files are duplicated instantly.
+/// Falls back to copying the bytes.
+pub fn clone_or_copy(src: &Path, dest: &Path) -> std::io::Result<()> {
+    if reflink(src, dest).is_ok() {
+        return Ok(());
+    }
+    fs::copy(src, dest).map(|_| ())
+}
+
+/// Copy the extended attributes of `src` to `dest`. Symlinks are skipped, as
+/// Linux only allows attributes on them in trusted namespaces.
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+fn copy_xattrs(src: &Path, dest: &Path) -> std::io::Result<()> {
+    if fs::symlink_metadata(src)?.is_symlink() {
+        return Ok(());
+    }
+    let src = c_path(src)?;
+    let dest = c_path(dest)?;
+    let names = match read_xattr_buffer(|buf, len| xattr::list(&src, buf, len)) {
+        Err(e) if e.raw_os_error() == Some(libc::ENOTSUP) => return Ok(()),
+        result => result?,
+    };
+    for name in names.split(|&b| b == 0).filter(|n| !n.is_empty()) {
+        let name = std::ffi::CString::new(name)
+            .map_err(|e| std::io::Error::new(ErrorKind::InvalidInput, e))?;
+        let value = read_xattr_buffer(|buf, len| xattr::get(&src, &name, buf, len))?;
+        xattr::set(&dest, &name, &value)?;
     }
     Ok(())
 }
 
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+fn copy_xattrs(_src: &Path, _dest: &Path) -> std::io::Result<()> {
+    Ok(())
+}
+
+/// Call `read` once for the size of the data, then again to fill a buffer of that size
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+fn read_xattr_buffer(read: impl Fn(*mut u8, usize) -> isize) -> std::io::Result<Vec<u8>> {
+    let size = read(std::ptr::null_mut(), 0);
+    if size < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    let mut buf = vec![0u8; size as usize];
+    let len = read(buf.as_mut_ptr(), buf.len());
+    if len < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    buf.truncate(len as usize);
+    Ok(buf)
+}
+
+/// Extended attribute calls on paths, without following symlinks
+#[cfg(target_os = "linux")]
+mod xattr {
+    use std::ffi::CStr;
+
+    pub fn list(path: &CStr, buf: *mut u8, len: usize) -> isize {
+        // SAFETY: `buf` is null with `len` 0, or valid for `len` bytes
+        unsafe { libc::llistxattr(path.as_ptr(), buf.cast(), len) }
+    }
+
+    pub fn get(path: &CStr, name: &CStr, buf: *mut u8, len: usize) -> isize {
+        // SAFETY: as for `list`
+        unsafe { libc::lgetxattr(path.as_ptr(), name.as_ptr(), buf.cast(), len) }
+    }
+
+    pub fn set(path: &CStr, name: &CStr, value: &[u8]) -> std::io::Result<()> {
+        // SAFETY: `value` is valid for its length
+        let result = unsafe {
+            libc::lsetxattr(
+                path.as_ptr(),
+                name.as_ptr(),
+                value.as_ptr().cast(),
+                value.len(),
+                0,
+            )
+        };
+        if result == -1 {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod xattr {
+    use std::ffi::CStr;
+
+    pub fn list(path: &CStr, buf: *mut u8, len: usize) -> isize {
+        // SAFETY: `buf` is null with `len` 0, or valid for `len` bytes
+        unsafe { libc::listxattr(path.as_ptr(), buf.cast(), len, libc::XATTR_NOFOLLOW) }
+    }
+
+    pub fn get(path: &CStr, name: &CStr, buf: *mut u8, len: usize) -> isize {
+        // SAFETY: as for `list`
+        unsafe {
+            libc::getxattr(
+                path.as_ptr(),
+                name.as_ptr(),
+                buf.cast(),
+                len,
+                0,
+                libc::XATTR_NOFOLLOW,
+            )
+        }
+    }
+
+    pub fn set(path: &CStr, name: &CStr, value: &[u8]) -> std::io::Result<()> {
+        // SAFETY: `value` is valid for its length
+        let result = unsafe {
+            libc::setxattr(
+                path.as_ptr(),
+                name.as_ptr(),
+                value.as_ptr().cast(),
+                value.len(),
+                0,
+                libc::XATTR_NOFOLLOW,
+            )
+        };
+        if result == -1 {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok(())
+    }
+}
+
+/// `FICLONE`, supported by btrfs, XFS and bcachefs among others
+#[cfg(target_os = "linux")]
+fn reflink(src: &Path, dest: &Path) -> std::io::Result<()> {
+    use std::os::unix::io::AsRawFd;
+
+    let source = fs::File::open(src)?;
+    let target = OpenOptions::new().write(true).create_new(true).open(dest)?;
+    // SAFETY: both descriptors are open for the duration of the call
+    let result = unsafe { libc::ioctl(target.as_raw_fd(), libc::FICLONE, source.as_raw_fd()) };
+    if result == -1 {
+        let err = std::io::Error::last_os_error();
+        drop(target);
+        let _ = fs::remove_file(dest);
+        return Err(err);
+    }
+    fs::set_permissions(dest, source.metadata()?.permissions())
+}
+
+/// `clonefile`, supported by APFS
+#[cfg(target_os = "macos")]
+fn reflink(src: &Path, dest: &Path) -> std::io::Result<()> {
+    let (src, dest) = (c_path(src)?, c_path(dest)?);
+    // SAFETY: both are valid NUL-terminated paths
+    if unsafe { libc::clonefile(src.as_ptr(), dest.as_ptr(), 0) } == -1 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+fn reflink(_src: &Path, _dest: &Path) -> std::io::Result<()> {
+    Err(ErrorKind::Unsupported.into())
+}
+
 fn get_unique_path(path: &Path) -> PathBuf {
     if !path.exists() {
         return path.to_path_buf();
@@ -176,6 +636,45 @@ fn get_unique_path(path: &Path) -> PathBuf {
     }
 }
 
+/// Total size of all files below `path`, not following symlinks.
+/// Unreadable entries are skipped. Returns `None` if cancelled.
+pub fn dir_size(path: &Path, cancel: &AtomicBool) -> Option<u64> {
+    dir_stats(path, cancel).map(|stats| stats.bytes)
+}
+
+/// Total size and number of the files below a directory
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct DirStats {
+    pub bytes: u64,
+    pub files: u64,
+}
+
+/// Like `dir_size`, also counting the files
+pub fn dir_stats(path: &Path, cancel: &AtomicBool) -> Option<DirStats> {
+    let mut stats = DirStats::default();
+    let mut pending = vec![path.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        if cancel.load(Ordering::Relaxed) {
+            return None;
+        }
+        let Ok(entries) = fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries.filter_map(|e| e.ok()) {
+            let Ok(metadata) = entry.metadata() else {
+                continue;
+            };
+            if metadata.is_dir() {
+                pending.push(entry.path());
+            } else {
+                stats.bytes += metadata.len();
+                stats.files += 1;
+            }
+        }
+    }
+    Some(stats)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -223,6 +722,18 @@ mod tests {
         cleanup_test_dir(&test_dir);
     }
 
+    #[test]
+    fn test_write_png_avoids_existing_names() {
+        let test_dir = setup_test_dir();
+        let first = write_png(&test_dir, "clipboard", 2, 1, vec![255; 8]).unwrap();
+        let second = write_png(&test_dir, "clipboard", 2, 1, vec![0; 8]).unwrap();
+        assert_eq!(first, test_dir.join("clipboard.png"));
+        assert_eq!(second, test_dir.join("clipboard_1.png"));
+        assert_eq!(image::open(&second).unwrap().width(), 2);
+        assert!(write_png(&test_dir, "bad", 2, 2, vec![0; 3]).is_err());
+        cleanup_test_dir(&test_dir);
+    }
+
     #[test]
     fn test_create_directory_success() {
         let test_dir = setup_test_dir();
@@ -256,6 +767,159 @@ mod tests {
         cleanup_test_dir(&test_dir);
     }
 
+    #[test]
+    #[cfg(unix)]
+    fn test_clone_or_copy() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let test_dir = setup_test_dir();
+        let src = test_dir.join("script.sh");
+        fs::write(&src, "#!/bin/sh\n").unwrap();
+        fs::set_permissions(&src, fs::Permissions::from_mode(0o750)).unwrap();
+        let dest = test_dir.join("copy.sh");
+        fs::write(&dest, "old").unwrap();
+
+        // Works whether or not the filesystem can clone, replacing the target like `fs::copy`
+        clone_or_copy(&src, &dest).unwrap();
+        assert_eq!(fs::read_to_string(&dest).unwrap(), "#!/bin/sh\n");
+        let mode = fs::metadata(&dest).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o750);
+        cleanup_test_dir(&test_dir);
+    }
+
+    #[test]
+    fn test_copy_directory_in_parallel() {
+        let test_dir = setup_test_dir();
+        let src = test_dir.join("src");
+        for dir in ["", "a", "a/b", "c"] {
+            fs::create_dir_all(src.join(dir)).unwrap();
+            for i in 0..20 {
+                fs::write(src.join(dir).join(format!("{}.txt", i)), i.to_string()).unwrap();
+            }
+        }
+        let dest_dir = test_dir.join("dest");
+        fs::create_dir(&dest_dir).unwrap();
+
+        let progress = CopyProgress::default();
+        let copy = copy_file_with(&src, &dest_dir, CopyOptions::default(), &progress).unwrap();
+        assert_eq!(copy, dest_dir.join("src"));
+        assert_eq!(progress.total.load(Ordering::Relaxed), 80);
+        assert_eq!(progress.copied.load(Ordering::Relaxed), 80);
+        for dir in ["", "a", "a/b", "c"] {
+            for i in 0..20 {
+                let path = copy.join(dir).join(format!("{}.txt", i));
+                assert_eq!(fs::read_to_string(path).unwrap(), i.to_string());
+            }
+        }
+        cleanup_test_dir(&test_dir);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_copy_keeps_metadata_and_symlinks() {
+        use std::os::unix::fs::{symlink, MetadataExt, PermissionsExt};
+
+        let test_dir = setup_test_dir();
+        let src = test_dir.join("src");
+        fs::create_dir_all(src.join("sub")).unwrap();
+        fs::write(src.join("run.sh"), "#!/bin/sh\n").unwrap();
+        fs::set_permissions(src.join("run.sh"), fs::Permissions::from_mode(0o751)).unwrap();
+        fs::write(src.join("sub/secret"), "x").unwrap();
+        fs::set_permissions(src.join("sub/secret"), fs::Permissions::from_mode(0o604)).unwrap();
+        symlink("run.sh", src.join("link")).unwrap();
+        symlink("/nonexistent", src.join("dangling")).unwrap();
+        let old = std::time::SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1_000_000);
+        for path in [src.join("run.sh"), src.join("sub")] {
+            let file = fs::File::open(&path).unwrap();
+            file.set_times(fs::FileTimes::new().set_modified(old))
+                .unwrap();
+        }
+        fs::set_permissions(src.join("sub"), fs::Permissions::from_mode(0o555)).unwrap();
+        let dest_dir = test_dir.join("dest");
+        fs::create_dir(&dest_dir).unwrap();
+
+        let copy = copy_file(&src, &dest_dir).unwrap();
+        let mode = |p: PathBuf| fs::symlink_metadata(p).unwrap().mode() & 0o777;
+        assert_eq!(mode(copy.join("run.sh")), 0o751);
+        assert_eq!(mode(copy.join("sub/secret")), 0o604);
+        assert_eq!(mode(copy.join("sub")), 0o555);
+        for name in ["run.sh", "sub"] {
+            let modified = fs::metadata(copy.join(name)).unwrap().modified().unwrap();
+            assert_eq!(modified, old);
+        }
+        assert_eq!(
+            fs::read_link(copy.join("link")).unwrap(),
+            Path::new("run.sh")
+        );
+        assert_eq!(
+            fs::read_link(copy.join("dangling")).unwrap(),
+            Path::new("/nonexistent")
+        );
+
+        for dir in [&src, &copy] {
+            fs::set_permissions(dir.join("sub"), fs::Permissions::from_mode(0o755)).unwrap();
+        }
+        cleanup_test_dir(&test_dir);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_copy_xattrs_when_asked() {
+        let test_dir = setup_test_dir();
+        let src = test_dir.join("tagged");
+        fs::write(&src, "").unwrap();
+        let path = c_path(&src).unwrap();
+        let name = c"user.filetree.test";
+        if xattr::set(&path, name, b"yes").is_err() {
+            // The filesystem has no user attributes
+            cleanup_test_dir(&test_dir);
+            return;
+        }
+        let read = |file: &Path| {
+            let path = c_path(file).unwrap();
+            read_xattr_buffer(|buf, len| xattr::get(&path, name, buf, len)).ok()
+        };
+
+        let dest_dir = test_dir.join("plain");
+        fs::create_dir(&dest_dir).unwrap();
+        let copy = copy_file(&src, &dest_dir).unwrap();
+        assert_eq!(read(&copy), None);
+
+        let dest_dir = test_dir.join("with_xattrs");
+        fs::create_dir(&dest_dir).unwrap();
+        let options = CopyOptions { xattrs: true };
+        let copy = copy_file_with(&src, &dest_dir, options, &CopyProgress::default()).unwrap();
+        assert_eq!(read(&copy), Some(b"yes".to_vec()));
+        cleanup_test_dir(&test_dir);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_failed_move_across_leaves_source_intact() {
+        let test_dir = setup_test_dir();
+        let src = test_dir.join("src");
+        fs::create_dir_all(src.join("sub")).unwrap();
+        fs::write(src.join("a.txt"), "a").unwrap();
+        fs::write(src.join("sub/b.txt"), "b").unwrap();
+        // A socket cannot be copied, failing the move part way
+        let _socket = std::os::unix::net::UnixListener::bind(src.join("sub/socket")).unwrap();
+        let dest_dir = test_dir.join("dest");
+        fs::create_dir(&dest_dir).unwrap();
+
+        let progress = CopyProgress::default();
+        let result = move_file_across(&src, &dest_dir, CopyOptions::default(), &progress);
+        assert!(result.is_err());
+        assert!(src.join("a.txt").exists());
+        assert!(src.join("sub/b.txt").exists());
+        assert!(!dest_dir.join("src").exists());
+
+        fs::remove_file(src.join("sub/socket")).unwrap();
+        let moved = move_file_across(&src, &dest_dir, CopyOptions::default(), &progress).unwrap();
+        assert_eq!(fs::read_to_string(moved.join("sub/b.txt")).unwrap(), "b");
+        assert!(!src.exists());
+        cleanup_test_dir(&test_dir);
+    }
+
     #[test]
     fn test_copy_file_unique_name() {
         let test_dir = setup_test_dir();
@@ -338,6 +1002,17 @@ mod tests {
         cleanup_test_dir(&test_dir);
     }
 
+    #[test]
+    fn test_is_permission_denied() {
+        let denied: anyhow::Error =
+            std::io::Error::new(ErrorKind::PermissionDenied, "denied").into();
+        assert!(is_permission_denied(&denied));
+
+        let not_found: anyhow::Error = std::io::Error::new(ErrorKind::NotFound, "gone").into();
+        assert!(!is_permission_denied(&not_found));
+        assert!(!is_permission_denied(&anyhow::anyhow!("other")));
+    }
+
     #[test]
     fn test_clipboard_operations() {
         let mut clipboard = Clipboard::default();
@@ -352,4 +1027,24 @@ mod tests {
         clipboard.cut(vec![PathBuf::from("/test/path")]);
         assert!(!clipboard.is_empty());
     }
+
+    #[test]
+    fn test_dir_size() {
+        let test_dir = setup_test_dir();
+        let root = test_dir.as_path();
+        fs::create_dir_all(root.join("a/b")).unwrap();
+        fs::write(root.join("top.bin"), [0u8; 10]).unwrap();
+        fs::write(root.join("a/b/deep.bin"), [0u8; 32]).unwrap();
+
+        assert_eq!(dir_size(root, &AtomicBool::new(false)), Some(42));
+        assert_eq!(dir_size(root, &AtomicBool::new(true)), None);
+        assert_eq!(
+            dir_stats(root, &AtomicBool::new(false)),
+            Some(DirStats {
+                bytes: 42,
+                files: 2
+            })
+        );
+        cleanup_test_dir(&test_dir);
+    }
 }
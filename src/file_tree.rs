@@ -1,14 +1,263 @@
+//! Directory tree model: lazily loaded [`FileNode`]s flattened into visible rows by [`FileTree`]
+
+use std::collections::{HashMap, HashSet};
 use std::fs;
+#[cfg(unix)]
+use std::os::unix::fs::MetadataExt;
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant, SystemTime};
+
+use serde::Deserialize;
+
+/// Filters applied when flattening the tree; filtered nodes stay loaded but are not shown
+#[derive(Debug, Clone, Default)]
+pub struct ViewFilter {
+    /// Paths hidden together with everything below them (e.g. git-ignored paths)
+    pub excluded: HashSet<PathBuf>,
+    /// Hide dotfiles even when hidden files are loaded
+    pub hide_dotfiles: bool,
+    /// Hide files, showing only the directory structure
+    pub dirs_only: bool,
+    /// Only show files with one of these (lowercase) extensions; directories stay visible
+    pub extensions: Option<HashSet<String>>,
+    /// Hide loaded directories that have nothing visible inside
+    pub hide_empty_dirs: bool,
+    /// Hide entries whose name matches one of these (lowercase) globs
+    pub ignore: Vec<String>,
+}
+
+impl ViewFilter {
+    pub fn is_visible(&self, node: &FileNode) -> bool {
+        if self.hide_dotfiles && node.name.starts_with('.') {
+            return false;
+        }
+        if self.dirs_only && !node.is_dir {
+            return false;
+        }
+        if !self.ignore.is_empty() {
+            let name = node.name.to_lowercase();
+            if self.ignore.iter().any(|pattern| glob_match(pattern, &name)) {
+                return false;
+            }
+        }
+        if let Some(extensions) = &self.extensions {
+            if !node.is_dir {
+                let extension = node
+                    .path
+                    .extension()
+                    .map(|e| e.to_string_lossy().to_lowercase());
+                if !extension.is_some_and(|e| extensions.contains(&e)) {
+                    return false;
+                }
+            }
+        }
+        if self.hide_empty_dirs
+            && node.is_dir
+            && node.children_loaded
+            && !node.children.iter().any(|c| self.is_visible(c))
+        {
+            return false;
+        }
+        !self.excluded.contains(&node.path)
+    }
+}
+
+/// Match `text` against a shell-style glob where `*` matches any run of
+/// characters and `?` matches exactly one
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut p, mut t) = (0, 0);
+    // Position of the last `*` and the text index it is currently matched up to
+    let mut star: Option<(usize, usize)> = None;
+
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, t));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match star {
+                Some((star_p, star_t)) => {
+                    // Let the last `*` swallow one more character and retry
+                    p = star_p + 1;
+                    t = star_t + 1;
+                    star = Some((star_p, star_t + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// Where directories go among the entries of their parent
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DirectoryOrder {
+    #[default]
+    First,
+    Last,
+    /// Sorted by name together with files
+    Mixed,
+}
+
+impl DirectoryOrder {
+    pub fn next(self) -> Self {
+        match self {
+            DirectoryOrder::First => DirectoryOrder::Last,
+            DirectoryOrder::Last => DirectoryOrder::Mixed,
+            DirectoryOrder::Mixed => DirectoryOrder::First,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            DirectoryOrder::First => "directories first",
+            DirectoryOrder::Last => "directories last",
+            DirectoryOrder::Mixed => "directories mixed with files",
+        }
+    }
+}
+
+/// How the entries of a directory are ordered
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SortOrder {
+    pub directories: DirectoryOrder,
+    /// Put dotfiles and dot-directories before all other entries
+    pub hidden_first: bool,
+    /// Compare runs of digits by their value, so `file2` comes before `file10`
+    pub natural: bool,
+    /// Ignore letter case, so `b` comes before `C`
+    pub case_insensitive: bool,
+}
+
+impl Default for SortOrder {
+    fn default() -> Self {
+        Self {
+            directories: DirectoryOrder::First,
+            hidden_first: false,
+            natural: true,
+            case_insensitive: false,
+        }
+    }
+}
+
+/// Sort sibling nodes: dotfiles first if grouped, then by directory placement and name
+pub fn sort_nodes(nodes: &mut [FileNode], sort: SortOrder) {
+    let is_hidden = |node: &FileNode| {
+        node.path
+            .file_name()
+            .is_some_and(|name| name.as_encoded_bytes().starts_with(b"."))
+    };
+    nodes.sort_by(|a, b| {
+        let directories = match sort.directories {
+            DirectoryOrder::First => b.is_dir.cmp(&a.is_dir),
+            DirectoryOrder::Last => a.is_dir.cmp(&b.is_dir),
+            DirectoryOrder::Mixed => std::cmp::Ordering::Equal,
+        };
+        let hidden = if sort.hidden_first {
+            is_hidden(b).cmp(&is_hidden(a))
+        } else {
+            std::cmp::Ordering::Equal
+        };
+        hidden
+            .then(directories)
+            .then_with(|| compare_names(&a.name, &b.name, sort))
+    });
+}
+
+/// Compare two entry names. Names that only differ in case or leading zeros
+/// still get a stable order by falling back to a plain comparison.
+pub fn compare_names(a: &str, b: &str, sort: SortOrder) -> std::cmp::Ordering {
+    let ordering = if sort.natural {
+        natural_cmp(a, b, sort.case_insensitive)
+    } else if sort.case_insensitive {
+        a.to_lowercase().cmp(&b.to_lowercase())
+    } else {
+        std::cmp::Ordering::Equal
+    };
+    ordering.then_with(|| a.cmp(b))
+}
+
+fn natural_cmp(a: &str, b: &str, case_insensitive: bool) -> std::cmp::Ordering {
+    use std::cmp::Ordering::*;
+
+    let (mut a, mut b) = (a.chars().peekable(), b.chars().peekable());
+    loop {
+        let ordering = match (a.peek().copied(), b.peek().copied()) {
+            (None, None) => return Equal,
+            (None, Some(_)) => return Less,
+            (Some(_), None) => return Greater,
+            (Some(x), Some(y)) if x.is_ascii_digit() && y.is_ascii_digit() => {
+                let x = take_digits(&mut a);
+                let y = take_digits(&mut b);
+                let (x_value, y_value) = (x.trim_start_matches('0'), y.trim_start_matches('0'));
+                // Longer numbers are larger; equal lengths compare digit by digit
+                x_value
+                    .len()
+                    .cmp(&y_value.len())
+                    .then_with(|| x_value.cmp(y_value))
+                    .then_with(|| x.len().cmp(&y.len()))
+            }
+            (Some(x), Some(y)) => {
+                a.next();
+                b.next();
+                if case_insensitive {
+                    x.to_lowercase().cmp(y.to_lowercase())
+                } else {
+                    x.cmp(&y)
+                }
+            }
+        };
+        if ordering != Equal {
+            return ordering;
+        }
+    }
+}
+
+fn take_digits(chars: &mut std::iter::Peekable<std::str::Chars>) -> String {
+    let mut digits = String::new();
+    while let Some(c) = chars.next_if(char::is_ascii_digit) {
+        digits.push(c);
+    }
+    digits
+}
+
+/// Unix permission bits and ownership of an entry
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Ownership {
+    pub mode: u32,
+    pub uid: u32,
+    pub gid: u32,
+}
 
 #[derive(Debug, Clone)]
 pub struct FileNode {
     pub path: PathBuf,
+    /// Display name, lossily converted for non-UTF-8 names. File operations use `path`.
     pub name: String,
     pub is_dir: bool,
     pub expanded: bool,
     pub depth: usize,
     pub children: Vec<FileNode>,
+    /// Where the entry points to, if it is a symbolic link
+    pub symlink_target: Option<PathBuf>,
+    /// A symbolic link whose target does not exist
+    pub broken_link: bool,
+    /// Whether `children` has been read from disk (an unloaded directory may not be empty)
+    pub children_loaded: bool,
+    /// File size in bytes (`None` for directories or unreadable entries)
+    pub size: Option<u64>,
+    pub modified: Option<SystemTime>,
+    pub ownership: Option<Ownership>,
+    /// Why the entries of this directory could not be listed, after a failed load
+    pub read_error: Option<String>,
 }
 
 impl FileNode {
@@ -17,7 +266,27 @@ impl FileNode {
             .file_name()
             .map(|n| n.to_string_lossy().to_string())
             .unwrap_or_else(|| path.to_string_lossy().to_string());
-        let is_dir = path.is_dir();
+        let metadata = fs::metadata(&path).ok();
+        let is_dir = metadata.as_ref().is_some_and(|m| m.is_dir());
+        let modified = metadata.as_ref().and_then(|m| m.modified().ok());
+        #[cfg(unix)]
+        let ownership = metadata.as_ref().map(|m| Ownership {
+            mode: m.mode(),
+            uid: m.uid(),
+            gid: m.gid(),
+        });
+        #[cfg(not(unix))]
+        let ownership = None;
+        // `metadata` follows links, so check the link itself to tell a dangling link
+        // from an entry that cannot be read
+        let is_symlink = fs::symlink_metadata(&path).is_ok_and(|m| m.file_type().is_symlink());
+        let symlink_target = if is_symlink {
+            fs::read_link(&path).ok()
+        } else {
+            None
+        };
+        let broken_link = is_symlink && metadata.is_none();
+        let size = metadata.filter(|m| m.is_file()).map(|m| m.len());
 
         Self {
             path,
@@ -26,53 +295,100 @@ impl FileNode {
             expanded: false,
             depth,
             children: Vec::new(),
+            symlink_target,
+            broken_link,
+            children_loaded: false,
+            size,
+            modified,
+            ownership,
+            read_error: None,
         }
     }
 
-    pub fn load_children(&mut self, show_hidden: bool) -> anyhow::Result<()> {
+    pub fn is_symlink(&self) -> bool {
+        self.symlink_target.is_some()
+    }
+
+    /// A copy of this entry without its children, as shown in one row of the tree
+    fn to_row(&self) -> FileNode {
+        FileNode {
+            path: self.path.clone(),
+            name: self.name.clone(),
+            is_dir: self.is_dir,
+            expanded: self.expanded,
+            depth: self.depth,
+            children: Vec::new(),
+            symlink_target: self.symlink_target.clone(),
+            broken_link: self.broken_link,
+            children_loaded: self.children_loaded,
+            size: self.size,
+            modified: self.modified,
+            ownership: self.ownership,
+            read_error: self.read_error.clone(),
+        }
+    }
+
+    /// Number of nodes in this subtree, including this one
+    pub fn loaded_count(&self) -> usize {
+        1 + self
+            .children
+            .iter()
+            .map(FileNode::loaded_count)
+            .sum::<usize>()
+    }
+
+    /// Directories in this subtree whose entries couldn't be listed
+    pub fn unreadable_count(&self) -> usize {
+        usize::from(self.read_error.is_some())
+            + self
+                .children
+                .iter()
+                .map(FileNode::unreadable_count)
+                .sum::<usize>()
+    }
+
+    pub fn load_children(&mut self, show_hidden: bool, sort: SortOrder) -> anyhow::Result<()> {
         if !self.is_dir {
             return Ok(());
         }
 
         self.children.clear();
-        let mut entries: Vec<_> = fs::read_dir(&self.path)?
+        let entries = match fs::read_dir(&self.path) {
+            Ok(entries) => entries,
+            Err(e) => {
+                let reason = match e.kind() {
+                    std::io::ErrorKind::PermissionDenied => "permission denied".to_string(),
+                    _ => e.to_string(),
+                };
+                let error = anyhow::anyhow!("Cannot read {}: {}", self.path.display(), reason);
+                self.read_error = Some(reason);
+                return Err(error);
+            }
+        };
+        self.read_error = None;
+        let entries: Vec<_> = entries
             .filter_map(|e| e.ok())
-            .filter(|e| {
-                show_hidden
-                    || e.file_name()
-                        .to_str()
-                        .map(|s| !s.starts_with('.'))
-                        .unwrap_or(true)
-            })
+            .filter(|e| show_hidden || !e.file_name().as_encoded_bytes().starts_with(b"."))
             .collect();
 
-        entries.sort_by(|a, b| {
-            let a_is_dir = a.path().is_dir();
-            let b_is_dir = b.path().is_dir();
-            match (a_is_dir, b_is_dir) {
-                (true, false) => std::cmp::Ordering::Less,
-                (false, true) => std::cmp::Ordering::Greater,
-                _ => a.file_name().cmp(&b.file_name()),
-            }
-        });
-
         for entry in entries {
             self.children
                 .push(FileNode::new(entry.path(), self.depth + 1));
         }
+        sort_nodes(&mut self.children, sort);
+        self.children_loaded = true;
 
         Ok(())
     }
 
-    #[allow(dead_code)]
-    pub fn toggle_expand(&mut self, show_hidden: bool) -> anyhow::Result<()> {
+    pub fn toggle_expand(&mut self, show_hidden: bool, sort: SortOrder) -> anyhow::Result<()> {
         if !self.is_dir {
             return Ok(());
         }
 
         self.expanded = !self.expanded;
         if self.expanded && self.children.is_empty() {
-            self.load_children(show_hidden)?;
+            self.load_children(show_hidden, sort)?;
         }
         Ok(())
     }
@@ -82,101 +398,172 @@ impl FileNode {
 pub struct FileTree {
     pub root: FileNode,
     pub flat_list: Vec<usize>,
+    /// The visible rows, without their children
     nodes: Vec<FileNode>,
     pub show_hidden: bool,
+    pub filter: ViewFilter,
+    /// Whether symlinked directories can be expanded
+    pub follow_symlinks: bool,
+    /// Order of entries within each directory
+    pub sort: SortOrder,
+    /// When set, list every loaded file below this directory as one flat list,
+    /// named by their path relative to it
+    pub flat_view: Option<PathBuf>,
+    /// Number of entries in directories, counted on first request and cleared on refresh
+    child_counts: HashMap<PathBuf, usize>,
+    /// How long the last `rebuild_flat_list` took, for the performance overlay
+    pub last_rebuild: Duration,
 }
 
 impl FileTree {
     pub fn new(path: &Path, show_hidden: bool) -> anyhow::Result<Self> {
+        Self::with_sort(path, show_hidden, SortOrder::default())
+    }
+
+    pub fn with_sort(path: &Path, show_hidden: bool, sort: SortOrder) -> anyhow::Result<Self> {
         let mut root = FileNode::new(path.to_path_buf(), 0);
         root.expanded = true;
-        root.load_children(show_hidden)?;
+        root.load_children(show_hidden, sort)?;
 
         let mut tree = Self {
             root,
             flat_list: Vec::new(),
             nodes: Vec::new(),
             show_hidden,
+            filter: ViewFilter::default(),
+            follow_symlinks: true,
+            sort,
+            flat_view: None,
+            child_counts: HashMap::new(),
+            last_rebuild: Duration::ZERO,
         };
         tree.rebuild_flat_list();
         Ok(tree)
     }
 
     pub fn rebuild_flat_list(&mut self) {
+        let started = Instant::now();
         self.nodes.clear();
         self.flat_list.clear();
-        self.flatten_node(&self.root.clone());
-        for i in 0..self.nodes.len() {
-            self.flat_list.push(i);
+        match self.flat_view.clone() {
+            Some(base) => match self.find_node(&base).cloned() {
+                Some(node) => {
+                    self.nodes.push(FileNode {
+                        depth: 0,
+                        ..node.to_row()
+                    });
+                    self.flatten_files(&node, &base);
+                }
+                // The directory went away; fall back to the normal tree
+                None => {
+                    self.flat_view = None;
+                    Self::flatten_node(&self.filter, &self.root, &mut self.nodes);
+                }
+            },
+            None => Self::flatten_node(&self.filter, &self.root, &mut self.nodes),
         }
+        self.flat_list.extend(0..self.nodes.len());
+        self.last_rebuild = started.elapsed();
     }
 
-    fn flatten_node(&mut self, node: &FileNode) {
-        self.nodes.push(node.clone());
+    fn flatten_node(filter: &ViewFilter, node: &FileNode, rows: &mut Vec<FileNode>) {
+        rows.push(node.to_row());
         if node.expanded {
             for child in &node.children {
-                self.flatten_node(child);
+                if filter.is_visible(child) {
+                    Self::flatten_node(filter, child, rows);
+                }
+            }
+        }
+    }
+
+    /// Replace the rows below the directory at row `index` after it was expanded
+    /// or collapsed. Only its descendants are touched, so this stays cheap in a
+    /// large tree; views whose other rows may change are rebuilt in full.
+    fn update_rows(&mut self, index: usize) {
+        let started = Instant::now();
+        if self.flat_view.is_some() || self.filter.hide_empty_dirs {
+            return self.rebuild_flat_list();
+        }
+        let Some(depth) = self.nodes.get(index).map(|row| row.depth) else {
+            return self.rebuild_flat_list();
+        };
+        let path = self.nodes[index].path.clone();
+        let Some(node) = self.find_node(&path) else {
+            return self.rebuild_flat_list();
+        };
+        let row = node.to_row();
+        let mut rows = Vec::new();
+        if node.expanded {
+            for child in node.children.iter().filter(|c| self.filter.is_visible(c)) {
+                Self::flatten_node(&self.filter, child, &mut rows);
+            }
+        }
+
+        let end = self.nodes[index + 1..]
+            .iter()
+            .position(|r| r.depth <= depth)
+            .map_or(self.nodes.len(), |i| index + 1 + i);
+        self.nodes[index] = row;
+        self.nodes.splice(index + 1..end, rows);
+        let len = self.nodes.len();
+        self.flat_list.truncate(len);
+        self.flat_list.extend(self.flat_list.len()..len);
+        self.last_rebuild = started.elapsed();
+    }
+
+    /// Loaded files below `node`, expanded or not, at depth 1 and sorted by relative path
+    /// according to `sort`
+    fn flatten_files(&mut self, node: &FileNode, base: &Path) {
+        let start = self.nodes.len();
+        let mut stack = vec![node];
+        while let Some(dir) = stack.pop() {
+            for child in dir.children.iter().filter(|c| self.filter.is_visible(c)) {
+                if child.is_dir {
+                    stack.push(child);
+                } else {
+                    let relative = child.path.strip_prefix(base).unwrap_or(&child.path);
+                    self.nodes.push(FileNode {
+                        name: relative.to_string_lossy().to_string(),
+                        depth: 1,
+                        ..child.to_row()
+                    });
+                }
             }
         }
+        sort_nodes(&mut self.nodes[start..], self.sort);
+    }
+
+    pub fn set_filter(&mut self, filter: ViewFilter) {
+        self.filter = filter;
+        self.rebuild_flat_list();
     }
 
     pub fn get_node(&self, index: usize) -> Option<&FileNode> {
         self.nodes.get(index)
     }
 
-    #[allow(dead_code)]
     pub fn get_node_mut(&mut self, index: usize) -> Option<&mut FileNode> {
         self.nodes.get_mut(index)
     }
 
-    #[allow(dead_code)]
     pub fn toggle_expand(&mut self, index: usize) -> anyhow::Result<()> {
-        let path = {
-            let node = self.nodes.get(index);
-            node.map(|n| n.path.clone())
+        let Some(path) = self.nodes.get(index).map(|n| n.path.clone()) else {
+            return Ok(());
         };
-
-        if let Some(path) = path {
-            self.toggle_expand_recursive(&mut self.root.clone(), &path)?;
-            self.rebuild_flat_list();
-        }
-        Ok(())
-    }
-
-    #[allow(dead_code)]
-    fn toggle_expand_recursive(
-        &mut self,
-        node: &mut FileNode,
-        target_path: &Path,
-    ) -> anyhow::Result<bool> {
-        if node.path == target_path {
-            node.toggle_expand(self.show_hidden)?;
-            self.update_root(node.clone());
-            return Ok(true);
-        }
-
-        if node.expanded {
-            for child in &mut node.children {
-                if self.toggle_expand_recursive(child, target_path)? {
-                    return Ok(true);
-                }
-            }
-        }
-        Ok(false)
-    }
-
-    #[allow(dead_code)]
-    fn update_root(&mut self, new_root: FileNode) {
-        if self.root.path == new_root.path {
-            self.root = new_root;
-        }
+        let (show_hidden, sort) = (self.show_hidden, self.sort);
+        let result = match self.find_node_mut(&path) {
+            Some(node) => node.toggle_expand(show_hidden, sort),
+            None => Ok(()),
+        };
+        self.update_rows(index);
+        result
     }
 
     pub fn len(&self) -> usize {
         self.nodes.len()
     }
 
-    #[allow(dead_code)]
     pub fn is_empty(&self) -> bool {
         self.nodes.is_empty()
     }
@@ -185,14 +572,15 @@ impl FileTree {
         // Collect expanded paths before refresh
         let expanded_paths = self.collect_expanded_paths();
 
+        self.child_counts.clear();
         let root_path = self.root.path.clone();
         self.root = FileNode::new(root_path, 0);
         self.root.expanded = true;
-        self.root.load_children(self.show_hidden)?;
+        self.root.load_children(self.show_hidden, self.sort)?;
 
         // Restore expanded state
         for path in &expanded_paths {
-            Self::restore_expanded_recursive(&mut self.root, path, self.show_hidden);
+            Self::restore_expanded_recursive(&mut self.root, path, self.show_hidden, self.sort);
         }
 
         self.rebuild_flat_list();
@@ -200,12 +588,21 @@ impl FileTree {
     }
 
     /// Collect all expanded directory paths
-    fn collect_expanded_paths(&self) -> Vec<PathBuf> {
+    pub fn collect_expanded_paths(&self) -> Vec<PathBuf> {
         let mut paths = Vec::new();
         Self::collect_expanded_recursive(&self.root, &mut paths);
         paths
     }
 
+    /// Expand `paths` (and their ancestors) again, e.g. from a saved session.
+    /// Paths that no longer exist are skipped.
+    pub fn restore_expanded(&mut self, paths: &[PathBuf]) {
+        for path in paths {
+            Self::restore_expanded_recursive(&mut self.root, path, self.show_hidden, self.sort);
+        }
+        self.rebuild_flat_list();
+    }
+
     fn collect_expanded_recursive(node: &FileNode, paths: &mut Vec<PathBuf>) {
         if node.is_dir && node.expanded {
             paths.push(node.path.clone());
@@ -215,7 +612,12 @@ impl FileTree {
         }
     }
 
-    fn restore_expanded_recursive(node: &mut FileNode, target_path: &Path, show_hidden: bool) {
+    fn restore_expanded_recursive(
+        node: &mut FileNode,
+        target_path: &Path,
+        show_hidden: bool,
+        sort: SortOrder,
+    ) {
         if !node.is_dir {
             return;
         }
@@ -223,7 +625,7 @@ impl FileTree {
         if node.path == target_path {
             node.expanded = true;
             if node.children.is_empty() {
-                let _ = node.load_children(show_hidden);
+                let _ = node.load_children(show_hidden, sort);
             }
             return;
         }
@@ -233,20 +635,52 @@ impl FileTree {
             if !node.expanded {
                 node.expanded = true;
                 if node.children.is_empty() {
-                    let _ = node.load_children(show_hidden);
+                    let _ = node.load_children(show_hidden, sort);
                 }
             }
             for child in &mut node.children {
-                Self::restore_expanded_recursive(child, target_path, show_hidden);
+                Self::restore_expanded_recursive(child, target_path, show_hidden, sort);
             }
         }
     }
 
+    /// Number of entries in `dir` (excluding dotfiles unless hidden files are shown)
+    pub fn child_count(&mut self, dir: &Path) -> usize {
+        if let Some(&count) = self.child_counts.get(dir) {
+            return count;
+        }
+        let show_hidden = self.show_hidden;
+        let count = fs::read_dir(dir)
+            .map(|entries| {
+                entries
+                    .filter_map(|e| e.ok())
+                    .filter(|e| show_hidden || !e.file_name().as_encoded_bytes().starts_with(b"."))
+                    .count()
+            })
+            .unwrap_or(0);
+        self.child_counts.insert(dir.to_path_buf(), count);
+        count
+    }
+
     pub fn set_show_hidden(&mut self, show_hidden: bool) -> anyhow::Result<()> {
         self.show_hidden = show_hidden;
         self.refresh()
     }
 
+    /// Reorder the loaded entries without reading the directories again
+    pub fn set_sort(&mut self, sort: SortOrder) {
+        self.sort = sort;
+        Self::sort_recursive(&mut self.root, sort);
+        self.rebuild_flat_list();
+    }
+
+    fn sort_recursive(node: &mut FileNode, sort: SortOrder) {
+        sort_nodes(&mut node.children, sort);
+        for child in &mut node.children {
+            Self::sort_recursive(child, sort);
+        }
+    }
+
     pub fn collapse_all(&mut self) {
         Self::collapse_all_recursive(&mut self.root);
         self.root.expanded = true; // Keep root expanded
@@ -260,30 +694,133 @@ impl FileTree {
         }
     }
 
-    pub fn expand_all(&mut self) -> anyhow::Result<()> {
-        Self::expand_all_recursive(&mut self.root, self.show_hidden)?;
+    /// Collapse every directory that is not an ancestor of `path`
+    pub fn collapse_others(&mut self, path: &Path) {
+        Self::collapse_others_recursive(&mut self.root, path);
+        self.root.expanded = true; // Keep root expanded
         self.rebuild_flat_list();
-        Ok(())
     }
 
-    fn expand_all_recursive(node: &mut FileNode, show_hidden: bool) -> anyhow::Result<()> {
-        if node.is_dir {
-            node.expanded = true;
-            if node.children.is_empty() {
-                node.load_children(show_hidden)?;
+    fn collapse_others_recursive(node: &mut FileNode, path: &Path) {
+        if node.path != path && path.starts_with(&node.path) {
+            for child in &mut node.children {
+                Self::collapse_others_recursive(child, path);
+            }
+        } else {
+            Self::collapse_all_recursive(node);
+        }
+    }
+
+    /// The loaded node at `path`, if any
+    pub fn find_node(&self, path: &Path) -> Option<&FileNode> {
+        let mut node = &self.root;
+        while node.path != path {
+            node = node.children.iter().find(|c| path.starts_with(&c.path))?;
+        }
+        Some(node)
+    }
+
+    fn find_node_mut(&mut self, path: &Path) -> Option<&mut FileNode> {
+        let mut node = &mut self.root;
+        while node.path != path {
+            node = node
+                .children
+                .iter_mut()
+                .find(|c| path.starts_with(&c.path))?;
+        }
+        Some(node)
+    }
+
+    /// Put `node` in place of the loaded node with the same path, e.g. one expanded by
+    /// [`expand_subtree`]. Returns false if that path is no longer in the tree.
+    pub fn replace_node(&mut self, node: FileNode) -> bool {
+        match self.find_node_mut(&node.path) {
+            Some(target) => *target = node,
+            None => return false,
+        }
+        self.rebuild_flat_list();
+        true
+    }
+
+    /// Re-read only the entries of `dirs`, e.g. after a file operation touched them.
+    /// Subdirectories that still exist keep their loaded contents and expansion;
+    /// directories that aren't loaded in the tree are skipped.
+    pub fn refresh_dirs(&mut self, dirs: &[PathBuf]) {
+        let (show_hidden, sort) = (self.show_hidden, self.sort);
+        for dir in dirs {
+            self.child_counts.remove(dir);
+            let Some(node) = self.find_node_mut(dir) else {
+                continue;
+            };
+            if !node.children_loaded {
+                continue;
+            }
+            let mut old: HashMap<PathBuf, FileNode> = std::mem::take(&mut node.children)
+                .into_iter()
+                .map(|c| (c.path.clone(), c))
+                .collect();
+            if node.load_children(show_hidden, sort).is_err() {
+                continue;
             }
             for child in &mut node.children {
-                Self::expand_all_recursive(child, show_hidden)?;
+                if let Some(previous) = old.remove(&child.path).filter(|p| p.is_dir) {
+                    if child.is_dir {
+                        child.expanded = previous.expanded;
+                        child.children = previous.children;
+                        child.children_loaded = previous.children_loaded;
+                        child.read_error = previous.read_error;
+                    }
+                }
             }
         }
+        self.rebuild_flat_list();
+    }
+
+    /// Expand all directories up to `max_depth` levels below the root
+    pub fn expand_to_depth(&mut self, max_depth: usize) -> anyhow::Result<()> {
+        Self::expand_to_depth_recursive(
+            &mut self.root,
+            max_depth,
+            self.show_hidden,
+            self.sort,
+            self.follow_symlinks,
+        )?;
+        self.rebuild_flat_list();
+        Ok(())
+    }
+
+    fn expand_to_depth_recursive(
+        node: &mut FileNode,
+        max_depth: usize,
+        show_hidden: bool,
+        sort: SortOrder,
+        follow_symlinks: bool,
+    ) -> anyhow::Result<()> {
+        if !node.is_dir || node.depth >= max_depth || (node.is_symlink() && !follow_symlinks) {
+            return Ok(());
+        }
+        node.expanded = true;
+        if node.children.is_empty() {
+            node.load_children(show_hidden, sort)?;
+        }
+        for child in &mut node.children {
+            Self::expand_to_depth_recursive(child, max_depth, show_hidden, sort, follow_symlinks)?;
+        }
         Ok(())
     }
 
     pub fn expand_node(&mut self, index: usize) -> anyhow::Result<()> {
         if let Some(node) = self.nodes.get(index) {
             if node.is_dir && !node.expanded {
+                if node.is_symlink() && !self.follow_symlinks {
+                    anyhow::bail!("Not following symlink (follow_symlinks = false)");
+                }
                 let path = node.path.clone();
-                self.expand_path(&path)?;
+                // Update the rows even on failure so an unreadable directory shows its error
+                let result =
+                    Self::expand_path_recursive(&mut self.root, &path, self.show_hidden, self.sort);
+                self.update_rows(index);
+                result?;
             }
         }
         Ok(())
@@ -293,14 +830,23 @@ impl FileTree {
         if let Some(node) = self.nodes.get(index) {
             if node.is_dir && node.expanded {
                 let path = node.path.clone();
-                self.collapse_path(&path)?;
+                Self::collapse_path_recursive(&mut self.root, &path);
+                self.update_rows(index);
             }
         }
         Ok(())
     }
 
-    fn expand_path(&mut self, target_path: &Path) -> anyhow::Result<()> {
-        Self::expand_path_recursive(&mut self.root, target_path, self.show_hidden)?;
+    /// Expand every directory between the root and `path` so it becomes visible
+    pub fn reveal(&mut self, path: &Path) -> anyhow::Result<()> {
+        let relative = path.strip_prefix(&self.root.path).map_err(|_| {
+            anyhow::anyhow!("{} is outside {}", path.display(), self.root.path.display())
+        })?;
+        let mut dir = self.root.path.clone();
+        for component in relative.parent().into_iter().flat_map(|p| p.components()) {
+            dir.push(component);
+            Self::expand_path_recursive(&mut self.root, &dir, self.show_hidden, self.sort)?;
+        }
         self.rebuild_flat_list();
         Ok(())
     }
@@ -309,12 +855,13 @@ impl FileTree {
         node: &mut FileNode,
         target_path: &Path,
         show_hidden: bool,
+        sort: SortOrder,
     ) -> anyhow::Result<bool> {
         if node.path == target_path {
             if !node.expanded {
                 node.expanded = true;
                 if node.children.is_empty() {
-                    node.load_children(show_hidden)?;
+                    node.load_children(show_hidden, sort)?;
                 }
             }
             return Ok(true);
@@ -322,7 +869,7 @@ impl FileTree {
 
         if node.expanded {
             for child in &mut node.children {
-                if Self::expand_path_recursive(child, target_path, show_hidden)? {
+                if Self::expand_path_recursive(child, target_path, show_hidden, sort)? {
                     return Ok(true);
                 }
             }
@@ -330,12 +877,6 @@ impl FileTree {
         Ok(false)
     }
 
-    fn collapse_path(&mut self, target_path: &Path) -> anyhow::Result<()> {
-        Self::collapse_path_recursive(&mut self.root, target_path);
-        self.rebuild_flat_list();
-        Ok(())
-    }
-
     fn collapse_path_recursive(node: &mut FileNode, target_path: &Path) -> bool {
         if node.path == target_path {
             node.expanded = false;
@@ -353,6 +894,111 @@ impl FileTree {
     }
 }
 
+/// Limits for expanding a whole subtree at once
+#[derive(Debug, Clone, Copy)]
+pub struct ExpandLimits {
+    /// Directories deeper than this below the expanded directory stay collapsed
+    pub max_depth: usize,
+    /// Give up once the subtree holds more entries than this
+    pub max_entries: usize,
+    /// Descend into symlinked directories (cycles are still skipped)
+    pub follow_symlinks: bool,
+}
+
+#[derive(Debug)]
+pub enum ExpandOutcome {
+    /// The expanded subtree and the number of entries loaded into it
+    Done(FileNode, usize),
+    /// The entry cap was hit before the subtree was fully expanded
+    CapReached,
+    Cancelled,
+}
+
+/// Expand `node` and its descendant directories within `limits`. Meant to run in a
+/// background job on a copy of the tree, so a huge tree can be abandoned.
+pub fn expand_subtree(
+    mut node: FileNode,
+    show_hidden: bool,
+    sort: SortOrder,
+    limits: ExpandLimits,
+    cancel: &AtomicBool,
+) -> anyhow::Result<ExpandOutcome> {
+    let mut entries = 0;
+    let limits = ExpandLimits {
+        max_depth: node.depth.saturating_add(limits.max_depth),
+        ..limits
+    };
+    let mut ancestors = Vec::new();
+    match expand_subtree_recursive(
+        &mut node,
+        show_hidden,
+        sort,
+        limits,
+        cancel,
+        &mut entries,
+        &mut ancestors,
+    )? {
+        Some(stopped) => Ok(stopped),
+        None => Ok(ExpandOutcome::Done(node, entries)),
+    }
+}
+
+/// Returns the reason for stopping early, if any. `limits.max_depth` is absolute here.
+/// `ancestors` holds the canonical paths of the directories being expanded above `node`,
+/// so a symlink pointing back up the tree is not followed in circles.
+fn expand_subtree_recursive(
+    node: &mut FileNode,
+    show_hidden: bool,
+    sort: SortOrder,
+    limits: ExpandLimits,
+    cancel: &AtomicBool,
+    entries: &mut usize,
+    ancestors: &mut Vec<PathBuf>,
+) -> anyhow::Result<Option<ExpandOutcome>> {
+    if !node.is_dir || node.depth >= limits.max_depth {
+        return Ok(None);
+    }
+    if cancel.load(Ordering::Relaxed) {
+        return Ok(Some(ExpandOutcome::Cancelled));
+    }
+    // Only symlinks can lead to a directory that is already on the stack, so other
+    // directories extend their parent's canonical path without a syscall
+    let real_path = match ancestors.last() {
+        Some(parent) if !node.is_symlink() => {
+            parent.join(node.path.file_name().unwrap_or_default())
+        }
+        _ => {
+            if node.is_symlink() && !limits.follow_symlinks {
+                return Ok(None);
+            }
+            fs::canonicalize(&node.path).unwrap_or_else(|_| node.path.clone())
+        }
+    };
+    if ancestors.contains(&real_path) {
+        return Ok(None);
+    }
+
+    node.expanded = true;
+    if node.children.is_empty() {
+        // An unreadable directory keeps its `read_error` and is shown as such
+        let _ = node.load_children(show_hidden, sort);
+    }
+    *entries += node.children.len();
+    if *entries > limits.max_entries {
+        return Ok(Some(ExpandOutcome::CapReached));
+    }
+    ancestors.push(real_path);
+    for child in &mut node.children {
+        if let Some(stopped) =
+            expand_subtree_recursive(child, show_hidden, sort, limits, cancel, entries, ancestors)?
+        {
+            return Ok(Some(stopped));
+        }
+    }
+    ancestors.pop();
+    Ok(None)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -411,7 +1057,7 @@ mod tests {
         let temp_dir = create_test_structure();
         let mut node = FileNode::new(temp_dir.path().to_path_buf(), 0);
 
-        node.load_children(false).unwrap();
+        node.load_children(false, SortOrder::default()).unwrap();
 
         let names: Vec<&str> = node.children.iter().map(|c| c.name.as_str()).collect();
         assert!(names.contains(&"dir_a"));
@@ -426,7 +1072,7 @@ mod tests {
         let temp_dir = create_test_structure();
         let mut node = FileNode::new(temp_dir.path().to_path_buf(), 0);
 
-        node.load_children(true).unwrap();
+        node.load_children(true, SortOrder::default()).unwrap();
 
         let names: Vec<&str> = node.children.iter().map(|c| c.name.as_str()).collect();
         assert!(names.contains(&"dir_a"));
@@ -434,12 +1080,34 @@ mod tests {
         assert!(names.contains(&".hidden_file"));
     }
 
+    #[test]
+    fn test_unreadable_directory_keeps_error() {
+        let temp_dir = create_test_structure();
+        let mut tree = FileTree::new(temp_dir.path(), false).unwrap();
+        let index = (0..tree.len())
+            .find(|&i| tree.get_node(i).unwrap().name == "dir_a")
+            .unwrap();
+        // Gone between listing and expanding, like a directory we may not read
+        fs::remove_dir_all(temp_dir.path().join("dir_a")).unwrap();
+
+        assert!(tree.expand_node(index).is_err());
+        let node = tree.get_node(index).unwrap();
+        assert!(node.expanded);
+        assert!(node.read_error.is_some());
+        assert_eq!(tree.root.unreadable_count(), 1);
+
+        fs::create_dir(temp_dir.path().join("dir_a")).unwrap();
+        tree.collapse_node(index).unwrap();
+        tree.expand_node(index).unwrap();
+        assert!(tree.get_node(index).unwrap().read_error.is_none());
+    }
+
     #[test]
     fn test_file_node_load_children_sorts_dirs_first() {
         let temp_dir = create_test_structure();
         let mut node = FileNode::new(temp_dir.path().to_path_buf(), 0);
 
-        node.load_children(false).unwrap();
+        node.load_children(false, SortOrder::default()).unwrap();
 
         // Find first file index
         let first_file_idx = node
@@ -459,6 +1127,66 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_compare_names_natural() {
+        fn sorted<'a>(names: &[&'a str], sort: SortOrder) -> Vec<&'a str> {
+            let mut names = names.to_vec();
+            names.sort_by(|a, b| compare_names(a, b, sort));
+            names
+        }
+        let natural = SortOrder::default();
+        assert_eq!(
+            sorted(&["file10.txt", "file2.txt", "file1.txt"], natural),
+            vec!["file1.txt", "file2.txt", "file10.txt"]
+        );
+        assert_eq!(
+            sorted(&["v1.10.0", "v1.9.2", "v1.9.10"], natural),
+            vec!["v1.9.2", "v1.9.10", "v1.10.0"]
+        );
+        // Leading zeros only break ties
+        assert_eq!(
+            sorted(&["img010", "img9", "img09"], natural),
+            vec!["img9", "img09", "img010"]
+        );
+        assert_eq!(sorted(&["b", "C", "a"], natural), vec!["C", "a", "b"]);
+
+        let insensitive = SortOrder {
+            case_insensitive: true,
+            ..natural
+        };
+        assert_eq!(
+            sorted(&["b", "C", "a", "A"], insensitive),
+            vec!["A", "a", "b", "C"]
+        );
+
+        let plain = SortOrder {
+            natural: false,
+            ..natural
+        };
+        assert_eq!(sorted(&["file2", "file10"], plain), vec!["file10", "file2"]);
+    }
+
+    #[test]
+    fn test_load_children_sorts_naturally() {
+        let temp_dir = TempDir::new().unwrap();
+        for name in ["page10.md", "page2.md", "Page3.md"] {
+            File::create(temp_dir.path().join(name)).unwrap();
+        }
+        let names = |tree: &FileTree| -> Vec<String> {
+            tree.root.children.iter().map(|c| c.name.clone()).collect()
+        };
+
+        let tree = FileTree::new(temp_dir.path(), false).unwrap();
+        assert_eq!(names(&tree), ["Page3.md", "page2.md", "page10.md"]);
+
+        let sort = SortOrder {
+            case_insensitive: true,
+            ..SortOrder::default()
+        };
+        let tree = FileTree::with_sort(temp_dir.path(), false, sort).unwrap();
+        assert_eq!(names(&tree), ["page2.md", "Page3.md", "page10.md"]);
+    }
+
     #[test]
     fn test_file_tree_new() {
         let temp_dir = create_test_structure();
@@ -516,6 +1244,30 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_file_tree_collapse_others() {
+        let temp_dir = create_test_structure();
+        let mut tree = FileTree::new(temp_dir.path(), false).unwrap();
+        for name in ["dir_a", "dir_b"] {
+            let idx = (0..tree.len())
+                .find(|&i| tree.get_node(i).unwrap().name == name)
+                .unwrap();
+            tree.expand_node(idx).unwrap();
+        }
+
+        tree.collapse_others(&temp_dir.path().join("dir_a/nested.txt"));
+
+        let expanded: Vec<&str> = tree
+            .root
+            .children
+            .iter()
+            .filter(|c| c.expanded)
+            .map(|c| c.name.as_str())
+            .collect();
+        assert_eq!(expanded, vec!["dir_a"]);
+        assert!(tree.root.expanded);
+    }
+
     #[test]
     fn test_file_tree_set_show_hidden() {
         let temp_dir = create_test_structure();
@@ -545,6 +1297,31 @@ mod tests {
         assert_eq!(tree.len(), initial_len + 1);
     }
 
+    #[test]
+    fn test_file_tree_refresh_dirs_keeps_expansion() {
+        let temp_dir = create_test_structure();
+        let base = temp_dir.path();
+        let mut tree = FileTree::new(base, false).unwrap();
+        let dir_a_idx = (0..tree.len())
+            .find(|&i| tree.get_node(i).is_some_and(|n| n.name == "dir_a"))
+            .unwrap();
+        tree.expand_node(dir_a_idx).unwrap();
+        let initial_len = tree.len();
+
+        File::create(base.join("new_file.txt")).unwrap();
+        fs::remove_file(base.join("file1.txt")).unwrap();
+        File::create(base.join("dir_b/unseen.txt")).unwrap();
+        tree.refresh_dirs(&[base.to_path_buf(), base.join("missing")]);
+
+        assert_eq!(tree.len(), initial_len);
+        assert!(tree.find_node(&base.join("new_file.txt")).is_some());
+        assert!(tree.find_node(&base.join("file1.txt")).is_none());
+        assert!(tree.find_node(&base.join("dir_a")).unwrap().expanded);
+        assert!(tree.find_node(&base.join("dir_a/nested.txt")).is_some());
+        // dir_b was never loaded, so it is left to be read when expanded
+        assert!(tree.find_node(&base.join("dir_b/unseen.txt")).is_none());
+    }
+
     #[test]
     fn test_file_tree_expand_and_collapse_node() {
         let temp_dir = create_test_structure();
@@ -572,6 +1349,348 @@ mod tests {
         assert_eq!(len_after_collapse, len_before);
     }
 
+    #[test]
+    fn test_expand_and_collapse_update_only_affected_rows() {
+        let temp_dir = TempDir::new().unwrap();
+        let base = temp_dir.path();
+        for dir in ["a/x", "a/y", "b", "c"] {
+            fs::create_dir_all(base.join(dir)).unwrap();
+        }
+        for file in ["a/x/1", "a/x/2", "a/y/3", "a/4", "b/5", "c/6", "7"] {
+            File::create(base.join(file)).unwrap();
+        }
+        let mut tree = FileTree::new(base, false).unwrap();
+        let rows = |tree: &FileTree| -> Vec<(String, usize, bool)> {
+            (0..tree.len())
+                .map(|i| tree.get_node(i).unwrap())
+                .map(|n| (n.name.clone(), n.depth, n.expanded))
+                .collect()
+        };
+        let index = |tree: &FileTree, name: &str| {
+            (0..tree.len())
+                .find(|&i| tree.get_node(i).unwrap().name == name)
+                .unwrap()
+        };
+
+        // Each step must leave the same rows as flattening the whole tree
+        let steps: [(&str, bool); 7] = [
+            ("a", true),
+            ("x", true),
+            ("c", true),
+            ("y", true),
+            ("a", false),
+            ("a", true),
+            ("x", false),
+        ];
+        for (name, expand) in steps {
+            let i = index(&tree, name);
+            if expand {
+                tree.expand_node(i).unwrap();
+            } else {
+                tree.collapse_node(i).unwrap();
+            }
+            let spliced = rows(&tree);
+            tree.rebuild_flat_list();
+            assert_eq!(spliced, rows(&tree), "after toggling {}", name);
+            assert_eq!(tree.flat_list, (0..tree.len()).collect::<Vec<_>>());
+        }
+        // Collapsing `a` kept `y` expanded underneath
+        assert!(tree.get_node(index(&tree, "y")).unwrap().expanded);
+
+        tree.toggle_expand(index(&tree, "b")).unwrap();
+        let spliced = rows(&tree);
+        tree.rebuild_flat_list();
+        assert_eq!(spliced, rows(&tree));
+        assert!(spliced.iter().any(|(name, ..)| name == "5"));
+    }
+
+    #[test]
+    fn test_file_tree_expand_to_depth() {
+        let temp_dir = create_test_structure();
+        let mut tree = FileTree::new(temp_dir.path(), false).unwrap();
+
+        tree.expand_to_depth(1).unwrap();
+        assert_eq!(tree.len(), 5);
+
+        tree.expand_to_depth(2).unwrap();
+        let names: Vec<&str> = (0..tree.len())
+            .filter_map(|i| tree.get_node(i).map(|n| n.name.as_str()))
+            .collect();
+        assert!(names.contains(&"nested.txt"));
+    }
+
+    #[test]
+    fn test_expand_subtree_limits() {
+        let temp_dir = create_test_structure();
+        let mut tree = FileTree::new(temp_dir.path(), false).unwrap();
+        let unlimited = ExpandLimits {
+            max_depth: usize::MAX,
+            max_entries: usize::MAX,
+            follow_symlinks: true,
+        };
+
+        let root = tree.root.clone();
+        match expand_subtree(
+            root,
+            false,
+            SortOrder::default(),
+            unlimited,
+            &AtomicBool::new(false),
+        )
+        .unwrap()
+        {
+            ExpandOutcome::Done(root, _) => assert!(tree.replace_node(root)),
+            outcome => panic!("unexpected {:?}", outcome),
+        }
+        let names: Vec<&str> = (0..tree.len())
+            .filter_map(|i| tree.get_node(i).map(|n| n.name.as_str()))
+            .collect();
+        assert!(names.contains(&"nested.txt"));
+
+        let shallow = ExpandLimits {
+            max_depth: 1,
+            ..unlimited
+        };
+        let root = FileTree::new(temp_dir.path(), false).unwrap().root;
+        match expand_subtree(
+            root,
+            false,
+            SortOrder::default(),
+            shallow,
+            &AtomicBool::new(false),
+        )
+        .unwrap()
+        {
+            ExpandOutcome::Done(root, _) => {
+                assert!(root.children.iter().all(|c| !c.expanded));
+            }
+            outcome => panic!("unexpected {:?}", outcome),
+        }
+
+        let capped = ExpandLimits {
+            max_entries: 1,
+            ..unlimited
+        };
+        let root = tree.root.clone();
+        assert!(matches!(
+            expand_subtree(
+                root,
+                false,
+                SortOrder::default(),
+                capped,
+                &AtomicBool::new(false)
+            )
+            .unwrap(),
+            ExpandOutcome::CapReached
+        ));
+
+        let root = FileTree::new(temp_dir.path(), false).unwrap().root;
+        assert!(matches!(
+            expand_subtree(
+                root,
+                false,
+                SortOrder::default(),
+                unlimited,
+                &AtomicBool::new(true)
+            )
+            .unwrap(),
+            ExpandOutcome::Cancelled
+        ));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_symlinks_show_target_and_skip_cycles() {
+        let temp_dir = create_test_structure();
+        let base = temp_dir.path();
+        std::os::unix::fs::symlink(base, base.join("dir_a/loop")).unwrap();
+        std::os::unix::fs::symlink("file1.txt", base.join("link.txt")).unwrap();
+
+        let node = FileNode::new(base.join("link.txt"), 1);
+        assert_eq!(node.symlink_target, Some(PathBuf::from("file1.txt")));
+        assert!(!node.broken_link);
+        assert!(!FileNode::new(base.join("file1.txt"), 1).is_symlink());
+
+        std::os::unix::fs::symlink("missing.txt", base.join("dangling")).unwrap();
+        let node = FileNode::new(base.join("dangling"), 1);
+        assert!(node.broken_link);
+        assert!(!node.is_dir);
+        assert_eq!(node.symlink_target, Some(PathBuf::from("missing.txt")));
+        fs::remove_file(base.join("dangling")).unwrap();
+
+        let mut limits = ExpandLimits {
+            max_depth: usize::MAX,
+            max_entries: 10_000,
+            follow_symlinks: true,
+        };
+        let root = FileTree::new(base, false).unwrap().root;
+        let root = match expand_subtree(
+            root,
+            false,
+            SortOrder::default(),
+            limits,
+            &AtomicBool::new(false),
+        )
+        .unwrap()
+        {
+            ExpandOutcome::Done(root, _) => root,
+            outcome => panic!("unexpected {:?}", outcome),
+        };
+        let dir_a = root.children.iter().find(|c| c.name == "dir_a").unwrap();
+        let link = dir_a.children.iter().find(|c| c.name == "loop").unwrap();
+        assert!(link.is_dir && link.is_symlink());
+        assert!(!link.expanded);
+
+        let mut tree = FileTree::new(base, false).unwrap();
+        tree.follow_symlinks = false;
+        tree.expand_to_depth(2).unwrap();
+        let loop_path = base.join("dir_a/loop");
+        let idx = (0..tree.len())
+            .find(|&i| tree.get_node(i).unwrap().path == loop_path)
+            .unwrap();
+        assert!(tree.expand_node(idx).is_err());
+
+        limits.follow_symlinks = false;
+        let node = tree.find_node(&loop_path).unwrap().clone();
+        match expand_subtree(
+            node,
+            false,
+            SortOrder::default(),
+            limits,
+            &AtomicBool::new(false),
+        )
+        .unwrap()
+        {
+            ExpandOutcome::Done(node, _) => assert!(!node.expanded),
+            outcome => panic!("unexpected {:?}", outcome),
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_non_utf8_names_keep_the_real_path() {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+        let temp_dir = create_test_structure();
+        let raw = OsStr::from_bytes(b"bad\xffname.txt");
+        File::create(temp_dir.path().join(raw)).unwrap();
+        File::create(temp_dir.path().join(OsStr::from_bytes(b".hidden\xff"))).unwrap();
+
+        let tree = FileTree::new(temp_dir.path(), false).unwrap();
+        let node = (0..tree.len())
+            .filter_map(|i| tree.get_node(i))
+            .find(|n| n.name.starts_with("bad"))
+            .unwrap();
+        assert_eq!(node.name, "bad\u{fffd}name.txt");
+        assert_eq!(node.path.file_name(), Some(raw));
+        assert!(node.path.exists());
+        assert!((0..tree.len()).all(|i| !tree.get_node(i).unwrap().name.starts_with(".hidden")));
+    }
+
+    #[test]
+    fn test_file_tree_replace_node() {
+        let temp_dir = create_test_structure();
+        let mut tree = FileTree::new(temp_dir.path(), false).unwrap();
+        let dir_a = temp_dir.path().join("dir_a");
+
+        let mut node = tree.find_node(&dir_a).unwrap().clone();
+        assert!(!node.expanded);
+        node.expanded = true;
+        node.load_children(false, SortOrder::default()).unwrap();
+        assert!(tree.replace_node(node));
+        assert!(tree.find_node(&dir_a.join("nested.txt")).is_some());
+        assert_eq!(tree.len(), 6);
+
+        let missing = FileNode::new(temp_dir.path().join("missing"), 1);
+        assert!(!tree.replace_node(missing));
+    }
+
+    #[test]
+    fn test_file_tree_flat_view() {
+        let temp_dir = create_test_structure();
+        let mut tree = FileTree::new(temp_dir.path(), false).unwrap();
+        tree.expand_to_depth(2).unwrap();
+        tree.flat_view = Some(temp_dir.path().to_path_buf());
+        tree.rebuild_flat_list();
+
+        let names: Vec<&str> = (1..tree.len())
+            .filter_map(|i| tree.get_node(i).map(|n| n.name.as_str()))
+            .collect();
+        assert_eq!(names, vec!["dir_a/nested.txt", "file1.txt", "file2.rs"]);
+        assert_eq!(tree.get_node(0).unwrap().path, temp_dir.path());
+
+        // A directory that is not in the tree falls back to the normal view
+        tree.flat_view = Some(temp_dir.path().join("missing"));
+        tree.rebuild_flat_list();
+        assert!(tree.flat_view.is_none());
+        assert_eq!(tree.get_node(1).unwrap().name, "dir_a");
+    }
+
+    #[test]
+    fn test_file_tree_view_filter() {
+        let temp_dir = create_test_structure();
+        let mut tree = FileTree::new(temp_dir.path(), true).unwrap();
+        let all = tree.len();
+
+        let mut filter = ViewFilter {
+            hide_dotfiles: true,
+            ..Default::default()
+        };
+        tree.set_filter(filter.clone());
+        assert_eq!(tree.len(), all - 2);
+
+        filter.excluded.insert(temp_dir.path().join("file1.txt"));
+        tree.set_filter(filter);
+        let names: Vec<&str> = (0..tree.len())
+            .filter_map(|i| tree.get_node(i).map(|n| n.name.as_str()))
+            .collect();
+        assert!(!names.contains(&"file1.txt"));
+        assert!(!names.contains(&".hidden_file"));
+        assert!(names.contains(&"file2.rs"));
+
+        tree.set_filter(ViewFilter {
+            dirs_only: true,
+            ..Default::default()
+        });
+        assert!((0..tree.len()).all(|i| tree.get_node(i).unwrap().is_dir));
+        assert_eq!(tree.len(), 4);
+
+        tree.set_filter(ViewFilter {
+            ignore: vec!["*.rs".to_string(), "file1.*".to_string()],
+            ..Default::default()
+        });
+        let names: Vec<&str> = (0..tree.len())
+            .filter_map(|i| tree.get_node(i).map(|n| n.name.as_str()))
+            .collect();
+        assert!(!names.contains(&"file1.txt"));
+        assert!(!names.contains(&"file2.rs"));
+        assert_eq!(tree.len(), all - 2);
+
+        tree.set_filter(ViewFilter {
+            extensions: Some(HashSet::from(["rs".to_string()])),
+            ..Default::default()
+        });
+        let files: Vec<&str> = (0..tree.len())
+            .filter_map(|i| tree.get_node(i))
+            .filter(|n| !n.is_dir)
+            .map(|n| n.name.as_str())
+            .collect();
+        assert_eq!(files, vec!["file2.rs"]);
+        assert_eq!(tree.len(), 5);
+
+        // dir_b is empty, and dir_a only has a .txt file once it is loaded
+        tree.expand_to_depth(2).unwrap();
+        tree.set_filter(ViewFilter {
+            extensions: Some(HashSet::from(["rs".to_string()])),
+            hide_empty_dirs: true,
+            ..Default::default()
+        });
+        let names: Vec<&str> = (1..tree.len())
+            .filter_map(|i| tree.get_node(i).map(|n| n.name.as_str()))
+            .collect();
+        assert_eq!(names, vec!["file2.rs"]);
+    }
+
     #[test]
     fn test_file_tree_is_empty() {
         let temp_dir = TempDir::new().unwrap();
@@ -580,4 +1699,36 @@ mod tests {
         // Tree has at least root
         assert!(!tree.is_empty());
     }
+
+    #[test]
+    fn test_node_size_from_metadata() {
+        let temp_dir = TempDir::new().unwrap();
+        fs::write(temp_dir.path().join("data.bin"), [0u8; 42]).unwrap();
+        fs::create_dir(temp_dir.path().join("sub")).unwrap();
+
+        let tree = FileTree::new(temp_dir.path(), false).unwrap();
+        let find = |name: &str| tree.root.children.iter().find(|n| n.name == name).unwrap();
+        assert_eq!(find("data.bin").size, Some(42));
+        assert_eq!(find("sub").size, None);
+        assert!(find("data.bin").modified.is_some());
+        let ownership = find("data.bin").ownership.unwrap();
+        assert_eq!(ownership.mode & 0o170000, 0o100000);
+    }
+
+    #[test]
+    fn test_child_count_is_cached_until_refresh() {
+        let temp_dir = create_test_structure();
+        let dir_a = temp_dir.path().join("dir_a");
+        let mut tree = FileTree::new(temp_dir.path(), false).unwrap();
+        assert_eq!(tree.child_count(&dir_a), 1);
+
+        File::create(dir_a.join("another.txt")).unwrap();
+        File::create(dir_a.join(".hidden")).unwrap();
+        assert_eq!(tree.child_count(&dir_a), 1);
+
+        tree.refresh().unwrap();
+        assert_eq!(tree.child_count(&dir_a), 2);
+        tree.set_show_hidden(true).unwrap();
+        assert_eq!(tree.child_count(&dir_a), 3);
+    }
 }
@@ -1,12 +1,20 @@
+//! Git status of the paths in a repository, via libgit2
+
 use std::collections::HashMap;
 use std::path::{Path, PathBuf};
 use std::process::Command;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, SystemTime};
+
+use git2::{DiffOptions, Patch, Repository, Status, StatusOptions};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
 pub enum GitStatus {
     #[default]
     None,
     Modified,
+    /// Modified with all changes staged (nothing left in the worktree)
+    Staged,
     Added,
     Deleted,
     Renamed,
@@ -15,12 +23,50 @@ pub enum GitStatus {
     Conflict,
 }
 
+impl GitStatus {
+    /// Lowercase name for output, `None` for unchanged entries
+    pub fn name(self) -> Option<&'static str> {
+        match self {
+            GitStatus::None => None,
+            GitStatus::Modified => Some("modified"),
+            GitStatus::Staged => Some("staged"),
+            GitStatus::Added => Some("added"),
+            GitStatus::Deleted => Some("deleted"),
+            GitStatus::Renamed => Some("renamed"),
+            GitStatus::Untracked => Some("untracked"),
+            GitStatus::Ignored => Some("ignored"),
+            GitStatus::Conflict => Some("conflict"),
+        }
+    }
+}
+
+/// How a line of a working tree file differs from `HEAD`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineChange {
+    Added,
+    Modified,
+    /// Lines were removed just before this one
+    Deleted,
+}
+
+/// The most recent commit that changed a path
+#[derive(Debug, Clone, PartialEq)]
+pub struct LastCommit {
+    pub short_id: String,
+    pub time: SystemTime,
+    pub summary: String,
+}
+
 #[derive(Debug, Default)]
 pub struct GitRepo {
     pub root: Option<PathBuf>,
     pub statuses: HashMap<PathBuf, GitStatus>,
     pub dir_status_cache: HashMap<PathBuf, GitStatus>,
     pub branch: Option<String>,
+    /// Commits (ahead, behind) the upstream of the current branch, if it has one
+    pub ahead_behind: Option<(usize, usize)>,
+    /// (changed, untracked) file counts for the whole repository
+    pub change_counts: (usize, usize),
 }
 
 impl GitRepo {
@@ -35,59 +81,52 @@ impl GitRepo {
         self.statuses.clear();
         self.dir_status_cache.clear();
         self.branch = None;
+        self.ahead_behind = None;
+        self.change_counts = (0, 0);
 
         if let Some(root) = self.root.clone() {
             self.load_statuses(&root);
             self.build_directory_cache();
             self.branch = get_current_branch(&root);
+            self.ahead_behind = get_ahead_behind(&root);
+            self.change_counts = self.count_changes_under(&root);
         }
     }
 
     fn load_statuses(&mut self, root: &Path) {
-        // Get modified/staged/untracked files
-        if let Ok(output) = Command::new("git")
-            .args(["status", "--porcelain", "-uall"])
-            .current_dir(root)
-            .output()
-        {
-            if output.status.success() {
-                let stdout = String::from_utf8_lossy(&output.stdout);
-                for line in stdout.lines() {
-                    if line.len() < 4 {
-                        continue;
-                    }
-                    let status_chars: Vec<char> = line.chars().take(2).collect();
-                    let file_path = &line[3..];
-
-                    // Handle renamed files (R  old -> new)
-                    let file_path = if file_path.contains(" -> ") {
-                        file_path.split(" -> ").last().unwrap_or(file_path)
-                    } else {
-                        file_path
-                    };
-
-                    let full_path = root.join(file_path);
-                    let status = parse_status(status_chars[0], status_chars[1]);
-                    self.statuses.insert(full_path, status);
+        let Ok(repo) = Repository::open(root) else {
+            return;
+        };
+
+        // Same view as `git status --porcelain --ignored -uall`:
+        // untracked directories are listed file by file, ignored ones collapsed
+        let mut options = StatusOptions::new();
+        options
+            .include_untracked(true)
+            .recurse_untracked_dirs(true)
+            .include_ignored(true)
+            .recurse_ignored_dirs(false)
+            .renames_head_to_index(true);
+
+        let Ok(entries) = repo.statuses(Some(&mut options)) else {
+            return;
+        };
+        for entry in entries.iter() {
+            let flags = entry.status();
+            // Renames are reported under the old path; show the new one
+            let file_path = match entry.head_to_index() {
+                Some(delta) if flags.contains(Status::INDEX_RENAMED) => {
+                    delta.new_file().path().map(Path::to_path_buf)
                 }
-            }
-        }
+                _ => entry.path().ok().map(PathBuf::from),
+            };
+            let Some(file_path) = file_path else {
+                continue;
+            };
 
-        // Get ignored files
-        if let Ok(output) = Command::new("git")
-            .args(["status", "--porcelain", "--ignored", "-uall"])
-            .current_dir(root)
-            .output()
-        {
-            if output.status.success() {
-                let stdout = String::from_utf8_lossy(&output.stdout);
-                for line in stdout.lines() {
-                    if let Some(file_path) = line.strip_prefix("!! ") {
-                        let full_path = root.join(file_path);
-                        self.statuses.insert(full_path, GitStatus::Ignored);
-                    }
-                }
-            }
+            let (index, worktree) = porcelain_chars(flags);
+            self.statuses
+                .insert(root.join(file_path), parse_status(index, worktree));
         }
     }
 
@@ -105,6 +144,7 @@ impl GitRepo {
 
                 match status {
                     GitStatus::Modified
+                    | GitStatus::Staged
                     | GitStatus::Added
                     | GitStatus::Deleted
                     | GitStatus::Renamed
@@ -153,25 +193,283 @@ impl GitRepo {
         GitStatus::None
     }
 
-    #[allow(dead_code)]
+    /// Stage paths (`git add`)
+    pub fn stage(&self, paths: &[PathBuf]) -> anyhow::Result<()> {
+        let root = self
+            .root
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("Not a git repository"))?;
+        run_git(root, &["add", "--"], paths)?;
+        Ok(())
+    }
+
+    /// Unstage paths, keeping worktree changes (`git restore --staged`)
+    pub fn unstage(&self, paths: &[PathBuf]) -> anyhow::Result<()> {
+        let root = self
+            .root
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("Not a git repository"))?;
+        run_git(root, &["restore", "--staged", "--"], paths)?;
+        Ok(())
+    }
+
+    /// Commit the staged changes, returning the short hash of the new commit
+    pub fn commit(&self, message: &str) -> anyhow::Result<String> {
+        let root = self
+            .root
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("Not a git repository"))?;
+        run_git(root, &["commit", "-q", "-m", message], &[])?;
+        Ok(run_git(root, &["rev-parse", "--short", "HEAD"], &[])?
+            .trim()
+            .to_string())
+    }
+
+    /// Changed files at or below each of `paths`, with their status (ignored files excluded)
+    pub fn changed_files_under(&self, paths: &[PathBuf]) -> Vec<(PathBuf, GitStatus)> {
+        let mut files: Vec<(PathBuf, GitStatus)> = self
+            .statuses
+            .iter()
+            .filter(|(file, status)| {
+                !matches!(status, GitStatus::Ignored | GitStatus::None)
+                    && paths.iter().any(|p| file.starts_with(p))
+            })
+            .map(|(file, status)| (file.clone(), *status))
+            .collect();
+        files.sort_by(|a, b| a.0.cmp(&b.0));
+        files
+    }
+
+    /// Throw away all changes to a file: restore it from HEAD, or remove it if it is new.
+    /// Renamed files are treated as new, so the original shows up as deleted afterwards.
+    pub fn discard(&self, path: &Path, status: GitStatus) -> anyhow::Result<()> {
+        let root = self
+            .root
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("Not a git repository"))?;
+        let paths = [path.to_path_buf()];
+        match status {
+            GitStatus::Untracked => std::fs::remove_file(path)?,
+            GitStatus::Added | GitStatus::Renamed => {
+                run_git(root, &["rm", "-f", "-q", "--"], &paths)?;
+            }
+            _ => {
+                run_git(
+                    root,
+                    &["restore", "--source=HEAD", "--staged", "--worktree", "--"],
+                    &paths,
+                )?;
+            }
+        }
+        Ok(())
+    }
+
+    /// All paths reported as ignored by git
+    pub fn ignored_paths(&self) -> impl Iterator<Item = &PathBuf> {
+        self.statuses
+            .iter()
+            .filter(|(_, status)| **status == GitStatus::Ignored)
+            .map(|(path, _)| path)
+    }
+
+    /// Count (changed, untracked) files below `dir`
+    pub fn count_changes_under(&self, dir: &Path) -> (usize, usize) {
+        let mut changed = 0;
+        let mut untracked = 0;
+        for (path, status) in &self.statuses {
+            if !path.starts_with(dir) {
+                continue;
+            }
+            match status {
+                GitStatus::Modified
+                | GitStatus::Staged
+                | GitStatus::Added
+                | GitStatus::Deleted
+                | GitStatus::Renamed
+                | GitStatus::Conflict => changed += 1,
+                GitStatus::Untracked => untracked += 1,
+                _ => {}
+            }
+        }
+        (changed, untracked)
+    }
+
+    /// The last `limit` commits touching `path`, one line each:
+    /// `<hash>  <date>  <author>  <subject>`
+    pub fn file_log(&self, path: &Path, limit: usize) -> anyhow::Result<Vec<String>> {
+        let root = self
+            .root
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("Not a git repository"))?;
+        let limit = format!("-{}", limit);
+        let output = run_git(
+            root,
+            &[
+                "log",
+                &limit,
+                "--date=short",
+                "--format=%h  %ad  %<(16,trunc)%an  %s",
+                "--",
+            ],
+            &[path.to_path_buf()],
+        )?;
+        Ok(output.lines().map(|line| line.to_string()).collect())
+    }
+
+    /// Changed lines of `path` in the working tree compared to `HEAD`, by
+    /// 1-based line number. Empty for files without tracked changes (including
+    /// untracked and binary files) and outside a repository.
+    pub fn line_changes(&self, path: &Path) -> HashMap<usize, LineChange> {
+        self.diff_lines(path).unwrap_or_default()
+    }
+
+    fn diff_lines(&self, path: &Path) -> Option<HashMap<usize, LineChange>> {
+        let root = self.root.as_ref()?;
+        let relative = path.strip_prefix(root).ok()?;
+        let repo = Repository::open(root).ok()?;
+        let head = repo.head().ok()?.peel_to_tree().ok()?;
+        let mut options = DiffOptions::new();
+        options
+            .pathspec(relative)
+            .disable_pathspec_match(true)
+            .context_lines(0);
+        let diff = repo
+            .diff_tree_to_workdir_with_index(Some(&head), Some(&mut options))
+            .ok()?;
+
+        let mut changes = HashMap::new();
+        let Ok(Some(patch)) = Patch::from_diff(&diff, 0) else {
+            return Some(changes);
+        };
+        for hunk in 0..patch.num_hunks() {
+            let (header, line_count) = patch.hunk(hunk).ok()?;
+            let lines: Vec<_> = (0..line_count)
+                .filter_map(|i| patch.line_in_hunk(hunk, i).ok())
+                .collect();
+            let removed = lines.iter().any(|l| l.origin() == '-');
+            let mut added = false;
+            for line in lines.iter().filter(|l| l.origin() == '+') {
+                if let Some(number) = line.new_lineno() {
+                    let change = if removed {
+                        LineChange::Modified
+                    } else {
+                        LineChange::Added
+                    };
+                    changes.insert(number as usize, change);
+                    added = true;
+                }
+            }
+            if removed && !added {
+                // Mark the line after the removal (a hunk at the very top starts at 0)
+                let line = (header.new_start() as usize + 1).max(1);
+                changes.entry(line).or_insert(LineChange::Deleted);
+            }
+        }
+        Some(changes)
+    }
+
     pub fn is_inside_repo(&self) -> bool {
         self.root.is_some()
     }
 }
 
-fn find_git_root(path: &Path) -> Option<PathBuf> {
+/// Run a git command in `root` with trailing path arguments, returning stdout.
+/// Fails with git's stderr message if the command exits unsuccessfully.
+fn run_git(root: &Path, args: &[&str], paths: &[PathBuf]) -> anyhow::Result<String> {
     let output = Command::new("git")
-        .args(["rev-parse", "--show-toplevel"])
-        .current_dir(path)
+        .args(args)
+        .args(paths)
+        .current_dir(root)
         .output()
-        .ok()?;
+        .map_err(|e| anyhow::anyhow!("Cannot run git: {}", e))?;
 
     if output.status.success() {
-        let root = String::from_utf8_lossy(&output.stdout).trim().to_string();
-        Some(PathBuf::from(root))
+        Ok(String::from_utf8_lossy(&output.stdout).to_string())
     } else {
-        None
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        anyhow::bail!("{}", stderr.lines().next().unwrap_or("git failed"))
+    }
+}
+
+/// The most recent commit on the first-parent history of `HEAD` in the
+/// repository at `root` that changed `path` (a file or a directory). Walks the
+/// history, so run it in the background; gives up once `cancel` is set.
+pub fn last_commit(root: &Path, path: &Path, cancel: &AtomicBool) -> Option<LastCommit> {
+    let relative = path.strip_prefix(root).ok()?;
+    let repo = Repository::open(root).ok()?;
+    let mut revwalk = repo.revwalk().ok()?;
+    revwalk.push_head().ok()?;
+    revwalk.simplify_first_parent().ok()?;
+    let entry_id = |commit: &git2::Commit| {
+        let tree = commit.tree().ok()?;
+        if relative.as_os_str().is_empty() {
+            return Some(tree.id());
+        }
+        tree.get_path(relative).ok().map(|entry| entry.id())
+    };
+
+    for oid in revwalk {
+        if cancel.load(Ordering::Relaxed) {
+            return None;
+        }
+        let commit = repo.find_commit(oid.ok()?).ok()?;
+        let parent = commit.parent(0).ok();
+        if entry_id(&commit) == parent.as_ref().and_then(entry_id) {
+            continue;
+        }
+        let short_id = commit.as_object().short_id().ok()?;
+        let seconds = commit.time().seconds().max(0) as u64;
+        return Some(LastCommit {
+            short_id: short_id.as_str().ok()?.to_string(),
+            time: SystemTime::UNIX_EPOCH + Duration::from_secs(seconds),
+            summary: commit
+                .summary()
+                .ok()
+                .flatten()
+                .unwrap_or_default()
+                .to_string(),
+        });
     }
+    None
+}
+
+fn find_git_root(path: &Path) -> Option<PathBuf> {
+    let repo = Repository::discover(path).ok()?;
+    // Normalize away the trailing separator libgit2 keeps on the workdir
+    Some(repo.workdir()?.components().collect())
+}
+
+/// The two-letter `git status --porcelain` code for a set of status flags
+fn porcelain_chars(flags: Status) -> (char, char) {
+    if flags.contains(Status::IGNORED) {
+        return ('!', '!');
+    }
+    if flags.contains(Status::CONFLICTED) {
+        return ('U', 'U');
+    }
+    if flags == Status::WT_NEW {
+        return ('?', '?');
+    }
+
+    let index = if flags.contains(Status::INDEX_NEW) {
+        'A'
+    } else if flags.contains(Status::INDEX_RENAMED) {
+        'R'
+    } else if flags.contains(Status::INDEX_DELETED) {
+        'D'
+    } else if flags.intersects(Status::INDEX_MODIFIED | Status::INDEX_TYPECHANGE) {
+        'M'
+    } else {
+        ' '
+    };
+    let worktree = if flags.contains(Status::WT_DELETED) {
+        'D'
+    } else if flags.intersects(Status::WT_MODIFIED | Status::WT_TYPECHANGE | Status::WT_RENAMED) {
+        'M'
+    } else {
+        ' '
+    };
+    (index, worktree)
 }
 
 fn parse_status(index: char, worktree: char) -> GitStatus {
@@ -182,23 +480,31 @@ fn parse_status(index: char, worktree: char) -> GitStatus {
         ('R', _) => GitStatus::Renamed,
         ('A', _) => GitStatus::Added,
         ('D', _) | (_, 'D') => GitStatus::Deleted,
+        ('M', ' ') => GitStatus::Staged,
         ('M', _) | (_, 'M') => GitStatus::Modified,
         _ => GitStatus::None,
     }
 }
 
+/// Short name of the checked-out branch, or `HEAD` when detached
 fn get_current_branch(root: &Path) -> Option<String> {
-    let output = Command::new("git")
-        .args(["rev-parse", "--abbrev-ref", "HEAD"])
-        .current_dir(root)
-        .output()
-        .ok()?;
+    let repo = Repository::open(root).ok()?;
+    let head = repo.head().ok()?;
+    if repo.head_detached().unwrap_or(false) {
+        return Some("HEAD".to_string());
+    }
+    head.shorthand().ok().map(|name| name.to_string())
+}
 
-    if output.status.success() {
-        Some(String::from_utf8_lossy(&output.stdout).trim().to_string())
-    } else {
-        None
+fn get_ahead_behind(root: &Path) -> Option<(usize, usize)> {
+    let repo = Repository::open(root).ok()?;
+    let head = repo.head().ok()?;
+    if !head.is_branch() {
+        return None;
     }
+    let local = head.target()?;
+    let upstream = git2::Branch::wrap(head).upstream().ok()?.get().target()?;
+    repo.graph_ahead_behind(local, upstream).ok()
 }
 
 #[cfg(test)]
@@ -223,11 +529,15 @@ mod tests {
 
     #[test]
     fn test_parse_status_modified() {
-        assert_eq!(parse_status('M', ' '), GitStatus::Modified);
         assert_eq!(parse_status(' ', 'M'), GitStatus::Modified);
         assert_eq!(parse_status('M', 'M'), GitStatus::Modified);
     }
 
+    #[test]
+    fn test_parse_status_staged() {
+        assert_eq!(parse_status('M', ' '), GitStatus::Staged);
+    }
+
     #[test]
     fn test_parse_status_deleted() {
         assert_eq!(parse_status('D', ' '), GitStatus::Deleted);
@@ -249,6 +559,40 @@ mod tests {
         assert_eq!(parse_status('D', 'D'), GitStatus::Conflict);
     }
 
+    #[test]
+    fn test_porcelain_chars() {
+        assert_eq!(porcelain_chars(Status::WT_NEW), ('?', '?'));
+        assert_eq!(porcelain_chars(Status::IGNORED), ('!', '!'));
+        assert_eq!(porcelain_chars(Status::INDEX_MODIFIED), ('M', ' '));
+        assert_eq!(
+            porcelain_chars(Status::INDEX_NEW | Status::WT_MODIFIED),
+            ('A', 'M')
+        );
+        assert_eq!(porcelain_chars(Status::WT_DELETED), (' ', 'D'));
+        assert_eq!(porcelain_chars(Status::CONFLICTED), ('U', 'U'));
+    }
+
+    #[test]
+    fn test_refresh_statuses_and_branch() {
+        let (_temp_dir, root) = init_test_repo();
+        std::fs::write(root.join(".gitignore"), "target/\n").unwrap();
+        std::fs::create_dir_all(root.join("target/debug")).unwrap();
+        std::fs::write(root.join("target/debug/out"), "").unwrap();
+        std::fs::create_dir(root.join("new_dir")).unwrap();
+        std::fs::write(root.join("new_dir/a.txt"), "").unwrap();
+        git(&root, &["mv", "file.txt", "moved.txt"]);
+
+        let repo = GitRepo::new(&root.join("new_dir"));
+        assert_eq!(repo.root.as_deref(), Some(root.as_path()));
+        assert!(repo.branch.is_some());
+        assert_eq!(repo.get_status(&root.join("moved.txt")), GitStatus::Renamed);
+        assert_eq!(
+            repo.get_status(&root.join("new_dir/a.txt")),
+            GitStatus::Untracked
+        );
+        assert_eq!(repo.get_status(&root.join("target")), GitStatus::Ignored);
+    }
+
     #[test]
     fn test_parse_status_none() {
         assert_eq!(parse_status(' ', ' '), GitStatus::None);
@@ -259,6 +603,191 @@ mod tests {
         assert_eq!(GitStatus::default(), GitStatus::None);
     }
 
+    #[test]
+    fn test_count_changes_under() {
+        let mut repo = GitRepo::default();
+        repo.statuses
+            .insert(PathBuf::from("/repo/src/a.rs"), GitStatus::Modified);
+        repo.statuses
+            .insert(PathBuf::from("/repo/src/b.rs"), GitStatus::Untracked);
+        repo.statuses
+            .insert(PathBuf::from("/repo/src/sub/c.rs"), GitStatus::Added);
+        repo.statuses
+            .insert(PathBuf::from("/repo/target/x"), GitStatus::Ignored);
+        repo.statuses
+            .insert(PathBuf::from("/repo/README.md"), GitStatus::Modified);
+
+        assert_eq!(repo.count_changes_under(Path::new("/repo/src")), (2, 1));
+        assert_eq!(repo.count_changes_under(Path::new("/repo")), (3, 1));
+        assert_eq!(repo.count_changes_under(Path::new("/repo/target")), (0, 0));
+    }
+
+    fn git(root: &Path, args: &[&str]) {
+        Command::new("git")
+            .args(["-c", "user.name=test", "-c", "user.email=test@example.com"])
+            .args(args)
+            .current_dir(root)
+            .output()
+            .unwrap();
+    }
+
+    /// A repository with `file.txt` committed as "one" and then modified to "two"
+    fn init_test_repo() -> (tempfile::TempDir, PathBuf) {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let root = temp_dir.path().canonicalize().unwrap();
+        git(&root, &["init", "-q"]);
+        git(&root, &["config", "user.name", "test"]);
+        git(&root, &["config", "user.email", "test@example.com"]);
+        std::fs::write(root.join("file.txt"), "one").unwrap();
+        git(&root, &["add", "file.txt"]);
+        git(&root, &["commit", "-q", "-m", "init"]);
+        std::fs::write(root.join("file.txt"), "two").unwrap();
+        (temp_dir, root)
+    }
+
+    #[test]
+    fn test_stage_and_unstage() {
+        let (_temp_dir, root) = init_test_repo();
+        let file = root.join("file.txt");
+
+        let mut repo = GitRepo::new(&root);
+        assert_eq!(repo.get_status(&file), GitStatus::Modified);
+
+        repo.stage(std::slice::from_ref(&file)).unwrap();
+        repo.refresh(&root);
+        assert_eq!(repo.get_status(&file), GitStatus::Staged);
+
+        repo.unstage(std::slice::from_ref(&file)).unwrap();
+        repo.refresh(&root);
+        assert_eq!(repo.get_status(&file), GitStatus::Modified);
+    }
+
+    #[test]
+    fn test_commit() {
+        let (_temp_dir, root) = init_test_repo();
+        let file = root.join("file.txt");
+
+        let mut repo = GitRepo::new(&root);
+        assert!(repo.commit("nothing staged").is_err());
+
+        repo.stage(std::slice::from_ref(&file)).unwrap();
+        let hash = repo.commit("update file").unwrap();
+        assert!(!hash.is_empty());
+        repo.refresh(&root);
+        assert_eq!(repo.get_status(&file), GitStatus::None);
+    }
+
+    #[test]
+    fn test_last_commit() {
+        let (_temp_dir, root) = init_test_repo();
+        let file = root.join("file.txt");
+        let other = root.join("other.txt");
+
+        let repo = GitRepo::new(&root);
+        repo.stage(std::slice::from_ref(&file)).unwrap();
+        let hash = repo.commit("update file").unwrap();
+        std::fs::write(&other, "").unwrap();
+        repo.stage(std::slice::from_ref(&other)).unwrap();
+        repo.commit("add other").unwrap();
+
+        let cancel = AtomicBool::new(false);
+        let commit = last_commit(&root, &file, &cancel).unwrap();
+        assert_eq!(commit.summary, "update file");
+        assert!(hash.starts_with(&commit.short_id));
+        assert_eq!(
+            last_commit(&root, &root, &cancel).unwrap().summary,
+            "add other"
+        );
+        cancel.store(true, Ordering::Relaxed);
+        assert_eq!(last_commit(&root, &file, &cancel), None);
+    }
+
+    #[test]
+    fn test_file_log() {
+        let (_temp_dir, root) = init_test_repo();
+        let file = root.join("file.txt");
+
+        let repo = GitRepo::new(&root);
+        repo.stage(std::slice::from_ref(&file)).unwrap();
+        repo.commit("second").unwrap();
+
+        let log = repo.file_log(&file, 10).unwrap();
+        assert_eq!(log.len(), 2);
+        assert!(log[0].ends_with("second"));
+        assert!(log[1].contains("test"));
+        assert!(log[1].ends_with("init"));
+        assert_eq!(repo.file_log(&file, 1).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_ahead_behind_and_change_counts() {
+        let (_temp_dir, root) = init_test_repo();
+        std::fs::write(root.join("new.txt"), "").unwrap();
+
+        let repo = GitRepo::new(&root);
+        assert_eq!(repo.ahead_behind, None);
+        assert_eq!(repo.change_counts, (1, 1));
+
+        git(&root, &["branch", "upstream"]);
+        git(&root, &["branch", "--set-upstream-to=upstream"]);
+        git(&root, &["commit", "-q", "-am", "local"]);
+        let repo = GitRepo::new(&root);
+        assert_eq!(repo.ahead_behind, Some((1, 0)));
+        assert_eq!(repo.change_counts, (0, 1));
+    }
+
+    #[test]
+    fn test_line_changes() {
+        let (_temp_dir, root) = init_test_repo();
+        let file = root.join("file.txt");
+        std::fs::write(&file, "a\nb\nc\nd\n").unwrap();
+        git(&root, &["commit", "-q", "-am", "four lines"]);
+
+        let repo = GitRepo::new(&root);
+        assert!(repo.line_changes(&file).is_empty());
+
+        std::fs::write(&file, "a\nB\nc\nnew\n").unwrap();
+        let changes = repo.line_changes(&file);
+        assert_eq!(changes.get(&2), Some(&LineChange::Modified));
+        assert_eq!(changes.get(&4), Some(&LineChange::Modified));
+        assert_eq!(changes.len(), 2);
+
+        std::fs::write(&file, "a\nb\nc\nd\ne\n").unwrap();
+        let changes = repo.line_changes(&file);
+        assert_eq!(changes, HashMap::from([(5, LineChange::Added)]));
+
+        std::fs::write(&file, "a\nd\n").unwrap();
+        let changes = repo.line_changes(&file);
+        assert_eq!(changes, HashMap::from([(2, LineChange::Deleted)]));
+
+        std::fs::write(root.join("new.txt"), "x\n").unwrap();
+        assert!(repo.line_changes(&root.join("new.txt")).is_empty());
+    }
+
+    #[test]
+    fn test_discard_changes() {
+        let (_temp_dir, root) = init_test_repo();
+        let file = root.join("file.txt");
+        let new_file = root.join("new.txt");
+        std::fs::write(&new_file, "new").unwrap();
+
+        let repo = GitRepo::new(&root);
+        let changed = repo.changed_files_under(std::slice::from_ref(&root));
+        assert_eq!(
+            changed,
+            vec![
+                (file.clone(), GitStatus::Modified),
+                (new_file.clone(), GitStatus::Untracked)
+            ]
+        );
+
+        for (path, status) in changed {
+            repo.discard(&path, status).unwrap();
+        }
+        assert_eq!(std::fs::read_to_string(&file).unwrap(), "one");
+        assert!(!new_file.exists());
+    }
+
     #[test]
     fn test_git_repo_default() {
         let repo = GitRepo::default();
@@ -1,6 +1,8 @@
-use crossterm::event::{KeyCode, KeyEvent, KeyModifiers, MouseEvent, MouseEventKind};
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers, MouseButton, MouseEvent, MouseEventKind};
 
+use crate::action::Action;
 use crate::app::{App, ConfirmAction, InputMode};
+use crate::keymap;
 
 pub fn handle_key_event(app: &mut App, key: KeyEvent, visible_height: usize) {
     match &app.input_mode {
@@ -9,11 +11,32 @@ pub fn handle_key_event(app: &mut App, key: KeyEvent, visible_height: usize) {
         | InputMode::Rename
         | InputMode::NewFile
         | InputMode::NewDir
-        | InputMode::ExternalCommand => {
+        | InputMode::MoveTo
+        | InputMode::CopyTo
+        | InputMode::ExternalCommand
+        | InputMode::Commit
+        | InputMode::MarkGlob
+        | InputMode::ExtensionFilter => {
             handle_input_mode(app, key);
         }
         InputMode::Confirm(_) => handle_confirm_mode(app, key),
         InputMode::Preview => handle_preview_mode(app, key, visible_height),
+        InputMode::PreviewSearch => handle_preview_search_mode(app, key, visible_height),
+        InputMode::JumpLabel => handle_jump_label_mode(app, key),
+        InputMode::TodoList => handle_todo_list_mode(app, key),
+        InputMode::Compare => handle_compare_mode(app, key),
+        InputMode::FileInfo => {
+            if matches!(
+                key.code,
+                KeyCode::Esc | KeyCode::Enter | KeyCode::Char('q') | KeyCode::Char('i')
+            ) {
+                app.input_mode = InputMode::Normal;
+            }
+        }
+        InputMode::MarkedList => handle_marked_list_mode(app, key),
+        InputMode::Plan => handle_plan_mode(app, key),
+        InputMode::DiskUsage => handle_disk_usage_mode(app, key),
+        InputMode::Help => handle_help_mode(app, key),
     }
 }
 
@@ -23,142 +46,280 @@ fn handle_normal_mode(app: &mut App, key: KeyEvent) {
         app.message = None;
     }
 
-    match key.code {
-        // Quit
-        KeyCode::Char('q') => app.should_quit = true,
-
-        // Navigation (update quick preview after movement)
-        KeyCode::Up | KeyCode::Char('k') => {
-            app.move_up();
-            app.update_quick_preview();
-        }
-        KeyCode::Down | KeyCode::Char('j') => {
-            app.move_down();
-            app.update_quick_preview();
+    // Second key of a multi-key sequence
+    if let Some(prefix) = app.pending_prefix.take() {
+        if let KeyCode::Char(c) = key.code {
+            app.run_sequence(prefix, c);
         }
-        KeyCode::Char('g') => {
-            app.move_to_top();
-            app.update_quick_preview();
-        }
-        KeyCode::Char('G') => {
-            app.move_to_bottom();
-            app.update_quick_preview();
+        return;
+    }
+
+    // Count prefix: digits accumulate, a leading 0 is not a count
+    if let KeyCode::Char(c @ '0'..='9') = key.code {
+        if key.modifiers.is_empty()
+            && app.drop_buffer.is_empty()
+            && (c != '0' || app.pending_count.is_some())
+        {
+            app.push_count_digit(c as u32 - '0' as u32);
+            return;
         }
+    }
+    let has_count = app.pending_count.is_some();
+    let count = app.take_count();
 
-        // Command execution
-        KeyCode::Enter => {
-            if key.modifiers.contains(KeyModifiers::SHIFT) {
-                // Shift-Enter: Always open command input
-                app.start_external_command();
-            } else if app.last_command.is_some() || app.default_command.is_some() {
-                // Enter with existing command: Execute it
-                app.execute_external_command(None);
-            } else {
-                // Enter without command: Open command input (first time)
-                app.start_external_command();
+    // Multi-key sequences (see keymap::SEQUENCES)
+    if let KeyCode::Char(c) = key.code {
+        if app.drop_buffer.is_empty() && keymap::sequence(c).is_some() {
+            // Keep the count for the completed sequence (e.g. `5gg`)
+            if has_count {
+                app.pending_count = Some(count);
             }
+            app.start_sequence(c);
+            return;
         }
+    }
+
+    if let Some(action) = normal_action(app, key, count, has_count) {
+        app.dispatch(action);
+    }
+}
+
+/// The action a key triggers in normal mode. `count` is the count prefix
+/// (1 if none was typed).
+fn normal_action(app: &App, key: KeyEvent, count: usize, has_count: bool) -> Option<Action> {
+    let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+    let action = match key.code {
+        // Quit
+        KeyCode::Char('q') => Action::Quit,
+
+        // Navigation
+        KeyCode::Up | KeyCode::Char('k') => Action::MoveUp(count),
+        KeyCode::Down | KeyCode::Char('j') => Action::MoveDown(count),
+        // With a count, G goes to that line like in vim
+        KeyCode::Char('G') if has_count => Action::GoToLine(count),
+        KeyCode::Char('G') => Action::GoToBottom,
+
+        // Picker mode: Enter chooses the selection (or toggles a directory)
+        KeyCode::Enter if app.picker.is_some() => Action::Choose,
+
+        // Command execution
+        // Shift-Enter: Always open command input
+        KeyCode::Enter if key.modifiers.contains(KeyModifiers::SHIFT) => Action::PromptCommand,
+        KeyCode::Enter => Action::RunCommand,
         // Alternative key binding for opening command input (for terminals that don't support Shift-Enter)
-        KeyCode::Char(':') => {
-            app.start_external_command();
-        }
-        KeyCode::Char('l') | KeyCode::Right => {
-            app.expand_current();
-            app.update_quick_preview();
-        }
-        KeyCode::Backspace | KeyCode::Char('h') | KeyCode::Left => {
-            app.collapse_current();
-            app.update_quick_preview();
-        }
-        KeyCode::Tab => app.toggle_expand(),
-        KeyCode::Char('H') => app.collapse_all(),
-        KeyCode::Char('L') => app.expand_all(),
+        KeyCode::Char(':') => Action::PromptCommand,
+        // Horizontal scroll for long names
+        KeyCode::Left if key.modifiers.contains(KeyModifiers::SHIFT) => Action::ScrollLeft(4),
+        KeyCode::Right if key.modifiers.contains(KeyModifiers::SHIFT) => Action::ScrollRight(4),
+        KeyCode::Char('<') => Action::ScrollLeft(4),
+        KeyCode::Char('>') => Action::ScrollRight(4),
+
+        KeyCode::Char('l') | KeyCode::Right => Action::Expand,
+        KeyCode::Backspace | KeyCode::Char('h') | KeyCode::Left => Action::Collapse,
+        KeyCode::Tab => Action::ToggleExpand,
+        KeyCode::Char('H') => Action::CollapseAll,
+        KeyCode::Char('L') => Action::ExpandAll,
+        KeyCode::Char('Z') => Action::CollapseOthers,
+        KeyCode::Char('E') => Action::ExpandRecursive,
+        KeyCode::Char('F') => Action::ToggleFlatView,
 
         // Marking
-        KeyCode::Char(' ') => app.toggle_mark(),
-        KeyCode::Esc => app.clear_marks(),
+        KeyCode::Char(' ') => Action::ToggleMark(count),
+        KeyCode::Esc => Action::ClearMarks,
+        KeyCode::Char('*') => Action::MarkAllInDir,
+        KeyCode::Char('~') => Action::InvertMarks,
+        // Before `x` so Ctrl-x doesn't clear marks
+        KeyCode::Char('x') if ctrl => Action::MoveTo,
+        KeyCode::Char('x') => Action::ClearAllMarks,
+        KeyCode::Char('m') => Action::MarkGlob,
+        KeyCode::Char('!') => Action::MarkChanged,
+        KeyCode::Char('B') => Action::MarkedList,
+
+        // Directories-only view (before `d` so Ctrl-d doesn't cut)
+        KeyCode::Char('d') if ctrl => Action::ToggleDirsOnly,
 
         // Clipboard operations
-        KeyCode::Char('y') => app.yank(),
-        KeyCode::Char('d') => {
-            if key.modifiers.contains(KeyModifiers::SHIFT) {
-                app.confirm_delete();
-            } else {
-                app.cut();
-            }
-        }
-        KeyCode::Char('D') | KeyCode::Delete => app.confirm_delete(),
-        KeyCode::Char('p') => app.paste(),
+        KeyCode::Char('y') if ctrl => Action::CopyTo,
+        KeyCode::Char('y') => Action::Yank,
+        KeyCode::Char('d') if key.modifiers.contains(KeyModifiers::SHIFT) => Action::Delete,
+        KeyCode::Char('d') => Action::Cut,
+        KeyCode::Char('D') | KeyCode::Delete => Action::Delete,
+        KeyCode::Char('v') if ctrl => Action::PasteSystemClipboard,
+        KeyCode::Char('p') => Action::Paste,
+        KeyCode::Char('o') if ctrl => Action::DragOut,
+        KeyCode::Char('e') if ctrl => Action::OpenInNvim,
+
+        // Git staging
+        KeyCode::Char('+') => Action::Stage,
+        KeyCode::Char('-') => Action::Unstage,
+        KeyCode::Char('X') => Action::Discard,
+        KeyCode::Char('M') => Action::Commit,
 
         // File operations
-        KeyCode::Char('r') => app.start_rename(),
-        KeyCode::Char('a') => app.start_new_file(),
-        KeyCode::Char('A') => app.start_new_dir(),
+        KeyCode::Char('r') => Action::Rename,
+        KeyCode::Char('a') => Action::NewFile,
+        KeyCode::Char('A') => Action::NewDir,
 
         // Search (buffered for drop detection)
         // Also buffer quotes and backslash for quoted/escaped paths
-        KeyCode::Char(c @ ('/' | '\'' | '"' | '\\')) => app.buffer_char(c),
-        KeyCode::Char('n') => app.search_next(),
+        KeyCode::Char(c @ ('/' | '\'' | '"' | '\\')) => Action::BufferChar(c),
+        KeyCode::Char('n') => Action::SearchNext,
+
+        // Breadcrumb: Alt+1..9 re-roots at the nth ancestor
+        KeyCode::Char(c @ '1'..='9') if key.modifiers.contains(KeyModifiers::ALT) => {
+            Action::BreadcrumbJump(c as usize - '0' as usize)
+        }
+
+        // Tabs
+        KeyCode::Char('w') if ctrl => Action::CloseTab,
+        KeyCode::Char('t') => Action::OpenTab,
+        KeyCode::Char(']') => Action::NextTab,
+        KeyCode::Char('[') => Action::PrevTab,
+
+        // TODO/FIXME scanner
+        KeyCode::Char('T') => Action::TodoScan,
+        KeyCode::Char('=') => Action::CompareDirs,
+        KeyCode::Char('i') => Action::FileInfo,
+
+        // Jump labels
+        KeyCode::Char('f') => Action::JumpLabels,
 
         // Reload tree
-        KeyCode::Char('R') | KeyCode::F(5) => app.refresh(),
+        KeyCode::Char('R') | KeyCode::F(5) => Action::Refresh,
+        KeyCode::F(11) => Action::ToggleCompact,
+        KeyCode::Char('|') => Action::ToggleMillerColumns,
+        KeyCode::F(12) => Action::TogglePerfOverlay,
 
         // Toggle hidden files
-        KeyCode::Char('.') => app.toggle_hidden(),
+        KeyCode::Char('.') => Action::ToggleHidden,
+        KeyCode::Char('I') => Action::ToggleCleanView,
+        KeyCode::Char('e') => Action::ExtensionFilter,
+        KeyCode::Char('z') => Action::ToggleHideEmptyDirs,
+        KeyCode::Char('S') => Action::ToggleSizeColumn,
+        KeyCode::Char('W') => Action::ToggleMtimeColumn,
+        KeyCode::Char('U') => Action::TogglePermissionsColumn,
+        KeyCode::Char('V') => Action::ToggleDetailView,
+        KeyCode::Char('s') => Action::CalculateDirSizes,
+        KeyCode::Char('u') => Action::DiskUsage,
 
         // Copy path to clipboard
-        KeyCode::Char('c') => app.copy_path(),
-        KeyCode::Char('C') => app.copy_filename(),
+        KeyCode::Char('c') => Action::CopyPath,
+        KeyCode::Char('C') => Action::CopyFilename,
 
         // Preview file
-        KeyCode::Char('o') => app.preview_file(),
-        KeyCode::Char('P') => app.toggle_quick_preview(),
+        KeyCode::Char('o') => Action::Preview,
+        KeyCode::Char('O') => Action::OpenMarked,
+        KeyCode::Char('v') => Action::GitLog,
+        KeyCode::Char('P') => Action::ToggleQuickPreview,
+        KeyCode::Char('w') if !ctrl => Action::TogglePreviewWrap,
+        KeyCode::Char('#') => Action::TogglePreviewLineNumbers,
 
         // Help
-        KeyCode::Char('?') => {
-            app.message = Some("o:preview  P:quick  c:path  C:name  y:yank  d:cut  p:paste  D:del  r:rename  a:file  A:dir  Enter:cmd  colon:new_cmd".to_string());
-        }
+        KeyCode::Char('?') => Action::Help,
 
         // Buffer unknown chars for drop detection
-        KeyCode::Char(c) if !app.drop_buffer.is_empty() => app.buffer_char(c),
+        KeyCode::Char(c) if !app.drop_buffer.is_empty() => Action::BufferChar(c),
 
-        _ => {}
-    }
+        _ => return None,
+    };
+    Some(action)
 }
 
-pub fn handle_mouse_event(app: &mut App, mouse: MouseEvent) {
+pub fn handle_mouse_event(app: &mut App, mouse: MouseEvent, visible_height: usize) {
+    if app.input_mode == InputMode::Preview {
+        match mouse.kind {
+            MouseEventKind::ScrollUp => {
+                for _ in 0..3 {
+                    app.preview_scroll_up();
+                }
+            }
+            MouseEventKind::ScrollDown => {
+                for _ in 0..3 {
+                    app.preview_scroll_down(visible_height);
+                }
+            }
+            _ => {}
+        }
+        return;
+    }
     if app.input_mode != InputMode::Normal {
         return;
     }
 
-    match mouse.kind {
-        // Tree area starts at row 1 (after border)
-        MouseEventKind::Down(crossterm::event::MouseButton::Left) if mouse.row > 0 => {
-            app.handle_click(mouse.row - 1);
-            app.update_quick_preview();
+    // The wheel scrolls whichever pane is under the cursor
+    if let Some((top, height)) = app.quick_preview_rows {
+        if (top..top + height).contains(&mouse.row) {
+            let preview_height = height.saturating_sub(2) as usize;
+            match mouse.kind {
+                MouseEventKind::ScrollUp => {
+                    for _ in 0..3 {
+                        app.quick_preview_scroll_up();
+                    }
+                }
+                MouseEventKind::ScrollDown => {
+                    for _ in 0..3 {
+                        app.quick_preview_scroll_down(preview_height);
+                    }
+                }
+                MouseEventKind::Up(MouseButton::Left) => app.end_drag(),
+                _ => {}
+            }
+            return;
         }
-        MouseEventKind::ScrollUp => {
-            app.scroll_up(3);
-            app.update_quick_preview();
+    }
+
+    let action = match mouse.kind {
+        MouseEventKind::Down(MouseButton::Left) if mouse.row == app.breadcrumb_y => {
+            Action::BreadcrumbClick(mouse.column)
         }
-        MouseEventKind::ScrollDown => {
-            app.scroll_down(3);
-            app.update_quick_preview();
+        // The columns layout has no row under the cursor to map clicks to;
+        // the wheel moves through the current column instead
+        MouseEventKind::ScrollUp if app.miller => Action::MoveUp(1),
+        MouseEventKind::ScrollDown if app.miller => Action::MoveDown(1),
+        _ if app.miller && mouse.kind != MouseEventKind::Up(MouseButton::Left) => return,
+        // `tree_area_y` is the first entry row, below any border
+        MouseEventKind::Down(MouseButton::Left) | MouseEventKind::Drag(MouseButton::Left)
+            if mouse.row >= app.tree_area_y && Some(mouse.column) == app.tree_scrollbar_x =>
+        {
+            Action::ScrollbarJump(mouse.row - app.tree_area_y)
         }
-        _ => {}
-    }
+        MouseEventKind::Down(MouseButton::Left) if mouse.row >= app.tree_area_y => {
+            Action::Click(mouse.row - app.tree_area_y)
+        }
+        MouseEventKind::Drag(MouseButton::Left) if mouse.row >= app.tree_area_y => {
+            Action::Drag(mouse.row - app.tree_area_y)
+        }
+        MouseEventKind::Up(MouseButton::Left) => Action::EndDrag,
+        MouseEventKind::ScrollUp => Action::ScrollUp(3),
+        MouseEventKind::ScrollDown => Action::ScrollDown(3),
+        _ => return,
+    };
+    app.dispatch(action);
 }
 
 fn handle_input_mode(app: &mut App, key: KeyEvent) {
+    if app.history_search.is_some() {
+        handle_history_search(app, key);
+        return;
+    }
     match key.code {
         KeyCode::Enter => app.confirm_input(),
         KeyCode::Esc => app.cancel_input(),
         KeyCode::Backspace => {
             app.input_buffer.pop();
         }
-        // History navigation only for ExternalCommand mode
-        KeyCode::Up if app.input_mode == InputMode::ExternalCommand => app.history_prev(),
-        KeyCode::Down if app.input_mode == InputMode::ExternalCommand => app.history_next(),
+        // History navigation for commands and destinations
+        KeyCode::Up if has_history(&app.input_mode) => app.history_prev(),
+        KeyCode::Down if has_history(&app.input_mode) => app.history_next(),
+        KeyCode::Tab if matches!(app.input_mode, InputMode::MoveTo | InputMode::CopyTo) => {
+            app.complete_destination()
+        }
+        KeyCode::Char('r')
+            if app.input_mode == InputMode::ExternalCommand
+                && key.modifiers.contains(KeyModifiers::CONTROL) =>
+        {
+            app.history_search_older()
+        }
         KeyCode::Char(c) => {
             app.input_buffer.push(c);
         }
@@ -166,18 +327,171 @@ fn handle_input_mode(app: &mut App, key: KeyEvent) {
     }
 }
 
+fn has_history(mode: &InputMode) -> bool {
+    matches!(
+        mode,
+        InputMode::ExternalCommand | InputMode::MoveTo | InputMode::CopyTo
+    )
+}
+
+fn handle_history_search(app: &mut App, key: KeyEvent) {
+    match key.code {
+        KeyCode::Char('r') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+            app.history_search_older()
+        }
+        KeyCode::Char('g') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+            app.cancel_history_search()
+        }
+        KeyCode::Esc => app.cancel_history_search(),
+        KeyCode::Enter => {
+            app.accept_history_search();
+            app.confirm_input();
+        }
+        KeyCode::Backspace => app.history_search_pop(),
+        KeyCode::Char(c) => app.history_search_push(c),
+        // Anything else keeps the match for editing
+        _ => app.accept_history_search(),
+    }
+}
+
+fn handle_todo_list_mode(app: &mut App, key: KeyEvent) {
+    match key.code {
+        KeyCode::Esc | KeyCode::Char('q') => app.close_todo_list(),
+        KeyCode::Up | KeyCode::Char('k') => app.todo_move(-1),
+        KeyCode::Down | KeyCode::Char('j') => app.todo_move(1),
+        KeyCode::PageUp => app.todo_move(-10),
+        KeyCode::PageDown => app.todo_move(10),
+        KeyCode::Char('g') | KeyCode::Home => app.todo_selected = 0,
+        KeyCode::Char('G') | KeyCode::End => app.todo_move(isize::MAX / 2),
+        KeyCode::Enter => app.open_todo(),
+        KeyCode::Char('R') => app.start_todo_scan(),
+        _ => {}
+    }
+}
+
+fn handle_compare_mode(app: &mut App, key: KeyEvent) {
+    match key.code {
+        KeyCode::Esc | KeyCode::Char('q') => app.close_compare(),
+        KeyCode::Up | KeyCode::Char('k') => app.compare_move(-1),
+        KeyCode::Down | KeyCode::Char('j') => app.compare_move(1),
+        KeyCode::PageUp => app.compare_move(-10),
+        KeyCode::PageDown => app.compare_move(10),
+        KeyCode::Char('g') | KeyCode::Home => app.compare_selected = 0,
+        KeyCode::Char('G') | KeyCode::End => app.compare_move(isize::MAX / 2),
+        KeyCode::Enter => app.open_compare_entry(),
+        KeyCode::Char('c') => app.copy_compare_entry(),
+        KeyCode::Char('R') => app.rescan_compare(),
+        _ => {}
+    }
+}
+
+fn handle_plan_mode(app: &mut App, key: KeyEvent) {
+    match key.code {
+        KeyCode::Esc | KeyCode::Char('q') | KeyCode::Char('n') => app.cancel_plan(),
+        KeyCode::Up | KeyCode::Char('k') => app.plan_move(-1),
+        KeyCode::Down | KeyCode::Char('j') => app.plan_move(1),
+        KeyCode::PageUp => app.plan_move(-10),
+        KeyCode::PageDown => app.plan_move(10),
+        KeyCode::Char('g') | KeyCode::Home => app.plan_selected = 0,
+        KeyCode::Char('G') | KeyCode::End => app.plan_move(isize::MAX / 2),
+        KeyCode::Char(' ') | KeyCode::Char('x') => app.toggle_plan_item(),
+        KeyCode::Enter | KeyCode::Char('y') => app.execute_plan(),
+        _ => {}
+    }
+}
+
+fn handle_marked_list_mode(app: &mut App, key: KeyEvent) {
+    match key.code {
+        KeyCode::Esc | KeyCode::Char('q') | KeyCode::Char('B') => app.close_marked_list(),
+        KeyCode::Up | KeyCode::Char('k') => app.marked_move(-1),
+        KeyCode::Down | KeyCode::Char('j') => app.marked_move(1),
+        KeyCode::PageUp => app.marked_move(-10),
+        KeyCode::PageDown => app.marked_move(10),
+        KeyCode::Char('g') | KeyCode::Home => app.marked_selected = 0,
+        KeyCode::Char('G') | KeyCode::End => app.marked_move(isize::MAX / 2),
+        KeyCode::Char(' ') | KeyCode::Char('x') | KeyCode::Delete => app.unmark_selected(),
+        _ => {}
+    }
+}
+
+fn handle_help_mode(app: &mut App, key: KeyEvent) {
+    if app.help_searching {
+        match key.code {
+            KeyCode::Enter | KeyCode::Esc => app.help_searching = false,
+            KeyCode::Backspace => {
+                app.help_query.pop();
+            }
+            KeyCode::Char(c) => {
+                app.help_query.push(c);
+                app.help_scroll = 0;
+            }
+            _ => {}
+        }
+        return;
+    }
+    match key.code {
+        KeyCode::Esc if !app.help_query.is_empty() => app.help_query.clear(),
+        KeyCode::Esc | KeyCode::Char('q') | KeyCode::Char('?') => {
+            app.input_mode = InputMode::Normal
+        }
+        KeyCode::Up | KeyCode::Char('k') => app.help_scroll_by(-1),
+        KeyCode::Down | KeyCode::Char('j') => app.help_scroll_by(1),
+        KeyCode::PageUp => app.help_scroll_by(-10),
+        KeyCode::PageDown => app.help_scroll_by(10),
+        KeyCode::Char('g') | KeyCode::Home => app.help_scroll = 0,
+        KeyCode::Char('G') | KeyCode::End => app.help_scroll = usize::MAX / 2,
+        KeyCode::Char('/') => {
+            app.help_searching = true;
+            app.help_query.clear();
+        }
+        _ => {}
+    }
+}
+
+fn handle_disk_usage_mode(app: &mut App, key: KeyEvent) {
+    match key.code {
+        KeyCode::Esc | KeyCode::Char('q') => app.close_disk_usage(),
+        KeyCode::Up | KeyCode::Char('k') => app.du_move(-1),
+        KeyCode::Down | KeyCode::Char('j') => app.du_move(1),
+        KeyCode::PageUp => app.du_move(-10),
+        KeyCode::PageDown => app.du_move(10),
+        KeyCode::Char('g') | KeyCode::Home => app.du_selected = 0,
+        KeyCode::Char('G') | KeyCode::End => app.du_move(isize::MAX / 2),
+        KeyCode::Enter | KeyCode::Right | KeyCode::Char('l') => app.du_enter(),
+        KeyCode::Backspace | KeyCode::Left | KeyCode::Char('h') => app.du_parent(),
+        KeyCode::Char('d') | KeyCode::Char('D') | KeyCode::Delete => app.du_delete(),
+        KeyCode::Char('R') => app.start_disk_usage(app.du_dir.clone()),
+        _ => {}
+    }
+}
+
+fn handle_jump_label_mode(app: &mut App, key: KeyEvent) {
+    match key.code {
+        KeyCode::Char(c) => {
+            app.jump_label_input(c);
+            app.update_quick_preview();
+        }
+        _ => app.cancel_input(),
+    }
+}
+
 fn handle_confirm_mode(app: &mut App, key: KeyEvent) {
     match key.code {
         KeyCode::Char('y') | KeyCode::Char('Y') | KeyCode::Enter => {
-            if let InputMode::Confirm(ConfirmAction::Delete(_)) = app.input_mode {
-                app.execute_delete();
+            match std::mem::replace(&mut app.input_mode, InputMode::Normal) {
+                InputMode::Confirm(ConfirmAction::Delete(info)) => app.execute_delete(info),
+                InputMode::Confirm(ConfirmAction::Discard(info)) => app.execute_discard(info),
+                InputMode::Confirm(ConfirmAction::Elevate(op)) => app.execute_elevated(op),
+                InputMode::Confirm(ConfirmAction::Retry(report)) => app.retry_batch(report),
+                InputMode::Confirm(ConfirmAction::Overwrite(info)) => app.execute_rename(info),
+                InputMode::Confirm(ConfirmAction::Open(info)) => app.execute_open(info),
+                InputMode::Confirm(ConfirmAction::Command(run)) => app.run_external_command(run),
+                InputMode::Confirm(ConfirmAction::Large(batch)) => app.execute_large(batch),
+                _ => {}
             }
-            app.input_mode = InputMode::Normal;
-        }
-        KeyCode::Char('n') | KeyCode::Char('N') | KeyCode::Esc => {
-            app.input_mode = InputMode::Normal;
-            app.message = Some("Cancelled".to_string());
         }
+        KeyCode::Char('e') => app.elevate_batch(),
+        KeyCode::Char('n') | KeyCode::Char('N') | KeyCode::Esc => app.cancel_confirm(),
         _ => {}
     }
 }
@@ -195,6 +509,26 @@ fn handle_preview_mode(app: &mut App, key: KeyEvent, visible_height: usize) {
         KeyCode::Char('G') => {
             app.preview_scroll = app.preview_content.len().saturating_sub(visible_height);
         }
+        KeyCode::Left | KeyCode::Char('h') => app.preview_scroll_left(4),
+        KeyCode::Right | KeyCode::Char('l') => app.preview_scroll_right(4),
+        KeyCode::Char('0') => app.preview_hscroll = 0,
+        KeyCode::Char('w') => app.toggle_preview_wrap(),
+        KeyCode::Char('#') => app.toggle_preview_line_numbers(),
+        KeyCode::Char('/') => app.start_preview_search(),
+        KeyCode::Char('n') => app.preview_search_next(true, visible_height),
+        KeyCode::Char('N') => app.preview_search_next(false, visible_height),
+        _ => {}
+    }
+}
+
+fn handle_preview_search_mode(app: &mut App, key: KeyEvent, visible_height: usize) {
+    match key.code {
+        KeyCode::Enter => app.search_preview(visible_height),
+        KeyCode::Esc => app.cancel_input(),
+        KeyCode::Backspace => {
+            app.input_buffer.pop();
+        }
+        KeyCode::Char(c) => app.input_buffer.push(c),
         _ => {}
     }
 }
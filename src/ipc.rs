@@ -0,0 +1,169 @@
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+/// A command sent to a running ft over its `--listen` socket, one JSON
+/// object per line, e.g. `{"cmd": "reveal", "path": "/src/main.rs"}`
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(tag = "cmd", rename_all = "snake_case")]
+pub enum Request {
+    /// Expand the tree down to `path` and select it
+    Reveal {
+        path: PathBuf,
+    },
+    /// Reload the tree from disk
+    Refresh,
+    /// Reply with the selected and the marked paths
+    GetSelection,
+    Quit,
+}
+
+/// A request together with the channel its JSON reply goes back on
+pub type Incoming = (Request, Sender<Value>);
+
+/// Accepts connections on a Unix socket in the background and hands their
+/// requests to the UI thread. The socket file is removed on drop.
+pub struct Server {
+    path: PathBuf,
+    receiver: Receiver<Incoming>,
+}
+
+impl Server {
+    pub fn bind(path: &Path) -> anyhow::Result<Self> {
+        if path.exists() {
+            // Only replace a socket nobody is listening on anymore
+            if UnixStream::connect(path).is_ok() {
+                anyhow::bail!("{} is already in use", path.display());
+            }
+            fs::remove_file(path)?;
+        }
+        let listener = UnixListener::bind(path)
+            .map_err(|e| anyhow::anyhow!("Cannot listen on {}: {}", path.display(), e))?;
+
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let sender = sender.clone();
+                thread::spawn(move || serve(stream, sender));
+            }
+        });
+        Ok(Self {
+            path: path.to_path_buf(),
+            receiver,
+        })
+    }
+
+    /// The next pending request, if any
+    pub fn try_recv(&self) -> Option<Incoming> {
+        self.receiver.try_recv().ok()
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// Answer the requests of one connection until it closes
+fn serve(stream: UnixStream, sender: Sender<Incoming>) {
+    let Ok(mut writer) = stream.try_clone() else {
+        return;
+    };
+    for line in BufReader::new(stream).lines() {
+        let Ok(line) = line else {
+            return;
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+        let reply = match serde_json::from_str::<Request>(&line) {
+            Ok(request) => {
+                let (reply_sender, reply_receiver) = mpsc::channel();
+                if sender.send((request, reply_sender)).is_err() {
+                    return;
+                }
+                reply_receiver
+                    .recv()
+                    .unwrap_or_else(|_| error("ft is shutting down"))
+            }
+            Err(e) => error(&format!("Invalid request: {}", e)),
+        };
+        if writeln!(writer, "{}", reply).is_err() {
+            return;
+        }
+    }
+}
+
+pub fn ok(fields: Value) -> Value {
+    let mut reply = json!({ "ok": true });
+    if let (Some(reply), Value::Object(fields)) = (reply.as_object_mut(), fields) {
+        reply.extend(fields);
+    }
+    reply
+}
+
+pub fn error(message: &str) -> Value {
+    json!({ "ok": false, "error": message })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_requests() {
+        let request: Request = serde_json::from_str(r#"{"cmd":"reveal","path":"/a/b"}"#).unwrap();
+        assert_eq!(
+            request,
+            Request::Reveal {
+                path: PathBuf::from("/a/b")
+            }
+        );
+        let request: Request = serde_json::from_str(r#"{"cmd":"get_selection"}"#).unwrap();
+        assert_eq!(request, Request::GetSelection);
+        assert!(serde_json::from_str::<Request>(r#"{"cmd":"explode"}"#).is_err());
+    }
+
+    #[test]
+    fn test_round_trip_over_socket() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let socket = dir.path().join("ft.sock");
+        let server = Server::bind(&socket).unwrap();
+        assert!(Server::bind(&socket).is_err());
+
+        let client = thread::spawn({
+            let socket = socket.clone();
+            move || {
+                let mut stream = UnixStream::connect(&socket).unwrap();
+                writeln!(stream, "{{\"cmd\":\"refresh\"}}\nnot json").unwrap();
+                let mut lines = BufReader::new(stream).lines();
+                let first = lines.next().unwrap().unwrap();
+                let second = lines.next().unwrap().unwrap();
+                (first, second)
+            }
+        });
+
+        let (request, reply) = loop {
+            if let Some(incoming) = server.try_recv() {
+                break incoming;
+            }
+            thread::sleep(std::time::Duration::from_millis(5));
+        };
+        assert_eq!(request, Request::Refresh);
+        reply.send(ok(json!({ "done": 1 }))).unwrap();
+
+        let (first, second) = client.join().unwrap();
+        assert_eq!(first, r#"{"done":1,"ok":true}"#);
+        assert!(second.contains("Invalid request"));
+
+        drop(server);
+        assert!(!socket.exists());
+    }
+}
@@ -0,0 +1,81 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::sync::Arc;
+use std::thread;
+
+/// A background computation running on its own thread.
+/// The closure receives a cancellation flag it should check periodically.
+/// Dropping the job requests cancellation.
+pub struct Job<T> {
+    receiver: Receiver<T>,
+    cancel: Arc<AtomicBool>,
+}
+
+impl<T: Send + 'static> Job<T> {
+    pub fn spawn<F>(f: F) -> Self
+    where
+        F: FnOnce(&AtomicBool) -> T + Send + 'static,
+    {
+        let (sender, receiver) = mpsc::channel();
+        let cancel = Arc::new(AtomicBool::new(false));
+        let flag = Arc::clone(&cancel);
+        thread::spawn(move || {
+            let result = f(&flag);
+            let _ = sender.send(result);
+        });
+        Self { receiver, cancel }
+    }
+
+    /// The result if the job has finished. Returns `Err(())` if the job died without a result.
+    pub fn poll(&self) -> Result<Option<T>, ()> {
+        match self.receiver.try_recv() {
+            Ok(result) => Ok(Some(result)),
+            Err(TryRecvError::Empty) => Ok(None),
+            Err(TryRecvError::Disconnected) => Err(()),
+        }
+    }
+
+    pub fn cancel(&self) {
+        self.cancel.store(true, Ordering::Relaxed);
+    }
+}
+
+impl<T> Drop for Job<T> {
+    fn drop(&mut self) {
+        self.cancel.store(true, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn wait<T: Send + 'static>(job: &Job<T>) -> Option<T> {
+        for _ in 0..200 {
+            if let Ok(Some(result)) = job.poll() {
+                return Some(result);
+            }
+            thread::sleep(Duration::from_millis(5));
+        }
+        None
+    }
+
+    #[test]
+    fn test_job_returns_result() {
+        let job = Job::spawn(|_| 21 * 2);
+        assert_eq!(wait(&job), Some(42));
+    }
+
+    #[test]
+    fn test_job_cancel() {
+        let job = Job::spawn(|cancel| {
+            while !cancel.load(Ordering::Relaxed) {
+                thread::sleep(Duration::from_millis(1));
+            }
+            "cancelled"
+        });
+        job.cancel();
+        assert_eq!(wait(&job), Some("cancelled"));
+    }
+}
@@ -0,0 +1,231 @@
+/// A key binding as shown in the help overlay
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Binding {
+    pub keys: &'static str,
+    pub category: &'static str,
+    pub description: &'static str,
+}
+
+const fn bind(category: &'static str, keys: &'static str, description: &'static str) -> Binding {
+    Binding {
+        keys,
+        category,
+        description,
+    }
+}
+
+/// Normal mode bindings, grouped by category in display order.
+/// Keep in sync with `normal_action` in `input.rs`.
+pub const NORMAL_MODE: &[Binding] = &[
+    bind("Navigation", "j / k, ↓ / ↑", "Move down / up"),
+    bind("Navigation", "l / →", "Expand directory"),
+    bind("Navigation", "h / ← / Backspace", "Collapse / go to parent"),
+    bind("Navigation", "gg / G", "Jump to top / bottom"),
+    bind("Navigation", "<n>j / <n>k", "Move down / up n entries"),
+    bind("Navigation", "<n>G", "Jump to entry n"),
+    bind("Navigation", "Tab", "Toggle expand/collapse"),
+    bind("Navigation", "H / L", "Collapse all / expand all"),
+    bind(
+        "Navigation",
+        "E",
+        "Expand the selected directory recursively",
+    ),
+    bind("Navigation", "Z", "Collapse all but the selection's path"),
+    bind("Navigation", "f", "Jump labels"),
+    bind(
+        "Navigation",
+        "< / >, Shift-← / Shift-→",
+        "Scroll horizontally",
+    ),
+    bind(
+        "Navigation",
+        "Alt-1 .. Alt-9",
+        "Re-root at a breadcrumb ancestor",
+    ),
+    bind("Navigation", "/ then n", "Search / next match"),
+    bind("Tabs", "t", "Open directory in a new tab"),
+    bind("Tabs", "] / [", "Next / previous tab"),
+    bind("Tabs", "Ctrl-w", "Close tab"),
+    bind("Marking", "Space", "Mark / unmark and move down"),
+    bind("Marking", "<n>Space", "Mark / unmark the next n entries"),
+    bind("Marking", "*", "Mark all in the current directory"),
+    bind("Marking", "~", "Invert marks in the current directory"),
+    bind("Marking", "m", "Mark entries matching a glob pattern"),
+    bind("Marking", "!", "Mark every file with git changes"),
+    bind("Marking", "B", "Review and unmark marked entries"),
+    bind("Marking", "x / Esc", "Clear all marks"),
+    bind("File operations", "y", "Copy (yank)"),
+    bind("File operations", "d", "Cut"),
+    bind("File operations", "p", "Paste"),
+    bind("File operations", "Ctrl-x", "Move to a typed directory"),
+    bind("File operations", "Ctrl-y", "Copy to a typed directory"),
+    bind(
+        "File operations",
+        "Ctrl-v",
+        "Paste files or an image from the system clipboard",
+    ),
+    bind("File operations", "Ctrl-o", "Drag out via ripdrag / dragon"),
+    bind("File operations", "D / Delete", "Delete"),
+    bind("File operations", "r", "Rename"),
+    bind("File operations", "a / A", "New file / new directory"),
+    bind(
+        "File operations",
+        "c / C",
+        "Copy path / file name to clipboard",
+    ),
+    bind("Git", "+ / -", "Stage / unstage"),
+    bind("Git", "X", "Discard changes"),
+    bind("Git", "M", "Commit staged changes"),
+    bind("Git", "v", "Git log of the selected path"),
+    bind("View", ".", "Toggle hidden files"),
+    bind("View", "I", "Toggle clean view"),
+    bind("View", "Ctrl-d", "Toggle directories-only view"),
+    bind("View", "e", "Filter files by extension"),
+    bind("View", "z", "Toggle hiding empty directories"),
+    bind(
+        "View",
+        "S / W / U",
+        "Toggle size / mtime / permissions column",
+    ),
+    bind("View", "V", "Toggle detailed list view"),
+    bind(
+        "View",
+        "F",
+        "Toggle flat list of all files below the directory",
+    ),
+    bind("View", "s", "Calculate directory sizes"),
+    bind("View", "i", "File info (metadata, MIME type, git status)"),
+    bind("View", "u", "Disk usage view"),
+    bind("View", "R / F5", "Refresh"),
+    bind("View", "F11", "Toggle compact borderless layout"),
+    bind(
+        "View",
+        "|",
+        "Toggle Miller columns (parent / current / preview)",
+    ),
+    bind("View", "F12", "Toggle performance overlay"),
+    bind("Preview", "o", "Preview file (full screen)"),
+    bind("Preview", "P", "Toggle quick preview"),
+    bind("Preview", "w", "Wrap long lines in previews"),
+    bind("Preview", "#", "Toggle line numbers in previews"),
+    bind("Preview", "O", "Open marked files in $EDITOR"),
+    bind(
+        "Preview",
+        "Ctrl-e",
+        "Open marked files in the running Neovim",
+    ),
+    bind("Commands", "Enter", "Run last / default command"),
+    bind("Commands", ":, Shift-Enter", "Enter a new command"),
+    bind("Tools", "T", "Scan for TODO / FIXME / HACK"),
+    bind("Tools", "=", "Compare the two marked directories"),
+    bind("Other", "?", "This help"),
+    bind("Other", "q", "Quit"),
+];
+
+/// Full-screen preview bindings
+pub const PREVIEW_MODE: &[Binding] = &[
+    bind("Preview mode", "j / k", "Scroll down / up"),
+    bind("Preview mode", "f / Space / b", "Page down / up"),
+    bind("Preview mode", "g / G", "Top / bottom"),
+    bind("Preview mode", "h / l, ← / →", "Scroll left / right"),
+    bind("Preview mode", "0", "Back to the first column"),
+    bind("Preview mode", "w", "Toggle line wrap"),
+    bind("Preview mode", "#", "Toggle line numbers"),
+    bind("Preview mode", "/", "Search in the file"),
+    bind("Preview mode", "n / N", "Next / previous match"),
+    bind("Preview mode", "q / Esc / o", "Close"),
+];
+
+/// A normal mode key that starts a multi-key sequence
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sequence {
+    pub prefix: char,
+    /// Complete sequences starting with `prefix` (their `keys` include the prefix)
+    pub continuations: &'static [Binding],
+}
+
+/// Multi-key sequences in normal mode. A prefix key waits for the next key,
+/// and a which-key popup lists the continuations if it takes a while.
+/// Keep in sync with `App::run_sequence`.
+pub const SEQUENCES: &[Sequence] = &[
+    Sequence {
+        prefix: 'g',
+        continuations: &[
+            bind("Go to", "gg", "Top (with a count: entry n)"),
+            bind("Go to", "gd", "Next entry with git changes"),
+            bind("Go to", "gp", "Re-root at the git project root"),
+            bind("Go to", "go", "Reveal in the system file manager"),
+        ],
+    },
+    Sequence {
+        prefix: ',',
+        continuations: &[
+            bind("Sort", ",d", "Directories first / last / mixed"),
+            bind("Sort", ",c", "Toggle case-insensitive names"),
+            bind("Sort", ",h", "Toggle hidden files first"),
+            bind("Sort", ",n", "Toggle natural number order"),
+        ],
+    },
+    Sequence {
+        prefix: 'Y',
+        continuations: &[
+            bind("Copy path", "Yp", "Absolute path"),
+            bind("Copy path", "Yr", "Path relative to the tree root"),
+            bind("Copy path", "Yg", "Path relative to the git root"),
+            bind("Copy path", "Yn", "File name"),
+            bind("Copy path", "Ys", "SHA-256 of the file"),
+            bind("Copy path", "Ym", "MD5 of the file"),
+        ],
+    },
+];
+
+pub fn sequence(prefix: char) -> Option<&'static Sequence> {
+    SEQUENCES.iter().find(|s| s.prefix == prefix)
+}
+
+/// All bindings shown in the help overlay, in display order
+pub fn all() -> impl Iterator<Item = &'static Binding> {
+    NORMAL_MODE
+        .iter()
+        .chain(SEQUENCES.iter().flat_map(|s| s.continuations))
+        .chain(PREVIEW_MODE)
+}
+
+/// Bindings whose keys, category or description contain `query` (case-insensitive)
+pub fn matching(query: &str) -> Vec<&'static Binding> {
+    let query = query.to_lowercase();
+    all()
+        .filter(|b| {
+            query.is_empty()
+                || b.keys.to_lowercase().contains(&query)
+                || b.category.to_lowercase().contains(&query)
+                || b.description.to_lowercase().contains(&query)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_matching_filters_case_insensitively() {
+        assert_eq!(matching("").len(), all().count());
+        let git = matching("GIT");
+        assert!(!git.is_empty());
+        assert!(git
+            .iter()
+            .all(|b| b.category == "Git" || b.description.to_lowercase().contains("git")));
+        assert!(matching("no such binding").is_empty());
+    }
+
+    #[test]
+    fn test_sequence_continuations_start_with_prefix() {
+        for sequence in SEQUENCES {
+            for binding in sequence.continuations {
+                assert!(binding.keys.starts_with(sequence.prefix));
+                assert_eq!(binding.keys.chars().count(), 2);
+            }
+        }
+    }
+}
@@ -0,0 +1,26 @@
+//! The file tree model behind the `ft` file explorer, usable without a terminal.
+//!
+//! - [`file_tree`]: a lazily loaded directory tree flattened into the rows a UI shows
+//! - [`file_ops`]: copy, move, delete and rename helpers that never overwrite
+//! - [`git_status`]: per-path git status of a repository
+//!
+//! ```
+//! use filetree::file_tree::FileTree;
+//!
+//! let dir = tempfile::tempdir()?;
+//! std::fs::create_dir(dir.path().join("src"))?;
+//! std::fs::write(dir.path().join("src/main.rs"), "fn main() {}")?;
+//!
+//! let mut tree = FileTree::new(dir.path(), false)?;
+//! tree.reveal(&dir.path().join("src/main.rs"))?;
+//! let rows: Vec<&str> = (0..tree.len())
+//!     .filter_map(|i| tree.get_node(i))
+//!     .map(|node| node.name.as_str())
+//!     .collect();
+//! assert_eq!(rows[1..], ["src", "main.rs"]);
+//! # Ok::<(), anyhow::Error>(())
+//! ```
+
+pub mod file_ops;
+pub mod file_tree;
+pub mod git_status;
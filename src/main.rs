@@ -1,20 +1,34 @@
+mod action;
 mod app;
-mod file_ops;
-mod file_tree;
-mod git_status;
+mod capture;
+mod checksum;
+mod cli;
+mod config;
+mod dir_compare;
+mod disk_usage;
+mod dump;
 mod input;
+#[cfg(unix)]
+mod ipc;
+mod job;
+mod keymap;
+mod preview;
+mod session;
+mod todo_scan;
 mod ui;
 
 use std::env;
-use std::io::{self, stdout};
+use std::fs::OpenOptions;
+use std::io::{self, stdout, Write};
 use std::path::PathBuf;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use anyhow::Result;
+use clap::Parser;
 use crossterm::{
     event::{
-        self, DisableBracketedPaste, DisableMouseCapture, EnableBracketedPaste, EnableMouseCapture,
-        Event,
+        self, DisableBracketedPaste, DisableFocusChange, DisableMouseCapture, EnableBracketedPaste,
+        EnableFocusChange, EnableMouseCapture, Event,
     },
     execute,
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
@@ -22,33 +36,65 @@ use crossterm::{
 use ratatui::prelude::*;
 
 use app::App;
+use cli::Cli;
 
 fn main() -> Result<()> {
-    // Get the path to browse (default: current directory)
-    let path = env::args()
-        .nth(1)
-        .map(PathBuf::from)
-        .unwrap_or_else(|| env::current_dir().unwrap_or_else(|_| PathBuf::from(".")));
+    let cli = Cli::parse();
+    let picker = cli.picker();
 
-    let path = path.canonicalize().unwrap_or(path);
+    // Get the paths to browse (default: current directory)
+    let cwd = env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
+    let (paths, select) = cli.roots(&cwd)?;
+    let config = cli.load_config(&paths[0])?;
 
-    // Read default command from environment variable
-    let default_command = env::var("FILETREE_DEFAULT_CMD").ok();
+    if cli.dump {
+        for path in &paths {
+            print!("{}", dump::dump(path, &config, cli.json)?);
+        }
+        return Ok(());
+    }
+
+    // Bind before touching the terminal so errors are readable
+    #[cfg(unix)]
+    let ipc = cli.listen.as_deref().map(ipc::Server::bind).transpose()?;
 
     // Setup terminal
+    // In picker mode stdout is reserved for the chosen path(s), so draw on the tty
+    let mut output: Box<dyn Write> = if picker.is_some() {
+        open_terminal()
+    } else {
+        Box::new(stdout())
+    };
     enable_raw_mode()?;
-    let mut stdout = stdout();
     execute!(
-        stdout,
+        output,
         EnterAlternateScreen,
         EnableMouseCapture,
-        EnableBracketedPaste
+        EnableBracketedPaste,
+        EnableFocusChange
     )?;
-    let backend = CrosstermBackend::new(stdout);
+    let backend = CrosstermBackend::new(output);
     let mut terminal = Terminal::new(backend)?;
 
     // Create app and run
-    let mut app = App::new(&path, default_command)?;
+    let mut app = App::new(&paths[0], config)?;
+    app.picker = picker;
+    #[cfg(unix)]
+    {
+        app.ipc = ipc;
+    }
+    // Additional paths open in their own tabs
+    for path in &paths[1..] {
+        app.add_tab(path)?;
+    }
+    if app.config.restore_session {
+        app.restore_sessions(session::load);
+    }
+    if let Some(path) = select {
+        if let Err(e) = app.reveal_path(&path) {
+            app.message = Some(format!("Error: {}", e));
+        }
+    }
     let result = run_app(&mut terminal, &mut app);
 
     // Restore terminal
@@ -57,7 +103,8 @@ fn main() -> Result<()> {
         terminal.backend_mut(),
         LeaveAlternateScreen,
         DisableMouseCapture,
-        DisableBracketedPaste
+        DisableBracketedPaste,
+        DisableFocusChange
     )?;
     terminal.show_cursor()?;
 
@@ -73,17 +120,93 @@ fn main() -> Result<()> {
         eprintln!("Error: {}", e);
     }
 
+    if app.config.restore_session {
+        if let Err(e) = session::save(app.sessions()) {
+            eprintln!("Cannot save session: {}", e);
+        }
+    }
+
+    if app.picker.is_some() {
+        if app.chosen.is_empty() {
+            std::process::exit(1);
+        }
+        let mut stdout = stdout().lock();
+        for path in &app.chosen {
+            // Print the raw bytes so non-UTF-8 names reach the caller intact
+            #[cfg(unix)]
+            {
+                use std::os::unix::ffi::OsStrExt;
+                stdout.write_all(path.as_os_str().as_bytes())?;
+                stdout.write_all(b"\n")?;
+            }
+            #[cfg(not(unix))]
+            writeln!(stdout, "{}", path.display())?;
+        }
+    }
+
     Ok(())
 }
 
-fn run_app<B: Backend>(terminal: &mut Terminal<B>, app: &mut App) -> io::Result<()> {
+/// Suspend the TUI, run a shell command attached to the terminal, then restore the TUI
+fn run_foreground<W: Write>(
+    terminal: &mut Terminal<CrosstermBackend<W>>,
+    command: &std::ffi::OsStr,
+) -> io::Result<io::Result<std::process::ExitStatus>> {
+    disable_raw_mode()?;
+    execute!(
+        terminal.backend_mut(),
+        LeaveAlternateScreen,
+        DisableMouseCapture,
+        DisableBracketedPaste,
+        DisableFocusChange
+    )?;
+    terminal.show_cursor()?;
+
+    let result = std::process::Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .status();
+
+    enable_raw_mode()?;
+    execute!(
+        terminal.backend_mut(),
+        EnterAlternateScreen,
+        EnableMouseCapture,
+        EnableBracketedPaste,
+        EnableFocusChange
+    )?;
+    terminal.clear()?;
+    Ok(result)
+}
+
+/// The terminal itself, for drawing while stdout carries the picked paths.
+/// Falls back to stderr where there is no such device.
+fn open_terminal() -> Box<dyn Write> {
+    #[cfg(unix)]
+    let terminal = OpenOptions::new().write(true).open("/dev/tty");
+    #[cfg(windows)]
+    let terminal = OpenOptions::new().read(true).write(true).open("CONOUT$");
+    #[cfg(not(any(unix, windows)))]
+    let terminal: io::Result<std::fs::File> = Err(io::ErrorKind::Unsupported.into());
+    match terminal {
+        Ok(file) => Box::new(file),
+        Err(_) => Box::new(io::stderr()),
+    }
+}
+
+fn run_app<W: Write>(
+    terminal: &mut Terminal<CrosstermBackend<W>>,
+    app: &mut App,
+) -> io::Result<()> {
     let mut visible_height = 20usize;
 
     loop {
+        let frame_start = Instant::now();
         terminal.draw(|f| {
-            app.tree_area_height = f.area().height.saturating_sub(5) as usize;
+            app.tree_area_height = app.tree_rows(f.area().height);
             visible_height = ui::draw(f, app);
         })?;
+        app.record_frame(frame_start.elapsed());
 
         if event::poll(Duration::from_millis(50))? {
             match event::read()? {
@@ -91,17 +214,31 @@ fn run_app<B: Backend>(terminal: &mut Terminal<B>, app: &mut App) -> io::Result<
                     input::handle_key_event(app, key, visible_height);
                 }
                 Event::Mouse(mouse) => {
-                    input::handle_mouse_event(app, mouse);
+                    input::handle_mouse_event(app, mouse, visible_height);
                 }
                 Event::Paste(text) => {
                     app.handle_drop(&text);
                 }
+                Event::FocusGained => app.focus_gained(),
+                Event::Resize(_, height) => {
+                    // The terminal may have reflowed the old screen (image previews
+                    // especially), so repaint everything instead of diffing
+                    terminal.autoresize()?;
+                    terminal.clear()?;
+                    app.handle_resize(height);
+                }
                 _ => {}
             }
         }
 
         // Check drop buffer timeout
         app.check_drop_buffer();
+        app.poll_jobs();
+
+        if let Some(command) = app.pending_foreground.take() {
+            let result = run_foreground(terminal, &command)?;
+            app.finish_foreground(&command, result);
+        }
 
         if app.should_quit {
             break;
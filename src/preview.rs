@@ -0,0 +1,305 @@
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// Number of leading bytes inspected to classify a file
+const SAMPLE_SIZE: usize = 8192;
+/// Maximum fraction of control characters still considered text
+const MAX_CONTROL_RATIO: f64 = 0.1;
+
+/// Classify a sample of file content as binary: any NUL byte, or too many control characters
+pub fn is_binary(sample: &[u8]) -> bool {
+    if sample.is_empty() {
+        return false;
+    }
+    if sample.contains(&0) {
+        return true;
+    }
+
+    let control = sample
+        .iter()
+        .filter(|&&b| b < 0x20 && !matches!(b, b'\t' | b'\n' | b'\r' | 0x0c | 0x1b))
+        .count();
+    control as f64 / sample.len() as f64 > MAX_CONTROL_RATIO
+}
+
+/// The first bytes of a file, used to classify it
+fn read_sample(path: &Path) -> io::Result<Vec<u8>> {
+    let mut sample = Vec::with_capacity(SAMPLE_SIZE);
+    File::open(path)?
+        .take(SAMPLE_SIZE as u64)
+        .read_to_end(&mut sample)?;
+    Ok(sample)
+}
+
+/// MIME type recognized from the file's magic bytes, if it has a known signature
+pub fn sniff_mime_type(path: &Path) -> Option<&'static str> {
+    let sample = read_sample(path).ok()?;
+    infer::get(&sample).map(|kind| kind.mime_type())
+}
+
+/// MIME type of a file: from its magic bytes, then its extension (text formats
+/// have no signature), then whether its first bytes look like text
+pub fn mime_type(path: &Path) -> &'static str {
+    let sample = read_sample(path).unwrap_or_default();
+    if let Some(kind) = infer::get(&sample) {
+        return kind.mime_type();
+    }
+
+    let ext = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_lowercase());
+    let by_extension = match ext.as_deref() {
+        Some("txt" | "log") => "text/plain",
+        Some("md" | "markdown") => "text/markdown",
+        Some("html" | "htm") => "text/html",
+        Some("css") => "text/css",
+        Some("csv") => "text/csv",
+        Some("js" | "mjs") => "text/javascript",
+        Some("rs") => "text/x-rust",
+        Some("py") => "text/x-python",
+        Some("c" | "h") => "text/x-c",
+        Some("json") => "application/json",
+        Some("toml") => "application/toml",
+        Some("yaml" | "yml") => "application/yaml",
+        Some("xml") => "application/xml",
+        Some("sh") => "application/x-sh",
+        Some("pdf") => "application/pdf",
+        Some("zip") => "application/zip",
+        Some("gz") => "application/gzip",
+        Some("tar") => "application/x-tar",
+        Some("wasm") => "application/wasm",
+        Some("png") => "image/png",
+        Some("jpg" | "jpeg") => "image/jpeg",
+        Some("gif") => "image/gif",
+        Some("webp") => "image/webp",
+        Some("bmp") => "image/bmp",
+        Some("svg") => "image/svg+xml",
+        Some("mp3") => "audio/mpeg",
+        Some("wav") => "audio/wav",
+        Some("mp4") => "video/mp4",
+        _ => "",
+    };
+    if !by_extension.is_empty() {
+        return by_extension;
+    }
+    if is_binary(&sample) {
+        "application/octet-stream"
+    } else {
+        "text/plain"
+    }
+}
+
+/// Decode text as UTF-8, falling back to Latin-1 for files that aren't valid UTF-8
+pub fn decode_text(bytes: Vec<u8>) -> String {
+    match String::from_utf8(bytes) {
+        Ok(text) => text,
+        Err(e) => e.into_bytes().iter().map(|&b| b as char).collect(),
+    }
+}
+
+pub fn format_hex_preview(bytes: &[u8], max_lines: usize) -> Vec<String> {
+    bytes
+        .chunks(16)
+        .take(max_lines)
+        .map(|chunk| {
+            let hex: Vec<String> = chunk.iter().map(|b| format!("{:02x}", b)).collect();
+            let ascii: String = chunk
+                .iter()
+                .map(|&b| {
+                    if b.is_ascii_graphic() || b == b' ' {
+                        b as char
+                    } else {
+                        '.'
+                    }
+                })
+                .collect();
+            format!("{:<48} {}", hex.join(" "), ascii)
+        })
+        .collect()
+}
+
+/// Load preview lines for a file: its text lines, or a hex dump of the first
+/// `max_hex_lines * 16` bytes if the content looks binary.
+/// Binary files are never read beyond what the hex dump needs.
+pub fn load_lines(path: &Path, max_hex_lines: usize) -> io::Result<Vec<String>> {
+    let mut file = File::open(path)?;
+    let mut sample = Vec::with_capacity(SAMPLE_SIZE);
+    (&mut file)
+        .take(SAMPLE_SIZE as u64)
+        .read_to_end(&mut sample)?;
+
+    if is_binary(&sample) {
+        let needed = max_hex_lines * 16;
+        if sample.len() < needed {
+            (&mut file)
+                .take((needed - sample.len()) as u64)
+                .read_to_end(&mut sample)?;
+        }
+        return Ok(format_hex_preview(&sample, max_hex_lines));
+    }
+
+    file.read_to_end(&mut sample)?;
+    Ok(decode_text(sample).lines().map(|s| s.to_string()).collect())
+}
+
+/// Recently shown previews keyed by path and modification time, so an edited
+/// file is read again. Keeps at most `capacity` entries, dropping the least
+/// recently used one.
+pub struct PreviewCache<V> {
+    capacity: usize,
+    // Least recently used first
+    entries: VecDeque<(PathBuf, SystemTime, V)>,
+}
+
+impl<V> PreviewCache<V> {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    pub fn get(&mut self, path: &Path, mtime: SystemTime) -> Option<&V> {
+        let index = self.entries.iter().position(|(p, _, _)| p == path)?;
+        let entry = self.entries.remove(index)?;
+        if entry.1 != mtime {
+            return None;
+        }
+        self.entries.push_back(entry);
+        self.entries.back().map(|(_, _, value)| value)
+    }
+
+    pub fn insert(&mut self, path: PathBuf, mtime: SystemTime, value: V) {
+        self.entries.retain(|(p, _, _)| *p != path);
+        if self.entries.len() >= self.capacity {
+            self.entries.pop_front();
+        }
+        self.entries.push_back((path, mtime, value));
+    }
+}
+
+/// Remove terminal escape sequences (colors, cursor movement) from previewer output
+pub fn strip_ansi(line: &str) -> String {
+    let mut result = String::with_capacity(line.len());
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        if c != '\x1b' {
+            result.push(c);
+            continue;
+        }
+        match chars.next() {
+            // CSI: parameters up to a final byte in @..~
+            Some('[') => {
+                for c in chars.by_ref() {
+                    if ('@'..='~').contains(&c) {
+                        break;
+                    }
+                }
+            }
+            // OSC: up to BEL or ST
+            Some(']') => {
+                while let Some(c) = chars.next() {
+                    if c == '\x07' || (c == '\x1b' && chars.next().is_some()) {
+                        break;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_is_binary_text() {
+        assert!(!is_binary(b"fn main() {\n\tprintln!(\"hi\");\r\n}\n"));
+        assert!(!is_binary(b""));
+    }
+
+    #[test]
+    fn test_is_binary_nul_and_control() {
+        assert!(is_binary(b"abc\0def"));
+        assert!(is_binary(&[0x01, 0x02, 0x03, b'a', 0x04, 0x05]));
+    }
+
+    #[test]
+    fn test_decode_latin1_fallback() {
+        assert_eq!(decode_text(b"caf\xe9".to_vec()), "café");
+        assert_eq!(decode_text("日本".as_bytes().to_vec()), "日本");
+    }
+
+    #[test]
+    fn test_strip_ansi() {
+        assert_eq!(strip_ansi("\x1b[1;31mred\x1b[0m plain"), "red plain");
+        assert_eq!(strip_ansi("\x1b]8;;http://x\x1b\\link\x1b]8;;\x07"), "link");
+        assert_eq!(strip_ansi("日本 ok"), "日本 ok");
+    }
+
+    #[test]
+    fn test_load_lines_text_and_binary() {
+        let dir = TempDir::new().unwrap();
+        let text = dir.path().join("latin1.txt");
+        fs::write(&text, b"na\xefve\nline two\n").unwrap();
+        assert_eq!(load_lines(&text, 10).unwrap(), vec!["naïve", "line two"]);
+
+        let bin = dir.path().join("data.bin");
+        fs::write(&bin, vec![0u8; 1000]).unwrap();
+        let lines = load_lines(&bin, 4).unwrap();
+        assert_eq!(lines.len(), 4);
+        assert!(lines[0].starts_with("00 00"));
+    }
+
+    #[test]
+    fn test_mime_type_sniffs_content_first() {
+        let dir = TempDir::new().unwrap();
+        let png = dir.path().join("screenshot");
+        fs::write(&png, b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR").unwrap();
+        assert_eq!(sniff_mime_type(&png), Some("image/png"));
+        assert_eq!(mime_type(&png), "image/png");
+
+        let misnamed = dir.path().join("archive.txt");
+        fs::write(&misnamed, b"PK\x03\x04\x14\0\0\0").unwrap();
+        assert_eq!(mime_type(&misnamed), "application/zip");
+
+        let config = dir.path().join("Cargo.toml");
+        fs::write(&config, "[package]\n").unwrap();
+        assert_eq!(sniff_mime_type(&config), None);
+        assert_eq!(mime_type(&config), "application/toml");
+
+        let unknown = dir.path().join("blob");
+        fs::write(&unknown, [0u8, 1, 2, 3]).unwrap();
+        assert_eq!(mime_type(&unknown), "application/octet-stream");
+        let notes = dir.path().join("NOTES");
+        fs::write(&notes, "plain words\n").unwrap();
+        assert_eq!(mime_type(&notes), "text/plain");
+    }
+
+    #[test]
+    fn test_preview_cache_evicts_least_recently_used() {
+        let mtime = SystemTime::UNIX_EPOCH;
+        let mut cache = PreviewCache::new(2);
+        cache.insert(PathBuf::from("/a"), mtime, 1);
+        cache.insert(PathBuf::from("/b"), mtime, 2);
+        assert_eq!(cache.get(Path::new("/a"), mtime), Some(&1));
+        cache.insert(PathBuf::from("/c"), mtime, 3);
+
+        assert_eq!(cache.get(Path::new("/b"), mtime), None);
+        assert_eq!(cache.get(Path::new("/a"), mtime), Some(&1));
+        assert_eq!(cache.get(Path::new("/c"), mtime), Some(&3));
+
+        // A changed modification time means the file was edited
+        let later = mtime + std::time::Duration::from_secs(1);
+        assert_eq!(cache.get(Path::new("/c"), later), None);
+        assert_eq!(cache.get(Path::new("/c"), mtime), None);
+    }
+}
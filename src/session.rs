@@ -0,0 +1,121 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+/// Number of root directories whose session is remembered
+const SESSION_LIMIT: usize = 50;
+
+/// View state of one root directory, restored when it is opened again
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Session {
+    pub root: PathBuf,
+    pub expanded: Vec<PathBuf>,
+    pub selected: Option<PathBuf>,
+    pub show_hidden: bool,
+    pub quick_preview: bool,
+    /// Previews wrap long lines; missing in sessions saved by older versions
+    #[serde(default)]
+    pub preview_wrap: bool,
+}
+
+/// Directory for state that isn't configuration (`$XDG_STATE_HOME/filetree`)
+pub fn state_dir() -> Option<PathBuf> {
+    if let Ok(xdg_state) = std::env::var("XDG_STATE_HOME") {
+        Some(PathBuf::from(xdg_state).join("filetree"))
+    } else if let Ok(home) = std::env::var("HOME") {
+        Some(
+            PathBuf::from(home)
+                .join(".local")
+                .join("state")
+                .join("filetree"),
+        )
+    } else {
+        None
+    }
+}
+
+fn sessions_file() -> Option<PathBuf> {
+    state_dir().map(|dir| dir.join("sessions.json"))
+}
+
+/// The saved session for `root`, if any
+pub fn load(root: &Path) -> Option<Session> {
+    load_from(&sessions_file()?, root)
+}
+
+/// Remember `sessions`, replacing older sessions of the same roots
+pub fn save(sessions: Vec<Session>) -> anyhow::Result<()> {
+    let file = sessions_file().ok_or_else(|| anyhow::anyhow!("No state directory"))?;
+    save_to(&file, sessions)
+}
+
+fn read_all(file: &Path) -> Vec<Session> {
+    fs::read_to_string(file)
+        .ok()
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or_default()
+}
+
+fn load_from(file: &Path, root: &Path) -> Option<Session> {
+    read_all(file).into_iter().find(|s| s.root == root)
+}
+
+fn save_to(file: &Path, sessions: Vec<Session>) -> anyhow::Result<()> {
+    // Most recently used last, so the oldest are dropped first
+    let mut all = read_all(file);
+    all.retain(|old| !sessions.iter().any(|s| s.root == old.root));
+    all.extend(sessions);
+    if all.len() > SESSION_LIMIT {
+        all.drain(0..all.len() - SESSION_LIMIT);
+    }
+    if let Some(parent) = file.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(file, serde_json::to_string(&all)?)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn session(root: &str) -> Session {
+        Session {
+            root: PathBuf::from(root),
+            expanded: vec![PathBuf::from(root).join("src")],
+            selected: Some(PathBuf::from(root).join("src/main.rs")),
+            show_hidden: false,
+            quick_preview: true,
+            preview_wrap: false,
+        }
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let file = dir.path().join("state/sessions.json");
+        assert!(load_from(&file, Path::new("/a")).is_none());
+
+        save_to(&file, vec![session("/a"), session("/b")]).unwrap();
+        let mut updated = session("/a");
+        updated.show_hidden = true;
+        save_to(&file, vec![updated.clone()]).unwrap();
+
+        assert_eq!(load_from(&file, Path::new("/a")), Some(updated));
+        assert_eq!(load_from(&file, Path::new("/b")), Some(session("/b")));
+        assert_eq!(read_all(&file).len(), 2);
+    }
+
+    #[test]
+    fn test_save_drops_oldest() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let file = dir.path().join("sessions.json");
+        for i in 0..SESSION_LIMIT + 2 {
+            save_to(&file, vec![session(&format!("/{}", i))]).unwrap();
+        }
+        assert_eq!(read_all(&file).len(), SESSION_LIMIT);
+        assert!(load_from(&file, Path::new("/0")).is_none());
+        assert!(load_from(&file, Path::new("/51")).is_some());
+    }
+}
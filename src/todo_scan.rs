@@ -0,0 +1,158 @@
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::preview;
+
+const MARKERS: &[&str] = &["TODO", "FIXME", "HACK"];
+/// Files larger than this are skipped
+const MAX_FILE_SIZE: u64 = 1024 * 1024;
+/// Stop collecting after this many items
+const MAX_ITEMS: usize = 5000;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TodoItem {
+    pub path: PathBuf,
+    /// 1-based line number
+    pub line: usize,
+    pub marker: &'static str,
+    pub text: String,
+}
+
+/// Find TODO/FIXME/HACK markers in text files below `root`, sorted by path and line.
+/// Skips `.git`, paths in `excluded` (e.g. git-ignored) and, unless `show_hidden`, dotfiles.
+pub fn scan(
+    root: &Path,
+    show_hidden: bool,
+    excluded: &HashSet<PathBuf>,
+    cancel: &AtomicBool,
+) -> Vec<TodoItem> {
+    let mut items = Vec::new();
+    scan_dir(root, show_hidden, excluded, cancel, &mut items);
+    items.sort_by(|a, b| a.path.cmp(&b.path).then(a.line.cmp(&b.line)));
+    items
+}
+
+fn scan_dir(
+    dir: &Path,
+    show_hidden: bool,
+    excluded: &HashSet<PathBuf>,
+    cancel: &AtomicBool,
+    items: &mut Vec<TodoItem>,
+) {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+
+    for entry in entries.filter_map(|e| e.ok()) {
+        if cancel.load(Ordering::Relaxed) || items.len() >= MAX_ITEMS {
+            return;
+        }
+
+        let name = entry.file_name();
+        let name = name.to_string_lossy();
+        if name == ".git" || (!show_hidden && name.starts_with('.')) {
+            continue;
+        }
+        let path = entry.path();
+        if excluded.contains(&path) {
+            continue;
+        }
+
+        // file_type() does not follow symlinks, which avoids cycles
+        let file_type = match entry.file_type() {
+            Ok(t) => t,
+            Err(_) => continue,
+        };
+        if file_type.is_dir() {
+            scan_dir(&path, show_hidden, excluded, cancel, items);
+        } else if file_type.is_file() {
+            scan_file(&path, items);
+        }
+    }
+}
+
+fn scan_file(path: &Path, items: &mut Vec<TodoItem>) {
+    if fs::metadata(path)
+        .map(|m| m.len() > MAX_FILE_SIZE)
+        .unwrap_or(true)
+    {
+        return;
+    }
+    let bytes = match fs::read(path) {
+        Ok(bytes) => bytes,
+        Err(_) => return,
+    };
+    if preview::is_binary(&bytes[..bytes.len().min(8192)]) {
+        return;
+    }
+
+    let text = preview::decode_text(bytes);
+    for (i, line) in text.lines().enumerate() {
+        if let Some((marker, rest)) = find_marker(line) {
+            items.push(TodoItem {
+                path: path.to_path_buf(),
+                line: i + 1,
+                marker,
+                text: rest.to_string(),
+            });
+        }
+    }
+}
+
+/// Find a marker appearing as a whole word, returning it and the text after it
+fn find_marker(line: &str) -> Option<(&'static str, &str)> {
+    let is_word = |c: char| c.is_alphanumeric() || c == '_';
+    for &marker in MARKERS {
+        for (pos, _) in line.match_indices(marker) {
+            let before = line[..pos].chars().next_back();
+            let after = line[pos + marker.len()..].chars().next();
+            if before.is_some_and(is_word) || after.is_some_and(is_word) {
+                continue;
+            }
+            let rest = line[pos + marker.len()..]
+                .trim_start_matches(|c: char| c == ':' || c == '(' || c.is_whitespace());
+            return Some((marker, rest.trim_end()));
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_find_marker() {
+        assert_eq!(
+            find_marker("// TODO: handle errors"),
+            Some(("TODO", "handle errors"))
+        );
+        assert_eq!(find_marker("# FIXME broken"), Some(("FIXME", "broken")));
+        assert_eq!(find_marker("let todos = TODOS;"), None);
+        assert_eq!(find_marker("nothing here"), None);
+    }
+
+    #[test]
+    fn test_scan_skips_hidden_and_excluded() {
+        let dir = TempDir::new().unwrap();
+        let root = dir.path();
+        fs::create_dir(root.join("src")).unwrap();
+        fs::create_dir(root.join("target")).unwrap();
+        fs::write(root.join("src/main.rs"), "fn main() {}\n// TODO: test\n").unwrap();
+        fs::write(root.join("target/gen.rs"), "// TODO: generated\n").unwrap();
+        fs::write(root.join(".hidden.rs"), "// HACK: hidden\n").unwrap();
+        fs::write(root.join("bin.dat"), b"\0\0TODO\0").unwrap();
+
+        let excluded: HashSet<PathBuf> = [root.join("target")].into_iter().collect();
+        let items = scan(root, false, &excluded, &AtomicBool::new(false));
+
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].path, root.join("src/main.rs"));
+        assert_eq!(items[0].line, 2);
+        assert_eq!(items[0].text, "test");
+    }
+}
@@ -1,39 +1,89 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
 use ratatui::{
-    layout::{Constraint, Direction, Layout, Rect},
+    layout::{Alignment, Constraint, Direction, Layout, Rect},
     style::{Color, Modifier, Style},
     text::{Line, Span},
-    widgets::{Block, Borders, Clear, List, ListItem, Paragraph},
+    widgets::{
+        Block, Borders, Clear, List, ListItem, Paragraph, Scrollbar, ScrollbarOrientation,
+        ScrollbarState,
+    },
     Frame,
 };
 
-use crate::app::{App, ConfirmAction, DeleteInfo, ImagePreview, InputMode};
-use crate::git_status::GitStatus;
+use crate::app::{
+    App, BatchOp, BatchReport, ConfirmAction, DeleteInfo, DiscardInfo, ElevatedOp, HistorySearch,
+    ImagePreview, InputMode, PerfStats, PreviewMatch,
+};
+use crate::config::IconConfig;
+use crate::dir_compare::Difference;
+use crate::keymap;
+use filetree::file_tree::FileNode;
+use filetree::git_status::{GitStatus, LineChange};
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+/// Background of marked rows when `highlight_marked` is enabled
+const MARKED_ROW_BG: Color = Color::Indexed(236);
+/// How long a sequence prefix waits before its continuations are shown
+const WHICH_KEY_DELAY: Duration = Duration::from_millis(400);
+const SPINNER_FRAMES: &[char] = &['⠋', '⠙', '⠹', '⠸', '⠼', '⠴', '⠦', '⠧', '⠇', '⠏'];
 
 pub fn draw(frame: &mut Frame, app: &mut App) -> usize {
     // If in preview mode, draw preview instead
-    if app.input_mode == InputMode::Preview {
+    if matches!(
+        app.input_mode,
+        InputMode::Preview | InputMode::PreviewSearch
+    ) {
         return draw_preview(frame, app);
     }
 
-    // Calculate layout based on quick preview state
-    let quick_preview_height = if app.quick_preview_enabled { 12 } else { 0 };
+    // Calculate layout based on quick preview state (the columns layout has its own)
+    let quick_preview_height = if app.quick_preview_enabled && !app.miller {
+        12
+    } else {
+        0
+    };
+    let tab_bar_height = if app.tabs.len() > 1 { 1 } else { 0 };
+    let status_height = if app.compact { 1 } else { 3 };
 
     let chunks = Layout::default()
         .direction(Direction::Vertical)
         .constraints([
+            Constraint::Length(tab_bar_height),
+            Constraint::Length(1),
             Constraint::Min(3),
             Constraint::Length(quick_preview_height),
-            Constraint::Length(3),
+            Constraint::Length(status_height),
         ])
         .split(frame.area());
 
-    draw_file_tree(frame, app, chunks[0]);
+    if app.tabs.len() > 1 {
+        draw_tab_bar(frame, app, chunks[0]);
+    }
+
+    draw_breadcrumb(frame, app, chunks[1]);
+    if app.miller {
+        draw_miller_columns(frame, app, chunks[2]);
+    } else {
+        draw_file_tree(frame, app, chunks[2]);
+    }
 
-    if app.quick_preview_enabled {
-        draw_quick_preview(frame, app, chunks[1]);
+    app.quick_preview_rows =
+        (app.quick_preview_enabled && !app.miller).then_some((chunks[3].y, chunks[3].height));
+    if app.quick_preview_enabled && !app.miller {
+        draw_quick_preview(frame, app, chunks[3]);
     }
 
-    draw_status_bar(frame, app, chunks[2]);
+    draw_status_bar(frame, app, chunks[4]);
+
+    if let Some(prefix) = app.pending_prefix {
+        if app.pending_since.elapsed() >= WHICH_KEY_DELAY {
+            draw_which_key(frame, prefix);
+        }
+    }
 
     // Draw input popup if in input mode
     match &app.input_mode {
@@ -41,21 +91,197 @@ pub fn draw(frame: &mut Frame, app: &mut App) -> usize {
         | InputMode::Rename
         | InputMode::NewFile
         | InputMode::NewDir
-        | InputMode::ExternalCommand => {
+        | InputMode::MoveTo
+        | InputMode::CopyTo
+        | InputMode::ExternalCommand
+        | InputMode::Commit
+        | InputMode::MarkGlob
+        | InputMode::ExtensionFilter => {
             draw_input_popup(frame, app);
         }
         InputMode::Confirm(action) => {
             draw_confirm_popup(frame, app, action);
         }
-        InputMode::Normal | InputMode::Preview => {}
+        InputMode::TodoList => draw_todo_list(frame, app),
+        InputMode::Compare => draw_compare(frame, app),
+        InputMode::FileInfo => draw_file_info(frame, app),
+        InputMode::MarkedList => draw_marked_list(frame, app),
+        InputMode::Plan => draw_plan(frame, app),
+        InputMode::DiskUsage => draw_disk_usage(frame, app),
+        InputMode::Help => draw_help(frame, app),
+        InputMode::Normal
+        | InputMode::Preview
+        | InputMode::PreviewSearch
+        | InputMode::JumpLabel => {}
+    }
+
+    if let Some(perf) = &app.perf {
+        draw_perf_overlay(frame, app, perf);
     }
 
     app.tree_area_height
 }
 
+fn draw_tab_bar(frame: &mut Frame, app: &App, area: Rect) {
+    let mut spans = Vec::new();
+    for (i, title) in app.tab_titles().iter().enumerate() {
+        let style = if i == app.active_tab {
+            Style::default()
+                .fg(Color::Black)
+                .bg(Color::Blue)
+                .add_modifier(Modifier::BOLD)
+        } else {
+            Style::default().fg(Color::Gray)
+        };
+        spans.push(Span::styled(format!(" {}:{} ", i + 1, title), style));
+        spans.push(Span::raw(" "));
+    }
+    frame.render_widget(Paragraph::new(Line::from(spans)), area);
+}
+
+/// Root path split into components; ancestors are clickable and numbered for Alt+n
+fn draw_breadcrumb(frame: &mut Frame, app: &mut App, area: Rect) {
+    const SEPARATOR: &str = " › ";
+    let ancestors: Vec<PathBuf> = app
+        .tree
+        .root
+        .path
+        .ancestors()
+        .map(Path::to_path_buf)
+        .collect();
+
+    // (number label, name, path) from the filesystem root down to the tree root
+    let segments: Vec<(String, String, PathBuf)> = ancestors
+        .iter()
+        .enumerate()
+        .rev()
+        .map(|(distance, path)| {
+            let name = path
+                .file_name()
+                .map(|n| n.to_string_lossy().to_string())
+                .unwrap_or_else(|| path.display().to_string());
+            let number = if (1..=9).contains(&distance) {
+                format!("{}:", distance)
+            } else {
+                String::new()
+            };
+            (number, name, path.clone())
+        })
+        .collect();
+
+    // Drop leading components until the rest fits
+    let width = |segs: &[(String, String, PathBuf)]| -> usize {
+        segs.iter()
+            .map(|(n, name, _)| n.width() + name.width())
+            .sum::<usize>()
+            + SEPARATOR.width() * segs.len().saturating_sub(1)
+    };
+    let mut start = 0;
+    while start + 1 < segments.len() && width(&segments[start..]) + 2 > area.width as usize {
+        start += 1;
+    }
+
+    let mut spans = Vec::new();
+    let mut hits = Vec::new();
+    let mut x = area.x;
+    if start > 0 {
+        spans.push(Span::styled("… ", Style::default().fg(Color::DarkGray)));
+        x += 2;
+    }
+    let last = segments.len() - 1;
+    for (i, (number, name, path)) in segments.iter().enumerate().skip(start) {
+        if i > start {
+            spans.push(Span::styled(
+                SEPARATOR,
+                Style::default().fg(Color::DarkGray),
+            ));
+            x += SEPARATOR.width() as u16;
+        }
+        let segment_width = (number.width() + name.width()) as u16;
+        spans.push(Span::styled(
+            number.clone(),
+            Style::default().fg(Color::DarkGray),
+        ));
+        if i == last {
+            spans.push(Span::styled(
+                name.clone(),
+                Style::default()
+                    .fg(Color::Blue)
+                    .add_modifier(Modifier::BOLD),
+            ));
+        } else {
+            spans.push(Span::styled(name.clone(), Style::default().fg(Color::Blue)));
+            hits.push((x, x + segment_width, path.clone()));
+        }
+        x += segment_width;
+    }
+
+    app.breadcrumb_y = area.y;
+    app.breadcrumb_hits = hits;
+    frame.render_widget(Paragraph::new(Line::from(spans)), area);
+}
+
 fn draw_file_tree(frame: &mut Frame, app: &mut App, area: Rect) {
-    let visible_height = area.height.saturating_sub(2) as usize;
+    // The compact layout has no border, so the scrollbar takes the last column
+    let block = if app.compact {
+        Block::default()
+    } else {
+        Block::default().borders(Borders::ALL)
+    };
+    let inner = block.inner(area);
+    let visible_height = inner.height as usize;
     app.adjust_scroll(visible_height);
+    app.tree_visible_rows = visible_height;
+    app.tree_area_y = inner.y;
+    let scrollbar_width = usize::from(app.compact && app.tree.len() > visible_height);
+
+    // Clamp horizontal scroll so the longest visible row stays in view
+    let row_width = (inner.width as usize).saturating_sub(scrollbar_width);
+    let visible_width = row_width.saturating_sub(1);
+    let max_row_width = (app.scroll_offset..app.tree.len())
+        .take(visible_height)
+        .filter_map(|i| app.tree.get_node(i))
+        .map(|node| node.depth * 2 + 2 + node.name.width())
+        .max()
+        .unwrap_or(0);
+    let max_hscroll = max_row_width.saturating_sub(visible_width);
+    // Follow the selection when its name starts past the right edge
+    if let Some(node) = app.tree.get_node(app.selected) {
+        let name_start = node.depth * 2 + 2;
+        if name_start >= app.tree_hscroll + visible_width {
+            app.tree_hscroll = name_start.saturating_sub(visible_width / 3);
+        }
+    }
+    app.tree_hscroll = app.tree_hscroll.min(max_hscroll);
+
+    let columns = Columns::for_width(app, row_width);
+
+    // Symlink targets and entry counts for collapsed directories in view
+    let badges: Vec<Option<String>> = (app.scroll_offset..app.tree.len())
+        .take(visible_height)
+        .map(|i| {
+            let node = app.tree.get_node(i)?;
+            let mut badge = node
+                .symlink_target
+                .as_ref()
+                .map(|target| format!(" -> {}", target.display()))
+                .unwrap_or_default();
+            if let Some(error) = &node.read_error {
+                // Stands in for the entries that couldn't be listed
+                badge.push_str(&format!(" [{}]", error));
+            } else if node.is_dir && !node.expanded {
+                let path = node.path.clone();
+                badge.push_str(&format!(" ({})", app.tree.child_count(&path)));
+            }
+            Some(badge).filter(|b| !b.is_empty())
+        })
+        .collect();
+
+    let jump_labels = if app.input_mode == InputMode::JumpLabel {
+        app.visible_jump_labels()
+    } else {
+        Vec::new()
+    };
 
     let items: Vec<ListItem> = (app.scroll_offset..app.tree.len())
         .take(visible_height)
@@ -63,19 +289,13 @@ fn draw_file_tree(frame: &mut Frame, app: &mut App, area: Rect) {
             let node = app.tree.get_node(i)?;
             let indent = "  ".repeat(node.depth);
 
-            let icon = if node.is_dir {
-                if node.expanded { "\u{f07c}" } else { "\u{f07b}" }
-            } else {
-                get_file_icon(&node.name)
-            };
+            let icon = node_icon(app, node);
 
             let is_selected = i == app.selected;
             let is_marked = app.marked.contains(&node.path);
             let is_cut = app.clipboard.content.as_ref().is_some_and(|c| {
-                matches!(c, crate::file_ops::ClipboardContent::Cut(paths) if paths.contains(&node.path))
+                matches!(c, filetree::file_ops::ClipboardContent::Cut(paths) if paths.contains(&node.path))
             });
-            let git_status = app.git_repo.get_status(&node.path);
-
             let mark_indicator = if is_marked { "*" } else { " " };
 
             let mut style = Style::default();
@@ -85,42 +305,303 @@ fn draw_file_tree(frame: &mut Frame, app: &mut App, area: Rect) {
             if is_cut {
                 style = style.fg(Color::DarkGray);
             } else {
-                // Apply git status color
-                style = style.fg(match git_status {
-                    GitStatus::Modified => Color::Yellow,
-                    GitStatus::Added => Color::Green,
-                    GitStatus::Untracked => Color::Green,
-                    GitStatus::Deleted => Color::Red,
-                    GitStatus::Renamed => Color::Cyan,
-                    GitStatus::Conflict => Color::Magenta,
-                    GitStatus::Ignored => Color::DarkGray,
-                    GitStatus::None => {
-                        if node.is_dir {
-                            Color::Blue
-                        } else {
-                            Color::Reset
-                        }
-                    }
-                });
+                style = style.fg(entry_color(app, node));
+            }
+            if is_marked {
+                style = style.add_modifier(Modifier::BOLD | Modifier::ITALIC);
             }
 
-            let line = Line::from(vec![
-                Span::styled(mark_indicator, Style::default().fg(Color::Yellow)),
-                Span::styled(format!("{}{} {}", indent, icon, node.name), style),
-            ]);
+            let mut spans = Vec::new();
+            if let Some(label) = jump_labels.get(i - app.scroll_offset) {
+                let typed = app.input_buffer.as_str();
+                let label_style = if label.starts_with(typed) {
+                    Style::default()
+                        .fg(Color::Black)
+                        .bg(Color::Yellow)
+                        .add_modifier(Modifier::BOLD)
+                } else {
+                    Style::default().fg(Color::DarkGray)
+                };
+                spans.push(Span::styled(label.clone(), label_style));
+            }
+            spans.push(Span::styled(
+                mark_indicator,
+                Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD),
+            ));
+            let columns = node_columns(app, node, columns, is_selected);
+            let badge = badges
+                .get(i - app.scroll_offset)
+                .cloned()
+                .flatten()
+                .unwrap_or_default();
+            let badge_style = style.fg(if is_selected {
+                Color::Gray
+            } else {
+                Color::DarkGray
+            });
+            let text = skip_width(
+                &format!("{}{} {}", indent, icon, node.name),
+                app.tree_hscroll,
+            );
+            if columns.is_empty() {
+                spans.push(Span::styled(text, style));
+                spans.push(Span::styled(badge, badge_style));
+            } else {
+                // Keep the columns right-aligned, truncating the name if needed
+                let columns_width: usize = columns.iter().map(|s| s.content.width()).sum();
+                let label_width: usize = spans.iter().map(|s| s.content.width()).sum();
+                let name_width =
+                    row_width.saturating_sub(label_width + columns_width + badge.width());
+                let text = take_width(&text, name_width);
+                let padding = name_width - text.width();
+                spans.push(Span::styled(text, style));
+                spans.push(Span::styled(badge, badge_style));
+                spans.push(Span::styled(" ".repeat(padding), style));
+                let base = if is_selected {
+                    Style::default().bg(Color::DarkGray)
+                } else {
+                    Style::default()
+                };
+                spans.extend(columns.into_iter().map(|s| s.patch_style(base)));
+            }
 
-            Some(ListItem::new(line))
+            // Row background spans the whole tree width, not just the text
+            let row_style = if is_selected {
+                Style::default().bg(Color::DarkGray)
+            } else if is_marked && app.config.highlight_marked {
+                Style::default().bg(MARKED_ROW_BG)
+            } else {
+                Style::default()
+            };
+            Some(ListItem::new(Line::from(spans)).style(row_style))
         })
         .collect();
 
     let max_title_width = area.width.saturating_sub(4) as usize; // Account for borders and padding
+    let hscroll_info = if app.tree_hscroll > 0 {
+        format!("[→{}] ", app.tree_hscroll)
+    } else {
+        String::new()
+    };
     let title = format!(
-        " {} ",
-        abbreviate_path(&app.tree.root.path, max_title_width)
+        " {}{} ",
+        hscroll_info,
+        abbreviate_path(
+            &app.tree.root.path,
+            max_title_width.saturating_sub(hscroll_info.width())
+        )
     );
-    let list = List::new(items).block(Block::default().borders(Borders::ALL).title(title));
+    let block = if app.compact {
+        block
+    } else {
+        block.title(title)
+    };
+    let list = List::new(items).block(block);
 
     frame.render_widget(list, area);
+
+    // Scrollbar on the right border, only when the tree doesn't fit
+    if app.tree.len() > visible_height {
+        let mut state = ScrollbarState::new(app.tree.len().saturating_sub(visible_height))
+            .position(app.scroll_offset)
+            .viewport_content_length(visible_height);
+        frame.render_stateful_widget(
+            Scrollbar::new(ScrollbarOrientation::VerticalRight),
+            Rect {
+                y: inner.y,
+                height: inner.height,
+                ..area
+            },
+            &mut state,
+        );
+        app.tree_scrollbar_x = Some(area.x + area.width.saturating_sub(1));
+    } else {
+        app.tree_scrollbar_x = None;
+    }
+}
+
+/// Miller layout: the parent's siblings, the selection's siblings and a preview of the selection
+fn draw_miller_columns(frame: &mut Frame, app: &mut App, area: Rect) {
+    let columns = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([
+            Constraint::Percentage(20),
+            Constraint::Percentage(35),
+            Constraint::Percentage(45),
+        ])
+        .split(area);
+
+    let (parent, siblings) = app.sibling_rows(app.selected);
+    let (_, parent_siblings) = match parent {
+        Some(parent) => app.sibling_rows(parent),
+        None => (None, Vec::new()),
+    };
+    draw_column(frame, app, columns[0], &parent_siblings, parent);
+    draw_column(frame, app, columns[1], &siblings, Some(app.selected));
+    // Page keys move by the height of the current column
+    app.tree_visible_rows = columns[1].height.saturating_sub(2) as usize;
+    app.tree_scrollbar_x = None;
+    draw_quick_preview(frame, app, columns[2]);
+}
+
+/// A column of the Miller layout listing `rows` of the tree, keeping `highlight` in view
+fn draw_column(frame: &mut Frame, app: &App, area: Rect, rows: &[usize], highlight: Option<usize>) {
+    let title = rows
+        .first()
+        .and_then(|&row| app.tree.get_node(row))
+        .and_then(|node| node.path.parent())
+        .and_then(Path::file_name)
+        .map(|name| format!(" {} ", name.to_string_lossy()))
+        .unwrap_or_default();
+    let block = if app.compact {
+        Block::default()
+    } else {
+        Block::default().borders(Borders::ALL).title(title)
+    };
+    let inner = block.inner(area);
+    let visible_height = inner.height as usize;
+    let position = rows
+        .iter()
+        .position(|&row| Some(row) == highlight)
+        .unwrap_or(0);
+    let scroll = position.saturating_sub(visible_height.saturating_sub(1));
+
+    let lines: Vec<Line> = rows
+        .iter()
+        .skip(scroll)
+        .take(visible_height)
+        .filter_map(|&row| {
+            let node = app.tree.get_node(row)?;
+            let mark = if app.marked.contains(&node.path) {
+                "*"
+            } else {
+                " "
+            };
+            let mut style = Style::default().fg(entry_color(app, node));
+            if Some(row) == highlight {
+                style = style.bg(Color::DarkGray).add_modifier(Modifier::BOLD);
+            }
+            let text = format!("{} {}", node_icon(app, node), node.name);
+            let text = take_width(&text, (inner.width as usize).saturating_sub(1));
+            let padding = (inner.width as usize).saturating_sub(1 + text.width());
+            Some(Line::from(vec![
+                Span::styled(mark, Style::default().fg(Color::Yellow)),
+                Span::styled(text, style),
+                Span::styled(" ".repeat(padding), style),
+            ]))
+        })
+        .collect();
+
+    frame.render_widget(Paragraph::new(lines).block(block), area);
+}
+
+/// Metadata columns shown next to the names in the tree
+#[derive(Debug, Clone, Copy, Default)]
+struct Columns {
+    size: bool,
+    mtime: bool,
+    permissions: bool,
+}
+
+const SIZE_COLUMN_WIDTH: usize = 10;
+const MTIME_COLUMN_WIDTH: usize = 11;
+const PERMISSIONS_COLUMN_WIDTH: usize = 28;
+/// Name width the detail view keeps before dropping columns
+const MIN_NAME_WIDTH: usize = 24;
+
+impl Columns {
+    /// The individually toggled columns, or in detail view as many as fit `row_width`
+    /// (permissions are dropped first, then mtime, then size)
+    fn for_width(app: &App, row_width: usize) -> Self {
+        if !app.detail_view {
+            return Self {
+                size: app.show_size,
+                mtime: app.show_mtime,
+                permissions: app.show_permissions,
+            };
+        }
+        let mut columns = Self {
+            size: true,
+            mtime: true,
+            permissions: true,
+        };
+        if row_width < MIN_NAME_WIDTH + columns.width() {
+            columns.permissions = false;
+        }
+        if row_width < MIN_NAME_WIDTH + columns.width() {
+            columns.mtime = false;
+        }
+        if row_width < MIN_NAME_WIDTH + columns.width() {
+            columns.size = false;
+        }
+        columns
+    }
+
+    fn width(&self) -> usize {
+        let mut width = 0;
+        if self.size {
+            width += SIZE_COLUMN_WIDTH;
+        }
+        if self.mtime {
+            width += MTIME_COLUMN_WIDTH;
+        }
+        if self.permissions {
+            width += PERMISSIONS_COLUMN_WIDTH;
+        }
+        width
+    }
+}
+
+/// Right-aligned metadata columns for a tree row (empty if no columns are enabled)
+fn node_columns(
+    app: &App,
+    node: &FileNode,
+    columns: Columns,
+    is_selected: bool,
+) -> Vec<Span<'static>> {
+    let dim = Style::default().fg(if is_selected {
+        Color::Gray
+    } else {
+        Color::DarkGray
+    });
+    let mut spans = Vec::new();
+    if columns.size {
+        let size = node
+            .size
+            .or_else(|| app.dir_sizes.get(&node.path).copied())
+            .map(App::format_size)
+            .unwrap_or_default();
+        spans.push(Span::styled(
+            format!(" {:>w$}", size, w = SIZE_COLUMN_WIDTH - 1),
+            dim,
+        ));
+    }
+    if columns.mtime {
+        let modified = node
+            .modified
+            .map(|m| App::format_mtime(m, SystemTime::now()))
+            .unwrap_or_default();
+        spans.push(Span::styled(
+            format!(" {:>w$}", modified, w = MTIME_COLUMN_WIDTH - 1),
+            dim,
+        ));
+    }
+    if columns.permissions {
+        let text = match node.ownership {
+            Some(o) => {
+                let (user, group) = app.owner_names(o.uid, o.gid);
+                format!(
+                    " {} {} {}",
+                    App::format_mode(o.mode),
+                    fit_width(&user, 8),
+                    fit_width(&group, 8)
+                )
+            }
+            None => " ".repeat(PERMISSIONS_COLUMN_WIDTH),
+        };
+        spans.push(Span::styled(text, Style::default().fg(Color::Cyan)));
+    }
+    spans
 }
 
 fn draw_status_bar(frame: &mut Frame, app: &App, area: Rect) {
@@ -130,8 +611,13 @@ fn draw_status_bar(frame: &mut Frame, app: &App, area: Rect) {
         .split(area);
 
     // Left: message or help
+    let block = if app.compact {
+        Block::default()
+    } else {
+        Block::default().borders(Borders::ALL)
+    };
     let message = app.message.as_deref().unwrap_or("? for help");
-    let msg = Paragraph::new(message).block(Block::default().borders(Borders::ALL));
+    let msg = Paragraph::new(message).block(block.clone());
     frame.render_widget(msg, chunks[0]);
 
     // Right: stats
@@ -140,23 +626,58 @@ fn draw_status_bar(frame: &mut Frame, app: &App, area: Rect) {
         String::new()
     } else {
         match &app.clipboard.content {
-            Some(crate::file_ops::ClipboardContent::Copy(p)) => format!(" | Copied: {}", p.len()),
-            Some(crate::file_ops::ClipboardContent::Cut(p)) => format!(" | Cut: {}", p.len()),
+            Some(filetree::file_ops::ClipboardContent::Copy(p)) => {
+                format!(" | Copied: {}", p.len())
+            }
+            Some(filetree::file_ops::ClipboardContent::Cut(p)) => format!(" | Cut: {}", p.len()),
             None => String::new(),
         }
     };
 
-    let branch_info = app
+    let mut branch_info = app
         .git_repo
         .branch
         .as_ref()
         .map(|b| format!(" {}", b))
         .unwrap_or_default();
+    if let Some((ahead, behind)) = app.git_repo.ahead_behind {
+        if ahead > 0 {
+            branch_info.push_str(&format!(" ↑{}", ahead));
+        }
+        if behind > 0 {
+            branch_info.push_str(&format!(" ↓{}", behind));
+        }
+    }
+    let (changed, untracked) = app.git_repo.change_counts;
+    if changed > 0 {
+        branch_info.push_str(&format!(" ~{}", changed));
+    }
+    if untracked > 0 {
+        branch_info.push_str(&format!(" ?{}", untracked));
+    }
+    if app.git_job.is_some() {
+        let frame_char = SPINNER_FRAMES[app.spinner_tick / 2 % SPINNER_FRAMES.len()];
+        branch_info.push_str(&format!(" {} git…", frame_char));
+    }
+
+    let filters = app.active_filters();
+    let filter_info = if filters.is_empty() {
+        String::new()
+    } else {
+        format!(" [{}]", filters.join(","))
+    };
+
+    let count_info = app
+        .pending_count
+        .map(|n| format!("{} | ", n))
+        .unwrap_or_default();
 
     let stats = format!(
-        "{}/{}{}{}{}",
+        "{}{}/{}{}{}{}{}",
+        count_info,
         app.selected + 1,
         app.tree.len(),
+        filter_info,
         if marked_count > 0 {
             format!(" | Marked: {}", marked_count)
         } else {
@@ -165,7 +686,12 @@ fn draw_status_bar(frame: &mut Frame, app: &App, area: Rect) {
         clipboard_info,
         branch_info
     );
-    let stats_widget = Paragraph::new(stats).block(Block::default().borders(Borders::ALL));
+    let stats_widget = Paragraph::new(stats).block(block);
+    let stats_widget = if app.compact {
+        stats_widget.alignment(Alignment::Right)
+    } else {
+        stats_widget
+    };
     frame.render_widget(stats_widget, chunks[1]);
 }
 
@@ -180,83 +706,936 @@ fn draw_quick_preview(frame: &mut Frame, app: &App, area: Rect) {
                     .file_name()
                     .map(|n| n.to_string_lossy().to_string())
                     .unwrap_or_default();
-                format!(" {} ({}x{}) [Ctrl+p: close] ", name, img.width, img.height)
+                format!(
+                    " {} ({}x{}) [Ctrl+p: close] ",
+                    name, img.dimensions.0, img.dimensions.1
+                )
             })
             .unwrap_or_else(|| " Quick Preview ".to_string());
 
         let img_width = area.width.saturating_sub(2) as u32;
         let img_height = (area.height.saturating_sub(2) * 2) as u32;
 
-        let lines = render_image_to_lines(img, img_width, img_height);
+        let lines = render_image_to_lines(img, img_width, img_height);
+
+        let preview =
+            Paragraph::new(lines).block(Block::default().borders(Borders::ALL).title(title));
+
+        frame.render_widget(preview, area);
+        return;
+    }
+
+    let visible_height = area.height.saturating_sub(2) as usize;
+
+    let title = app
+        .quick_preview_path
+        .as_ref()
+        .map(|p| {
+            let name = p
+                .file_name()
+                .map(|n| n.to_string_lossy().to_string())
+                .unwrap_or_default();
+            format!(" {} [Ctrl+p: close] ", name)
+        })
+        .unwrap_or_else(|| " Quick Preview ".to_string());
+
+    let gutter = Gutter {
+        line_numbers: app.preview_line_numbers,
+        changes: &app.quick_preview_changes,
+    };
+    let wrap_width = app
+        .preview_wrap
+        .then(|| area.width.saturating_sub(2) as usize);
+    let lines = numbered_lines(
+        &app.quick_preview_content,
+        app.quick_preview_scroll,
+        visible_height,
+        0,
+        wrap_width,
+        &gutter,
+        &Highlights::NONE,
+    );
+
+    let preview = Paragraph::new(lines).block(Block::default().borders(Borders::ALL).title(title));
+
+    frame.render_widget(preview, area);
+}
+
+fn draw_todo_list(frame: &mut Frame, app: &App) {
+    let frame_area = frame.area();
+    let area = centered_rect(80, frame_area.height.saturating_sub(4), frame_area);
+    let visible_height = area.height.saturating_sub(2) as usize;
+
+    // Group items by file: a header row whenever the path changes
+    let mut lines: Vec<Line> = Vec::new();
+    let mut selected_row = 0;
+    let mut current_path = None;
+    for (i, item) in app.todo_items.iter().enumerate() {
+        if current_path != Some(&item.path) {
+            current_path = Some(&item.path);
+            let relative = item
+                .path
+                .strip_prefix(&app.tree.root.path)
+                .unwrap_or(&item.path);
+            lines.push(Line::from(Span::styled(
+                relative.display().to_string(),
+                Style::default()
+                    .fg(Color::Blue)
+                    .add_modifier(Modifier::BOLD),
+            )));
+        }
+        if i == app.todo_selected {
+            selected_row = lines.len();
+        }
+        let marker_color = match item.marker {
+            "FIXME" => Color::Red,
+            "HACK" => Color::Magenta,
+            _ => Color::Yellow,
+        };
+        let mut line = Line::from(vec![
+            Span::styled(
+                format!("  {:>5} ", item.line),
+                Style::default().fg(Color::DarkGray),
+            ),
+            Span::styled(item.marker, Style::default().fg(marker_color)),
+            Span::raw(" "),
+            Span::raw(item.text.as_str()),
+        ]);
+        if i == app.todo_selected {
+            line = line.style(Style::default().bg(Color::DarkGray));
+        }
+        lines.push(line);
+    }
+
+    if lines.is_empty() {
+        let text = if app.todo_job.is_some() {
+            "Scanning..."
+        } else {
+            "No TODO/FIXME/HACK markers found"
+        };
+        lines.push(Line::from(Span::styled(
+            text,
+            Style::default().fg(Color::DarkGray),
+        )));
+    }
+
+    let scroll = selected_row.saturating_sub(visible_height.saturating_sub(1));
+    let lines: Vec<Line> = lines
+        .into_iter()
+        .skip(scroll)
+        .take(visible_height)
+        .collect();
+
+    let title = if app.todo_job.is_some() {
+        " TODOs (scanning...) ".to_string()
+    } else {
+        format!(
+            " TODOs ({}) | Enter:open  R:rescan  q/Esc:close ",
+            app.todo_items.len()
+        )
+    };
+
+    let popup = Paragraph::new(lines).block(Block::default().borders(Borders::ALL).title(title));
+    frame.render_widget(Clear, area);
+    frame.render_widget(popup, area);
+}
+
+/// Metadata of the selected entry as label / value rows
+fn draw_file_info(frame: &mut Frame, app: &App) {
+    let label_width = app
+        .file_info
+        .iter()
+        .map(|(label, _)| label.width())
+        .max()
+        .unwrap_or(0);
+    let lines: Vec<Line> = app
+        .file_info
+        .iter()
+        .map(|(label, value)| {
+            Line::from(vec![
+                Span::styled(
+                    format!(" {:<width$}  ", label, width = label_width),
+                    Style::default().fg(Color::Blue),
+                ),
+                Span::raw(value.as_str()),
+            ])
+        })
+        .collect();
+
+    let frame_area = frame.area();
+    let area = centered_rect(80, lines.len() as u16 + 2, frame_area);
+    let popup = Paragraph::new(lines)
+        .wrap(ratatui::widgets::Wrap { trim: false })
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title(" Info | q/Esc:close "),
+        );
+    frame.render_widget(Clear, area);
+    frame.render_widget(popup, area);
+}
+
+/// Report of files only in A, only in B and differing between the compared directories
+fn draw_compare(frame: &mut Frame, app: &App) {
+    let frame_area = frame.area();
+    let area = centered_rect(80, frame_area.height.saturating_sub(4), frame_area);
+    let visible_height = area.height.saturating_sub(2) as usize;
+    let scroll = app
+        .compare_selected
+        .saturating_sub(visible_height.saturating_sub(1));
+
+    let mut lines: Vec<Line> = app
+        .compare_entries
+        .iter()
+        .enumerate()
+        .skip(scroll)
+        .take(visible_height)
+        .map(|(i, entry)| {
+            let (label, color) = match entry.difference {
+                Difference::OnlyInA => ("only A ", Color::Green),
+                Difference::OnlyInB => ("only B ", Color::Cyan),
+                Difference::Differs => ("differs", Color::Yellow),
+            };
+            let mut name = entry.path.display().to_string();
+            if entry.is_dir {
+                name.push('/');
+            }
+            let mut line = Line::from(vec![
+                Span::styled(format!("  {} ", label), Style::default().fg(color)),
+                Span::raw(name),
+            ]);
+            if i == app.compare_selected {
+                line = line.style(Style::default().bg(Color::DarkGray));
+            }
+            line
+        })
+        .collect();
+
+    if lines.is_empty() {
+        let text = if app.compare_job.is_some() {
+            "Comparing..."
+        } else {
+            "The directories are identical"
+        };
+        lines.push(Line::from(Span::styled(
+            text,
+            Style::default().fg(Color::DarkGray),
+        )));
+    }
+
+    let name = |path: &Path| {
+        path.strip_prefix(&app.tree.root.path)
+            .unwrap_or(path)
+            .display()
+            .to_string()
+    };
+    let (a, b) = &app.compare_dirs;
+    let title = if app.compare_job.is_some() {
+        format!(" A: {}  B: {} (comparing...) ", name(a), name(b))
+    } else {
+        format!(
+            " A: {}  B: {} ({}) | Enter:preview  c:copy across  R:rescan  q/Esc:close ",
+            name(a),
+            name(b),
+            app.compare_entries.len()
+        )
+    };
+
+    let popup = Paragraph::new(lines).block(Block::default().borders(Borders::ALL).title(title));
+    frame.render_widget(Clear, area);
+    frame.render_widget(popup, area);
+}
+
+/// Review panel listing every marked path, including those in collapsed directories
+fn draw_marked_list(frame: &mut Frame, app: &App) {
+    let frame_area = frame.area();
+    let area = centered_rect(80, frame_area.height.saturating_sub(4), frame_area);
+    let visible_height = area.height.saturating_sub(2) as usize;
+    let scroll = app
+        .marked_selected
+        .saturating_sub(visible_height.saturating_sub(1));
+
+    let paths = app.marked_list();
+    let lines: Vec<Line> = paths
+        .iter()
+        .enumerate()
+        .skip(scroll)
+        .take(visible_height)
+        .map(|(i, path)| {
+            let relative = path.strip_prefix(&app.tree.root.path).unwrap_or(path);
+            let color = if path.is_dir() {
+                Color::Blue
+            } else {
+                Color::White
+            };
+            let line = Line::from(Span::styled(
+                relative.display().to_string(),
+                Style::default().fg(color),
+            ));
+            if i == app.marked_selected {
+                line.style(Style::default().bg(Color::DarkGray))
+            } else {
+                line
+            }
+        })
+        .collect();
+
+    let title = format!(" Marked ({}) | Space/x:unmark  q/Esc:close ", paths.len());
+    let popup = Paragraph::new(lines).block(Block::default().borders(Borders::ALL).title(title));
+    frame.render_widget(Clear, area);
+    frame.render_widget(popup, area);
+}
+
+/// Every item of a batch under review, with those left out struck through
+fn draw_plan(frame: &mut Frame, app: &App) {
+    let Some(plan) = &app.plan else {
+        return;
+    };
+    let frame_area = frame.area();
+    let area = centered_rect(80, frame_area.height.saturating_sub(4), frame_area);
+    let visible_height = area.height.saturating_sub(2) as usize;
+    let scroll = app
+        .plan_selected
+        .saturating_sub(visible_height.saturating_sub(1));
+
+    let relative = |path: &Path| {
+        path.strip_prefix(&app.tree.root.path)
+            .unwrap_or(path)
+            .display()
+            .to_string()
+    };
+    let lines: Vec<Line> = plan
+        .items
+        .iter()
+        .enumerate()
+        .skip(scroll)
+        .take(visible_height)
+        .map(|(i, item)| {
+            let mut name = relative(&item.path);
+            if item.path.is_dir() {
+                name.push('/');
+            }
+            let style = if !item.included {
+                Style::default()
+                    .fg(Color::DarkGray)
+                    .add_modifier(Modifier::CROSSED_OUT)
+            } else if item.path.is_dir() && plan.op == BatchOp::Delete {
+                Style::default().fg(Color::Red).add_modifier(Modifier::BOLD)
+            } else {
+                Style::default().fg(Color::White)
+            };
+            let check = if item.included { "[x] " } else { "[ ] " };
+            let mut spans = vec![Span::raw(format!("  {}", check)), Span::styled(name, style)];
+            if let Some(target) = &item.target {
+                spans.push(Span::styled(
+                    format!(" -> {}", relative(target)),
+                    Style::default().fg(Color::DarkGray),
+                ));
+            }
+            let line = Line::from(spans);
+            if i == app.plan_selected {
+                line.style(Style::default().bg(Color::DarkGray))
+            } else {
+                line
+            }
+        })
+        .collect();
+
+    let verb = if plan.op == BatchOp::Delete {
+        "Delete"
+    } else {
+        "Move"
+    };
+    let included = plan.items.iter().filter(|i| i.included).count();
+    let title = format!(
+        " {} {} of {} item(s) | Space/x:leave out  y/Enter:run  q/Esc:cancel ",
+        verb,
+        included,
+        plan.items.len()
+    );
+    let border = if plan.op == BatchOp::Delete {
+        Color::Red
+    } else {
+        Color::Yellow
+    };
+    let popup = Paragraph::new(lines).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .border_style(Style::default().fg(border))
+            .title(title),
+    );
+    frame.render_widget(Clear, area);
+    frame.render_widget(popup, area);
+}
+
+/// Timings and sizes in the top-right corner (F12)
+fn draw_perf_overlay(frame: &mut Frame, app: &App, perf: &PerfStats) {
+    let millis = |d: std::time::Duration| format!("{:.1} ms", d.as_secs_f64() * 1000.0);
+    let memory = App::resident_memory()
+        .map(App::format_size)
+        .unwrap_or_else(|| "-".to_string());
+    let rows = [
+        ("frame", millis(perf.last_frame)),
+        ("slowest", millis(perf.slowest_frame)),
+        ("rebuild", millis(app.tree.last_rebuild)),
+        ("rows", app.tree.len().to_string()),
+        ("loaded", app.tree.root.loaded_count().to_string()),
+        ("preview", app.preview_content.len().to_string()),
+        ("frames", perf.frames.to_string()),
+        ("memory", memory),
+    ];
+    let lines: Vec<Line> = rows
+        .iter()
+        .map(|(label, value)| {
+            Line::from(vec![
+                Span::styled(
+                    format!(" {:<8}", label),
+                    Style::default().fg(Color::DarkGray),
+                ),
+                Span::raw(format!("{:>10} ", value)),
+            ])
+        })
+        .collect();
+
+    let frame_area = frame.area();
+    let width = 22.min(frame_area.width);
+    let height = (lines.len() as u16 + 2).min(frame_area.height);
+    let area = Rect::new(frame_area.width.saturating_sub(width), 0, width, height);
+    let popup = Paragraph::new(lines).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .border_style(Style::default().fg(Color::DarkGray))
+            .title(" perf "),
+    );
+    frame.render_widget(Clear, area);
+    frame.render_widget(popup, area);
+}
+
+/// Which-key popup listing the continuations of a pending sequence prefix
+fn draw_which_key(frame: &mut Frame, prefix: char) {
+    let Some(sequence) = keymap::sequence(prefix) else {
+        return;
+    };
+    let lines: Vec<Line> = sequence
+        .continuations
+        .iter()
+        .map(|binding| {
+            Line::from(vec![
+                Span::styled(
+                    format!(" {}", fit_width(binding.keys, 4)),
+                    Style::default().fg(Color::Yellow),
+                ),
+                Span::raw(binding.description),
+            ])
+        })
+        .collect();
+
+    let frame_area = frame.area();
+    let width = 40.min(frame_area.width);
+    let height = (lines.len() as u16 + 2).min(frame_area.height);
+    // Bottom-right corner, above the status bar
+    let area = Rect::new(
+        frame_area.width.saturating_sub(width),
+        frame_area.height.saturating_sub(height + 3),
+        width,
+        height,
+    );
+    let popup = Paragraph::new(lines).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title(format!(" {}… ", prefix)),
+    );
+    frame.render_widget(Clear, area);
+    frame.render_widget(popup, area);
+}
+
+fn draw_help(frame: &mut Frame, app: &mut App) {
+    let frame_area = frame.area();
+    let area = centered_rect(80, frame_area.height.saturating_sub(4), frame_area);
+    let visible_height = area.height.saturating_sub(2) as usize;
+
+    let bindings = keymap::matching(&app.help_query);
+    let keys_width = bindings.iter().map(|b| b.keys.width()).max().unwrap_or(0);
+
+    let mut lines: Vec<Line> = Vec::new();
+    let mut category = "";
+    for binding in &bindings {
+        if binding.category != category {
+            if !category.is_empty() {
+                lines.push(Line::from(""));
+            }
+            category = binding.category;
+            lines.push(Line::from(Span::styled(
+                category,
+                Style::default()
+                    .fg(Color::Blue)
+                    .add_modifier(Modifier::BOLD),
+            )));
+        }
+        lines.push(Line::from(vec![
+            Span::styled(
+                format!("  {}  ", fit_width(binding.keys, keys_width)),
+                Style::default().fg(Color::Yellow),
+            ),
+            Span::raw(binding.description),
+        ]));
+    }
+    if lines.is_empty() {
+        lines.push(Line::from(Span::styled(
+            "No matching key bindings",
+            Style::default().fg(Color::DarkGray),
+        )));
+    }
+
+    app.help_scroll = app
+        .help_scroll
+        .min(lines.len().saturating_sub(visible_height));
+    let lines: Vec<Line> = lines
+        .into_iter()
+        .skip(app.help_scroll)
+        .take(visible_height)
+        .collect();
+
+    let title = if app.help_searching {
+        format!(" Help | /{}_ ", app.help_query)
+    } else if !app.help_query.is_empty() {
+        format!(" Help | /{} | Esc:clear filter ", app.help_query)
+    } else {
+        " Help | j/k:scroll  /:search  q/Esc:close ".to_string()
+    };
+
+    let popup = Paragraph::new(lines).block(Block::default().borders(Borders::ALL).title(title));
+    frame.render_widget(Clear, area);
+    frame.render_widget(popup, area);
+}
+
+fn draw_disk_usage(frame: &mut Frame, app: &App) {
+    const BAR_WIDTH: usize = 20;
+    let frame_area = frame.area();
+    let area = centered_rect(80, frame_area.height.saturating_sub(4), frame_area);
+    let visible_height = area.height.saturating_sub(2) as usize;
+
+    let total: u64 = app.du_entries.iter().map(|e| e.size).sum();
+    let mut lines: Vec<Line> = app
+        .du_entries
+        .iter()
+        .enumerate()
+        .map(|(i, entry)| {
+            let ratio = if total == 0 {
+                0.0
+            } else {
+                entry.size as f64 / total as f64
+            };
+            let filled = (ratio * BAR_WIDTH as f64).round() as usize;
+            let bar = format!("{}{}", "█".repeat(filled), "░".repeat(BAR_WIDTH - filled));
+            let (name, name_style) = if entry.is_dir {
+                (format!("{}/", entry.name), Style::default().fg(Color::Blue))
+            } else {
+                (entry.name.clone(), Style::default())
+            };
+            let mut line = Line::from(vec![
+                Span::styled(
+                    format!("{:>10} ", App::format_size(entry.size)),
+                    Style::default().fg(Color::Yellow),
+                ),
+                Span::styled(
+                    format!("{:>5.1}% ", ratio * 100.0),
+                    Style::default().fg(Color::DarkGray),
+                ),
+                Span::styled(bar, Style::default().fg(Color::Cyan)),
+                Span::raw(" "),
+                Span::styled(name, name_style),
+            ]);
+            if i == app.du_selected {
+                line = line.style(Style::default().bg(Color::DarkGray));
+            }
+            line
+        })
+        .collect();
+
+    if lines.is_empty() {
+        let text = if app.du_job.is_some() {
+            "Scanning..."
+        } else {
+            "Empty directory"
+        };
+        lines.push(Line::from(Span::styled(
+            text,
+            Style::default().fg(Color::DarkGray),
+        )));
+    }
+
+    let scroll = app
+        .du_selected
+        .saturating_sub(visible_height.saturating_sub(1));
+    let lines: Vec<Line> = lines
+        .into_iter()
+        .skip(scroll)
+        .take(visible_height)
+        .collect();
+
+    let title = if app.du_job.is_some() {
+        format!(" {} (scanning...) ", app.du_dir.display())
+    } else {
+        format!(
+            " {} ({}) | Enter:open  h:up  d:delete  R:rescan  q/Esc:close ",
+            app.du_dir.display(),
+            App::format_size(total)
+        )
+    };
+
+    let popup = Paragraph::new(lines).block(Block::default().borders(Borders::ALL).title(title));
+    frame.render_widget(Clear, area);
+    frame.render_widget(popup, area);
+}
+
+fn draw_input_popup(frame: &mut Frame, app: &App) {
+    let area = centered_rect(60, 3, frame.area());
+
+    let title = match app.input_mode {
+        InputMode::Search => "Search",
+        InputMode::Rename => "Rename",
+        InputMode::NewFile => "New File",
+        InputMode::NewDir => "New Directory",
+        InputMode::MoveTo => "Move to (Tab: complete, Up/Down: recent)",
+        InputMode::CopyTo => "Copy to (Tab: complete, Up/Down: recent)",
+        InputMode::ExternalCommand => {
+            "External Command (<filepath> <dir> <name> <stem> <ext> <root>; prefix ! to capture, @ to run in the terminal)"
+        }
+        InputMode::Commit => "Commit Message (staged changes)",
+        InputMode::MarkGlob => "Mark by Pattern (e.g. *.log, test_*)",
+        InputMode::ExtensionFilter => "Show Extensions (e.g. rs,toml; empty to clear)",
+        _ => "",
+    };
+
+    if let Some(search) = &app.history_search {
+        draw_history_search(frame, app, search, area);
+        return;
+    }
+
+    let input = Paragraph::new(app.input_buffer.as_str())
+        .style(Style::default().fg(Color::Yellow))
+        .block(Block::default().borders(Borders::ALL).title(title));
+
+    frame.render_widget(Clear, area);
+    frame.render_widget(input, area);
+}
+
+/// The reverse history search prompt, with the query highlighted in the match
+fn draw_history_search(frame: &mut Frame, app: &App, search: &HistorySearch, area: Rect) {
+    let title = format!(
+        "Reverse Search: {} (Ctrl-R: older, Enter: run, Esc: back)",
+        search.query
+    );
+    let input_style = Style::default().fg(Color::Yellow);
+    let line = match search.index.map(|i| app.command_history[i].as_str()) {
+        Some(entry) => {
+            let start = entry.rfind(&search.query).unwrap_or(0);
+            let end = start + search.query.len();
+            Line::from(vec![
+                Span::styled(&entry[..start], input_style),
+                Span::styled(
+                    &entry[start..end],
+                    input_style.add_modifier(Modifier::REVERSED),
+                ),
+                Span::styled(&entry[end..], input_style),
+            ])
+        }
+        None if search.query.is_empty() => Line::from(""),
+        None => Line::from(Span::styled(
+            "(no match)",
+            Style::default().fg(Color::DarkGray),
+        )),
+    };
+    let input = Paragraph::new(line).block(Block::default().borders(Borders::ALL).title(title));
+
+    frame.render_widget(Clear, area);
+    frame.render_widget(input, area);
+}
+
+fn draw_confirm_popup(frame: &mut Frame, _app: &App, action: &ConfirmAction) {
+    match action {
+        ConfirmAction::Delete(info) => draw_delete_confirm_popup(frame, info),
+        ConfirmAction::Discard(info) => draw_discard_confirm_popup(frame, info),
+        ConfirmAction::Elevate(op) => draw_elevate_confirm_popup(frame, op),
+        ConfirmAction::Retry(report) => draw_retry_popup(frame, report),
+        ConfirmAction::Overwrite(info) => draw_question_popup(
+            frame,
+            " Overwrite ",
+            format!("{} already exists", info.new_name),
+            format!(
+                "Replace it with {}?",
+                info.path.file_name().unwrap_or_default().to_string_lossy()
+            ),
+        ),
+        ConfirmAction::Open(info) => draw_question_popup(
+            frame,
+            " Open Files ",
+            format!("Open {} files at once?", info.paths.len()),
+            if info.nvim {
+                "They are sent to the Neovim server".to_string()
+            } else {
+                "Matching open_with commands run first, the rest open in the editor".to_string()
+            },
+        ),
+        ConfirmAction::Command(run) => draw_question_popup(
+            frame,
+            " Run Command ",
+            format!("Run the command on {} files?", run.paths.len()),
+            "It runs once for each marked file".to_string(),
+        ),
+        ConfirmAction::Large(batch) => {
+            let verb = if batch.op == BatchOp::Delete {
+                "delete"
+            } else {
+                "copy"
+            };
+            let what = match batch.paths.as_slice() {
+                [path] => path
+                    .file_name()
+                    .unwrap_or_default()
+                    .to_string_lossy()
+                    .to_string(),
+                paths => format!("{} items", paths.len()),
+            };
+            draw_question_popup(
+                frame,
+                " Large Operation ",
+                format!(
+                    "This will {} {} / {} files - continue?",
+                    verb,
+                    App::format_size(batch.stats.bytes),
+                    batch.stats.files
+                ),
+                format!("Measured {}", what),
+            )
+        }
+    }
+}
+
+/// A yes / no question with one line of detail
+fn draw_question_popup(frame: &mut Frame, title: &str, question: String, detail: String) {
+    let area = centered_rect(70, 7, frame.area());
+    let key_style = |color| Style::default().fg(color).add_modifier(Modifier::BOLD);
+
+    let content = vec![
+        Line::from(Span::styled(
+            question,
+            Style::default().add_modifier(Modifier::BOLD),
+        )),
+        Line::from(detail),
+        Line::from(""),
+        Line::from(vec![
+            Span::styled("y", key_style(Color::Green)),
+            Span::raw(" to continue, "),
+            Span::styled("n", key_style(Color::Red)),
+            Span::raw(" to cancel"),
+        ]),
+    ];
+
+    let popup = Paragraph::new(content)
+        .wrap(ratatui::widgets::Wrap { trim: false })
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .border_style(Style::default().fg(Color::Yellow))
+                .title(title),
+        );
+
+    frame.render_widget(Clear, area);
+    frame.render_widget(popup, area);
+}
+
+fn draw_retry_popup(frame: &mut Frame, report: &BatchReport) {
+    let max_items_to_show = 10;
+    let items_count = report.failures.len().min(max_items_to_show);
+    let has_more = report.failures.len() > max_items_to_show;
+    let any_denied = report.failures.iter().any(|f| f.permission_denied);
+
+    // Height: title(1) + items + "more" line + blank + confirm line + borders(2)
+    let more_line = if has_more { 1 } else { 0 };
+    let height = (1 + items_count + more_line + 2 + 2) as u16;
+    let area = centered_rect(80, height, frame.area());
+
+    let operation = match &report.op {
+        BatchOp::Delete => "delete".to_string(),
+        BatchOp::Copy(dest) => format!("copy to {}", dest.display()),
+        BatchOp::Move(dest) => format!("move to {}", dest.display()),
+    };
+    let mut content = vec![Line::from(vec![Span::styled(
+        format!("Could not {} {} item(s):", operation, report.failures.len()),
+        Style::default().add_modifier(Modifier::BOLD),
+    )])];
+
+    for failure in report.failures.iter().take(max_items_to_show) {
+        let name = failure
+            .path
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_else(|| failure.path.display().to_string());
+        content.push(Line::from(vec![
+            Span::raw(format!("  {} ", name)),
+            Span::styled(failure.error.clone(), Style::default().fg(Color::Red)),
+        ]));
+    }
 
-        let preview =
-            Paragraph::new(lines).block(Block::default().borders(Borders::ALL).title(title));
+    if has_more {
+        content.push(Line::from(vec![Span::styled(
+            format!(
+                "  ... and {} more",
+                report.failures.len() - max_items_to_show
+            ),
+            Style::default().fg(Color::DarkGray),
+        )]));
+    }
 
-        frame.render_widget(preview, area);
-        return;
+    let key_style = |color| Style::default().fg(color).add_modifier(Modifier::BOLD);
+    content.push(Line::from(""));
+    let mut keys = vec![
+        Span::styled("y", key_style(Color::Green)),
+        Span::raw(" to retry, "),
+    ];
+    if any_denied {
+        keys.push(Span::styled("e", key_style(Color::Yellow)));
+        keys.push(Span::raw(" to retry the denied ones elevated, "));
     }
+    keys.push(Span::styled("n", key_style(Color::Red)));
+    keys.push(Span::raw(" to close"));
+    content.push(Line::from(keys));
 
-    let visible_height = area.height.saturating_sub(2) as usize;
+    let popup = Paragraph::new(content).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .border_style(Style::default().fg(Color::Red))
+            .title(" Errors "),
+    );
 
-    let title = app
-        .quick_preview_path
-        .as_ref()
-        .map(|p| {
-            let name = p
-                .file_name()
-                .map(|n| n.to_string_lossy().to_string())
-                .unwrap_or_default();
-            format!(" {} [Ctrl+p: close] ", name)
-        })
-        .unwrap_or_else(|| " Quick Preview ".to_string());
+    frame.render_widget(Clear, area);
+    frame.render_widget(popup, area);
+}
 
-    let lines: Vec<Line> = app
-        .quick_preview_content
-        .iter()
-        .skip(app.quick_preview_scroll)
-        .take(visible_height)
-        .enumerate()
-        .map(|(i, line)| {
-            let line_num = app.quick_preview_scroll + i + 1;
-            Line::from(vec![
-                Span::styled(
-                    format!("{:4} ", line_num),
-                    Style::default().fg(Color::DarkGray),
-                ),
-                Span::raw(line.as_str()),
-            ])
-        })
-        .collect();
+fn draw_discard_confirm_popup(frame: &mut Frame, info: &DiscardInfo) {
+    let max_items_to_show = 8;
+    let items_count = info.files.len().min(max_items_to_show);
+    let has_more = info.files.len() > max_items_to_show;
 
-    let preview = Paragraph::new(lines).block(Block::default().borders(Borders::ALL).title(title));
+    // Height: warning(3) + title(1) + items + "more" line + blank + confirm line + borders(2)
+    let more_line = if has_more { 1 } else { 0 };
+    let height = (3 + 1 + items_count + more_line + 2 + 2) as u16;
+    let area = centered_rect(60, height, frame.area());
 
-    frame.render_widget(preview, area);
-}
+    let mut content = vec![
+        Line::from(vec![Span::styled(
+            "!! WARNING: CHANGES WILL BE LOST !!",
+            Style::default()
+                .fg(Color::Red)
+                .add_modifier(Modifier::BOLD | Modifier::SLOW_BLINK),
+        )]),
+        Line::from(vec![Span::styled(
+            "Modified files are restored from HEAD, new files are deleted",
+            Style::default().fg(Color::Yellow),
+        )]),
+        Line::from(""),
+        Line::from(vec![Span::styled(
+            format!("Discard changes in {} file(s):", info.files.len()),
+            Style::default().add_modifier(Modifier::BOLD),
+        )]),
+    ];
+
+    for (path, status) in info.files.iter().take(max_items_to_show) {
+        let name = path
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_else(|| path.display().to_string());
+        let (action, color) = match status {
+            GitStatus::Untracked | GitStatus::Added | GitStatus::Renamed => ("delete ", Color::Red),
+            _ => ("restore", Color::Yellow),
+        };
+        content.push(Line::from(vec![
+            Span::raw("  "),
+            Span::styled(action, Style::default().fg(color)),
+            Span::raw(format!(" {}", name)),
+        ]));
+    }
 
-fn draw_input_popup(frame: &mut Frame, app: &App) {
-    let area = centered_rect(60, 3, frame.area());
+    if has_more {
+        content.push(Line::from(vec![Span::styled(
+            format!("  ... and {} more", info.files.len() - max_items_to_show),
+            Style::default().fg(Color::DarkGray),
+        )]));
+    }
 
-    let title = match app.input_mode {
-        InputMode::Search => "Search",
-        InputMode::Rename => "Rename",
-        InputMode::NewFile => "New File",
-        InputMode::NewDir => "New Directory",
-        InputMode::ExternalCommand => "External Command (use <filepath> for selected file)",
-        _ => "",
-    };
+    content.push(Line::from(""));
+    content.push(Line::from(vec![
+        Span::styled(
+            "y",
+            Style::default()
+                .fg(Color::Green)
+                .add_modifier(Modifier::BOLD),
+        ),
+        Span::raw(" to confirm, "),
+        Span::styled(
+            "n",
+            Style::default().fg(Color::Red).add_modifier(Modifier::BOLD),
+        ),
+        Span::raw(" to cancel"),
+    ]));
 
-    let input = Paragraph::new(app.input_buffer.as_str())
-        .style(Style::default().fg(Color::Yellow))
-        .block(Block::default().borders(Borders::ALL).title(title));
+    let popup = Paragraph::new(content).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .border_style(Style::default().fg(Color::Red))
+            .title(Span::styled(
+                " !! DISCARD CHANGES !! ",
+                Style::default().fg(Color::Red).add_modifier(Modifier::BOLD),
+            )),
+    );
 
     frame.render_widget(Clear, area);
-    frame.render_widget(input, area);
+    frame.render_widget(popup, area);
 }
 
-fn draw_confirm_popup(frame: &mut Frame, _app: &App, action: &ConfirmAction) {
-    match action {
-        ConfirmAction::Delete(info) => draw_delete_confirm_popup(frame, info),
-    }
+fn draw_elevate_confirm_popup(frame: &mut Frame, op: &ElevatedOp) {
+    let area = centered_rect(70, 8, frame.area());
+
+    let content = vec![
+        Line::from(vec![Span::styled(
+            format!("{}: permission denied", op.description),
+            Style::default().fg(Color::Red).add_modifier(Modifier::BOLD),
+        )]),
+        Line::from("Retry with elevated privileges?"),
+        Line::from(vec![Span::styled(
+            op.command.to_string_lossy(),
+            Style::default().fg(Color::Yellow),
+        )]),
+        Line::from(""),
+        Line::from(vec![
+            Span::styled(
+                "y",
+                Style::default()
+                    .fg(Color::Green)
+                    .add_modifier(Modifier::BOLD),
+            ),
+            Span::raw(" to run, "),
+            Span::styled(
+                "n",
+                Style::default().fg(Color::Red).add_modifier(Modifier::BOLD),
+            ),
+            Span::raw(" to cancel"),
+        ]),
+    ];
+
+    let popup = Paragraph::new(content)
+        .wrap(ratatui::widgets::Wrap { trim: false })
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .border_style(Style::default().fg(Color::Red))
+                .title(" Permission Denied "),
+        );
+
+    frame.render_widget(Clear, area);
+    frame.render_widget(popup, area);
 }
 
 fn draw_delete_confirm_popup(frame: &mut Frame, info: &DeleteInfo) {
@@ -379,29 +1758,40 @@ fn draw_preview(frame: &mut Frame, app: &App) -> usize {
 
     let visible_height = chunks[0].height.saturating_sub(2) as usize;
 
-    let title = app
-        .preview_path
-        .as_ref()
-        .map(|p| format!(" {} ", p.display()))
-        .unwrap_or_else(|| " Preview ".to_string());
+    let title = app.preview_title.clone().unwrap_or_else(|| {
+        app.preview_path
+            .as_ref()
+            .map(|p| format!(" {} ", p.display()))
+            .unwrap_or_else(|| " Preview ".to_string())
+    });
+
+    let title = if app.preview_wrap {
+        format!("{}[wrap] ", title)
+    } else if app.preview_hscroll > 0 {
+        format!("{}[→{}] ", title, app.preview_hscroll)
+    } else {
+        title
+    };
 
-    let lines: Vec<Line> = app
-        .preview_content
-        .iter()
-        .skip(app.preview_scroll)
-        .take(visible_height)
-        .enumerate()
-        .map(|(i, line)| {
-            let line_num = app.preview_scroll + i + 1;
-            Line::from(vec![
-                Span::styled(
-                    format!("{:4} ", line_num),
-                    Style::default().fg(Color::DarkGray),
-                ),
-                Span::raw(line.as_str()),
-            ])
-        })
-        .collect();
+    let gutter = Gutter {
+        line_numbers: app.preview_line_numbers,
+        changes: &app.preview_changes,
+    };
+    let wrap_width = app
+        .preview_wrap
+        .then(|| chunks[0].width.saturating_sub(2) as usize);
+    let lines = numbered_lines(
+        &app.preview_content,
+        app.preview_scroll,
+        visible_height,
+        app.preview_hscroll,
+        wrap_width,
+        &gutter,
+        &Highlights {
+            matches: &app.preview_matches,
+            current: app.preview_match,
+        },
+    );
 
     let preview = Paragraph::new(lines).block(Block::default().borders(Borders::ALL).title(title));
 
@@ -412,10 +1802,31 @@ fn draw_preview(frame: &mut Frame, app: &App) -> usize {
     let current_line = app.preview_scroll + 1;
     let percent = (current_line * 100).checked_div(total_lines).unwrap_or(100);
 
-    let status = format!(
-        " Line {}/{} ({}%) | j/k:scroll  f/b:page  g/G:top/bottom  q/Esc:close ",
-        current_line, total_lines, percent
-    );
+    let message = app
+        .message
+        .as_ref()
+        .map(|m| format!(" {} |", m))
+        .unwrap_or_default();
+    let search = if app.preview_query.is_empty() {
+        String::new()
+    } else if app.preview_matches.is_empty() {
+        format!(" /{} (no matches) |", app.preview_query)
+    } else {
+        format!(
+            " /{} [{}/{}] |",
+            app.preview_query,
+            app.preview_match + 1,
+            app.preview_matches.len()
+        )
+    };
+    let status = if app.input_mode == InputMode::PreviewSearch {
+        format!(" /{}█ | Enter:search  Esc:cancel ", app.input_buffer)
+    } else {
+        format!(
+            "{}{} Line {}/{} ({}%) | j/k:scroll  h/l:pan  f/b:page  g/G:top/bottom  /:search  n/N:match  w:wrap  q/Esc:close ",
+            message, search, current_line, total_lines, percent
+        )
+    };
     let status_widget = Paragraph::new(status).style(Style::default().bg(Color::DarkGray));
 
     frame.render_widget(status_widget, chunks[1]);
@@ -468,7 +1879,7 @@ fn draw_image_preview(frame: &mut Frame, app: &App) -> usize {
                 .file_name()
                 .map(|n| n.to_string_lossy().to_string())
                 .unwrap_or_default();
-            format!(" {} ({}x{}) ", name, img.width, img.height)
+            format!(" {} ({}x{}) ", name, img.dimensions.0, img.dimensions.1)
         })
         .unwrap_or_else(|| " Image Preview ".to_string());
 
@@ -485,7 +1896,7 @@ fn draw_image_preview(frame: &mut Frame, app: &App) -> usize {
     // Status bar at bottom
     let status_area = Rect::new(area.x, area.height - 1, area.width, 1);
 
-    let status = format!(" {}x{} | q/Esc:close ", img.width, img.height);
+    let status = format!(" {}x{} | q/Esc:close ", img.dimensions.0, img.dimensions.1);
     let status_widget = Paragraph::new(status).style(Style::default().bg(Color::DarkGray));
 
     frame.render_widget(status_widget, status_area);
@@ -587,7 +1998,7 @@ fn centered_rect(percent_x: u16, height: u16, area: Rect) -> Rect {
 fn abbreviate_path(path: &std::path::Path, max_width: usize) -> String {
     let full_path = path.display().to_string();
 
-    if full_path.len() <= max_width {
+    if full_path.width() <= max_width {
         return full_path;
     }
 
@@ -606,7 +2017,7 @@ fn abbreviate_path(path: &std::path::Path, max_width: usize) -> String {
             if c.is_empty() {
                 String::new()
             } else {
-                c.chars().next().unwrap_or_default().to_string()
+                c.graphemes(true).next().unwrap_or_default().to_string()
             }
         })
         .collect();
@@ -615,9 +2026,9 @@ fn abbreviate_path(path: &std::path::Path, max_width: usize) -> String {
     let result = abbreviated.join("/");
 
     // If still too long, just show the last component
-    if result.len() > max_width {
-        if last.len() > max_width {
-            format!("…{}", &last[last.len().saturating_sub(max_width - 1)..])
+    if result.width() > max_width {
+        if last.width() > max_width {
+            format!("…{}", take_last_width(last, max_width.saturating_sub(1)))
         } else {
             last.to_string()
         }
@@ -626,9 +2037,311 @@ fn abbreviate_path(path: &std::path::Path, max_width: usize) -> String {
     }
 }
 
-fn get_file_icon(name: &str) -> &'static str {
-    let ext = name.rsplit('.').next().unwrap_or("");
-    match ext.to_lowercase().as_str() {
+/// Longest prefix of `text` that fits in `max_width` terminal columns,
+/// never splitting a grapheme cluster
+fn take_width(text: &str, max_width: usize) -> String {
+    let mut width = 0;
+    let mut result = String::new();
+    for grapheme in text.graphemes(true) {
+        width += grapheme.width();
+        if width > max_width {
+            break;
+        }
+        result.push_str(grapheme);
+    }
+    result
+}
+
+/// Longest suffix of `text` that fits in `max_width` terminal columns
+fn take_last_width(text: &str, max_width: usize) -> String {
+    let mut width = 0;
+    let mut start = text.len();
+    for (i, grapheme) in text.grapheme_indices(true).rev() {
+        width += grapheme.width();
+        if width > max_width {
+            break;
+        }
+        start = i;
+    }
+    text[start..].to_string()
+}
+
+/// Drops the first `columns` terminal columns of `text`. A wide character
+/// cut in half is replaced by a space so the rest stays aligned.
+/// What is shown left of each preview line
+struct Gutter<'a> {
+    line_numbers: bool,
+    /// Lines changed since `HEAD`; a marker column is only shown if there are any
+    changes: &'a HashMap<usize, LineChange>,
+}
+
+impl Gutter<'_> {
+    fn width(&self) -> usize {
+        let numbers = if self.line_numbers { 5 } else { 0 };
+        let markers = if self.changes.is_empty() { 0 } else { 1 };
+        numbers + markers
+    }
+
+    /// Gutter for 1-based line `number`, or blank for its continuation rows
+    fn spans(&self, number: Option<usize>) -> Vec<Span<'static>> {
+        let mut spans = Vec::new();
+        if !self.changes.is_empty() {
+            let marker = match number.and_then(|n| self.changes.get(&n)) {
+                Some(LineChange::Added) => Span::styled("▎", Style::default().fg(Color::Green)),
+                Some(LineChange::Modified) => Span::styled("▎", Style::default().fg(Color::Yellow)),
+                Some(LineChange::Deleted) => Span::styled("▔", Style::default().fg(Color::Red)),
+                None => Span::raw(" "),
+            };
+            spans.push(marker);
+        }
+        if self.line_numbers {
+            spans.push(match number {
+                Some(n) => Span::styled(format!("{:4} ", n), Style::default().fg(Color::DarkGray)),
+                None => Span::raw("     "),
+            });
+        }
+        spans
+    }
+}
+
+/// Matches of the preview search to highlight, in line order
+struct Highlights<'a> {
+    matches: &'a [PreviewMatch],
+    /// Index of the match shown in a different color
+    current: usize,
+}
+
+impl Highlights<'_> {
+    const NONE: Highlights<'static> = Highlights {
+        matches: &[],
+        current: 0,
+    };
+
+    /// Split `line` (line index `i`) into graphemes, each with its style
+    fn cells<'l>(&self, i: usize, line: &'l str) -> Vec<(&'l str, Style)> {
+        let first = self.matches.partition_point(|(l, _)| *l < i);
+        let last = self.matches.partition_point(|(l, _)| *l <= i);
+        let style = |pos: usize| {
+            (first..last)
+                .find(|&k| self.matches[k].1.contains(&pos))
+                .map(|k| {
+                    let bg = if k == self.current {
+                        Color::LightRed
+                    } else {
+                        Color::Yellow
+                    };
+                    Style::default().fg(Color::Black).bg(bg)
+                })
+                .unwrap_or_default()
+        };
+        line.grapheme_indices(true)
+            .map(|(pos, grapheme)| (grapheme, style(pos)))
+            .collect()
+    }
+}
+
+/// Preview rows for `content` from line `scroll` on, behind `gutter`. With
+/// `wrap_width` (the width of the whole row), long lines continue on further
+/// rows instead of being cut off; otherwise they are panned by `hscroll`.
+fn numbered_lines(
+    content: &[String],
+    scroll: usize,
+    height: usize,
+    hscroll: usize,
+    wrap_width: Option<usize>,
+    gutter: &Gutter,
+    highlights: &Highlights,
+) -> Vec<Line<'static>> {
+    let mut rows = Vec::new();
+    for (i, line) in content.iter().enumerate().skip(scroll) {
+        if rows.len() >= height {
+            break;
+        }
+        let cells = highlights.cells(i, line);
+        let Some(width) = wrap_width else {
+            let mut spans = gutter.spans(Some(i + 1));
+            spans.extend(cell_spans(skip_cells(&cells, hscroll)));
+            rows.push(Line::from(spans));
+            continue;
+        };
+        let width = width.saturating_sub(gutter.width()).max(1);
+        for (j, part) in wrap_cells(&cells, width).into_iter().enumerate() {
+            let mut spans = gutter.spans((j == 0).then_some(i + 1));
+            spans.extend(cell_spans(part.to_vec()));
+            rows.push(Line::from(spans));
+        }
+    }
+    rows.truncate(height);
+    rows
+}
+
+/// Drop the first `columns` terminal columns of `cells`, padding with spaces
+/// where a wide character was cut in half
+fn skip_cells<'l>(cells: &[(&'l str, Style)], columns: usize) -> Vec<(&'l str, Style)> {
+    let mut skipped = 0;
+    let mut rest = cells;
+    while skipped < columns {
+        match rest.split_first() {
+            Some(((grapheme, _), tail)) => {
+                skipped += grapheme.width();
+                rest = tail;
+            }
+            None => break,
+        }
+    }
+    let padding = std::iter::repeat_n((" ", Style::default()), skipped.saturating_sub(columns));
+    padding.chain(rest.iter().copied()).collect()
+}
+
+/// Split `cells` into rows of at most `width` terminal columns (at least one
+/// grapheme each, so a wide character in a narrow pane still makes progress)
+fn wrap_cells<'c, 'l>(cells: &'c [(&'l str, Style)], width: usize) -> Vec<&'c [(&'l str, Style)]> {
+    let mut rows = Vec::new();
+    let mut start = 0;
+    let mut used = 0;
+    for (k, (grapheme, _)) in cells.iter().enumerate() {
+        let w = grapheme.width();
+        if used + w > width && used > 0 {
+            rows.push(&cells[start..k]);
+            start = k;
+            used = 0;
+        }
+        used += w;
+    }
+    rows.push(&cells[start..]);
+    rows
+}
+
+/// Join runs of graphemes with the same style into spans
+fn cell_spans(cells: Vec<(&str, Style)>) -> Vec<Span<'static>> {
+    let mut spans: Vec<Span<'static>> = Vec::new();
+    for (grapheme, style) in cells {
+        match spans.last_mut() {
+            Some(span) if span.style == style => span.content.to_mut().push_str(grapheme),
+            _ => spans.push(Span::styled(grapheme.to_string(), style)),
+        }
+    }
+    spans
+}
+
+fn skip_width(text: &str, columns: usize) -> String {
+    let mut skipped = 0;
+    let mut graphemes = text.graphemes(true);
+    while skipped < columns {
+        match graphemes.next() {
+            Some(grapheme) => skipped += grapheme.width(),
+            None => break,
+        }
+    }
+    let mut result = " ".repeat(skipped.saturating_sub(columns));
+    result.extend(graphemes);
+    result
+}
+
+/// Truncates or pads `text` to exactly `width` terminal columns
+fn fit_width(text: &str, width: usize) -> String {
+    let text = take_width(text, width);
+    let padding = width - text.width();
+    text + &" ".repeat(padding)
+}
+
+fn node_icon<'a>(app: &'a App, node: &FileNode) -> &'a str {
+    if node.broken_link {
+        "\u{f127}" // chain-broken
+    } else if node.read_error.is_some() {
+        "\u{f023}" // lock
+    } else if node.is_dir {
+        if node.expanded {
+            "\u{f07c}"
+        } else {
+            "\u{f07b}"
+        }
+    } else {
+        get_file_icon(&node.name, &app.config.icons)
+    }
+}
+
+/// Name color of an entry: red for broken links, else its git status color
+fn entry_color(app: &App, node: &FileNode) -> Color {
+    if node.broken_link {
+        return Color::Red;
+    }
+    match app.git_repo.get_status(&node.path) {
+        GitStatus::Modified => Color::Yellow,
+        GitStatus::Staged => Color::LightGreen,
+        GitStatus::Added => Color::Green,
+        GitStatus::Untracked => Color::Green,
+        GitStatus::Deleted => Color::Red,
+        GitStatus::Renamed => Color::Cyan,
+        GitStatus::Conflict => Color::Magenta,
+        GitStatus::Ignored => Color::DarkGray,
+        GitStatus::None => name_color(app, node),
+    }
+}
+
+/// Color of an entry without git changes: the first matching color rule,
+/// else blue for directories and the kind color of well-known files
+fn name_color(app: &App, node: &FileNode) -> Color {
+    let executable = !node.is_dir && node.ownership.is_some_and(|o| o.mode & 0o111 != 0);
+    if let Some(rule) = app
+        .config
+        .color_rules
+        .iter()
+        .find(|rule| rule.matches(&node.name, executable))
+    {
+        return rule.color.0;
+    }
+    if node.is_dir {
+        return Color::Blue;
+    }
+    special_file(&node.name)
+        .filter(|_| app.config.special_file_colors)
+        .map_or(Color::Reset, |(_, color)| color)
+}
+
+/// Well-known files recognized by name regardless of extension: icon and color
+fn special_file(name: &str) -> Option<(&'static str, Color)> {
+    let lower = name.to_lowercase();
+    let stem = lower.split('.').next().unwrap_or("");
+    Some(match lower.as_str() {
+        "dockerfile" | "containerfile" | "docker-compose.yml" | "compose.yaml" => {
+            ("\u{f308}", Color::LightBlue)
+        }
+        _ if stem == "dockerfile" || lower.ends_with(".dockerfile") => {
+            ("\u{f308}", Color::LightBlue)
+        }
+        "makefile" | "gnumakefile" | "justfile" | ".justfile" | "cmakelists.txt" => {
+            ("\u{f0ad}", Color::LightMagenta)
+        }
+        "cargo.toml" | "cargo.lock" => ("\u{e7a8}", Color::LightRed),
+        "package.json" | "package-lock.json" => ("\u{e71e}", Color::LightRed),
+        ".gitignore" | ".gitattributes" | ".gitmodules" => ("\u{e702}", Color::Gray),
+        _ if stem == "readme" => ("\u{f02d}", Color::LightYellow),
+        _ if stem.starts_with("license") || stem.starts_with("licence") || stem == "copying" => {
+            ("\u{f0a3}", Color::LightYellow)
+        }
+        _ => return None,
+    })
+}
+
+/// Icon for a file: a configured file name icon, a well-known file's icon,
+/// a configured extension icon, else the built-in one for the extension
+fn get_file_icon<'a>(name: &str, icons: &'a IconConfig) -> &'a str {
+    if let Some(icon) = icons.filenames.get(name) {
+        return icon;
+    }
+    if let Some((icon, _)) = special_file(name) {
+        return icon;
+    }
+    let ext = name.rsplit('.').next().unwrap_or("").to_lowercase();
+    let configured = icons
+        .extensions
+        .iter()
+        .find(|(key, _)| key.to_lowercase() == ext);
+    if let Some((_, icon)) = configured {
+        return icon;
+    }
+    match ext.as_str() {
         "rs" => "",
         "py" => "",
         "js" | "jsx" => "",
@@ -651,3 +2364,202 @@ fn get_file_icon(name: &str) -> &'static str {
         _ => "",
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(lines: Vec<Line>) -> Vec<String> {
+        lines.iter().map(|l| l.to_string()).collect()
+    }
+
+    #[test]
+    fn test_configured_icons_take_precedence() {
+        let mut icons = IconConfig::default();
+        assert_eq!(
+            get_file_icon("main.rs", &icons),
+            get_file_icon("lib.RS", &icons)
+        );
+
+        icons.extensions.insert("RS".to_string(), "R".to_string());
+        icons.extensions.insert("nix".to_string(), "N".to_string());
+        icons
+            .filenames
+            .insert("build.rs".to_string(), "B".to_string());
+        assert_eq!(get_file_icon("main.rs", &icons), "R");
+        assert_eq!(get_file_icon("flake.nix", &icons), "N");
+        assert_eq!(get_file_icon("build.rs", &icons), "B");
+
+        icons
+            .filenames
+            .insert("Makefile".to_string(), "M".to_string());
+        assert_eq!(get_file_icon("Makefile", &icons), "M");
+        assert_eq!(get_file_icon("makefile", &icons), "\u{f0ad}");
+    }
+
+    #[test]
+    fn test_special_files_recognized_by_name() {
+        for name in ["Dockerfile", "app.dockerfile", "Dockerfile.dev", "Justfile"] {
+            assert!(special_file(name).is_some(), "{}", name);
+        }
+        assert_eq!(special_file("README.md"), special_file("readme"));
+        assert_eq!(special_file("Cargo.toml").unwrap().1, Color::LightRed);
+        assert_eq!(special_file("LICENSE-MIT"), special_file("COPYING"));
+        assert_eq!(special_file("LICENSE.txt").unwrap().1, Color::LightYellow);
+        assert_eq!(special_file("main.rs"), None);
+        assert_eq!(special_file("other.toml"), None);
+    }
+
+    #[test]
+    fn test_wrapped_preview_lines() {
+        let cells = Highlights::NONE.cells(0, "日本語");
+        let rows: Vec<usize> = wrap_cells(&cells, 3).iter().map(|r| r.len()).collect();
+        assert_eq!(rows, vec![1, 1, 1]);
+        assert_eq!(skip_cells(&cells, 1)[0].0, " ");
+
+        let content = vec!["abcdef".to_string(), "xy".to_string()];
+        let no_changes = HashMap::new();
+        let numbers = Gutter {
+            line_numbers: true,
+            changes: &no_changes,
+        };
+        let none = &Highlights::NONE;
+        assert_eq!(
+            text(numbered_lines(&content, 0, 10, 0, Some(9), &numbers, none)),
+            vec!["   1 abcd", "     ef", "   2 xy"]
+        );
+        assert_eq!(
+            text(numbered_lines(&content, 0, 2, 2, None, &numbers, none)),
+            vec!["   1 cdef", "   2 "]
+        );
+        assert_eq!(
+            text(numbered_lines(&content, 1, 1, 0, Some(9), &numbers, none)),
+            vec!["   2 xy"]
+        );
+    }
+
+    #[test]
+    fn test_preview_gutter() {
+        let content = vec!["a".to_string(), "b".to_string(), "c".to_string()];
+        let none = &Highlights::NONE;
+        let changes = HashMap::from([(2, LineChange::Modified), (3, LineChange::Added)]);
+        let gutter = Gutter {
+            line_numbers: true,
+            changes: &changes,
+        };
+        assert_eq!(
+            text(numbered_lines(&content, 0, 3, 0, None, &gutter, none)),
+            vec!["    1 a", "▎   2 b", "▎   3 c"]
+        );
+        let gutter = Gutter {
+            line_numbers: false,
+            changes: &changes,
+        };
+        assert_eq!(
+            text(numbered_lines(&content, 1, 3, 0, None, &gutter, none)),
+            vec!["▎b", "▎c"]
+        );
+        let no_changes = HashMap::new();
+        let bare = Gutter {
+            line_numbers: false,
+            changes: &no_changes,
+        };
+        assert_eq!(
+            text(numbered_lines(&content, 0, 1, 0, None, &bare, none)),
+            vec!["a"]
+        );
+    }
+
+    #[test]
+    fn test_preview_search_highlights() {
+        let content = vec!["foo bar foo".to_string()];
+        let matches = vec![(0, 0..3), (0, 8..11)];
+        let no_changes = HashMap::new();
+        let bare = Gutter {
+            line_numbers: false,
+            changes: &no_changes,
+        };
+        let highlights = Highlights {
+            matches: &matches,
+            current: 1,
+        };
+        let lines = numbered_lines(&content, 0, 1, 0, None, &bare, &highlights);
+        let spans: Vec<(String, Option<Color>)> = lines[0]
+            .spans
+            .iter()
+            .map(|s| (s.content.to_string(), s.style.bg))
+            .collect();
+        assert_eq!(
+            spans,
+            vec![
+                ("foo".to_string(), Some(Color::Yellow)),
+                (" bar ".to_string(), None),
+                ("foo".to_string(), Some(Color::LightRed)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_width_helpers_respect_wide_chars() {
+        // Each CJK character is two columns wide
+        assert_eq!(take_width("日本語.txt", 5), "日本");
+        assert_eq!(take_width("日本語.txt", 6), "日本語");
+        assert_eq!(take_last_width("日本語.txt", 6), "語.txt");
+        assert_eq!(skip_width("日本語", 1), " 本語");
+        assert_eq!(skip_width("日本語", 2), "本語");
+        assert_eq!(fit_width("日本", 5), "日本 ");
+        assert_eq!(fit_width("日本語", 5), "日本 ");
+    }
+
+    #[test]
+    fn test_abbreviate_path_does_not_split_multibyte_names() {
+        let path = Path::new("/home/ユーザー/ドキュメント");
+        let abbreviated = abbreviate_path(path, 8);
+        assert_eq!(abbreviated, "…メント");
+        assert!(abbreviated.width() <= 8);
+        assert_eq!(abbreviate_path(path, 20), "/h/ユ/ドキュメント");
+    }
+
+    #[test]
+    fn test_compact_layout_drops_borders() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        for i in 0..20 {
+            std::fs::write(root.join(format!("{:02}.txt", i)), "").unwrap();
+        }
+        let config = crate::config::Config {
+            git: false,
+            compact: true,
+            ..crate::config::Config::default()
+        };
+        let mut app = App::new(&root, config).unwrap();
+        let mut terminal =
+            ratatui::Terminal::new(ratatui::backend::TestBackend::new(40, 10)).unwrap();
+        let mut render = |app: &mut App| {
+            terminal
+                .draw(|frame| {
+                    draw(frame, app);
+                })
+                .unwrap();
+            terminal
+                .backend()
+                .buffer()
+                .content()
+                .iter()
+                .map(|cell| cell.symbol().to_string())
+                .collect::<String>()
+        };
+
+        let screen = render(&mut app);
+        assert!(!screen.contains('┌'));
+        assert_eq!(app.tree_area_y, 1);
+        assert_eq!(app.tree_visible_rows, 8);
+        assert_eq!(app.tree_rows(10), 8);
+
+        app.toggle_compact();
+        let screen = render(&mut app);
+        assert!(screen.contains('┌'));
+        assert_eq!(app.tree_area_y, 2);
+        assert_eq!(app.tree_visible_rows, 4);
+    }
+}